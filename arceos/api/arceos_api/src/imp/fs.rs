@@ -50,6 +50,10 @@ pub fn ax_flush_file(file: &AxFileHandle) -> AxResult {
     file.0.flush()
 }
 
+pub fn ax_sync_file(file: &AxFileHandle) -> AxResult {
+    file.0.sync()
+}
+
 pub fn ax_seek_file(file: &mut AxFileHandle, pos: AxSeekFrom) -> AxResult<u64> {
     file.0.seek(pos)
 }
@@ -78,6 +82,30 @@ pub fn ax_rename(old: &str, new: &str) -> AxResult {
     axfs::api::rename(old, new)
 }
 
+pub fn ax_symlink_attr(path: &str) -> AxResult<AxFileAttr> {
+    Ok(*axfs::api::symlink_metadata(path)?.raw_metadata())
+}
+
+pub fn ax_symlink(original: &str, link: &str) -> AxResult {
+    axfs::api::symlink(original, link)
+}
+
+pub fn ax_read_link(path: &str) -> AxResult<String> {
+    axfs::api::read_link(path)
+}
+
+pub fn ax_hard_link(original: &str, link: &str) -> AxResult {
+    axfs::api::hard_link(original, link)
+}
+
+pub fn ax_set_permissions(path: &str, perm: AxFilePerm) -> AxResult {
+    axfs::api::set_permissions(path, perm)
+}
+
+pub fn ax_chown(path: &str, uid: Option<u32>, gid: Option<u32>) -> AxResult {
+    axfs::api::chown(path, uid, gid)
+}
+
 pub fn ax_current_dir() -> AxResult<String> {
     axfs::api::current_dir()
 }
@@ -85,3 +113,19 @@ pub fn ax_current_dir() -> AxResult<String> {
 pub fn ax_set_current_dir(path: &str) -> AxResult {
     axfs::api::set_current_dir(path)
 }
+
+pub fn ax_mount(source: &str, target: &str, fstype: &str) -> AxResult {
+    axfs::api::mount(source, target, fstype)
+}
+
+pub fn ax_umount(target: &str, detach: bool) -> AxResult {
+    if detach {
+        axfs::api::umount_lazy(target)
+    } else {
+        axfs::api::umount(target)
+    }
+}
+
+pub fn ax_sync() -> AxResult {
+    axfs::api::sync()
+}
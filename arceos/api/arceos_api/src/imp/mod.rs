@@ -44,5 +44,5 @@ pub use self::stdio::*;
 pub use self::task::*;
 pub use self::time::*;
 
-pub use axhal::misc::terminate as ax_terminate;
 pub use axio::PollState as AxPollState;
+pub use axruntime::terminate as ax_terminate;
@@ -20,7 +20,7 @@ pub fn ax_exit(_exit_code: i32) -> ! {
     #[cfg(feature = "multitask")]
     axtask::exit(_exit_code);
     #[cfg(not(feature = "multitask"))]
-    axhal::misc::terminate();
+    axruntime::terminate();
 }
 
 cfg_task! {
@@ -39,6 +39,9 @@ cfg_task! {
         }
     }
 
+    /// A set of CPUs a task is allowed to run on.
+    pub type AxCpuMask = axtask::CpuMask;
+
     /// A handle to a wait queue.
     ///
     /// A wait queue is used to store sleeping tasks waiting for a certain event
@@ -82,6 +85,17 @@ cfg_task! {
         }
     }
 
+    pub fn ax_set_current_affinity(cpus: AxCpuMask) -> crate::AxResult {
+        if axtask::set_current_affinity(cpus) {
+            Ok(())
+        } else {
+            axerrno::ax_err!(
+                InvalidInput,
+                "ax_set_current_affinity: no CPU in the mask is available"
+            )
+        }
+    }
+
     pub fn ax_wait_queue_wait(
         wq: &AxWaitQueueHandle,
         until_condition: impl Fn() -> bool,
@@ -121,6 +121,7 @@ pub mod task {
         @cfg "multitask";
         pub type AxTaskHandle;
         pub type AxWaitQueueHandle;
+        pub type AxCpuMask;
     }
 
     define_api! {
@@ -155,6 +156,9 @@ pub mod task {
         pub fn ax_wait_for_exit(task: AxTaskHandle) -> Option<i32>;
         /// Sets the priority of the current task.
         pub fn ax_set_current_priority(prio: isize) -> crate::AxResult;
+        /// Sets the CPUs the current task is allowed to run on, and migrates
+        /// it if it's not allowed to run on the current CPU.
+        pub fn ax_set_current_affinity(cpus: AxCpuMask) -> crate::AxResult;
 
         /// Blocks the current task and put it into the wait queue, until the
         /// given condition becomes true, or the the given duration has elapsed
@@ -225,6 +229,8 @@ pub mod fs {
         pub fn ax_truncate_file(file: &AxFileHandle, size: u64) -> AxResult;
         /// Flushes the file, writes all buffered data to the underlying device.
         pub fn ax_flush_file(file: &AxFileHandle) -> AxResult;
+        /// Writes back the cached data and metadata of the file to the disk.
+        pub fn ax_sync_file(file: &AxFileHandle) -> AxResult;
         /// Sets the cursor of the file to the specified offset. Returns the new
         /// position after the seek.
         pub fn ax_seek_file(file: &mut AxFileHandle, pos: AxSeekFrom) -> AxResult<u64>;
@@ -249,11 +255,37 @@ pub mod fs {
         ///
         /// It will delete the original file if `old` already exists.
         pub fn ax_rename(old: &str, new: &str) -> AxResult;
+        /// Returns attributes of the file at the path, without following
+        /// the symbolic link if it is one.
+        pub fn ax_symlink_attr(path: &str) -> AxResult<AxFileAttr>;
+        /// Creates a symbolic link `link` pointing to `original`.
+        pub fn ax_symlink(original: &str, link: &str) -> AxResult;
+        /// Returns the target of the symbolic link.
+        pub fn ax_read_link(path: &str) -> AxResult<alloc::string::String>;
+        /// Creates a hard link `link` to the file `original`.
+        pub fn ax_hard_link(original: &str, link: &str) -> AxResult;
+        /// Changes the permission bits of the file.
+        pub fn ax_set_permissions(path: &str, perm: AxFilePerm) -> AxResult;
+        /// Changes the owner and the group of the file, the ones given as
+        /// `None` are not changed.
+        pub fn ax_chown(path: &str, uid: Option<u32>, gid: Option<u32>) -> AxResult;
 
         /// Returns the current working directory.
         pub fn ax_current_dir() -> AxResult<alloc::string::String>;
         /// Changes the current working directory to the specified path.
         pub fn ax_set_current_dir(path: &str) -> AxResult;
+
+        /// Mounts a new filesystem of the type `fstype` on the directory
+        /// `target`. The disk-based filesystems are created on the block
+        /// device file `source`.
+        pub fn ax_mount(source: &str, target: &str, fstype: &str) -> AxResult;
+        /// Unmounts the filesystem mounted on `target`.
+        ///
+        /// If `detach` is set, it is detached even if it is busy, and
+        /// unmounted after the files opened in it are closed.
+        pub fn ax_umount(target: &str, detach: bool) -> AxResult;
+        /// Writes back all the cached data of the filesystems to the disks.
+        pub fn ax_sync() -> AxResult;
     }
 }
 
@@ -41,14 +41,26 @@ typedef struct {{
             "ssize_t",
             "off_t",
             "mode_t",
+            "uid_t",
+            "gid_t",
             "sock.*",
             "fd_set",
             "timeval",
+            "cpu_set_t",
+            "sched_param",
+            "sched_attr",
             "pthread_t",
             "pthread_attr_t",
             "pthread_mutex_t",
             "pthread_mutexattr_t",
+            "pthread_cond_t",
+            "pthread_condattr_t",
+            "pthread_rwlock_t",
+            "pthread_rwlockattr_t",
+            "sem_t",
             "epoll_event",
+            "flock",
+            "inotify_event",
             "iovec",
             "clockid_t",
             "rlimit",
@@ -68,6 +80,14 @@ typedef struct {{
             "RLIMIT_.*",
             "EAI_.*",
             "MAXADDRS",
+            "MS_.*",
+            "MNT_.*",
+            "UMOUNT_.*",
+            "LOCK_.*",
+            "XATTR_.*",
+            "IN_.*",
+            "SCHED_.*",
+            "[RWX]_OK",
         ];
 
         #[derive(Debug)]
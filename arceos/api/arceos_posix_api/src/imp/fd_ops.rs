@@ -48,6 +48,10 @@ pub fn close_file_like(fd: c_int) -> LinuxResult {
         .write()
         .remove(fd as usize)
         .ok_or(LinuxError::EBADF)?;
+    #[cfg(feature = "fs")]
+    if let Ok(file) = f.clone().into_any().downcast::<super::fs::File>() {
+        file.unlock_records();
+    }
     drop(f);
     Ok(())
 }
@@ -103,6 +107,9 @@ pub fn sys_dup2(old_fd: c_int, new_fd: c_int) -> c_int {
 
 /// Manipulate file descriptor.
 ///
+/// The byte-range locks (`F_GETLK`, `F_SETLK` and `F_SETLKW`) are supported
+/// on the regular files, with the `fs` feature.
+///
 /// TODO: `SET/GET` command is ignored, hard-code stdin/stdout
 pub fn sys_fcntl(fd: c_int, cmd: c_int, arg: usize) -> c_int {
     debug!("sys_fcntl <= fd: {} cmd: {} arg: {}", fd, cmd, arg);
@@ -120,6 +127,10 @@ pub fn sys_fcntl(fd: c_int, cmd: c_int, arg: usize) -> c_int {
                 get_file_like(fd)?.set_nonblocking(arg & (ctypes::O_NONBLOCK as usize) > 0)?;
                 Ok(0)
             }
+            #[cfg(feature = "fs")]
+            ctypes::F_GETLK | ctypes::F_SETLK | ctypes::F_SETLKW => {
+                super::fs::fcntl_lock(fd, cmd as u32, arg as *mut ctypes::flock)
+            }
             _ => {
                 warn!("unsupported fcntl parameters: cmd {}", cmd);
                 Ok(0)
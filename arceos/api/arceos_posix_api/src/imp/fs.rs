@@ -1,21 +1,25 @@
-use alloc::sync::Arc;
-use core::ffi::{c_char, c_int};
+use alloc::{string::String, sync::Arc, vec::Vec};
+use core::ffi::{c_char, c_int, c_ulong, c_void};
 
-use axerrno::{LinuxError, LinuxResult};
-use axfs::fops::OpenOptions;
+use axerrno::{AxError, LinuxError, LinuxResult};
+use axfs::fops::{FileAttr, FileTimes, LockHandle, LockType, OpenOptions, XattrMode};
 use axio::{PollState, SeekFrom};
 use axsync::Mutex;
 
 use super::fd_ops::{get_file_like, FileLike};
 use crate::{ctypes, utils::char_ptr_to_str};
 
+/// A file opened by [`sys_open`].
 pub struct File {
     inner: Mutex<axfs::fops::File>,
+    /// The advisory locks, which are taken without holding `inner`.
+    lock: LockHandle,
 }
 
 impl File {
     fn new(inner: axfs::fops::File) -> Self {
         Self {
+            lock: inner.lock_handle().clone(),
             inner: Mutex::new(inner),
         }
     }
@@ -24,12 +28,24 @@ impl File {
         super::fd_ops::add_file_like(Arc::new(self))
     }
 
-    fn from_fd(fd: c_int) -> LinuxResult<Arc<Self>> {
+    /// Returns the file of `fd`, or `EINVAL` if `fd` is not a regular file.
+    pub fn from_fd(fd: c_int) -> LinuxResult<Arc<Self>> {
         let f = super::fd_ops::get_file_like(fd)?;
         f.into_any()
             .downcast::<Self>()
             .map_err(|_| LinuxError::EINVAL)
     }
+
+    /// Returns the underlying [`axfs::fops::File`].
+    pub fn inner(&self) -> &Mutex<axfs::fops::File> {
+        &self.inner
+    }
+
+    /// Releases the byte-range locks of the current process on the file,
+    /// which is done when any of its file descriptors is closed.
+    pub(super) fn unlock_records(&self) {
+        self.lock.unlock_all(LOCK_OWNER);
+    }
 }
 
 impl FileLike for File {
@@ -42,21 +58,8 @@ impl FileLike for File {
     }
 
     fn stat(&self) -> LinuxResult<ctypes::stat> {
-        let metadata = self.inner.lock().get_attr()?;
-        let ty = metadata.file_type() as u8;
-        let perm = metadata.perm().bits() as u32;
-        let st_mode = ((ty as u32) << 12) | perm;
-        Ok(ctypes::stat {
-            st_ino: 1,
-            st_nlink: 1,
-            st_mode,
-            st_uid: 1000,
-            st_gid: 1000,
-            st_size: metadata.size() as _,
-            st_blocks: metadata.blocks() as _,
-            st_blksize: 512,
-            ..Default::default()
-        })
+        let inner = self.inner.lock();
+        Ok(attr_to_stat(&inner.get_attr()?, &inner.get_times()?))
     }
 
     fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
@@ -75,8 +78,29 @@ impl FileLike for File {
     }
 }
 
+/// Convert the file attributes and timestamps to [`ctypes::stat`].
+fn attr_to_stat(attr: &FileAttr, times: &FileTimes) -> ctypes::stat {
+    let ty = attr.file_type() as u8;
+    let perm = attr.perm().bits() as u32;
+    let st_mode = ((ty as u32) << 12) | perm;
+    ctypes::stat {
+        st_ino: 1,
+        st_nlink: 1,
+        st_mode,
+        st_uid: 1000,
+        st_gid: 1000,
+        st_size: attr.size() as _,
+        st_blocks: attr.blocks() as _,
+        st_blksize: 512,
+        st_atime: times.accessed.into(),
+        st_mtime: times.modified.into(),
+        st_ctime: times.changed.into(),
+        ..Default::default()
+    }
+}
+
 /// Convert open flags to [`OpenOptions`].
-fn flags_to_options(flags: c_int, _mode: ctypes::mode_t) -> OpenOptions {
+fn flags_to_options(flags: c_int, mode: ctypes::mode_t) -> OpenOptions {
     let flags = flags as u32;
     let mut options = OpenOptions::new();
     match flags & 0b11 {
@@ -99,6 +123,7 @@ fn flags_to_options(flags: c_int, _mode: ctypes::mode_t) -> OpenOptions {
     if flags & ctypes::O_EXEC != 0 {
         options.create_new(true);
     }
+    options.mode(mode);
     options
 }
 
@@ -177,7 +202,13 @@ pub unsafe fn sys_lstat(path: *const c_char, buf: *mut ctypes::stat) -> ctypes::
         if buf.is_null() {
             return Err(LinuxError::EFAULT);
         }
-        unsafe { *buf = Default::default() }; // TODO
+        let metadata = axfs::api::symlink_metadata(path?)?;
+        let times = FileTimes {
+            accessed: metadata.accessed(),
+            modified: metadata.modified(),
+            changed: metadata.changed(),
+        };
+        unsafe { *buf = attr_to_stat(metadata.raw_metadata(), &times) };
         Ok(0)
     })
 }
@@ -215,3 +246,513 @@ pub fn sys_rename(old: *const c_char, new: *const c_char) -> c_int {
         Ok(0)
     })
 }
+
+/// Create a symbolic link `linkpath` pointing to `target`.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+pub fn sys_symlink(target: *const c_char, linkpath: *const c_char) -> c_int {
+    syscall_body!(sys_symlink, {
+        let target = char_ptr_to_str(target)?;
+        let linkpath = char_ptr_to_str(linkpath)?;
+        debug!(
+            "sys_symlink <= target: {:?}, linkpath: {:?}",
+            target, linkpath
+        );
+        axfs::api::symlink(target, linkpath)?;
+        Ok(0)
+    })
+}
+
+/// Read the target of the symbolic link `path` into `buf`, which is not
+/// null-terminated and is truncated if `buf` is too small.
+///
+/// Return the number of bytes placed in `buf`.
+pub fn sys_readlink(path: *const c_char, buf: *mut c_char, size: usize) -> ctypes::ssize_t {
+    let path = char_ptr_to_str(path);
+    debug!("sys_readlink <= {:?} {:#x} {}", path, buf as usize, size);
+    syscall_body!(sys_readlink, {
+        if buf.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let target = axfs::api::read_link(path?)?;
+        let len = target.len().min(size);
+        let dst = unsafe { core::slice::from_raw_parts_mut(buf as *mut u8, len) };
+        dst.copy_from_slice(&target.as_bytes()[..len]);
+        Ok(len as ctypes::ssize_t)
+    })
+}
+
+/// Create a hard link `new` to the file `old`.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+pub fn sys_link(old: *const c_char, new: *const c_char) -> c_int {
+    syscall_body!(sys_link, {
+        let old_path = char_ptr_to_str(old)?;
+        let new_path = char_ptr_to_str(new)?;
+        debug!("sys_link <= old: {:?}, new: {:?}", old_path, new_path);
+        axfs::api::hard_link(old_path, new_path).map_err(|e| match e {
+            axerrno::AxError::Unsupported => LinuxError::EXDEV,
+            e => e.into(),
+        })?;
+        Ok(0)
+    })
+}
+
+/// Change the permission bits of the file `path` to `mode`.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+pub fn sys_chmod(path: *const c_char, mode: ctypes::mode_t) -> c_int {
+    syscall_body!(sys_chmod, {
+        let path = char_ptr_to_str(path)?;
+        debug!("sys_chmod <= {:?} {:#o}", path, mode);
+        axfs::api::set_mode(path, mode as u32).map_err(|e| match e {
+            axerrno::AxError::Unsupported => LinuxError::EPERM,
+            e => e.into(),
+        })?;
+        Ok(0)
+    })
+}
+
+/// Change the owner and the group of the file `path`. The ID `-1` leaves it
+/// unchanged.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+pub fn sys_chown(path: *const c_char, uid: ctypes::uid_t, gid: ctypes::gid_t) -> c_int {
+    syscall_body!(sys_chown, {
+        let path = char_ptr_to_str(path)?;
+        debug!("sys_chown <= {:?} {} {}", path, uid as i32, gid as i32);
+        let uid = (uid != ctypes::uid_t::MAX).then_some(uid);
+        let gid = (gid != ctypes::gid_t::MAX).then_some(gid);
+        axfs::api::chown(path, uid, gid).map_err(|e| match e {
+            axerrno::AxError::Unsupported => LinuxError::EPERM,
+            e => e.into(),
+        })?;
+        Ok(0)
+    })
+}
+
+/// Set the file mode creation mask of the current task to `mask`.
+///
+/// Return the previous mask.
+pub fn sys_umask(mask: ctypes::mode_t) -> ctypes::mode_t {
+    debug!("sys_umask <= {:#o}", mask);
+    let mut cred = axtask::current_cred();
+    let old = cred.umask;
+    cred.umask = mask & 0o777;
+    axtask::set_current_cred(cred);
+    old
+}
+
+/// Check whether the current task can access the file `path` with `mode`,
+/// which is `F_OK` or the bitwise OR of `R_OK`, `W_OK` and `X_OK`.
+///
+/// Return 0 if the access is permitted, otherwise return -1.
+pub fn sys_access(path: *const c_char, mode: c_int) -> c_int {
+    syscall_body!(sys_access, {
+        let path = char_ptr_to_str(path)?;
+        debug!("sys_access <= {:?} {:#o}", path, mode);
+        let mode = mode as u32;
+        if mode & !(ctypes::R_OK | ctypes::W_OK | ctypes::X_OK) != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let perm = axfs::fops::FilePerm::from_bits_truncate((mode << 6) as u16);
+        axfs::api::access(path, perm)?;
+        Ok(0)
+    })
+}
+
+/// Mount the filesystem of the type `fstype` on the directory `target`.
+///
+/// `source` is the block device file for the disk-based filesystems, and may
+/// be null for the others (e.g., `tmpfs`). Remounting, bind mounts and moving
+/// mounts are not supported, the other flags and `data` are ignored.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+pub fn sys_mount(
+    source: *const c_char,
+    target: *const c_char,
+    fstype: *const c_char,
+    flags: c_ulong,
+    data: *const c_void,
+) -> c_int {
+    syscall_body!(sys_mount, {
+        let source = if source.is_null() {
+            ""
+        } else {
+            char_ptr_to_str(source)?
+        };
+        let target = char_ptr_to_str(target)?;
+        let fstype = char_ptr_to_str(fstype)?;
+        debug!(
+            "sys_mount <= source: {:?}, target: {:?}, fstype: {:?}, flags: {:#x}, data: {:#x}",
+            source, target, fstype, flags, data as usize
+        );
+        if flags & (ctypes::MS_REMOUNT | ctypes::MS_BIND | ctypes::MS_MOVE) as c_ulong != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        axfs::api::mount(source, target, fstype).map_err(|e| match e {
+            axerrno::AxError::Unsupported => LinuxError::ENODEV,
+            e => e.into(),
+        })?;
+        Ok(0)
+    })
+}
+
+/// Unmount the filesystem mounted on `target`.
+///
+/// With `MNT_DETACH`, the filesystem is detached even if it is busy, and
+/// unmounted after the files opened in it are closed. `MNT_FORCE` and
+/// `UMOUNT_NOFOLLOW` are ignored.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+pub fn sys_umount2(target: *const c_char, flags: c_int) -> c_int {
+    syscall_body!(sys_umount2, {
+        let target = char_ptr_to_str(target)?;
+        debug!("sys_umount2 <= target: {:?}, flags: {:#x}", target, flags);
+        let known = ctypes::MNT_FORCE | ctypes::MNT_DETACH | ctypes::UMOUNT_NOFOLLOW;
+        if flags as u32 & !known != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        if flags as u32 & ctypes::MNT_DETACH != 0 {
+            axfs::api::umount_lazy(target)?;
+        } else {
+            axfs::api::umount(target)?;
+        }
+        Ok(0)
+    })
+}
+
+/// Writes back all the cached data of the filesystems to the disks.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+pub fn sys_sync() -> c_int {
+    debug!("sys_sync");
+    syscall_body!(sys_sync, {
+        axfs::api::sync()?;
+        Ok(0)
+    })
+}
+
+/// Writes back the cached data and metadata of the file `fd` to the disk.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+pub fn sys_fsync(fd: c_int) -> c_int {
+    debug!("sys_fsync <= {}", fd);
+    syscall_body!(sys_fsync, {
+        File::from_fd(fd)?.inner().lock().sync()?;
+        Ok(0)
+    })
+}
+
+/// Writes back the cached data of the file `fd` to the disk, with the
+/// metadata needed to read it back.
+///
+/// All the metadata is written for now, the same as [`sys_fsync`].
+pub fn sys_fdatasync(fd: c_int) -> c_int {
+    debug!("sys_fdatasync <= {}", fd);
+    syscall_body!(sys_fdatasync, {
+        File::from_fd(fd)?.inner().lock().sync()?;
+        Ok(0)
+    })
+}
+
+/// The owner of the byte-range locks. All the tasks are threads of one
+/// process, so they share the locks, which are released when the process
+/// closes any file descriptor of the file.
+const LOCK_OWNER: u64 = 1;
+
+/// Tests, takes or releases the byte-range lock described by `flock` on the
+/// file `fd`, for the `F_GETLK`, `F_SETLK` and `F_SETLKW` commands of
+/// `fcntl`.
+pub(super) fn fcntl_lock(fd: c_int, cmd: u32, flock: *mut ctypes::flock) -> LinuxResult<c_int> {
+    if flock.is_null() {
+        return Err(LinuxError::EFAULT);
+    }
+    let flock = unsafe { &mut *flock };
+    let file = File::from_fd(fd).map_err(|_| LinuxError::EBADF)?;
+    let ty = match flock.l_type as u32 {
+        ctypes::F_RDLCK => Some(LockType::Shared),
+        ctypes::F_WRLCK => Some(LockType::Exclusive),
+        ctypes::F_UNLCK => None,
+        _ => return Err(LinuxError::EINVAL),
+    };
+    let base = match flock.l_whence {
+        0 => 0, // SEEK_SET
+        1 => file.inner.lock().seek(SeekFrom::Current(0))? as i64,
+        2 => file.inner.lock().get_attr()?.size() as i64,
+        _ => return Err(LinuxError::EINVAL),
+    };
+    // A negative length locks the bytes before the start, and zero locks up
+    // to the end of the file.
+    let start = base.checked_add(flock.l_start).ok_or(LinuxError::EINVAL)?;
+    let (start, end) = match flock.l_len {
+        0 => (start, u64::MAX),
+        len if len > 0 => (start, start.saturating_add(len) as u64),
+        len => (start + len, start as u64),
+    };
+    if start < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let start = start as u64;
+
+    if cmd == ctypes::F_GETLK {
+        let ty = ty.ok_or(LinuxError::EINVAL)?;
+        match file.lock.test_lock(LOCK_OWNER, ty, start, end) {
+            Some(lock) => {
+                flock.l_type = match lock.ty {
+                    LockType::Shared => ctypes::F_RDLCK,
+                    LockType::Exclusive => ctypes::F_WRLCK,
+                } as _;
+                flock.l_whence = 0;
+                flock.l_start = lock.start as _;
+                flock.l_len = match lock.end {
+                    u64::MAX => 0,
+                    end => (end - lock.start) as _,
+                };
+                flock.l_pid = lock.owner as _;
+            }
+            None => flock.l_type = ctypes::F_UNLCK as _,
+        }
+        return Ok(0);
+    }
+    let wait = cmd == ctypes::F_SETLKW;
+    file.lock
+        .lock_range(LOCK_OWNER, ty, start, end, wait)
+        .map_err(|e| match e {
+            AxError::PermissionDenied => LinuxError::EBADF,
+            e => e.into(),
+        })?;
+    Ok(0)
+}
+
+/// Apply or remove an advisory lock on the whole file `fd`, as `operation`
+/// is `LOCK_SH`, `LOCK_EX` or `LOCK_UN`, with `LOCK_NB` not to block.
+///
+/// The lock is held by the opened file, shared by the duplicated file
+/// descriptors, and released when all of them are closed.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+pub fn sys_flock(fd: c_int, operation: c_int) -> c_int {
+    debug!("sys_flock <= {} {:#x}", fd, operation);
+    syscall_body!(sys_flock, {
+        let operation = operation as u32;
+        let ty = match operation & !ctypes::LOCK_NB {
+            ctypes::LOCK_SH => Some(LockType::Shared),
+            ctypes::LOCK_EX => Some(LockType::Exclusive),
+            ctypes::LOCK_UN => None,
+            _ => return Err(LinuxError::EINVAL),
+        };
+        let wait = operation & ctypes::LOCK_NB == 0;
+        File::from_fd(fd)?.lock.lock(ty, wait)?;
+        Ok(0)
+    })
+}
+
+/// Converts the errors of the extended attribute operations, where
+/// `NotFound` means that the attribute does not exist.
+fn xattr_err(e: AxError) -> LinuxError {
+    match e {
+        AxError::NotFound => LinuxError::ENODATA,
+        AxError::Unsupported => LinuxError::EOPNOTSUPP,
+        AxError::InvalidInput => LinuxError::ERANGE,
+        e => e.into(),
+    }
+}
+
+/// Checks that the file `path` exists, so that `NotFound` of the extended
+/// attribute operations on it means that the attribute does not exist.
+fn check_xattr_path(path: &str) -> LinuxResult {
+    axfs::api::access(path, axfs::fops::FilePerm::empty())?;
+    Ok(())
+}
+
+/// Copies `data`, the value of an extended attribute or the list of names,
+/// to `buf` of `size` bytes. Returns the size of `data`.
+///
+/// Nothing is copied if `size` is 0, and `ERANGE` is returned if `size` is
+/// too small.
+fn copy_xattr(data: &[u8], buf: *mut c_void, size: usize) -> LinuxResult<usize> {
+    if size == 0 {
+        return Ok(data.len());
+    }
+    if size < data.len() {
+        return Err(LinuxError::ERANGE);
+    }
+    if buf.is_null() {
+        return Err(LinuxError::EFAULT);
+    }
+    unsafe { core::ptr::copy_nonoverlapping(data.as_ptr(), buf as *mut u8, data.len()) };
+    Ok(data.len())
+}
+
+/// Returns the names of the extended attributes, each ending with a NUL.
+fn xattr_list(names: Vec<String>) -> Vec<u8> {
+    let mut list = Vec::new();
+    for name in names {
+        list.extend_from_slice(name.as_bytes());
+        list.push(0);
+    }
+    list
+}
+
+/// Returns the value to set an extended attribute, and the mode by `flags`.
+fn xattr_value<'a>(
+    value: *const c_void,
+    size: usize,
+    flags: c_int,
+) -> LinuxResult<(&'a [u8], XattrMode)> {
+    let mode = match flags as u32 {
+        0 => XattrMode::Set,
+        ctypes::XATTR_CREATE => XattrMode::Create,
+        ctypes::XATTR_REPLACE => XattrMode::Replace,
+        _ => return Err(LinuxError::EINVAL),
+    };
+    if size > axfs::fops::XATTR_SIZE_MAX {
+        return Err(LinuxError::E2BIG);
+    }
+    let value = if size == 0 {
+        &[]
+    } else if value.is_null() {
+        return Err(LinuxError::EFAULT);
+    } else {
+        unsafe { core::slice::from_raw_parts(value as *const u8, size) }
+    };
+    Ok((value, mode))
+}
+
+/// Get the value of the extended attribute `name` of the file `path` into
+/// `value` of `size` bytes. If `size` is 0, only the size of the value is
+/// returned.
+///
+/// Return the size of the value, otherwise return -1, e.g., with `ENODATA`
+/// if the attribute does not exist.
+pub fn sys_getxattr(
+    path: *const c_char,
+    name: *const c_char,
+    value: *mut c_void,
+    size: usize,
+) -> ctypes::ssize_t {
+    syscall_body!(sys_getxattr, {
+        let path = char_ptr_to_str(path)?;
+        let name = char_ptr_to_str(name)?;
+        debug!("sys_getxattr <= {:?} {:?} {}", path, name, size);
+        check_xattr_path(path)?;
+        let data = axfs::api::get_xattr(path, name).map_err(xattr_err)?;
+        copy_xattr(&data, value, size)
+    })
+}
+
+/// Get the value of the extended attribute `name` of the file `fd`, the same
+/// as [`sys_getxattr`].
+pub fn sys_fgetxattr(
+    fd: c_int,
+    name: *const c_char,
+    value: *mut c_void,
+    size: usize,
+) -> ctypes::ssize_t {
+    syscall_body!(sys_fgetxattr, {
+        let name = char_ptr_to_str(name)?;
+        debug!("sys_fgetxattr <= {} {:?} {}", fd, name, size);
+        let data = File::from_fd(fd)?.inner.lock().get_xattr(name);
+        copy_xattr(&data.map_err(xattr_err)?, value, size)
+    })
+}
+
+/// Set the value of the extended attribute `name` of the file `path` to the
+/// `size` bytes of `value`. `flags` is `XATTR_CREATE` to fail if it exists,
+/// `XATTR_REPLACE` to fail if it does not exist, or 0 for either.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+pub fn sys_setxattr(
+    path: *const c_char,
+    name: *const c_char,
+    value: *const c_void,
+    size: usize,
+    flags: c_int,
+) -> c_int {
+    syscall_body!(sys_setxattr, {
+        let path = char_ptr_to_str(path)?;
+        let name = char_ptr_to_str(name)?;
+        debug!(
+            "sys_setxattr <= {:?} {:?} {} {:#x}",
+            path, name, size, flags
+        );
+        let (value, mode) = xattr_value(value, size, flags)?;
+        check_xattr_path(path)?;
+        axfs::api::set_xattr(path, name, value, mode).map_err(xattr_err)?;
+        Ok(0)
+    })
+}
+
+/// Set the value of the extended attribute `name` of the file `fd`, the same
+/// as [`sys_setxattr`].
+pub fn sys_fsetxattr(
+    fd: c_int,
+    name: *const c_char,
+    value: *const c_void,
+    size: usize,
+    flags: c_int,
+) -> c_int {
+    syscall_body!(sys_fsetxattr, {
+        let name = char_ptr_to_str(name)?;
+        debug!("sys_fsetxattr <= {} {:?} {} {:#x}", fd, name, size, flags);
+        let (value, mode) = xattr_value(value, size, flags)?;
+        let res = File::from_fd(fd)?.inner.lock().set_xattr(name, value, mode);
+        res.map_err(xattr_err)?;
+        Ok(0)
+    })
+}
+
+/// List the names of the extended attributes of the file `path` into `list`
+/// of `size` bytes, each ending with a NUL. If `size` is 0, only the size of
+/// the list is returned.
+///
+/// Return the size of the list, otherwise return -1.
+pub fn sys_listxattr(path: *const c_char, list: *mut c_char, size: usize) -> ctypes::ssize_t {
+    syscall_body!(sys_listxattr, {
+        let path = char_ptr_to_str(path)?;
+        debug!("sys_listxattr <= {:?} {}", path, size);
+        let names = axfs::api::list_xattr(path).map_err(|e| match e {
+            AxError::Unsupported => LinuxError::EOPNOTSUPP,
+            e => e.into(),
+        })?;
+        copy_xattr(&xattr_list(names), list as _, size)
+    })
+}
+
+/// List the names of the extended attributes of the file `fd`, the same as
+/// [`sys_listxattr`].
+pub fn sys_flistxattr(fd: c_int, list: *mut c_char, size: usize) -> ctypes::ssize_t {
+    syscall_body!(sys_flistxattr, {
+        debug!("sys_flistxattr <= {} {}", fd, size);
+        let names = File::from_fd(fd)?.inner.lock().list_xattr();
+        copy_xattr(&xattr_list(names.map_err(xattr_err)?), list as _, size)
+    })
+}
+
+/// Remove the extended attribute `name` of the file `path`.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+pub fn sys_removexattr(path: *const c_char, name: *const c_char) -> c_int {
+    syscall_body!(sys_removexattr, {
+        let path = char_ptr_to_str(path)?;
+        let name = char_ptr_to_str(name)?;
+        debug!("sys_removexattr <= {:?} {:?}", path, name);
+        check_xattr_path(path)?;
+        axfs::api::remove_xattr(path, name).map_err(xattr_err)?;
+        Ok(0)
+    })
+}
+
+/// Remove the extended attribute `name` of the file `fd`.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+pub fn sys_fremovexattr(fd: c_int, name: *const c_char) -> c_int {
+    syscall_body!(sys_fremovexattr, {
+        let name = char_ptr_to_str(name)?;
+        debug!("sys_fremovexattr <= {} {:?}", fd, name);
+        let res = File::from_fd(fd)?.inner.lock().remove_xattr(name);
+        res.map_err(xattr_err)?;
+        Ok(0)
+    })
+}
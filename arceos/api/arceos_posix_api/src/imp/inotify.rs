@@ -0,0 +1,153 @@
+//! `inotify` implementation, on top of [`axfs::notify`].
+
+use alloc::sync::Arc;
+use core::ffi::{c_char, c_int};
+use core::mem::size_of;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use axerrno::{LinuxError, LinuxResult};
+use axfs::notify::{Event, EventMask, Watcher};
+use axio::PollState;
+
+use super::fd_ops::{add_file_like, get_file_like, FileLike};
+use crate::{ctypes, utils::char_ptr_to_str};
+
+/// The size of `struct inotify_event` without the name.
+const EVENT_SIZE: usize = size_of::<ctypes::inotify_event>();
+
+/// Returns the length of the name field of the event, which is padded with
+/// NULs to align the next event.
+fn name_len(event: &Event) -> usize {
+    if event.name.is_empty() {
+        0
+    } else {
+        (event.name.len() + 1).next_multiple_of(EVENT_SIZE)
+    }
+}
+
+pub struct Inotify {
+    watcher: Watcher,
+    nonblocking: AtomicBool,
+}
+
+impl Inotify {
+    fn new(nonblocking: bool) -> Self {
+        Self {
+            watcher: Watcher::new(),
+            nonblocking: AtomicBool::new(nonblocking),
+        }
+    }
+
+    fn from_fd(fd: c_int) -> LinuxResult<Arc<Self>> {
+        get_file_like(fd)?
+            .into_any()
+            .downcast::<Inotify>()
+            .map_err(|_| LinuxError::EINVAL)
+    }
+}
+
+impl FileLike for Inotify {
+    /// Reads the events as `struct inotify_event`, as many as fit in the
+    /// buffer. Fails with `EINVAL` if the buffer is too small for the first
+    /// one.
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        let wait = !self.nonblocking.load(Ordering::Relaxed);
+        let mut size = 0;
+        let events = self.watcher.read_events(wait, |event| {
+            let len = EVENT_SIZE + name_len(event);
+            let fits = size + len <= buf.len();
+            if fits {
+                size += len;
+            }
+            fits
+        })?;
+        if events.is_empty() {
+            return Err(LinuxError::EINVAL);
+        }
+
+        let mut pos = 0;
+        for event in events {
+            let len = name_len(&event);
+            let header = [
+                event.wd.to_ne_bytes(),
+                event.mask.bits().to_ne_bytes(),
+                event.cookie.to_ne_bytes(),
+                (len as u32).to_ne_bytes(),
+            ];
+            buf[pos..pos + EVENT_SIZE].copy_from_slice(header.as_flattened());
+            pos += EVENT_SIZE;
+            let name = &mut buf[pos..pos + len];
+            name.fill(0);
+            name[..event.name.len()].copy_from_slice(event.name.as_bytes());
+            pos += len;
+        }
+        Ok(pos)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn stat(&self) -> LinuxResult<ctypes::stat> {
+        let st_mode = 0o600u32; // rw-------
+        Ok(ctypes::stat {
+            st_ino: 1,
+            st_nlink: 1,
+            st_mode,
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: self.watcher.has_events(),
+            writable: false,
+        })
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult {
+        self.nonblocking.store(nonblocking, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// Creates a new inotify instance, returns the file descriptor referring to
+/// it.
+///
+/// The `flags` can be `IN_NONBLOCK` and `IN_CLOEXEC`.
+pub fn sys_inotify_init1(flags: c_int) -> c_int {
+    debug!("sys_inotify_init1 <= {:#x}", flags);
+    syscall_body!(sys_inotify_init1, {
+        let flags = flags as u32;
+        if flags & !(ctypes::IN_NONBLOCK | ctypes::IN_CLOEXEC) != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let inotify = Inotify::new(flags & ctypes::IN_NONBLOCK != 0);
+        add_file_like(Arc::new(inotify))
+    })
+}
+
+/// Watches the file or directory at the path for the events in `mask`,
+/// returns the watch descriptor.
+pub fn sys_inotify_add_watch(fd: c_int, pathname: *const c_char, mask: u32) -> c_int {
+    syscall_body!(sys_inotify_add_watch, {
+        let path = char_ptr_to_str(pathname)?;
+        debug!("sys_inotify_add_watch <= {} {:?} {:#x}", fd, path, mask);
+        let inotify = Inotify::from_fd(fd)?;
+        let mask = EventMask::from_bits_truncate(mask);
+        Ok(inotify.watcher.add_watch(path, mask)?)
+    })
+}
+
+/// Removes the watch `wd` from the inotify instance.
+pub fn sys_inotify_rm_watch(fd: c_int, wd: c_int) -> c_int {
+    debug!("sys_inotify_rm_watch <= {} {}", fd, wd);
+    syscall_body!(sys_inotify_rm_watch, {
+        Inotify::from_fd(fd)?.watcher.remove_watch(wd)?;
+        Ok(0)
+    })
+}
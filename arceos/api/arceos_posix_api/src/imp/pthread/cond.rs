@@ -0,0 +1,151 @@
+use crate::{ctypes, utils::check_null_mut_ptr};
+
+use axerrno::LinuxResult;
+use axsync::Condvar;
+
+use core::ffi::c_int;
+use core::mem::{self, size_of};
+use core::ptr;
+use core::time::Duration;
+
+use super::{mutex::PthreadMutex, timeout_from_abstime};
+
+static_assertions::const_assert!(size_of::<PthreadCond>() <= size_of::<ctypes::pthread_cond_t>());
+
+#[repr(C)]
+pub struct PthreadCond(Condvar);
+
+impl PthreadCond {
+    const fn new() -> Self {
+        Self(Condvar::new())
+    }
+
+    fn wait(&self, mutex: &PthreadMutex) -> LinuxResult {
+        // the mutex is held by the caller, and is still held when returning.
+        let guard = unsafe { mutex.0.make_guard_unchecked() };
+        mem::forget(self.0.wait(guard));
+        Ok(())
+    }
+
+    fn timedwait(&self, mutex: &PthreadMutex, dur: Duration) -> LinuxResult {
+        let guard = unsafe { mutex.0.make_guard_unchecked() };
+        #[cfg(feature = "irq")]
+        {
+            let (guard, res) = self.0.wait_timeout(guard, dur);
+            mem::forget(guard);
+            if res.timed_out() {
+                return Err(axerrno::LinuxError::ETIMEDOUT);
+            }
+        }
+        #[cfg(not(feature = "irq"))]
+        {
+            warn!(
+                "pthread_cond_timedwait: the timeout {dur:?} is ignored without the `irq` feature"
+            );
+            mem::forget(self.0.wait(guard));
+        }
+        Ok(())
+    }
+
+    fn signal(&self) -> LinuxResult {
+        self.0.notify_one();
+        Ok(())
+    }
+
+    fn broadcast(&self) -> LinuxResult {
+        self.0.notify_all();
+        Ok(())
+    }
+}
+
+/// Initialize a condition variable.
+pub fn sys_pthread_cond_init(
+    cond: *mut ctypes::pthread_cond_t,
+    _attr: *const ctypes::pthread_condattr_t,
+) -> c_int {
+    debug!("sys_pthread_cond_init <= {:#x}", cond as usize);
+    syscall_body!(sys_pthread_cond_init, {
+        check_null_mut_ptr(cond)?;
+        unsafe {
+            cond.cast::<PthreadCond>().write(PthreadCond::new());
+        }
+        Ok(0)
+    })
+}
+
+/// Destroy a condition variable.
+pub fn sys_pthread_cond_destroy(cond: *mut ctypes::pthread_cond_t) -> c_int {
+    debug!("sys_pthread_cond_destroy <= {:#x}", cond as usize);
+    syscall_body!(sys_pthread_cond_destroy, {
+        check_null_mut_ptr(cond)?;
+        unsafe {
+            ptr::drop_in_place(cond.cast::<PthreadCond>());
+        }
+        Ok(0)
+    })
+}
+
+/// Wait on the condition variable, the mutex is unlocked while waiting.
+pub fn sys_pthread_cond_wait(
+    cond: *mut ctypes::pthread_cond_t,
+    mutex: *mut ctypes::pthread_mutex_t,
+) -> c_int {
+    debug!(
+        "sys_pthread_cond_wait <= {:#x}, {:#x}",
+        cond as usize, mutex as usize
+    );
+    syscall_body!(sys_pthread_cond_wait, {
+        check_null_mut_ptr(cond)?;
+        check_null_mut_ptr(mutex)?;
+        unsafe {
+            (*cond.cast::<PthreadCond>()).wait(&*mutex.cast::<PthreadMutex>())?;
+        }
+        Ok(0)
+    })
+}
+
+/// Wait on the condition variable until the absolute time `abstime` of
+/// `CLOCK_REALTIME`.
+pub fn sys_pthread_cond_timedwait(
+    cond: *mut ctypes::pthread_cond_t,
+    mutex: *mut ctypes::pthread_mutex_t,
+    abstime: *const ctypes::timespec,
+) -> c_int {
+    debug!(
+        "sys_pthread_cond_timedwait <= {:#x}, {:#x}",
+        cond as usize, mutex as usize
+    );
+    syscall_body!(sys_pthread_cond_timedwait, {
+        check_null_mut_ptr(cond)?;
+        check_null_mut_ptr(mutex)?;
+        let dur = timeout_from_abstime(abstime)?;
+        unsafe {
+            (*cond.cast::<PthreadCond>()).timedwait(&*mutex.cast::<PthreadMutex>(), dur)?;
+        }
+        Ok(0)
+    })
+}
+
+/// Wake up one task waiting on the condition variable.
+pub fn sys_pthread_cond_signal(cond: *mut ctypes::pthread_cond_t) -> c_int {
+    debug!("sys_pthread_cond_signal <= {:#x}", cond as usize);
+    syscall_body!(sys_pthread_cond_signal, {
+        check_null_mut_ptr(cond)?;
+        unsafe {
+            (*cond.cast::<PthreadCond>()).signal()?;
+        }
+        Ok(0)
+    })
+}
+
+/// Wake up all tasks waiting on the condition variable.
+pub fn sys_pthread_cond_broadcast(cond: *mut ctypes::pthread_cond_t) -> c_int {
+    debug!("sys_pthread_cond_broadcast <= {:#x}", cond as usize);
+    syscall_body!(sys_pthread_cond_broadcast, {
+        check_null_mut_ptr(cond)?;
+        unsafe {
+            (*cond.cast::<PthreadCond>()).broadcast()?;
+        }
+        Ok(0)
+    })
+}
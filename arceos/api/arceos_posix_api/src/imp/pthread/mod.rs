@@ -1,14 +1,18 @@
 use alloc::{boxed::Box, collections::BTreeMap, sync::Arc};
 use core::cell::UnsafeCell;
 use core::ffi::{c_int, c_void};
+use core::time::Duration;
 
 use axerrno::{LinuxError, LinuxResult};
 use axtask::AxTaskRef;
 use spin::RwLock;
 
-use crate::ctypes;
+use crate::{ctypes, utils::check_null_ptr};
 
+pub mod cond;
 pub mod mutex;
+pub mod rwlock;
+pub mod semaphore;
 
 lazy_static::lazy_static! {
     static ref TID_TO_PTHREAD: RwLock<BTreeMap<u64, ForceSendSync<ctypes::pthread_t>>> = {
@@ -152,3 +156,14 @@ struct ForceSendSync<T>(T);
 
 unsafe impl<T> Send for ForceSendSync<T> {}
 unsafe impl<T> Sync for ForceSendSync<T> {}
+
+/// Converts the absolute time `abstime` of `CLOCK_REALTIME` to the timeout
+/// from now, used by the timed waiting functions.
+fn timeout_from_abstime(abstime: *const ctypes::timespec) -> LinuxResult<Duration> {
+    check_null_ptr(abstime)?;
+    let abstime = unsafe { *abstime };
+    if !(0..1_000_000_000).contains(&abstime.tv_nsec) {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(Duration::from(abstime).saturating_sub(axhal::time::wall_time()))
+}
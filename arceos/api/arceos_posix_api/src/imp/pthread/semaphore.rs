@@ -0,0 +1,152 @@
+use crate::{ctypes, utils::check_null_mut_ptr};
+
+use axerrno::{LinuxError, LinuxResult};
+use axsync::Semaphore;
+
+use core::ffi::{c_int, c_uint};
+use core::mem::size_of;
+use core::ptr;
+use core::time::Duration;
+
+use super::timeout_from_abstime;
+
+static_assertions::const_assert!(size_of::<PosixSemaphore>() <= size_of::<ctypes::sem_t>());
+
+/// The maximum value of a semaphore, same as `SEM_VALUE_MAX` in `limits.h`.
+const SEM_VALUE_MAX: usize = c_int::MAX as usize;
+
+#[repr(C)]
+pub struct PosixSemaphore(Semaphore);
+
+impl PosixSemaphore {
+    const fn new(value: usize) -> Self {
+        Self(Semaphore::new(value))
+    }
+
+    fn wait(&self) -> LinuxResult {
+        self.0.acquire();
+        Ok(())
+    }
+
+    fn trywait(&self) -> LinuxResult {
+        if self.0.try_acquire() {
+            Ok(())
+        } else {
+            Err(LinuxError::EAGAIN)
+        }
+    }
+
+    fn timedwait(&self, dur: Duration) -> LinuxResult {
+        #[cfg(feature = "irq")]
+        if !self.0.acquire_timeout(dur) {
+            return Err(LinuxError::ETIMEDOUT);
+        }
+        #[cfg(not(feature = "irq"))]
+        {
+            warn!("sem_timedwait: the timeout {dur:?} is ignored without the `irq` feature");
+            self.0.acquire();
+        }
+        Ok(())
+    }
+
+    fn post(&self) -> LinuxResult {
+        if self.0.try_release(SEM_VALUE_MAX) {
+            Ok(())
+        } else {
+            Err(LinuxError::EOVERFLOW)
+        }
+    }
+}
+
+/// Initialize an unnamed semaphore with the given value.
+///
+/// The semaphores are always shared between all tasks, `pshared` is ignored.
+pub fn sys_sem_init(sem: *mut ctypes::sem_t, _pshared: c_int, value: c_uint) -> c_int {
+    debug!("sys_sem_init <= {:#x} {}", sem as usize, value);
+    syscall_body!(sys_sem_init, {
+        check_null_mut_ptr(sem)?;
+        if value as usize > SEM_VALUE_MAX {
+            return Err(LinuxError::EINVAL);
+        }
+        unsafe {
+            sem.cast::<PosixSemaphore>()
+                .write(PosixSemaphore::new(value as usize));
+        }
+        Ok(0)
+    })
+}
+
+/// Destroy an unnamed semaphore.
+pub fn sys_sem_destroy(sem: *mut ctypes::sem_t) -> c_int {
+    debug!("sys_sem_destroy <= {:#x}", sem as usize);
+    syscall_body!(sys_sem_destroy, {
+        check_null_mut_ptr(sem)?;
+        unsafe {
+            ptr::drop_in_place(sem.cast::<PosixSemaphore>());
+        }
+        Ok(0)
+    })
+}
+
+/// Decrement the semaphore, blocking until its value is greater than zero.
+pub fn sys_sem_wait(sem: *mut ctypes::sem_t) -> c_int {
+    debug!("sys_sem_wait <= {:#x}", sem as usize);
+    syscall_body!(sys_sem_wait, {
+        check_null_mut_ptr(sem)?;
+        unsafe {
+            (*sem.cast::<PosixSemaphore>()).wait()?;
+        }
+        Ok(0)
+    })
+}
+
+/// Try to decrement the semaphore, returns `EAGAIN` if its value is zero.
+pub fn sys_sem_trywait(sem: *mut ctypes::sem_t) -> c_int {
+    debug!("sys_sem_trywait <= {:#x}", sem as usize);
+    syscall_body!(sys_sem_trywait, {
+        check_null_mut_ptr(sem)?;
+        unsafe {
+            (*sem.cast::<PosixSemaphore>()).trywait()?;
+        }
+        Ok(0)
+    })
+}
+
+/// Decrement the semaphore, blocking until the absolute time `abstime` of
+/// `CLOCK_REALTIME` at most.
+pub fn sys_sem_timedwait(sem: *mut ctypes::sem_t, abstime: *const ctypes::timespec) -> c_int {
+    debug!("sys_sem_timedwait <= {:#x}", sem as usize);
+    syscall_body!(sys_sem_timedwait, {
+        check_null_mut_ptr(sem)?;
+        let dur = timeout_from_abstime(abstime)?;
+        unsafe {
+            (*sem.cast::<PosixSemaphore>()).timedwait(dur)?;
+        }
+        Ok(0)
+    })
+}
+
+/// Increment the semaphore, and wake up a task waiting on it.
+pub fn sys_sem_post(sem: *mut ctypes::sem_t) -> c_int {
+    debug!("sys_sem_post <= {:#x}", sem as usize);
+    syscall_body!(sys_sem_post, {
+        check_null_mut_ptr(sem)?;
+        unsafe {
+            (*sem.cast::<PosixSemaphore>()).post()?;
+        }
+        Ok(0)
+    })
+}
+
+/// Get the value of the semaphore.
+pub fn sys_sem_getvalue(sem: *mut ctypes::sem_t, sval: *mut c_int) -> c_int {
+    debug!("sys_sem_getvalue <= {:#x}", sem as usize);
+    syscall_body!(sys_sem_getvalue, {
+        check_null_mut_ptr(sem)?;
+        check_null_mut_ptr(sval)?;
+        unsafe {
+            *sval = (*sem.cast::<PosixSemaphore>()).0.available_permits() as c_int;
+        }
+        Ok(0)
+    })
+}
@@ -1,4 +1,9 @@
-use core::ffi::c_int;
+use core::ffi::{c_int, c_uint};
+use core::mem::size_of;
+
+use axerrno::{LinuxError, LinuxResult};
+
+use crate::ctypes;
 
 /// Relinquish the CPU, and switches to another task.
 ///
@@ -16,6 +21,280 @@ pub fn sys_sched_yield() -> c_int {
     0
 }
 
+/// Returns the task with the thread ID `pid`, or the current task if it's 0.
+#[cfg(feature = "multitask")]
+fn find_task(pid: c_int) -> LinuxResult<axtask::AxTaskRef> {
+    if pid == 0 {
+        Ok(axtask::current().as_task_ref().clone())
+    } else {
+        axtask::find_task(pid as u64).ok_or(LinuxError::ESRCH)
+    }
+}
+
+/// Returns the bits of the CPUs in `mask`, only the CPUs in the first word
+/// can be used.
+unsafe fn cpu_set_bits(cpusetsize: usize, mask: *const ctypes::cpu_set_t) -> LinuxResult<usize> {
+    if mask.is_null() {
+        return Err(LinuxError::EFAULT);
+    }
+    if cpusetsize < size_of::<usize>() {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(unsafe { (*mask).__bits[0] as usize })
+}
+
+/// Set the CPUs the thread `pid` is allowed to run on, `pid` 0 means the
+/// current thread.
+///
+/// The thread is migrated if it's not allowed to run on its current CPU.
+pub unsafe fn sys_sched_setaffinity(
+    pid: c_int,
+    cpusetsize: usize,
+    mask: *const ctypes::cpu_set_t,
+) -> c_int {
+    debug!("sys_sched_setaffinity <= {} {:#x}", pid, cpusetsize);
+    syscall_body!(sys_sched_setaffinity, {
+        let bits = unsafe { cpu_set_bits(cpusetsize, mask)? };
+        #[cfg(feature = "multitask")]
+        if !find_task(pid)?.set_affinity(axtask::CpuMask::from_bits(bits)) {
+            return Err(LinuxError::EINVAL);
+        }
+        #[cfg(not(feature = "multitask"))]
+        {
+            if pid != 0 && pid != 2 {
+                return Err(LinuxError::ESRCH);
+            }
+            if bits & 1 == 0 {
+                return Err(LinuxError::EINVAL); // only CPU 0 is used
+            }
+        }
+        Ok(0)
+    })
+}
+
+/// Get the CPUs the thread `pid` is allowed to run on, `pid` 0 means the
+/// current thread.
+pub unsafe fn sys_sched_getaffinity(
+    pid: c_int,
+    cpusetsize: usize,
+    mask: *mut ctypes::cpu_set_t,
+) -> c_int {
+    debug!("sys_sched_getaffinity <= {} {:#x}", pid, cpusetsize);
+    syscall_body!(sys_sched_getaffinity, {
+        unsafe { cpu_set_bits(cpusetsize, mask)? };
+        #[cfg(feature = "multitask")]
+        let bits = find_task(pid)?.cpumask().bits();
+        #[cfg(not(feature = "multitask"))]
+        let bits = if pid == 0 || pid == 2 {
+            1 // only CPU 0 is used
+        } else {
+            return Err(LinuxError::ESRCH);
+        };
+        unsafe {
+            core::ptr::write_bytes(mask as *mut u8, 0, cpusetsize);
+            (*mask).__bits[0] = bits as _;
+        }
+        Ok(0)
+    })
+}
+
+/// Converts the policy and the priority of `sched_setscheduler` to
+/// [`axtask::SchedPolicy`].
+#[cfg(feature = "multitask")]
+fn sched_policy(policy: c_int, priority: c_int) -> LinuxResult<axtask::SchedPolicy> {
+    use axtask::SchedPolicy;
+    let rt_prio = || u8::try_from(priority).map_err(|_| LinuxError::EINVAL);
+    match policy as u32 {
+        ctypes::SCHED_OTHER if priority == 0 => Ok(SchedPolicy::Normal),
+        ctypes::SCHED_FIFO => Ok(SchedPolicy::Fifo(rt_prio()?)),
+        ctypes::SCHED_RR => Ok(SchedPolicy::RoundRobin(rt_prio()?)),
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+/// Sets the scheduling policy of the thread `pid`, only the superuser can
+/// make it a real-time or deadline thread.
+#[cfg(feature = "multitask")]
+fn set_sched_policy(pid: c_int, policy: axtask::SchedPolicy) -> LinuxResult<c_int> {
+    let task = find_task(pid)?;
+    if policy != axtask::SchedPolicy::Normal && !axtask::current_cred().is_root() {
+        return Err(LinuxError::EPERM);
+    }
+    axtask::set_sched_policy(&task, policy).map_err(|err| match err {
+        axerrno::AxError::Unsupported => LinuxError::EINVAL,
+        err => err.into(),
+    })?;
+    Ok(0)
+}
+
+/// Set the scheduling policy and the priority of the thread `pid`, `pid` 0
+/// means the current thread.
+///
+/// The `policy` can be `SCHED_OTHER` (the priority must be 0), `SCHED_FIFO`
+/// or `SCHED_RR`. Use [`sys_sched_setattr`] for `SCHED_DEADLINE`.
+#[cfg(feature = "multitask")]
+pub unsafe fn sys_sched_setscheduler(
+    pid: c_int,
+    policy: c_int,
+    param: *const ctypes::sched_param,
+) -> c_int {
+    debug!("sys_sched_setscheduler <= {} {}", pid, policy);
+    syscall_body!(sys_sched_setscheduler, {
+        if param.is_null() {
+            return Err(LinuxError::EINVAL);
+        }
+        let policy = sched_policy(policy, unsafe { (*param).sched_priority })?;
+        set_sched_policy(pid, policy)
+    })
+}
+
+/// Get the scheduling policy of the thread `pid`.
+#[cfg(feature = "multitask")]
+pub fn sys_sched_getscheduler(pid: c_int) -> c_int {
+    debug!("sys_sched_getscheduler <= {}", pid);
+    syscall_body!(sys_sched_getscheduler, {
+        let policy = match find_task(pid)?.sched_policy() {
+            axtask::SchedPolicy::Normal => ctypes::SCHED_OTHER,
+            axtask::SchedPolicy::Fifo(_) => ctypes::SCHED_FIFO,
+            axtask::SchedPolicy::RoundRobin(_) => ctypes::SCHED_RR,
+            axtask::SchedPolicy::Deadline(_) => ctypes::SCHED_DEADLINE,
+        };
+        Ok(policy as c_int)
+    })
+}
+
+/// Set the priority of the thread `pid`, keeping its scheduling policy.
+#[cfg(feature = "multitask")]
+pub unsafe fn sys_sched_setparam(pid: c_int, param: *const ctypes::sched_param) -> c_int {
+    debug!("sys_sched_setparam <= {}", pid);
+    syscall_body!(sys_sched_setparam, {
+        if param.is_null() {
+            return Err(LinuxError::EINVAL);
+        }
+        let priority = unsafe { (*param).sched_priority };
+        let policy = match find_task(pid)?.sched_policy() {
+            axtask::SchedPolicy::Normal => ctypes::SCHED_OTHER,
+            axtask::SchedPolicy::Fifo(_) => ctypes::SCHED_FIFO,
+            axtask::SchedPolicy::RoundRobin(_) => ctypes::SCHED_RR,
+            axtask::SchedPolicy::Deadline(_) => return Err(LinuxError::EINVAL),
+        };
+        set_sched_policy(pid, sched_policy(policy as c_int, priority)?)
+    })
+}
+
+/// Get the priority of the thread `pid`, it's 0 if it's not a real-time
+/// thread.
+#[cfg(feature = "multitask")]
+pub unsafe fn sys_sched_getparam(pid: c_int, param: *mut ctypes::sched_param) -> c_int {
+    debug!("sys_sched_getparam <= {}", pid);
+    syscall_body!(sys_sched_getparam, {
+        if param.is_null() {
+            return Err(LinuxError::EINVAL);
+        }
+        let priority = match find_task(pid)?.sched_policy() {
+            axtask::SchedPolicy::Fifo(prio) | axtask::SchedPolicy::RoundRobin(prio) => prio,
+            _ => 0,
+        };
+        unsafe { (*param).sched_priority = priority as c_int };
+        Ok(0)
+    })
+}
+
+/// Set the scheduling policy and the attributes of the thread `pid`,
+/// including `SCHED_DEADLINE`.
+///
+/// The `flags` must be 0.
+#[cfg(feature = "multitask")]
+pub unsafe fn sys_sched_setattr(
+    pid: c_int,
+    attr: *const ctypes::sched_attr,
+    flags: c_uint,
+) -> c_int {
+    debug!("sys_sched_setattr <= {} {:#x}", pid, flags);
+    syscall_body!(sys_sched_setattr, {
+        if attr.is_null() || flags != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let attr = unsafe { &*attr };
+        let policy = match attr.sched_policy {
+            ctypes::SCHED_DEADLINE => axtask::SchedPolicy::Deadline(axtask::DeadlineParams {
+                runtime: attr.sched_runtime,
+                deadline: attr.sched_deadline,
+                // the period is the same as the deadline if it's 0
+                period: match attr.sched_period {
+                    0 => attr.sched_deadline,
+                    period => period,
+                },
+            }),
+            policy => sched_policy(policy as c_int, attr.sched_priority as c_int)?,
+        };
+        set_sched_policy(pid, policy)
+    })
+}
+
+/// Get the scheduling policy and the attributes of the thread `pid`.
+///
+/// The `size` is the size of the buffer `attr`, and the `flags` must be 0.
+#[cfg(feature = "multitask")]
+pub unsafe fn sys_sched_getattr(
+    pid: c_int,
+    attr: *mut ctypes::sched_attr,
+    size: c_uint,
+    flags: c_uint,
+) -> c_int {
+    debug!("sys_sched_getattr <= {} {} {:#x}", pid, size, flags);
+    syscall_body!(sys_sched_getattr, {
+        if attr.is_null() || (size as usize) < size_of::<ctypes::sched_attr>() || flags != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let mut res = ctypes::sched_attr {
+            size: size_of::<ctypes::sched_attr>() as u32,
+            ..Default::default()
+        };
+        match find_task(pid)?.sched_policy() {
+            axtask::SchedPolicy::Normal => res.sched_policy = ctypes::SCHED_OTHER,
+            axtask::SchedPolicy::Fifo(prio) => {
+                res.sched_policy = ctypes::SCHED_FIFO;
+                res.sched_priority = prio as u32;
+            }
+            axtask::SchedPolicy::RoundRobin(prio) => {
+                res.sched_policy = ctypes::SCHED_RR;
+                res.sched_priority = prio as u32;
+            }
+            axtask::SchedPolicy::Deadline(params) => {
+                res.sched_policy = ctypes::SCHED_DEADLINE;
+                res.sched_runtime = params.runtime;
+                res.sched_deadline = params.deadline;
+                res.sched_period = params.period;
+            }
+        }
+        unsafe { attr.write(res) };
+        Ok(0)
+    })
+}
+
+/// Get the highest priority of the scheduling policy.
+pub fn sys_sched_get_priority_max(policy: c_int) -> c_int {
+    syscall_body!(sys_sched_get_priority_max, {
+        match policy as u32 {
+            ctypes::SCHED_FIFO | ctypes::SCHED_RR => Ok(99),
+            ctypes::SCHED_OTHER | ctypes::SCHED_DEADLINE => Ok(0),
+            _ => Err(LinuxError::EINVAL),
+        }
+    })
+}
+
+/// Get the lowest priority of the scheduling policy.
+pub fn sys_sched_get_priority_min(policy: c_int) -> c_int {
+    syscall_body!(sys_sched_get_priority_min, {
+        match policy as u32 {
+            ctypes::SCHED_FIFO | ctypes::SCHED_RR => Ok(1),
+            ctypes::SCHED_OTHER | ctypes::SCHED_DEADLINE => Ok(0),
+            _ => Err(LinuxError::EINVAL),
+        }
+    })
+}
+
 /// Get current thread ID.
 pub fn sys_getpid() -> c_int {
     syscall_body!(sys_getpid,
@@ -30,11 +309,53 @@ pub fn sys_getpid() -> c_int {
     )
 }
 
+/// Get the user ID of the current task.
+pub fn sys_getuid() -> c_uint {
+    axtask::current_cred().uid
+}
+
+/// Get the group ID of the current task.
+pub fn sys_getgid() -> c_uint {
+    axtask::current_cred().gid
+}
+
+/// Set the user ID of the current task. Only the superuser can change it.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+pub fn sys_setuid(uid: c_uint) -> c_int {
+    debug!("sys_setuid <= {}", uid);
+    syscall_body!(sys_setuid, {
+        let mut cred = axtask::current_cred();
+        if !cred.is_root() && cred.uid != uid {
+            return Err(LinuxError::EPERM);
+        }
+        cred.uid = uid;
+        axtask::set_current_cred(cred);
+        Ok(0)
+    })
+}
+
+/// Set the group ID of the current task. Only the superuser can change it.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+pub fn sys_setgid(gid: c_uint) -> c_int {
+    debug!("sys_setgid <= {}", gid);
+    syscall_body!(sys_setgid, {
+        let mut cred = axtask::current_cred();
+        if !cred.is_root() && cred.gid != gid {
+            return Err(LinuxError::EPERM);
+        }
+        cred.gid = gid;
+        axtask::set_current_cred(cred);
+        Ok(0)
+    })
+}
+
 /// Exit current task
 pub fn sys_exit(exit_code: c_int) -> ! {
     debug!("sys_exit <= {}", exit_code);
     #[cfg(feature = "multitask")]
     axtask::exit(exit_code);
     #[cfg(not(feature = "multitask"))]
-    axhal::misc::terminate();
+    axruntime::terminate();
 }
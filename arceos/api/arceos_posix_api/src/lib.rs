@@ -34,12 +34,33 @@ pub use imp::io::{sys_read, sys_write, sys_writev};
 pub use imp::resources::{sys_getrlimit, sys_setrlimit};
 pub use imp::sys::sys_sysconf;
 pub use imp::task::{sys_exit, sys_getpid, sys_sched_yield};
+pub use imp::task::{sys_sched_get_priority_max, sys_sched_get_priority_min};
+pub use imp::task::{sys_sched_getaffinity, sys_sched_setaffinity};
+pub use imp::task::{sys_getgid, sys_getuid, sys_setgid, sys_setuid};
 pub use imp::time::{sys_clock_gettime, sys_nanosleep};
 
 #[cfg(feature = "fd")]
 pub use imp::fd_ops::{sys_close, sys_dup, sys_dup2, sys_fcntl, get_file_like};
+#[cfg(feature = "fd")]
+pub use imp::fd_ops::{add_file_like, FileLike};
 #[cfg(feature = "fs")]
 pub use imp::fs::{sys_fstat, sys_getcwd, sys_lseek, sys_lstat, sys_open, sys_rename, sys_stat};
+#[cfg(feature = "fs")]
+pub use imp::fs::{sys_access, sys_chmod, sys_chown, sys_umask};
+#[cfg(feature = "fs")]
+pub use imp::fs::{sys_link, sys_readlink, sys_symlink};
+#[cfg(feature = "fs")]
+pub use imp::fs::{sys_mount, sys_umount2};
+#[cfg(feature = "fs")]
+pub use imp::fs::{sys_fdatasync, sys_fsync, sys_sync};
+#[cfg(feature = "fs")]
+pub use imp::fs::{sys_fgetxattr, sys_flistxattr, sys_fremovexattr, sys_fsetxattr};
+#[cfg(feature = "fs")]
+pub use imp::fs::{sys_flock, sys_getxattr, sys_listxattr, sys_removexattr, sys_setxattr};
+#[cfg(feature = "fs")]
+pub use imp::fs::File;
+#[cfg(feature = "fs")]
+pub use imp::inotify::{sys_inotify_add_watch, sys_inotify_init1, sys_inotify_rm_watch};
 #[cfg(feature = "select")]
 pub use imp::io_mpx::sys_select;
 #[cfg(feature = "epoll")]
@@ -53,8 +74,30 @@ pub use imp::net::{
 #[cfg(feature = "pipe")]
 pub use imp::pipe::sys_pipe;
 #[cfg(feature = "multitask")]
+pub use imp::pthread::cond::{
+    sys_pthread_cond_broadcast, sys_pthread_cond_destroy, sys_pthread_cond_init,
+    sys_pthread_cond_signal, sys_pthread_cond_timedwait, sys_pthread_cond_wait,
+};
+#[cfg(feature = "multitask")]
 pub use imp::pthread::mutex::{
     sys_pthread_mutex_init, sys_pthread_mutex_lock, sys_pthread_mutex_unlock,
 };
 #[cfg(feature = "multitask")]
+pub use imp::pthread::rwlock::{
+    sys_pthread_rwlock_destroy, sys_pthread_rwlock_init, sys_pthread_rwlock_rdlock,
+    sys_pthread_rwlock_tryrdlock, sys_pthread_rwlock_trywrlock, sys_pthread_rwlock_unlock,
+    sys_pthread_rwlock_wrlock,
+};
+#[cfg(feature = "multitask")]
+pub use imp::pthread::semaphore::{
+    sys_sem_destroy, sys_sem_getvalue, sys_sem_init, sys_sem_post, sys_sem_timedwait,
+    sys_sem_trywait, sys_sem_wait,
+};
+#[cfg(feature = "multitask")]
 pub use imp::pthread::{sys_pthread_create, sys_pthread_exit, sys_pthread_join, sys_pthread_self};
+#[cfg(feature = "multitask")]
+pub use imp::task::{sys_sched_getattr, sys_sched_setattr};
+#[cfg(feature = "multitask")]
+pub use imp::task::{sys_sched_getparam, sys_sched_getscheduler};
+#[cfg(feature = "multitask")]
+pub use imp::task::{sys_sched_setparam, sys_sched_setscheduler};
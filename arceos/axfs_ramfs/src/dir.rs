@@ -2,11 +2,13 @@ use alloc::collections::BTreeMap;
 use alloc::sync::{Arc, Weak};
 use alloc::{string::String, vec::Vec};
 
-use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType};
+use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType};
 use axfs_vfs::{VfsError, VfsResult};
 use spin::RwLock;
 
 use crate::file::FileNode;
+use crate::symlink::SymlinkNode;
+use crate::NodeMeta;
 
 /// The directory node in the RAM filesystem.
 ///
@@ -15,6 +17,7 @@ pub struct DirNode {
     this: Weak<DirNode>,
     parent: RwLock<Weak<dyn VfsNodeOps>>,
     children: RwLock<BTreeMap<String, VfsNodeRef>>,
+    meta: NodeMeta,
 }
 
 impl DirNode {
@@ -23,9 +26,15 @@ impl DirNode {
             this: this.clone(),
             parent: RwLock::new(parent.unwrap_or_else(|| Weak::<Self>::new())),
             children: RwLock::new(BTreeMap::new()),
+            meta: NodeMeta::new(VfsNodePerm::default_dir()),
         })
     }
 
+    /// Returns the permission bits and the owner of the directory.
+    pub fn meta(&self) -> &NodeMeta {
+        &self.meta
+    }
+
     pub(super) fn set_parent(&self, parent: Option<&VfsNodeRef>) {
         *self.parent.write() = parent.map_or(Weak::<Self>::new() as _, Arc::downgrade);
     }
@@ -41,17 +50,48 @@ impl DirNode {
     }
 
     /// Creates a new node with the given name and type in this directory.
+    ///
+    /// A new symbolic link has an empty target, which is set by writing it.
     pub fn create_node(&self, name: &str, ty: VfsNodeType) -> VfsResult {
-        if self.exist(name) {
+        self.create_node_with(name, ty, |_| {})
+    }
+
+    /// Same as [`create_node`](Self::create_node), but `init` sets up the
+    /// metadata of the new node (e.g., the permission bits and the owner)
+    /// before it's added to the directory.
+    pub fn create_node_with(
+        &self,
+        name: &str,
+        ty: VfsNodeType,
+        init: impl FnOnce(&NodeMeta),
+    ) -> VfsResult {
+        let mut children = self.children.write();
+        if children.contains_key(name) {
             log::error!("AlreadyExists {}", name);
             return Err(VfsError::AlreadyExists);
         }
         let node: VfsNodeRef = match ty {
             VfsNodeType::File => Arc::new(FileNode::new()),
             VfsNodeType::Dir => Self::new(Some(self.this.clone())),
+            VfsNodeType::SymLink => Arc::new(SymlinkNode::new()),
             _ => return Err(VfsError::Unsupported),
         };
-        self.children.write().insert(name.into(), node);
+        init(crate::node_meta(&node).unwrap());
+        children.insert(name.into(), node);
+        Ok(())
+    }
+
+    /// Adds an existing node with the given name to this directory.
+    ///
+    /// It allows nodes of other filesystems (e.g., generated files) to be
+    /// placed in the RAM filesystem. Adding a node of this filesystem creates
+    /// a hard link to it, which shares the content with the other names.
+    pub fn add_node(&self, name: &str, node: VfsNodeRef) -> VfsResult {
+        let mut children = self.children.write();
+        if children.contains_key(name) {
+            return Err(VfsError::AlreadyExists);
+        }
+        children.insert(name.into(), node);
         Ok(())
     }
 
@@ -90,7 +130,12 @@ impl DirNode {
 
 impl VfsNodeOps for DirNode {
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        Ok(VfsNodeAttr::new_dir(4096, 0))
+        Ok(VfsNodeAttr::new(
+            self.meta.perm(),
+            VfsNodeType::Dir,
+            4096,
+            0,
+        ))
     }
 
     fn parent(&self) -> Option<VfsNodeRef> {
@@ -1,25 +1,40 @@
 use alloc::vec::Vec;
 use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeAttr, VfsNodeOps, VfsResult};
+use axfs_vfs::{VfsNodePerm, VfsNodeType};
 use spin::RwLock;
 
+use crate::NodeMeta;
+
 /// The file node in the RAM filesystem.
 ///
 /// It implements [`axfs_vfs::VfsNodeOps`].
 pub struct FileNode {
     content: RwLock<Vec<u8>>,
+    meta: NodeMeta,
 }
 
 impl FileNode {
     pub(super) const fn new() -> Self {
         Self {
             content: RwLock::new(Vec::new()),
+            meta: NodeMeta::new(VfsNodePerm::default_file()),
         }
     }
+
+    /// Returns the permission bits and the owner of the file.
+    pub fn meta(&self) -> &NodeMeta {
+        &self.meta
+    }
 }
 
 impl VfsNodeOps for FileNode {
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        Ok(VfsNodeAttr::new_file(self.content.read().len() as _, 0))
+        Ok(VfsNodeAttr::new(
+            self.meta.perm(),
+            VfsNodeType::File,
+            self.content.read().len() as _,
+            0,
+        ))
     }
 
     fn truncate(&self, size: u64) -> VfsResult {
@@ -52,5 +67,10 @@ impl VfsNodeOps for FileNode {
         Ok(buf.len())
     }
 
+    fn fsync(&self) -> VfsResult {
+        // The content is only in memory.
+        Ok(())
+    }
+
     impl_vfs_non_dir_default! {}
 }
@@ -8,12 +8,16 @@ extern crate alloc;
 
 mod dir;
 mod file;
+mod meta;
+mod symlink;
 
 #[cfg(test)]
 mod tests;
 
 pub use self::dir::DirNode;
 pub use self::file::FileNode;
+pub use self::meta::NodeMeta;
+pub use self::symlink::SymlinkNode;
 
 use alloc::sync::Arc;
 use axfs_vfs::{VfsNodeRef, VfsOps, VfsResult};
@@ -55,6 +59,19 @@ impl VfsOps for RamFileSystem {
     }
 }
 
+/// Returns the permission bits and the owner of `node`, or `None` if it is
+/// not a node of the RAM filesystem.
+pub fn node_meta(node: &VfsNodeRef) -> Option<&NodeMeta> {
+    let node = node.as_any();
+    if let Some(file) = node.downcast_ref::<FileNode>() {
+        Some(file.meta())
+    } else if let Some(dir) = node.downcast_ref::<DirNode>() {
+        Some(dir.meta())
+    } else {
+        node.downcast_ref::<SymlinkNode>().map(SymlinkNode::meta)
+    }
+}
+
 impl Default for RamFileSystem {
     fn default() -> Self {
         Self::new()
@@ -0,0 +1,121 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU16, AtomicU32, Ordering};
+
+use axfs_vfs::{VfsError, VfsNodePerm, VfsResult};
+use spin::RwLock;
+
+/// The sticky bit, kept with the permission bits.
+const STICKY: u16 = 0o1000;
+
+/// The permission bits, the owner and the extended attributes of a node,
+/// which can be changed, e.g., by `chmod`, `chown` and `setxattr`.
+///
+/// The nodes are owned by the user and group 0 when created.
+pub struct NodeMeta {
+    perm: AtomicU16,
+    uid: AtomicU32,
+    gid: AtomicU32,
+    xattrs: RwLock<BTreeMap<String, Vec<u8>>>,
+}
+
+impl NodeMeta {
+    pub(crate) const fn new(perm: VfsNodePerm) -> Self {
+        Self {
+            perm: AtomicU16::new(perm.bits()),
+            uid: AtomicU32::new(0),
+            gid: AtomicU32::new(0),
+            xattrs: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// Returns the permission bits.
+    pub fn perm(&self) -> VfsNodePerm {
+        VfsNodePerm::from_bits_truncate(self.perm.load(Ordering::Relaxed))
+    }
+
+    /// Sets the permission bits, the sticky bit is not changed.
+    pub fn set_perm(&self, perm: VfsNodePerm) {
+        let _ = self
+            .perm
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some((bits & STICKY) | perm.bits())
+            });
+    }
+
+    /// Returns whether the sticky bit is set.
+    ///
+    /// The entries in a directory with it can only be removed or renamed by
+    /// their owners, the owner of the directory, or the superuser.
+    pub fn sticky(&self) -> bool {
+        self.perm.load(Ordering::Relaxed) & STICKY != 0
+    }
+
+    /// Sets or clears the sticky bit.
+    pub fn set_sticky(&self, sticky: bool) {
+        if sticky {
+            self.perm.fetch_or(STICKY, Ordering::Relaxed);
+        } else {
+            self.perm.fetch_and(!STICKY, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the user and group IDs of the owner.
+    pub fn owner(&self) -> (u32, u32) {
+        (self.uid.load(Ordering::Relaxed), self.gid.load(Ordering::Relaxed))
+    }
+
+    /// Sets the user and group IDs of the owner.
+    pub fn set_owner(&self, uid: u32, gid: u32) {
+        self.uid.store(uid, Ordering::Relaxed);
+        self.gid.store(gid, Ordering::Relaxed);
+    }
+
+    /// Returns the value of the extended attribute `name`, or `None` if it
+    /// does not exist.
+    pub fn xattr(&self, name: &str) -> Option<Vec<u8>> {
+        self.xattrs.read().get(name).cloned()
+    }
+
+    /// Returns the names of the extended attributes in order.
+    pub fn xattr_names(&self) -> Vec<String> {
+        self.xattrs.read().keys().cloned().collect()
+    }
+
+    /// Sets the value of the extended attribute `name`, it's created if it
+    /// does not exist.
+    pub fn set_xattr(&self, name: &str, value: &[u8]) {
+        self.xattrs.write().insert(name.into(), value.into());
+    }
+
+    /// Creates the extended attribute `name`, returns `AlreadyExists` if it
+    /// exists.
+    pub fn create_xattr(&self, name: &str, value: &[u8]) -> VfsResult {
+        let mut xattrs = self.xattrs.write();
+        if xattrs.contains_key(name) {
+            return Err(VfsError::AlreadyExists);
+        }
+        xattrs.insert(name.into(), value.into());
+        Ok(())
+    }
+
+    /// Replaces the value of the extended attribute `name`, returns
+    /// `NotFound` if it does not exist.
+    pub fn replace_xattr(&self, name: &str, value: &[u8]) -> VfsResult {
+        let mut xattrs = self.xattrs.write();
+        let old = xattrs.get_mut(name).ok_or(VfsError::NotFound)?;
+        *old = value.into();
+        Ok(())
+    }
+
+    /// Removes the extended attribute `name`, returns `NotFound` if it does
+    /// not exist.
+    pub fn remove_xattr(&self, name: &str) -> VfsResult {
+        self.xattrs
+            .write()
+            .remove(name)
+            .map(|_| ())
+            .ok_or(VfsError::NotFound)
+    }
+}
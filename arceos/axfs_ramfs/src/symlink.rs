@@ -0,0 +1,69 @@
+use alloc::vec::Vec;
+use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeAttr, VfsNodeOps, VfsResult};
+use axfs_vfs::{VfsNodePerm, VfsNodeType};
+use spin::RwLock;
+
+use crate::NodeMeta;
+
+/// The symbolic link node in the RAM filesystem.
+///
+/// The target path is read and written as the content of the node. It is
+/// not followed by the filesystem itself.
+///
+/// It implements [`axfs_vfs::VfsNodeOps`].
+pub struct SymlinkNode {
+    target: RwLock<Vec<u8>>,
+    meta: NodeMeta,
+}
+
+impl SymlinkNode {
+    pub(super) const fn new() -> Self {
+        Self {
+            target: RwLock::new(Vec::new()),
+            meta: NodeMeta::new(VfsNodePerm::from_bits_truncate(0o777)),
+        }
+    }
+
+    /// Returns the owner of the symbolic link. The permission bits are not
+    /// used.
+    pub fn meta(&self) -> &NodeMeta {
+        &self.meta
+    }
+}
+
+impl VfsNodeOps for SymlinkNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new(
+            self.meta.perm(),
+            VfsNodeType::SymLink,
+            self.target.read().len() as _,
+            0,
+        ))
+    }
+
+    fn truncate(&self, size: u64) -> VfsResult {
+        self.target.write().resize(size as _, 0);
+        Ok(())
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let target = self.target.read();
+        let start = target.len().min(offset as usize);
+        let end = target.len().min(offset as usize + buf.len());
+        let src = &target[start..end];
+        buf[..src.len()].copy_from_slice(src);
+        Ok(src.len())
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let offset = offset as usize;
+        let mut target = self.target.write();
+        if offset + buf.len() > target.len() {
+            target.resize(offset + buf.len(), 0);
+        }
+        target[offset..offset + buf.len()].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    impl_vfs_non_dir_default! {}
+}
@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use axfs_vfs::{VfsError, VfsNodeType, VfsResult};
+use axfs_vfs::{VfsError, VfsNodePerm, VfsNodeType, VfsResult};
 
 use crate::*;
 
@@ -134,3 +134,103 @@ fn test_ramfs() {
     assert_eq!(root.remove("./foo"), Ok(()));
     assert!(ramfs.root_dir_node().get_entries().is_empty());
 }
+
+#[test]
+fn test_links() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    let mut buf = [0; 8];
+
+    // symbolic links keep the target as the content
+    root.create("f1", VfsNodeType::File).unwrap();
+    root.create("l1", VfsNodeType::SymLink).unwrap();
+    let link = root.clone().lookup("l1").unwrap();
+    assert_eq!(link.write_at(0, b"f1").unwrap(), 2);
+    assert_eq!(link.get_attr().unwrap().file_type(), VfsNodeType::SymLink);
+    assert_eq!(link.get_attr().unwrap().size(), 2);
+    assert_eq!(link.read_at(0, &mut buf).unwrap(), 2);
+    assert_eq!(&buf[..2], b"f1");
+
+    // hard links share the node
+    let file = root.clone().lookup("f1").unwrap();
+    let dir = ramfs.root_dir_node();
+    assert_eq!(dir.add_node("f2", file.clone()), Ok(()));
+    assert_eq!(
+        dir.add_node("f2", file.clone()).err(),
+        Some(VfsError::AlreadyExists)
+    );
+    assert_eq!(file.write_at(0, b"hello").unwrap(), 5);
+    assert_eq!(root.remove("f1"), Ok(()));
+    let file2 = root.clone().lookup("f2").unwrap();
+    assert!(Arc::ptr_eq(&file, &file2));
+    assert_eq!(file2.read_at(0, &mut buf).unwrap(), 5);
+
+    // the link is kept after the target is removed
+    assert_eq!(root.clone().lookup("f1").err(), Some(VfsError::NotFound));
+    assert!(root.clone().lookup("l1").is_ok());
+    assert_eq!(root.remove("l1"), Ok(()));
+    assert_eq!(dir.get_entries(), ["f2"]);
+}
+
+#[test]
+fn test_node_meta() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("f1", VfsNodeType::File).unwrap();
+    let file = root.clone().lookup("f1").unwrap();
+
+    let meta = node_meta(&file).unwrap();
+    assert_eq!(meta.perm(), VfsNodePerm::default_file());
+    assert_eq!(meta.owner(), (0, 0));
+    meta.set_perm(VfsNodePerm::from_bits_truncate(0o600));
+    meta.set_owner(1000, 100);
+    assert_eq!(file.get_attr().unwrap().perm().bits(), 0o600);
+    assert_eq!(node_meta(&file).unwrap().owner(), (1000, 100));
+
+    let dir_meta = node_meta(&root).unwrap();
+    assert_eq!(dir_meta.perm(), VfsNodePerm::default_dir());
+    assert!(!dir_meta.sticky());
+    dir_meta.set_sticky(true);
+    dir_meta.set_perm(VfsNodePerm::from_bits_truncate(0o777));
+    assert!(dir_meta.sticky());
+    assert_eq!(root.get_attr().unwrap().perm().bits(), 0o777);
+    dir_meta.set_sticky(false);
+    assert!(!dir_meta.sticky());
+
+    // the metadata is set up before the node is visible
+    ramfs
+        .root_dir_node()
+        .create_node_with("f2", VfsNodeType::File, |meta| {
+            meta.set_perm(VfsNodePerm::from_bits_truncate(0o640));
+            meta.set_owner(1000, 100);
+        })
+        .unwrap();
+    let meta = node_meta(&root.clone().lookup("f2").unwrap()).unwrap();
+    assert_eq!(meta.perm().bits(), 0o640);
+    assert_eq!(meta.owner(), (1000, 100));
+}
+
+#[test]
+fn test_xattrs() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("f1", VfsNodeType::File).unwrap();
+    let meta = node_meta(&root.clone().lookup("f1").unwrap()).unwrap();
+
+    assert_eq!(meta.xattr("user.a"), None);
+    assert_eq!(meta.replace_xattr("user.a", b"1"), Err(VfsError::NotFound));
+    assert_eq!(meta.create_xattr("user.a", b"1"), Ok(()));
+    assert_eq!(
+        meta.create_xattr("user.a", b"2"),
+        Err(VfsError::AlreadyExists)
+    );
+    assert_eq!(meta.replace_xattr("user.a", b"3"), Ok(()));
+    meta.set_xattr("user.b", b"");
+    assert_eq!(meta.xattr("user.a").as_deref(), Some(&b"3"[..]));
+    assert_eq!(meta.xattr_names(), ["user.a", "user.b"]);
+
+    assert_eq!(meta.remove_xattr("user.a"), Ok(()));
+    assert_eq!(meta.remove_xattr("user.a"), Err(VfsError::NotFound));
+    assert_eq!(meta.xattr_names(), ["user.b"]);
+    assert!(node_meta(&root).unwrap().xattr_names().is_empty());
+}
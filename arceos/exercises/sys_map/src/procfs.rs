@@ -0,0 +1,169 @@
+//! The per-process directories and the tunables in `/proc`.
+
+use core::fmt::Write;
+
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use axerrno::AxError;
+use axfs::procfs::{self, register_process_info, ProcFile, ProcessInfo};
+use axhal::paging::MappingFlags;
+use axmm::{AreaInfo, AreaKind};
+use axprocess::Process;
+use axtask::{current, TaskExtRef, TaskState};
+
+struct ProcInfo;
+
+impl ProcessInfo for ProcInfo {
+    fn current_pid(&self) -> u64 {
+        let curr = current();
+        // Kernel tasks do not belong to any process.
+        if unsafe { curr.task_ext_ptr() }.is_null() {
+            return 0;
+        }
+        curr.task_ext().proc_id()
+    }
+
+    fn pids(&self) -> Vec<u64> {
+        axprocess::all_processes().iter().map(|p| p.pid()).collect()
+    }
+
+    fn file_names(&self) -> &'static [&'static str] {
+        &["maps", "stat", "status"]
+    }
+
+    fn read_file(&self, pid: u64, name: &str) -> Option<String> {
+        let process = find_process(pid)?;
+        match name {
+            "maps" => Some(maps(&process)),
+            "stat" => Some(stat(pid, &process)),
+            "status" => Some(status(&process)),
+            _ => None,
+        }
+    }
+
+    fn has_pid(&self, pid: u64) -> bool {
+        find_process(pid).is_some()
+    }
+}
+
+/// Finds the process by its ID, or by the ID of one of its threads, as
+/// `/proc/<tid>` is also accessible on Linux.
+fn find_process(id: u64) -> Option<Arc<Process>> {
+    axprocess::find_process(id).or_else(|| {
+        let task = axtask::find_task(id)?;
+        // Kernel tasks do not belong to any process.
+        if unsafe { task.task_ext_ptr() }.is_null() {
+            return None;
+        }
+        Some(task.task_ext().process.clone())
+    })
+}
+
+/// Formats an area as a line of `/proc/<pid>/maps`.
+fn write_area(out: &mut String, area: &AreaInfo) {
+    let flag = |f: MappingFlags, c: char| if area.flags.contains(f) { c } else { '-' };
+    let (offset, shared) = match area.kind {
+        AreaKind::File { offset, shared } => (offset, shared),
+        AreaKind::Shared => (0, true),
+        AreaKind::Linear | AreaKind::Alloc => (0, false),
+    };
+    let _ = writeln!(
+        out,
+        "{:08x}-{:08x} {}{}{}{} {:08x} 00:00 0",
+        area.va_range.start,
+        area.va_range.end,
+        flag(MappingFlags::READ, 'r'),
+        flag(MappingFlags::WRITE, 'w'),
+        flag(MappingFlags::EXECUTE, 'x'),
+        if shared { 's' } else { 'p' },
+        offset,
+    );
+}
+
+/// Generates `/proc/<pid>/maps`.
+fn maps(process: &Process) -> String {
+    let areas = process.aspace().lock().areas();
+    let mut out = String::new();
+    for area in &areas {
+        write_area(&mut out, area);
+    }
+    out
+}
+
+/// Generates `/proc/<tid>/stat`, the fields not tracked are zeros.
+fn stat(tid: u64, process: &Process) -> String {
+    let state = match axtask::find_task(tid).map(|task| task.state()) {
+        Some(TaskState::Running | TaskState::Ready) => 'R',
+        Some(TaskState::Blocked) => 'S',
+        Some(TaskState::Exited) | None if process.is_zombie() => 'Z',
+        Some(TaskState::Exited) | None => 'X',
+    };
+    let stats = process.aspace().lock().stats();
+    let mut out = String::new();
+    // pid (comm) state ppid pgrp session tty_nr tpgid flags minflt cminflt
+    // majflt cmajflt utime stime cutime cstime priority nice num_threads
+    // itrealvalue starttime vsize rss
+    let _ = writeln!(
+        out,
+        "{} ({}) {} {} {} {} 0 0 0 0 0 0 0 0 0 0 0 20 0 {} 0 0 {} {}",
+        tid,
+        process.name(),
+        state,
+        process.ppid(),
+        process.pid(),
+        process.pid(),
+        process.thread_count(),
+        stats.vm_size,
+        stats.vm_rss / memory_addr::PAGE_SIZE_4K,
+    );
+    out
+}
+
+/// Generates `/proc/<pid>/status`.
+fn status(process: &Process) -> String {
+    let stats = process.aspace().lock().stats();
+    let state = if process.is_zombie() {
+        "Z (zombie)"
+    } else {
+        "R (running)"
+    };
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "Name:\t{}\nState:\t{}\nPid:\t{}\nPPid:\t{}\nThreads:\t{}\n\
+         VmSize:\t{:8} kB\nVmRSS:\t{:8} kB\n",
+        process.name(),
+        state,
+        process.pid(),
+        process.ppid(),
+        process.thread_count(),
+        stats.vm_size / 1024,
+        stats.vm_rss / 1024,
+    );
+    out
+}
+
+/// Generates `/proc/sys/vm/min_free_kbytes`.
+fn min_free_kbytes() -> String {
+    let kbytes = axmm::low_watermark() * memory_addr::PAGE_SIZE_4K / 1024;
+    kbytes.to_string() + "\n"
+}
+
+/// Sets the free memory below which pages are swapped out.
+fn set_min_free_kbytes(value: &str) -> Result<(), AxError> {
+    let kbytes: usize = value.parse().map_err(|_| AxError::InvalidInput)?;
+    axmm::set_low_watermark(kbytes.div_ceil(memory_addr::PAGE_SIZE_4K / 1024));
+    Ok(())
+}
+
+/// Makes the processes and the tunables visible in `/proc`.
+pub fn init() {
+    static PROC_INFO: ProcInfo = ProcInfo;
+    register_process_info(&PROC_INFO);
+    let tunable = ProcFile::new_writable(min_free_kbytes, set_min_free_kbytes);
+    if let Err(e) = procfs::add_file("sys/vm/min_free_kbytes", tunable) {
+        warn!("failed to add /proc/sys/vm/min_free_kbytes: {:?}", e);
+    }
+}
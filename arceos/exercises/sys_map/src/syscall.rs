@@ -1,15 +1,21 @@
-#![allow(dead_code)]
-
 use core::ffi::{c_void, c_char, c_int};
-use axhal::arch::TrapFrame;
+use alloc::sync::Arc;
+use axhal::arch::{TrapFrame, UspaceContext};
 use axhal::trap::{register_trap_handler, SYSCALL};
-use axerrno::LinuxError;
+use axerrno::{AxError, AxResult, LinuxError};
 use axtask::current;
 use axtask::TaskExtRef;
 use axhal::paging::MappingFlags;
+use axhal::mem::{MemoryAddr, VirtAddr};
+use axmm::MmapFile;
+use axprocess::shm::{self, ShmFile};
+use axprocess::{user_str, CloneFlags};
+use memory_addr::{align_up_4k, is_aligned_4k, VirtAddrRange};
 use arceos_posix_api as api;
 
 const SYS_IOCTL: usize = 29;
+const SYS_UNLINKAT: usize = 35;
+const SYS_FTRUNCATE: usize = 46;
 const SYS_OPENAT: usize = 56;
 const SYS_CLOSE: usize = 57;
 const SYS_READ: usize = 63;
@@ -18,9 +24,29 @@ const SYS_WRITEV: usize = 66;
 const SYS_EXIT: usize = 93;
 const SYS_EXIT_GROUP: usize = 94;
 const SYS_SET_TID_ADDRESS: usize = 96;
+const SYS_FUTEX: usize = 98;
+const SYS_SCHED_YIELD: usize = 124;
+const SYS_GETPID: usize = 172;
+const SYS_GETPPID: usize = 173;
+const SYS_GETTID: usize = 178;
+const SYS_SHMGET: usize = 194;
+const SYS_SHMCTL: usize = 195;
+const SYS_SHMAT: usize = 196;
+const SYS_SHMDT: usize = 197;
+const SYS_CLONE: usize = 220;
+const SYS_EXECVE: usize = 221;
+const SYS_MUNMAP: usize = 215;
 const SYS_MMAP: usize = 222;
+const SYS_MSYNC: usize = 227;
+const SYS_WAIT4: usize = 260;
+
+const TCGETS: usize = 0x5401;
+const TCSETS: usize = 0x5402;
+const TCSETSW: usize = 0x5403;
+const TCSETSF: usize = 0x5404;
 
 const AT_FDCWD: i32 = -100;
+const AT_REMOVEDIR: i32 = 0x200;
 
 /// Macro to generate syscall body
 ///
@@ -99,22 +125,57 @@ bitflags::bitflags! {
 #[register_trap_handler(SYSCALL)]
 fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
     ax_println!("handle_syscall [{}] ...", syscall_num);
+    if current().task_ext().process.is_group_exiting() {
+        // Another thread has called `exit_group`.
+        axprocess::exit_current(0, false);
+    }
     let ret = match syscall_num {
          SYS_IOCTL => sys_ioctl(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _) as _,
-        SYS_SET_TID_ADDRESS => sys_set_tid_address(tf.arg0() as _),
+        SYS_SET_TID_ADDRESS => syscall_body!(sys_set_tid_address, {
+            axprocess::sys_set_tid_address(tf.arg0() as _)
+        }),
         SYS_OPENAT => sys_openat(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _, tf.arg3() as _),
         SYS_CLOSE => sys_close(tf.arg0() as _),
         SYS_READ => sys_read(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
         SYS_WRITE => sys_write(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
         SYS_WRITEV => sys_writev(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
         SYS_EXIT_GROUP => {
-            ax_println!("[SYS_EXIT_GROUP]: system is exiting ..");
-            axtask::exit(tf.arg0() as _)
+            ax_println!("[SYS_EXIT_GROUP]: process is exiting ..");
+            axprocess::exit_current(tf.arg0() as _, true)
         },
         SYS_EXIT => {
-            ax_println!("[SYS_EXIT]: system is exiting ..");
-            axtask::exit(tf.arg0() as _)
+            ax_println!("[SYS_EXIT]: thread is exiting ..");
+            axprocess::exit_current(tf.arg0() as _, false)
+        },
+        SYS_FUTEX => syscall_body!(sys_futex, {
+            axprocess::sys_futex(
+                tf.arg0() as _,
+                tf.arg1() as _,
+                tf.arg2() as _,
+                tf.arg3() as _,
+                tf.arg4() as _,
+                tf.arg5() as _,
+            )
+        }),
+        SYS_SCHED_YIELD => {
+            axtask::yield_now();
+            0
         },
+        SYS_GETPID => current().task_ext().proc_id() as _,
+        SYS_GETPPID => current().task_ext().process.ppid() as _,
+        SYS_GETTID => current().id().as_u64() as _,
+        SYS_CLONE => sys_clone(
+            tf,
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg3() as _,
+            tf.arg4() as _,
+        ),
+        SYS_EXECVE => sys_execve(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        SYS_WAIT4 => syscall_body!(sys_wait4, {
+            axprocess::sys_wait4(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _)
+        }),
         SYS_MMAP => sys_mmap(
             tf.arg0() as _,
             tf.arg1() as _,
@@ -123,15 +184,30 @@ fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
             tf.arg4() as _,
             tf.arg5() as _,
         ),
+        SYS_MUNMAP => sys_munmap(tf.arg0() as _, tf.arg1() as _),
+        SYS_UNLINKAT => sys_unlinkat(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        SYS_FTRUNCATE => sys_ftruncate(tf.arg0() as _, tf.arg1() as _),
+        SYS_SHMGET => syscall_body!(sys_shmget, {
+            shm::shmget(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _)
+        }),
+        SYS_SHMAT => syscall_body!(sys_shmat, {
+            axprocess::sys_shmat(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _)
+        }),
+        SYS_SHMDT => syscall_body!(sys_shmdt, axprocess::sys_shmdt(tf.arg0() as _)),
+        SYS_SHMCTL => syscall_body!(sys_shmctl, shm::shmctl(tf.arg0() as _, tf.arg1() as _)),
+        SYS_MSYNC => sys_msync(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
         _ => {
             ax_println!("Unimplemented syscall: {}", syscall_num);
             -LinuxError::ENOSYS.code() as _
         }
     };
+    if current().task_ext().process.is_group_exiting() {
+        // Another thread has called `exit_group` while it was blocked.
+        axprocess::exit_current(0, false);
+    }
     ret
 }
 
-#[allow(unused_variables)]
 fn sys_mmap(
     addr: *mut usize,
     length: usize,
@@ -141,76 +217,217 @@ fn sys_mmap(
     offset: isize,
 ) -> isize {
     syscall_body!(sys_mmap, {
-        use axhal::mem::{VirtAddr, PAGE_SIZE_4K, MemoryAddr};
-        use core::ffi::c_void;
-        use alloc::vec;
-        use memory_addr::VirtAddrRange;
-        
         let prot_flags = MmapProt::from_bits(prot).ok_or(LinuxError::EINVAL)?;
         let mmap_flags = MmapFlags::from_bits(flags).ok_or(LinuxError::EINVAL)?;
-        
+        let shared = mmap_flags.contains(MmapFlags::MAP_SHARED);
+        if length == 0 || shared == mmap_flags.contains(MmapFlags::MAP_PRIVATE) {
+            return Err(LinuxError::EINVAL);
+        }
+        if offset < 0 || !is_aligned_4k(offset as usize) {
+            return Err(LinuxError::EINVAL);
+        }
+
         // Convert protection flags to mapping flags
         let mapping_flags = MappingFlags::from(prot_flags);
-        
-        let curr = current();
-        let mut aspace = curr.task_ext().aspace.lock();
-        
-        // Align size to page boundary
-        let aligned_length = (length + PAGE_SIZE_4K - 1) & !(PAGE_SIZE_4K - 1);
-        
-        // Find a suitable virtual address in user space
-        let start_addr = if addr.is_null() {
-            // Use find_free_area to find a suitable address
-            // Start searching from a reasonable user space address
-            let hint = VirtAddr::from(0x10000000usize);
-            let limit = VirtAddrRange::from_start_size(aspace.base(), aspace.size());
-            aspace.find_free_area(hint, aligned_length, limit)
-                .ok_or(LinuxError::ENOMEM)?
-        } else {
-            VirtAddr::from(addr as usize)
-        };
-        
-        // Map memory in user address space
-        aspace.map_alloc(start_addr, aligned_length, mapping_flags, true)
-            .map_err(|_| LinuxError::ENOMEM)?;
-        
-        // If it's not anonymous mapping, read from file
+
+        // The file stays open as long as it is mapped, even if `fd` is closed.
+        let mut file: Option<Arc<dyn MmapFile>> = None;
+        let mut shm_pages = None;
+        let mut device_memory = None;
         if !mmap_flags.contains(MmapFlags::MAP_ANONYMOUS) {
             if fd < 0 {
                 return Err(LinuxError::EBADF);
             }
-            
-            // Read file content into a temporary buffer
-            let mut file_data = vec![0u8; length];
-            
-            // Seek to the specified offset if needed
-            if offset > 0 {
-                let seek_result = api::sys_lseek(fd, offset as i64, 0); // 0 = SEEK_SET
-                if seek_result < 0 {
-                    return Err(LinuxError::EIO);
+            // Writes to a shared mapping reach the file, so it must be opened for writing.
+            let check_writable = |writable: bool| {
+                if shared && prot_flags.contains(MmapProt::PROT_WRITE) && !writable {
+                    Err(LinuxError::EACCES)
+                } else {
+                    Ok(())
+                }
+            };
+            match ShmFile::from_fd(fd) {
+                Ok(shm) if shared => {
+                    check_writable(shm.is_writable())?;
+                    shm_pages = Some(shm.pages().clone());
+                }
+                Ok(shm) => file = Some(shm),
+                Err(_) => {
+                    let f = api::File::from_fd(fd)?;
+                    check_writable(f.inner().lock().is_writable())?;
+                    // Device memory (e.g., `/dev/fb0`) is mapped directly.
+                    let phys_range = axfs::devfs::phys_range(&f.inner().lock());
+                    match phys_range {
+                        Some(range) if shared => device_memory = Some(range),
+                        _ => file = Some(Arc::new(MappedFile(f))),
+                    }
                 }
             }
-            
-            // Read file content
-            let n = api::sys_read(fd, file_data.as_mut_ptr() as *mut c_void, length);
-            if n < 0 {
-                return Err(LinuxError::EIO);
+        }
+
+        let curr = current();
+        let mut aspace = curr.task_ext().aspace.lock();
+
+        // Align size to page boundary
+        let aligned_length = align_up_4k(length);
+
+        let start_addr = if mmap_flags.contains(MmapFlags::MAP_FIXED) {
+            let start_addr = VirtAddr::from(addr as usize);
+            if !start_addr.is_aligned_4k() {
+                return Err(LinuxError::EINVAL);
+            }
+            // Replace the existing mappings in the range.
+            match aspace.unmap(start_addr, aligned_length) {
+                Ok(()) | Err(AxError::NotFound) => {}
+                Err(e) => return Err(e.into()),
+            }
+            start_addr
+        } else {
+            // Find a suitable virtual address in user space, `addr` is only a hint.
+            let hint = if addr.is_null() {
+                VirtAddr::from(0x10000000usize)
+            } else {
+                VirtAddr::from(addr as usize).align_down_4k()
+            };
+            let limit = VirtAddrRange::from_start_size(aspace.base(), aspace.size());
+            aspace
+                .find_free_area(hint, aligned_length, limit)
+                .ok_or(LinuxError::ENOMEM)?
+        };
+
+        if let Some((paddr, size)) = device_memory {
+            if offset as usize + aligned_length > align_up_4k(size) {
+                return Err(LinuxError::ENXIO);
             }
-            
-            // Write file data to the mapped memory
-            aspace.write(start_addr, &file_data[..n as usize])
-                .map_err(|_| LinuxError::EIO)?;
+            aspace.map_linear_area(
+                start_addr,
+                paddr + offset as usize,
+                aligned_length,
+                mapping_flags,
+            )?;
+        } else if let Some(pages) = shm_pages {
+            aspace.map_shared(
+                start_addr,
+                aligned_length,
+                mapping_flags,
+                pages,
+                offset as usize,
+            )?;
+        } else if let Some(file) = file {
+            // Pages are read from the file on demand.
+            aspace.map_file(
+                start_addr,
+                aligned_length,
+                mapping_flags,
+                file,
+                offset as u64,
+                shared,
+            )?;
+        } else {
+            aspace.map_alloc(start_addr, aligned_length, mapping_flags, true)?;
         }
-        
+
         Ok(start_addr.as_usize())
     })
 }
 
+fn sys_munmap(addr: *mut usize, length: usize) -> isize {
+    syscall_body!(sys_munmap, {
+        let start_addr = VirtAddr::from(addr as usize);
+        if length == 0 || !start_addr.is_aligned_4k() {
+            return Err(LinuxError::EINVAL);
+        }
+        let curr = current();
+        let mut aspace = curr.task_ext().aspace.lock();
+        // Shared file mappings are written back to the files here.
+        match aspace.unmap(start_addr, align_up_4k(length)) {
+            Ok(()) | Err(AxError::NotFound) => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    })
+}
+
+fn sys_msync(addr: *mut usize, length: usize, _flags: i32) -> isize {
+    syscall_body!(sys_msync, {
+        let start_addr = VirtAddr::from(addr as usize);
+        if !start_addr.is_aligned_4k() {
+            return Err(LinuxError::EINVAL);
+        }
+        // Both `MS_SYNC` and `MS_ASYNC` write back synchronously.
+        let curr = current();
+        curr.task_ext()
+            .aspace
+            .lock()
+            .sync(start_addr, align_up_4k(length))?;
+        Ok(0)
+    })
+}
+
+/// A file mapped by `mmap`.
+struct MappedFile(Arc<api::File>);
+
+impl MmapFile for MappedFile {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> AxResult<usize> {
+        self.0.inner().lock().read_at(offset, buf)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> AxResult<usize> {
+        self.0.inner().lock().write_at(offset, buf)
+    }
+
+    fn size(&self) -> AxResult<u64> {
+        Ok(self.0.inner().lock().get_attr()?.size())
+    }
+
+    fn id(&self) -> Option<(u64, u64)> {
+        self.0.inner().lock().id()
+    }
+}
+
 fn sys_openat(dfd: c_int, fname: *const c_char, flags: c_int, mode: api::ctypes::mode_t) -> isize {
     assert_eq!(dfd, AT_FDCWD);
+    if let Ok(path) = user_str(fname) {
+        if let Some(name) = path.strip_prefix(shm::SHM_DIR) {
+            return syscall_body!(sys_openat, shm::shm_open(name, flags));
+        }
+    }
     api::sys_open(fname, flags, mode) as isize
 }
 
+fn sys_unlinkat(dfd: c_int, path: *const c_char, flags: c_int) -> isize {
+    syscall_body!(sys_unlinkat, {
+        if dfd != AT_FDCWD {
+            return Err(LinuxError::EINVAL);
+        }
+        let path = user_str(path)?;
+        if let Some(name) = path.strip_prefix(shm::SHM_DIR) {
+            shm::shm_unlink(name)?;
+        } else if flags & AT_REMOVEDIR != 0 {
+            std::fs::remove_dir(&path)?;
+        } else {
+            std::fs::remove_file(&path)?;
+        }
+        Ok(0)
+    })
+}
+
+fn sys_ftruncate(fd: c_int, length: i64) -> isize {
+    syscall_body!(sys_ftruncate, {
+        if length < 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        match ShmFile::from_fd(fd) {
+            // Shared memory objects can only grow, see `SharedPages::grow`.
+            Ok(shm) => shm.pages().grow(length as usize)?,
+            Err(_) => api::File::from_fd(fd)?
+                .inner()
+                .lock()
+                .truncate(length as u64)?,
+        }
+        Ok(0)
+    })
+}
+
 fn sys_close(fd: i32) -> isize {
     api::sys_close(fd) as isize
 }
@@ -227,13 +444,114 @@ fn sys_writev(fd: i32, iov: *const api::ctypes::iovec, iocnt: i32) -> isize {
     unsafe { api::sys_writev(fd, iov, iocnt) }
 }
 
-fn sys_set_tid_address(tid_ptd: *const i32) -> isize {
-    let curr = current();
-    curr.task_ext().set_clear_child_tid(tid_ptd as _);
-    curr.id().as_u64() as isize
+/// The `struct termios` of Linux, only the flags in [`axfs::devfs::Termios`]
+/// take effect.
+#[repr(C)]
+struct KernelTermios {
+    c_iflag: u32,
+    c_oflag: u32,
+    c_cflag: u32,
+    c_lflag: u32,
+    c_line: u8,
+    c_cc: [u8; 19],
+}
+
+const ICRNL: u32 = 0o400;
+const OPOST: u32 = 0o1;
+const ONLCR: u32 = 0o4;
+const CS8: u32 = 0o60;
+const CREAD: u32 = 0o200;
+const ICANON: u32 = 0o2;
+const ECHO: u32 = 0o10;
+
+impl From<axfs::devfs::Termios> for KernelTermios {
+    fn from(termios: axfs::devfs::Termios) -> Self {
+        let flag = |on: bool, flag: u32| if on { flag } else { 0 };
+        let mut c_cc = [0; 19];
+        c_cc[0] = 0x03; // VINTR
+        c_cc[2] = 0x7f; // VERASE
+        c_cc[3] = 0x15; // VKILL
+        c_cc[4] = 0x04; // VEOF
+        c_cc[6] = 1; // VMIN
+        Self {
+            c_iflag: flag(termios.icrnl, ICRNL),
+            c_oflag: OPOST | ONLCR,
+            c_cflag: CS8 | CREAD,
+            c_lflag: flag(termios.icanon, ICANON) | flag(termios.echo, ECHO),
+            c_line: 0,
+            c_cc,
+        }
+    }
+}
+
+/// Controls the terminal, the other requests are ignored.
+fn sys_ioctl(fd: i32, op: usize, argp: *mut c_void) -> i32 {
+    syscall_body!(sys_ioctl, {
+        match op {
+            TCGETS | TCSETS | TCSETSW | TCSETSF if argp.is_null() => Err(LinuxError::EFAULT),
+            TCGETS => {
+                let termios = KernelTermios::from(axfs::devfs::termios());
+                unsafe { (argp as *mut KernelTermios).write(termios) };
+                Ok(0)
+            }
+            TCSETS | TCSETSW | TCSETSF => {
+                let termios = unsafe { &*(argp as *const KernelTermios) };
+                axfs::devfs::set_termios(axfs::devfs::Termios {
+                    icanon: termios.c_lflag & ICANON != 0,
+                    echo: termios.c_lflag & ECHO != 0,
+                    icrnl: termios.c_iflag & ICRNL != 0,
+                });
+                Ok(0)
+            }
+            _ => {
+                ax_println!("Ignore SYS_IOCTL: fd={} op={:#x}", fd, op);
+                Ok(0)
+            }
+        }
+    })
+}
+
+/// Creates a child thread or process, see [`axprocess::sys_clone`].
+fn sys_clone(
+    tf: &TrapFrame,
+    flags: usize,
+    newsp: usize,
+    ptid: usize,
+    tls: usize,
+    ctid: usize,
+) -> isize {
+    syscall_body!(sys_clone, {
+        // The child returns 0 from the syscall. The trap handler only skips
+        // the `ecall` instruction for the parent, so do it for the child here.
+        let mut child_tf = *tf;
+        child_tf.sepc += 4;
+        child_tf.regs.a0 = 0;
+        if newsp != 0 {
+            child_tf.regs.sp = newsp;
+        }
+        if CloneFlags::from_bits_truncate(flags).contains(CloneFlags::CLONE_SETTLS) {
+            child_tf.regs.tp = tls;
+        }
+        axprocess::sys_clone(flags, UspaceContext::from(&child_tf), ptid, ctid)
+    })
 }
 
-fn sys_ioctl(_fd: i32, _op: usize, _argp: *mut c_void) -> i32 {
-    ax_println!("Ignore SYS_IOCTL");
-    0
+/// Replaces the image of the current process with the program at `path`.
+///
+/// It never returns on success.
+fn sys_execve(
+    path: *const c_char,
+    argv: *const *const c_char,
+    envp: *const *const c_char,
+) -> isize {
+    match axprocess::sys_execve(path, argv, envp, crate::loader::load_user_app) {
+        Ok(uctx) => {
+            let kstack_top = current().kernel_stack_top().unwrap();
+            unsafe { uctx.enter_uspace(kstack_top) }
+        }
+        Err(e) => {
+            info!("sys_execve => {:?}", e);
+            -e.code() as _
+        }
+    }
 }
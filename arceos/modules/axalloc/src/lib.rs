@@ -12,8 +12,10 @@ extern crate log;
 extern crate alloc;
 
 mod page;
+mod reclaim;
 
-use allocator::{AllocResult, BaseAllocator, BitmapPageAllocator, ByteAllocator, PageAllocator};
+use allocator::{AllocError, AllocResult, BaseAllocator, BitmapPageAllocator};
+use allocator::{ByteAllocator, PageAllocator};
 use core::alloc::{GlobalAlloc, Layout};
 use core::ptr::NonNull;
 use kspin::SpinNoIrq;
@@ -22,6 +24,7 @@ const PAGE_SIZE: usize = 0x1000;
 const MIN_HEAP_SIZE: usize = 0x8000; // 32 K
 
 pub use page::GlobalPage;
+pub use reclaim::{reclaim, register_reclaimer, Reclaimer};
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "slab")] {
@@ -101,8 +104,18 @@ impl GlobalAllocator {
     ///
     /// It firstly tries to allocate from the byte allocator. If there is no
     /// memory, it asks the page allocator for more memory and adds it to the
-    /// byte allocator.
+    /// byte allocator. If the page allocator is also out of memory, the
+    /// registered reclaimers are asked to free some memory, see [`reclaim`].
     pub fn alloc(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        match self.try_alloc(layout) {
+            Err(AllocError::NoMemory) if reclaim(layout.size().div_ceil(PAGE_SIZE)) > 0 => {
+                self.try_alloc(layout)
+            }
+            res => res,
+        }
+    }
+
+    fn try_alloc(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
         // simple two-level allocator: if no heap memory, allocate from the page allocator.
         let mut balloc = self.balloc.lock();
         loop {
@@ -114,7 +127,12 @@ impl GlobalAllocator {
                     .max(layout.size())
                     .next_power_of_two()
                     .max(PAGE_SIZE);
-                let heap_ptr = self.alloc_pages(expand_size / PAGE_SIZE, PAGE_SIZE)?;
+                // Don't reclaim with the byte allocator locked, which may be
+                // needed to free memory.
+                let heap_ptr = self
+                    .palloc
+                    .lock()
+                    .alloc_pages(expand_size / PAGE_SIZE, PAGE_SIZE)?;
                 debug!(
                     "expand heap memory: [{:#x}, {:#x})",
                     heap_ptr,
@@ -141,9 +159,16 @@ impl GlobalAllocator {
     /// It allocates `num_pages` pages from the page allocator.
     ///
     /// `align_pow2` must be a power of 2, and the returned region bound will be
-    /// aligned to it.
+    /// aligned to it. If there is no memory, the registered reclaimers are
+    /// asked to free some memory, see [`reclaim`].
     pub fn alloc_pages(&self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
-        self.palloc.lock().alloc_pages(num_pages, align_pow2)
+        let res = self.palloc.lock().alloc_pages(num_pages, align_pow2);
+        match res {
+            Err(AllocError::NoMemory) if reclaim(num_pages) > 0 => {
+                self.palloc.lock().alloc_pages(num_pages, align_pow2)
+            }
+            res => res,
+        }
     }
 
     /// Gives back the allocated pages starts from `pos` to the page allocator.
@@ -0,0 +1,51 @@
+use kspin::SpinNoIrq;
+
+/// The maximum number of reclaimers that can be registered.
+const MAX_RECLAIMERS: usize = 8;
+
+static RECLAIMERS: SpinNoIrq<[Option<Reclaimer>; MAX_RECLAIMERS]> =
+    SpinNoIrq::new([None; MAX_RECLAIMERS]);
+
+/// A function that frees some memory under memory pressure, e.g., by dropping
+/// the clean pages of a cache.
+///
+/// It's given the number of pages wanted, and returns the number of pages
+/// freed, which may be approximate. It's called when an allocation fails, in
+/// the context of the allocating task, so it must not block on the locks that
+/// may be held by the task (use `try_lock` instead), and must not wait for I/O.
+pub type Reclaimer = fn(usize) -> usize;
+
+/// Registers a function to be called when the memory is running out.
+///
+/// Returns `false` if there are too many reclaimers registered.
+pub fn register_reclaimer(reclaimer: Reclaimer) -> bool {
+    let mut reclaimers = RECLAIMERS.lock();
+    match reclaimers.iter_mut().find(|r| r.is_none()) {
+        Some(slot) => {
+            *slot = Some(reclaimer);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Asks the registered reclaimers to free `nr_pages` pages, returns the
+/// number of pages freed.
+///
+/// The reclaimers are called in the order of registration, until enough
+/// pages are freed.
+pub fn reclaim(nr_pages: usize) -> usize {
+    // Don't hold the lock while reclaiming, which may free memory.
+    let reclaimers = *RECLAIMERS.lock();
+    let mut freed = 0;
+    for reclaimer in reclaimers.iter().flatten() {
+        if freed >= nr_pages {
+            break;
+        }
+        freed += reclaimer(nr_pages - freed);
+    }
+    if freed > 0 {
+        debug!("reclaimed {} pages under memory pressure", freed);
+    }
+    freed
+}
@@ -51,6 +51,12 @@ impl<D> AxDeviceContainer<D> {
         self.0.take()
     }
 
+    /// Takes the last device out of the container (will remove it from the
+    /// container).
+    pub fn take_last(&mut self) -> Option<D> {
+        self.0.take()
+    }
+
     /// Constructs the container from one device.
     pub const fn from_one(dev: D) -> Self {
         Self(Some(dev))
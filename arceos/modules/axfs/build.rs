@@ -0,0 +1,9 @@
+fn main() {
+    // Link the initramfs archive into the kernel if its path is given.
+    println!("cargo:rerun-if-env-changed=AX_INITRD");
+    println!("cargo::rustc-check-cfg=cfg(builtin_initrd)");
+    if let Some(path) = std::env::var_os("AX_INITRD").filter(|p| !p.is_empty()) {
+        println!("cargo:rerun-if-changed={}", path.to_string_lossy());
+        println!("cargo:rustc-cfg=builtin_initrd");
+    }
+}
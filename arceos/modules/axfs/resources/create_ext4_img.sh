@@ -0,0 +1,42 @@
+#!/bin/sh
+# Creates `ext4.img` for `test_extfs.rs`: a small ext4 filesystem whose files
+# are mapped by extents, including a fragmented file with an extent tree of
+# depth 1 and a sparse file. It needs `mkfs.ext4` and `debugfs` of e2fsprogs,
+# and `python3`.
+set -e
+cd "$(dirname "$0")"
+img=ext4.img
+tmp=$(mktemp -d)
+trap 'rm -rf "$tmp"' EXIT
+
+export E2FSPROGS_FAKE_TIME=1700000000
+uuid=d0e4a2f6-5c3b-4e1a-9f8d-7b6c5a4e3d2c
+mkfs.ext4 -q -F -b 1024 -N 256 -O ^has_journal,^resize_inode \
+    -U $uuid -E root_owner=0:0,hash_seed=$uuid $img 1M
+
+printf 'Hello, ext4!\n' > "$tmp/hello.txt"
+printf 'Rust is cool!\n' > "$tmp/nested.txt"
+# The bytes of the large file are `i % 251`, as in `test_large_file()`.
+python3 -c "import sys; sys.stdout.buffer.write(bytes(i % 251 for i in range(64 * 1024)))" > "$tmp/large.bin"
+# The sparse file has 4 KiB of `x` at 0 and at 40 KiB, and holes between.
+printf 'x%.0s' $(seq 4096) > "$tmp/x"
+dd if="$tmp/x" of="$tmp/sparse.bin" bs=4096 2>/dev/null
+dd if="$tmp/x" of="$tmp/sparse.bin" bs=4096 seek=10 conv=notrunc 2>/dev/null
+printf 'f%.0s' $(seq 8192) > "$tmp/fill"
+
+{
+    echo "write $tmp/hello.txt hello.txt"
+    echo "mkdir dir"
+    echo "write $tmp/nested.txt dir/nested.txt"
+    echo "write $tmp/sparse.bin sparse.bin"
+    # Fill the disk with small files and remove every other one, so the
+    # large file written at last is split into many extents.
+    echo "mkdir fill"
+    for i in $(seq 0 109); do
+        echo "write $tmp/fill fill/$i"
+    done
+    for i in $(seq 0 2 109); do
+        echo "rm fill/$i"
+    done
+    echo "write $tmp/large.bin large.bin"
+} | debugfs -w -f - $img >/dev/null
@@ -23,9 +23,10 @@ pub struct DirEntry<'a> {
 }
 
 /// A builder used to create directories in various manners.
-#[derive(Default, Debug)]
+#[derive(Debug)]
 pub struct DirBuilder {
     recursive: bool,
+    mode: u32,
 }
 
 impl<'a> ReadDir<'a> {
@@ -120,7 +121,17 @@ impl DirBuilder {
     /// Creates a new set of options with default mode/security settings for all
     /// platforms and also non-recursive.
     pub fn new() -> Self {
-        Self { recursive: false }
+        Self {
+            recursive: false,
+            mode: 0o777,
+        }
+    }
+
+    /// Sets the permission bits of the new directories, which are masked by
+    /// the umask of the current task. The default is `0o777`.
+    pub fn mode(&mut self, mode: u32) -> &mut Self {
+        self.mode = mode;
+        self
     }
 
     /// Indicates that directories should be created recursively, creating all
@@ -137,7 +148,7 @@ impl DirBuilder {
         if self.recursive {
             self.create_dir_all(path)
         } else {
-            crate::root::create_dir(None, path)
+            crate::root::create_dir(None, path, self.mode)
         }
     }
 
@@ -148,3 +159,9 @@ impl DirBuilder {
         )
     }
 }
+
+impl Default for DirBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,5 +1,6 @@
 use axio::{prelude::*, Result, SeekFrom};
 use core::fmt;
+use core::time::Duration;
 
 use crate::fops;
 
@@ -16,7 +17,10 @@ pub struct File {
 }
 
 /// Metadata information about a file.
-pub struct Metadata(fops::FileAttr);
+pub struct Metadata {
+    attr: fops::FileAttr,
+    times: fops::FileTimes,
+}
 
 /// Options and flags which can be used to configure how a file is opened.
 #[derive(Clone, Debug)]
@@ -71,44 +75,74 @@ impl OpenOptions {
 }
 
 impl Metadata {
+    pub(super) const fn new(attr: fops::FileAttr, times: fops::FileTimes) -> Self {
+        Self { attr, times }
+    }
+
+    /// Returns the underlying raw attributes of the file.
+    pub const fn raw_metadata(&self) -> &fops::FileAttr {
+        &self.attr
+    }
+
     /// Returns the file type for this metadata.
     pub const fn file_type(&self) -> FileType {
-        self.0.file_type()
+        self.attr.file_type()
     }
 
     /// Returns `true` if this metadata is for a directory. The
     /// result is mutually exclusive to the result of
     /// [`Metadata::is_file`].
     pub const fn is_dir(&self) -> bool {
-        self.0.is_dir()
+        self.attr.is_dir()
     }
 
     /// Returns `true` if this metadata is for a regular file. The
     /// result is mutually exclusive to the result of
     /// [`Metadata::is_dir`].
     pub const fn is_file(&self) -> bool {
-        self.0.is_file()
+        self.attr.is_file()
+    }
+
+    /// Returns `true` if this metadata is for a symbolic link, which is only
+    /// possible for the metadata from [`symlink_metadata`](super::symlink_metadata).
+    pub const fn is_symlink(&self) -> bool {
+        matches!(self.attr.file_type(), FileType::SymLink)
     }
 
     /// Returns the size of the file, in bytes, this metadata is for.
     #[allow(clippy::len_without_is_empty)]
     pub const fn len(&self) -> u64 {
-        self.0.size()
+        self.attr.size()
     }
 
     /// Returns the permissions of the file this metadata is for.
     pub const fn permissions(&self) -> Permissions {
-        self.0.perm()
+        self.attr.perm()
     }
 
     /// Returns the total size of this file in bytes.
     pub const fn size(&self) -> u64 {
-        self.0.size()
+        self.attr.size()
     }
 
     /// Returns the number of blocks allocated to the file, in 512-byte units.
     pub const fn blocks(&self) -> u64 {
-        self.0.blocks()
+        self.attr.blocks()
+    }
+
+    /// Returns the last access time, since the UNIX epoch.
+    pub const fn accessed(&self) -> Duration {
+        self.times.accessed
+    }
+
+    /// Returns the last modification time, since the UNIX epoch.
+    pub const fn modified(&self) -> Duration {
+        self.times.modified
+    }
+
+    /// Returns the last status change time, since the UNIX epoch.
+    pub const fn changed(&self) -> Duration {
+        self.times.changed
     }
 }
 
@@ -119,6 +153,7 @@ impl fmt::Debug for Metadata {
             .field("is_dir", &self.is_dir())
             .field("is_file", &self.is_file())
             .field("permissions", &self.permissions())
+            .field("modified", &self.modified())
             .finish_non_exhaustive()
     }
 }
@@ -158,9 +193,67 @@ impl File {
         self.inner.truncate(size)
     }
 
+    /// Writes back the data and metadata of the file to the disk.
+    pub fn sync_all(&self) -> Result<()> {
+        self.inner.sync()
+    }
+
+    /// Writes back the data of the file to the disk.
+    ///
+    /// The metadata needed to read the data back, e.g., the size, is also
+    /// written, which is all the metadata for now, so it is the same as
+    /// [`sync_all`](Self::sync_all).
+    pub fn sync_data(&self) -> Result<()> {
+        self.inner.sync()
+    }
+
     /// Queries metadata about the underlying file.
     pub fn metadata(&self) -> Result<Metadata> {
-        self.inner.get_attr().map(Metadata)
+        Ok(Metadata {
+            attr: self.inner.get_attr()?,
+            times: self.inner.get_times()?,
+        })
+    }
+
+    /// Acquires an exclusive advisory lock on the file, blocking until it
+    /// can be acquired.
+    ///
+    /// The lock is held by this file object, and is released when it is
+    /// dropped or [`unlock`](Self::unlock) is called.
+    pub fn lock(&self) -> Result<()> {
+        self.inner
+            .lock_handle()
+            .lock(Some(fops::LockType::Exclusive), true)
+    }
+
+    /// Acquires a shared advisory lock on the file, blocking until it can be
+    /// acquired.
+    pub fn lock_shared(&self) -> Result<()> {
+        self.inner
+            .lock_handle()
+            .lock(Some(fops::LockType::Shared), true)
+    }
+
+    /// Tries to acquire an exclusive advisory lock on the file, returns
+    /// [`WouldBlock`](axio::Error::WouldBlock) if another file holds a lock.
+    pub fn try_lock(&self) -> Result<()> {
+        self.inner
+            .lock_handle()
+            .lock(Some(fops::LockType::Exclusive), false)
+    }
+
+    /// Tries to acquire a shared advisory lock on the file, returns
+    /// [`WouldBlock`](axio::Error::WouldBlock) if another file holds an
+    /// exclusive lock.
+    pub fn try_lock_shared(&self) -> Result<()> {
+        self.inner
+            .lock_handle()
+            .lock(Some(fops::LockType::Shared), false)
+    }
+
+    /// Releases the advisory lock on the file held by this file object.
+    pub fn unlock(&self) -> Result<()> {
+        self.inner.lock_handle().lock(None, false)
     }
 }
 
@@ -5,9 +5,12 @@ mod file;
 
 pub use self::dir::{DirBuilder, DirEntry, ReadDir};
 pub use self::file::{File, FileType, Metadata, OpenOptions, Permissions};
+pub use crate::fops::XattrMode;
 
-use alloc::{string::String, vec::Vec};
+use alloc::{string::String, sync::Arc, vec::Vec};
+use axfs_vfs::VfsOps;
 use axio::{self as io, prelude::*};
+use cap_access::Cap;
 
 /// Returns an iterator over the entries within a directory.
 pub fn read_dir(path: &str) -> io::Result<ReadDir> {
@@ -59,6 +62,111 @@ pub fn metadata(path: &str) -> io::Result<Metadata> {
     File::open(path)?.metadata()
 }
 
+/// Queries the metadata about a file without following symbolic links.
+pub fn symlink_metadata(path: &str) -> io::Result<Metadata> {
+    let (node, _) = crate::root::lookup_link(None, path)?;
+    let times = crate::fs::node_times(&node).unwrap_or_default();
+    Ok(Metadata::new(node.get_attr()?, times))
+}
+
+/// Creates a new symbolic link `link` pointing to `original`.
+///
+/// The target `original` is stored as is, and is resolved relative to the
+/// directory containing the link when it is followed.
+pub fn symlink(original: &str, link: &str) -> io::Result<()> {
+    crate::root::symlink(original, link)
+}
+
+/// Reads a symbolic link, returning the path it points to.
+pub fn read_link(path: &str) -> io::Result<String> {
+    crate::root::read_link(path)
+}
+
+/// Creates a new hard link `link` to the file `original`, which shares the
+/// contents.
+///
+/// Both paths must be in the same mounted fs, and `original` must not be a
+/// directory.
+pub fn hard_link(original: &str, link: &str) -> io::Result<()> {
+    crate::root::link(original, link)
+}
+
+/// Changes the permissions found on a file or a directory.
+///
+/// Only the owner of the file or the superuser can change them. Returns
+/// [`Unsupported`](io::Error::Unsupported) if the filesystem does not record
+/// the permissions.
+pub fn set_permissions(path: &str, perm: Permissions) -> io::Result<()> {
+    crate::root::set_mode(path, perm.bits() as u32)
+}
+
+/// Same as [`set_permissions`], but the mode may also have the sticky bit
+/// (`0o1000`), which is cleared otherwise.
+///
+/// Only the owners of the entries in a sticky directory, the owner of the
+/// directory, or the superuser can remove or rename them.
+pub fn set_mode(path: &str, mode: u32) -> io::Result<()> {
+    crate::root::set_mode(path, mode)
+}
+
+/// Changes the owner and the group of the file at the path. The ones given
+/// as `None` are not changed.
+///
+/// Only the superuser can change the owner, and the owner of the file can
+/// only change the group to its own.
+pub fn chown(path: &str, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+    crate::root::set_owner(path, uid, gid)
+}
+
+/// Checks whether the current task can access the file at the path with the
+/// rights in the owner bits of `mode`, e.g., [`Permissions::OWNER_READ`].
+///
+/// Returns [`PermissionDenied`](io::Error::PermissionDenied) if any of the
+/// rights is not granted.
+pub fn access(path: &str, mode: Permissions) -> io::Result<()> {
+    let mut cap = Cap::empty();
+    if mode.owner_readable() {
+        cap |= Cap::READ;
+    }
+    if mode.owner_writable() {
+        cap |= Cap::WRITE;
+    }
+    if mode.owner_executable() {
+        cap |= Cap::EXECUTE;
+    }
+    crate::root::access(path, cap)
+}
+
+/// Returns the value of the extended attribute `name` of the file at the
+/// path, the symbolic links are followed.
+///
+/// Returns [`NotFound`](io::Error::NotFound) if the attribute does not exist,
+/// or [`Unsupported`](io::Error::Unsupported) if the namespace of the name is
+/// unknown or the filesystem does not record the extended attributes.
+pub fn get_xattr(path: &str, name: &str) -> io::Result<Vec<u8>> {
+    let (node, _) = crate::root::lookup(None, path)?;
+    crate::xattr::get(&node, name)
+}
+
+/// Returns the names of the extended attributes of the file at the path.
+pub fn list_xattr(path: &str) -> io::Result<Vec<String>> {
+    let (node, _) = crate::root::lookup(None, path)?;
+    crate::xattr::list(&node)
+}
+
+/// Sets the value of the extended attribute `name` of the file at the path,
+/// which is created or replaced as `mode`.
+pub fn set_xattr(path: &str, name: &str, value: &[u8], mode: XattrMode) -> io::Result<()> {
+    let (node, _) = crate::root::lookup(None, path)?;
+    crate::xattr::set(&node, name, value, mode)
+}
+
+/// Removes the extended attribute `name` of the file at the path.
+pub fn remove_xattr(path: &str, name: &str) -> io::Result<()> {
+    let (node, _) = crate::root::lookup(None, path)?;
+    crate::xattr::remove(&node, name)
+}
+
 /// Creates a new, empty directory at the provided path.
 pub fn create_dir(path: &str) -> io::Result<()> {
     DirBuilder::new().create(path)
@@ -87,3 +195,42 @@ pub fn remove_file(path: &str) -> io::Result<()> {
 pub fn rename(old: &str, new: &str) -> io::Result<()> {
     crate::root::rename(old, new)
 }
+
+/// Creates a filesystem of the type `fstype` and mounts it on the directory
+/// at `path`, which hides the original contents of the directory.
+///
+/// The disk-based filesystems (`ext2`, `ext3` and `ext4`) are created on the
+/// block device file `source`, e.g., `/dev/vdb`. An `overlay` filesystem
+/// stacks a RAM filesystem on the directory `source`, which is not modified.
+/// `source` is ignored by `tmpfs` (or `ramfs`). Other types are unsupported.
+pub fn mount(source: &str, path: &str, fstype: &str) -> io::Result<()> {
+    crate::root::mount(path, crate::mounts::new_fs(source, fstype)?)
+}
+
+/// Mounts the filesystem `fs` on the directory at `path`.
+pub fn mount_fs(path: &str, fs: Arc<dyn VfsOps>) -> io::Result<()> {
+    crate::root::mount(path, fs)
+}
+
+/// Unmounts the filesystem mounted at `path`.
+///
+/// It fails with [`ResourceBusy`](io::Error::ResourceBusy) if there are
+/// files or directories opened in the filesystem, the current directory is
+/// in it, or other filesystems are mounted in it.
+pub fn umount(path: &str) -> io::Result<()> {
+    crate::root::umount(path, false)
+}
+
+/// Detaches the filesystem mounted at `path`, together with the ones mounted
+/// in it, even if they are busy.
+///
+/// They are no longer reachable by paths, and are unmounted after the files
+/// and directories opened in them are closed.
+pub fn umount_lazy(path: &str) -> io::Result<()> {
+    crate::root::umount(path, true)
+}
+
+/// Writes back all the cached data of the filesystems to the disks.
+pub fn sync() -> io::Result<()> {
+    crate::dev::cache::sync_all().map_err(|_| io::Error::Io)
+}
@@ -0,0 +1,77 @@
+use axdriver::prelude::*;
+use axfs_vfs::{VfsError, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType, VfsResult};
+
+use super::{Disk, SharedBlockDevice};
+
+/// A block device file (e.g., `/dev/vda`), for raw access to the disk.
+///
+/// The disk may be used by a mounted filesystem at the same time.
+pub struct BlockDev {
+    dev: SharedBlockDevice,
+}
+
+impl BlockDev {
+    pub(crate) fn new(dev: SharedBlockDevice) -> Self {
+        Self { dev }
+    }
+
+    /// Returns the disk of the device, e.g., to mount the filesystem on it.
+    #[cfg(feature = "extfs")]
+    pub(crate) fn disk(&self) -> Disk {
+        Disk::from_shared(self.dev.clone())
+    }
+
+    fn disk_at(&self, offset: u64) -> Disk {
+        let mut disk = Disk::from_shared(self.dev.clone());
+        disk.set_position(offset);
+        disk
+    }
+}
+
+impl VfsNodeOps for BlockDev {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let dev = self.dev.lock();
+        let size = dev.num_blocks() * dev.block_size() as u64;
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(0o660),
+            VfsNodeType::BlockDevice,
+            size,
+            dev.num_blocks(),
+        ))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let mut disk = self.disk_at(offset);
+        let len = buf.len().min(disk.size().saturating_sub(offset) as usize);
+        let mut read = 0;
+        while read < len {
+            read += disk
+                .read_one(&mut buf[read..len])
+                .map_err(|_| VfsError::Io)?;
+        }
+        Ok(read)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let mut disk = self.disk_at(offset);
+        let len = buf.len().min(disk.size().saturating_sub(offset) as usize);
+        if len == 0 && !buf.is_empty() {
+            return Err(VfsError::StorageFull);
+        }
+        let mut written = 0;
+        while written < len {
+            written += disk
+                .write_one(&buf[written..len])
+                .map_err(|_| VfsError::Io)?;
+        }
+        Ok(written)
+    }
+
+    fn fsync(&self) -> VfsResult {
+        Disk::from_shared(self.dev.clone())
+            .sync()
+            .map_err(|_| VfsError::Io)
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
@@ -0,0 +1,452 @@
+//! The page cache between the filesystems and the block devices.
+//!
+//! The blocks of each device are cached in pages of [`PAGE_SIZE`] bytes,
+//! which are read with one request, and read ahead on sequential reads. The
+//! writes only go to the cache, the dirty pages are written back on
+//! [`sync`]/[`sync_all`], or before they are evicted. Each device has its own
+//! cache of at most [`capacity`] pages, where the least recently used pages
+//! are evicted first, and the clean ones are dropped under memory pressure by
+//! [`reclaim`], which is registered to [`axalloc`].
+//!
+//! The device I/O is done without holding the lock of the cached pages, so
+//! that the accesses to the cached pages are not blocked by a slow device.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use axdriver::prelude::*;
+use axerrno::{AxError, AxResult};
+use axsync::{Mutex, MutexGuard};
+
+use super::{SharedBlockDevice, BLOCK_SIZE};
+
+/// The size of a cached page.
+pub const PAGE_SIZE: usize = 4096;
+const BLOCKS_PER_PAGE: u64 = (PAGE_SIZE / BLOCK_SIZE) as u64;
+
+/// The maximum number of pages to read ahead.
+const MAX_READ_AHEAD: u64 = 32;
+/// The maximum number of cached pages of a device by default (4 MiB).
+const DEFAULT_CAPACITY: usize = 1024;
+
+/// The caches of the devices. The devices are never removed, so that they
+/// are not dropped while their pages are cached.
+static DEVICES: Mutex<Vec<Arc<DeviceCache>>> = Mutex::new(Vec::new());
+static CAPACITY: AtomicUsize = AtomicUsize::new(DEFAULT_CAPACITY);
+
+/// How a page is going to be accessed.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Access {
+    /// Read, the following pages are read ahead if it's sequential.
+    Read,
+    /// Written partially, the page is read first.
+    Write,
+    /// Written entirely, the page doesn't need to be read.
+    Overwrite,
+}
+
+struct Page {
+    data: Vec<u8>,
+    dirty: bool,
+    /// Being written back. The page cannot be evicted until the write is
+    /// done, otherwise it may be read again from the device before that.
+    writeback: bool,
+    /// When the page is used last time, the key in the LRU list.
+    last_used: u64,
+}
+
+/// The cached pages of a device.
+struct CacheState {
+    pages: BTreeMap<u64, Page>,
+    /// The indices of the cached pages ordered by their last uses, the least
+    /// recently used first.
+    lru: BTreeMap<u64, u64>,
+    clock: u64,
+    /// The number of evictions. Pages read from the device are cached only
+    /// if nothing is evicted during the read, as the data may be older than
+    /// an evicted page.
+    evictions: u64,
+    /// The page expected to be read next by a sequential reader.
+    next_read: u64,
+    /// The number of pages read ahead on the last miss.
+    read_ahead: u64,
+}
+
+/// The cache of a device.
+struct DeviceCache {
+    dev: SharedBlockDevice,
+    num_blocks: u64,
+    state: Mutex<CacheState>,
+    /// Serializes the write-backs, so that a page is not written back with
+    /// older data than the last write-back.
+    writeback: Mutex<()>,
+}
+
+impl CacheState {
+    const fn new() -> Self {
+        Self {
+            pages: BTreeMap::new(),
+            lru: BTreeMap::new(),
+            clock: 0,
+            evictions: 0,
+            next_read: 0,
+            read_ahead: 0,
+        }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    /// Caches a clean page at `index` if it is not cached.
+    fn insert(&mut self, index: u64, data: Vec<u8>) {
+        if self.pages.contains_key(&index) {
+            return;
+        }
+        let clock = self.tick();
+        let page = Page {
+            data,
+            dirty: false,
+            writeback: false,
+            last_used: clock,
+        };
+        self.pages.insert(index, page);
+        self.lru.insert(clock, index);
+    }
+
+    /// Returns the cached page at `index`, and moves it to the end of the LRU
+    /// list.
+    fn touch(&mut self, index: u64) -> Option<&mut Page> {
+        let clock = self.tick();
+        let page = self.pages.get_mut(&index)?;
+        self.lru.remove(&page.last_used);
+        self.lru.insert(clock, index);
+        page.last_used = clock;
+        Some(page)
+    }
+
+    /// Evicts at most `count` least recently used clean pages, returns the
+    /// number of pages evicted.
+    ///
+    /// The pages are removed from the LRU list one by one without allocating,
+    /// so it can be called when an allocation fails.
+    fn evict(&mut self, count: usize) -> usize {
+        let mut evicted = 0;
+        // The dirty pages before it are skipped.
+        let mut from = 0;
+        while evicted < count {
+            let pages = &self.pages;
+            let victim = self.lru.range(from..).find(|(_, index)| {
+                let page = &pages[index];
+                !page.dirty && !page.writeback
+            });
+            let Some((&clock, &index)) = victim else {
+                break;
+            };
+            self.lru.remove(&clock);
+            self.pages.remove(&index);
+            evicted += 1;
+            from = clock + 1;
+        }
+        if evicted > 0 {
+            self.evictions += 1;
+        }
+        evicted
+    }
+}
+
+impl DeviceCache {
+    fn size(&self) -> u64 {
+        self.num_blocks * BLOCK_SIZE as u64
+    }
+
+    fn num_pages(&self) -> u64 {
+        self.num_blocks.div_ceil(BLOCKS_PER_PAGE)
+    }
+
+    /// The number of valid blocks in the page, which is less than
+    /// [`BLOCKS_PER_PAGE`] for the last page if the device size is not a
+    /// multiple of the page size.
+    fn blocks_in_page(&self, index: u64) -> usize {
+        let first = index * BLOCKS_PER_PAGE;
+        self.num_blocks.saturating_sub(first).min(BLOCKS_PER_PAGE) as usize
+    }
+
+    /// Returns the length to access at `pos` within one page, at most `len`.
+    fn len_in_page(&self, pos: u64, len: usize) -> DevResult<usize> {
+        if pos >= self.size() {
+            return Err(DevError::InvalidParam);
+        }
+        let len = len.min(PAGE_SIZE - pos as usize % PAGE_SIZE);
+        Ok(len.min((self.size() - pos) as usize))
+    }
+
+    /// Reads `count` pages from `index` from the device.
+    fn read_pages(&self, index: u64, count: u64) -> DevResult<Vec<u8>> {
+        let blocks = (index..index + count)
+            .map(|i| self.blocks_in_page(i))
+            .sum::<usize>();
+        let mut buf = vec![0; count as usize * PAGE_SIZE];
+        self.dev
+            .lock()
+            .read_block(index * BLOCKS_PER_PAGE, &mut buf[..blocks * BLOCK_SIZE])?;
+        Ok(buf)
+    }
+
+    fn write_page(&self, index: u64, data: &[u8]) -> DevResult {
+        let len = self.blocks_in_page(index) * BLOCK_SIZE;
+        self.dev
+            .lock()
+            .write_block(index * BLOCKS_PER_PAGE, &data[..len])
+    }
+
+    /// Writes back at most `count` least recently used dirty pages.
+    ///
+    /// The pages stay in the cache as clean ones, and the pages failed to be
+    /// written back are dirty again.
+    fn write_back(&self, count: usize) -> DevResult {
+        let _writeback = self.writeback.lock();
+        let mut batch = {
+            let mut state = self.state.lock();
+            let indices = state
+                .lru
+                .values()
+                .copied()
+                .filter(|index| state.pages[index].dirty)
+                .take(count)
+                .collect::<Vec<_>>();
+            let mut batch = Vec::with_capacity(indices.len());
+            for index in indices {
+                let page = state.pages.get_mut(&index).unwrap();
+                page.dirty = false;
+                page.writeback = true;
+                batch.push((index, page.data.clone()));
+            }
+            batch
+        };
+        // Write in the order of the blocks.
+        batch.sort_unstable_by_key(|&(index, _)| index);
+
+        let mut res = Ok(());
+        let mut written = 0;
+        for (index, data) in &batch {
+            res = self.write_page(*index, data);
+            if res.is_err() {
+                break;
+            }
+            written += 1;
+        }
+
+        let mut state = self.state.lock();
+        for (i, (index, _)) in batch.iter().enumerate() {
+            let page = state.pages.get_mut(index).unwrap();
+            page.writeback = false;
+            if i >= written {
+                page.dirty = true;
+            }
+        }
+        res
+    }
+
+    /// Writes back all the dirty pages and flushes the device.
+    fn sync(&self) -> DevResult {
+        self.write_back(usize::MAX)?;
+        self.dev.lock().flush()
+    }
+
+    /// Makes room for `count` more pages, the least recently used dirty pages
+    /// are written back if there are not enough clean pages to evict.
+    ///
+    /// Returns the locked state with the room.
+    fn reserve(&self, count: usize) -> DevResult<MutexGuard<'_, CacheState>> {
+        loop {
+            let mut state = self.state.lock();
+            let capacity = capacity().max(count);
+            let excess = (state.pages.len() + count).saturating_sub(capacity);
+            let dirty = excess - state.evict(excess);
+            if dirty == 0 {
+                return Ok(state);
+            }
+            drop(state);
+            self.write_back(dirty)?;
+        }
+    }
+
+    /// Caches the page at `index`, reads it (and the following pages to read
+    /// ahead) from the device unless it is going to be overwritten.
+    ///
+    /// Returns the locked state, where the page may be missing if it was
+    /// evicted or something else was evicted during the read. A new page to
+    /// overwrite is always there, as it must not be seen before overwritten.
+    fn fill(&self, index: u64, access: Access) -> DevResult<MutexGuard<'_, CacheState>> {
+        let count = if access == Access::Read {
+            let mut state = self.state.lock();
+            // Read ahead more pages each time the sequential reader misses
+            // the cache.
+            state.read_ahead = if index == state.next_read {
+                (state.read_ahead * 2).clamp(1, MAX_READ_AHEAD)
+            } else {
+                0
+            };
+            let max_read_ahead = capacity().saturating_sub(1);
+            let read_ahead = (index + 1..self.num_pages())
+                .take(max_read_ahead.min(state.read_ahead as usize))
+                .take_while(|i| !state.pages.contains_key(i))
+                .count();
+            1 + read_ahead as u64
+        } else {
+            1
+        };
+
+        let mut state = self.reserve(count as usize)?;
+        if access == Access::Overwrite {
+            state.insert(index, vec![0; PAGE_SIZE]);
+            return Ok(state);
+        }
+        let evictions = state.evictions;
+        drop(state);
+
+        let buf = self.read_pages(index, count)?;
+        let mut state = self.state.lock();
+        if state.evictions == evictions {
+            for (i, data) in (index..).zip(buf.chunks_exact(PAGE_SIZE)) {
+                state.insert(i, data.to_vec());
+            }
+        }
+        Ok(state)
+    }
+
+    /// Calls `f` on the cached page at `index`, reads it from the device if
+    /// not cached.
+    fn access<R>(
+        &self,
+        index: u64,
+        access: Access,
+        f: impl FnOnce(&mut Page) -> R,
+    ) -> DevResult<R> {
+        let mut state = self.state.lock();
+        while !state.pages.contains_key(&index) {
+            drop(state);
+            state = self.fill(index, access)?;
+        }
+        if access == Access::Read {
+            state.next_read = index + 1;
+        }
+        Ok(f(state.touch(index).unwrap()))
+    }
+}
+
+/// Returns the cache of the device, adds it if not present.
+fn device_cache(dev: &SharedBlockDevice) -> Arc<DeviceCache> {
+    let mut devices = DEVICES.lock();
+    if let Some(dc) = devices.iter().find(|dc| Arc::ptr_eq(&dc.dev, dev)) {
+        return dc.clone();
+    }
+    let num_blocks = dev.lock().num_blocks();
+    let dc = Arc::new(DeviceCache {
+        dev: dev.clone(),
+        num_blocks,
+        state: Mutex::new(CacheState::new()),
+        writeback: Mutex::new(()),
+    });
+    devices.push(dc.clone());
+    dc
+}
+
+/// Reads the device at `pos` within one page through the cache, returns the
+/// number of bytes read.
+pub(super) fn read(dev: &SharedBlockDevice, pos: u64, buf: &mut [u8]) -> DevResult<usize> {
+    let dc = device_cache(dev);
+    let len = dc.len_in_page(pos, buf.len())?;
+    let offset = pos as usize % PAGE_SIZE;
+    dc.access(pos / PAGE_SIZE as u64, Access::Read, |page| {
+        buf[..len].copy_from_slice(&page.data[offset..offset + len]);
+    })?;
+    Ok(len)
+}
+
+/// Writes the device at `pos` within one page through the cache, returns the
+/// number of bytes written.
+///
+/// The data is written to the device when the page is written back.
+pub(super) fn write(dev: &SharedBlockDevice, pos: u64, buf: &[u8]) -> DevResult<usize> {
+    let dc = device_cache(dev);
+    let len = dc.len_in_page(pos, buf.len())?;
+    let offset = pos as usize % PAGE_SIZE;
+    let access = if len < PAGE_SIZE {
+        Access::Write
+    } else {
+        Access::Overwrite
+    };
+    dc.access(pos / PAGE_SIZE as u64, access, |page| {
+        page.data[offset..offset + len].copy_from_slice(&buf[..len]);
+        page.dirty = true;
+    })?;
+    Ok(len)
+}
+
+/// Writes back the dirty pages of the device and flushes it.
+pub(super) fn sync(dev: &SharedBlockDevice) -> DevResult {
+    device_cache(dev).sync()
+}
+
+/// Writes back the dirty pages of all the devices and flushes them.
+pub(crate) fn sync_all() -> DevResult {
+    let devices = DEVICES.lock().clone();
+    // Try all the devices even if some of them fail.
+    devices
+        .iter()
+        .map(|dc| dc.sync())
+        .fold(Ok(()), |res, r| res.and(r))
+}
+
+/// Drops at most `nr_pages` clean pages, returns the number of pages dropped.
+///
+/// Nothing is dropped from the caches in use, so it can be called when an
+/// allocation fails.
+pub fn reclaim(nr_pages: usize) -> usize {
+    let Some(devices) = DEVICES.try_lock() else {
+        return 0;
+    };
+    let mut dropped = 0;
+    for dc in devices.iter() {
+        if dropped == nr_pages {
+            break;
+        }
+        if let Some(mut state) = dc.state.try_lock() {
+            dropped += state.evict(nr_pages - dropped);
+        }
+    }
+    dropped
+}
+
+/// Returns the maximum number of cached pages of a device.
+pub fn capacity() -> usize {
+    CAPACITY.load(Ordering::Relaxed)
+}
+
+/// Sets the maximum number of cached pages of a device, the least recently
+/// used pages are evicted if there are more.
+pub fn set_capacity(nr_pages: usize) -> AxResult {
+    CAPACITY.store(nr_pages, Ordering::Relaxed);
+    let devices = DEVICES.lock().clone();
+    for dc in devices {
+        dc.reserve(0).map_err(|_| AxError::Io)?;
+    }
+    Ok(())
+}
+
+/// Returns the number of cached pages of all the devices and the number of
+/// dirty ones.
+pub fn stats() -> (usize, usize) {
+    let devices = DEVICES.lock().clone();
+    devices.iter().fold((0, 0), |(cached, dirty), dc| {
+        let state = dc.state.lock();
+        let nr_dirty = state.pages.values().filter(|page| page.dirty).count();
+        (cached + state.pages.len(), dirty + nr_dirty)
+    })
+}
@@ -0,0 +1,69 @@
+use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType, VfsResult};
+use axhal::mem::{virt_to_phys, PhysAddr, VirtAddr};
+
+/// A framebuffer device (`/dev/fb0`).
+///
+/// Reads and writes access the pixels directly, and the screen is updated by
+/// the `flush` function after each write or `fsync`. It can also be mapped
+/// into user space, see [`phys_range`](crate::devfs::phys_range).
+pub struct FrameBufferDev {
+    base: VirtAddr,
+    size: usize,
+    flush: fn(),
+}
+
+impl FrameBufferDev {
+    /// Creates a framebuffer device for the memory at `base` with `size`
+    /// bytes, which must be in the linear mapping of the physical memory.
+    pub fn new(base: VirtAddr, size: usize, flush: fn()) -> Self {
+        Self { base, size, flush }
+    }
+
+    /// Returns the physical address and the size of the framebuffer.
+    pub fn phys_range(&self) -> (PhysAddr, usize) {
+        (virt_to_phys(self.base), self.size)
+    }
+
+    /// Returns the range in the framebuffer to access at `offset`.
+    fn range(&self, offset: u64, len: usize) -> (usize, usize) {
+        let start = self.size.min(offset as usize);
+        (start, self.size.min(start + len) - start)
+    }
+}
+
+impl VfsNodeOps for FrameBufferDev {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(0o660),
+            VfsNodeType::CharDevice,
+            self.size as u64,
+            0,
+        ))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let (start, len) = self.range(offset, buf.len());
+        let src = (self.base + start).as_ptr();
+        unsafe { core::ptr::copy_nonoverlapping(src, buf.as_mut_ptr(), len) };
+        Ok(len)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let (start, len) = self.range(offset, buf.len());
+        let dst = (self.base + start).as_mut_ptr();
+        unsafe { core::ptr::copy_nonoverlapping(buf.as_ptr(), dst, len) };
+        (self.flush)();
+        Ok(len)
+    }
+
+    fn fsync(&self) -> VfsResult {
+        (self.flush)();
+        Ok(())
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Ok(())
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
@@ -0,0 +1,108 @@
+use alloc::sync::Arc;
+use axdriver::prelude::*;
+use axsync::Mutex;
+
+pub mod cache;
+
+#[cfg(feature = "devfs")]
+mod block;
+#[cfg(feature = "devfs")]
+mod fb;
+#[cfg(feature = "devfs")]
+mod random;
+#[cfg(feature = "devfs")]
+mod tty;
+
+#[cfg(feature = "devfs")]
+pub use self::block::BlockDev;
+#[cfg(feature = "devfs")]
+pub use self::fb::FrameBufferDev;
+#[cfg(feature = "devfs")]
+pub use self::random::RandomDev;
+#[cfg(feature = "devfs")]
+pub(crate) use self::tty::init_input_irq;
+#[cfg(feature = "devfs")]
+pub use self::tty::{receive_input, set_termios, termios, Termios, TtyDev};
+
+const BLOCK_SIZE: usize = 512;
+
+const BLOCK_DEV_NAMES: [&str; 26] = [
+    "vda", "vdb", "vdc", "vdd", "vde", "vdf", "vdg", "vdh", "vdi", "vdj", "vdk", "vdl", "vdm",
+    "vdn", "vdo", "vdp", "vdq", "vdr", "vds", "vdt", "vdu", "vdv", "vdw", "vdx", "vdy", "vdz",
+];
+
+/// Returns the name of the `idx`-th block device passed to
+/// [`init_filesystems`](crate::init_filesystems), as `vda`, `vdb`, etc.
+///
+/// It's the name in `/dev` and `/sys/block`.
+pub fn block_dev_name(idx: usize) -> Option<&'static str> {
+    BLOCK_DEV_NAMES.get(idx).copied()
+}
+
+/// A block device that can be shared, e.g., by a filesystem and its device
+/// file.
+pub(crate) type SharedBlockDevice = Arc<Mutex<AxBlockDevice>>;
+
+/// A disk device with a cursor.
+///
+/// The accesses go through the page cache shared by all the disks, see
+/// [`cache`].
+pub struct Disk {
+    pos: u64,
+    dev: SharedBlockDevice,
+}
+
+impl Disk {
+    /// Create a new disk.
+    pub fn new(dev: AxBlockDevice) -> Self {
+        Self::from_shared(Arc::new(Mutex::new(dev)))
+    }
+
+    /// Create a new disk on a shared block device.
+    pub(crate) fn from_shared(dev: SharedBlockDevice) -> Self {
+        assert_eq!(BLOCK_SIZE, dev.lock().block_size());
+        Self { pos: 0, dev }
+    }
+
+    /// Get the underlying block device.
+    #[cfg(feature = "devfs")]
+    pub(crate) fn device(&self) -> &SharedBlockDevice {
+        &self.dev
+    }
+
+    /// Get the size of the disk.
+    pub fn size(&self) -> u64 {
+        self.dev.lock().num_blocks() * BLOCK_SIZE as u64
+    }
+
+    /// Get the position of the cursor.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Set the position of the cursor.
+    pub fn set_position(&mut self, pos: u64) {
+        self.pos = pos;
+    }
+
+    /// Read within one page, returns the number of bytes read.
+    pub fn read_one(&mut self, buf: &mut [u8]) -> DevResult<usize> {
+        let read_size = cache::read(&self.dev, self.pos, buf)?;
+        self.pos += read_size as u64;
+        Ok(read_size)
+    }
+
+    /// Write within one page, returns the number of bytes written.
+    ///
+    /// The data may stay in the cache until [`sync`](Self::sync) is called.
+    pub fn write_one(&mut self, buf: &[u8]) -> DevResult<usize> {
+        let write_size = cache::write(&self.dev, self.pos, buf)?;
+        self.pos += write_size as u64;
+        Ok(write_size)
+    }
+
+    /// Write back the cached data of the disk, and flush the device.
+    pub fn sync(&mut self) -> DevResult {
+        cache::sync(&self.dev)
+    }
+}
@@ -0,0 +1,254 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType, VfsResult};
+use axsync::spin::SpinNoIrq;
+#[cfg(all(feature = "irq", feature = "multitask"))]
+use axtask::WaitQueue;
+
+const ERASE: u8 = 0x7f; // DEL, sent by the backspace key
+const BACKSPACE: u8 = 0x08; // ^H
+const KILL: u8 = 0x15; // ^U
+const EOF: u8 = 0x04; // ^D
+
+/// The input is received in the console IRQ handler, so it's locked with
+/// IRQs disabled.
+static CONSOLE: SpinNoIrq<LineDiscipline> = SpinNoIrq::new(LineDiscipline::new());
+
+/// Whether the console input raises IRQs, otherwise it's polled by the
+/// readers.
+static INPUT_IRQ: AtomicBool = AtomicBool::new(false);
+
+/// The readers wait here until some input is available.
+#[cfg(all(feature = "irq", feature = "multitask"))]
+static INPUT_WQ: WaitQueue = WaitQueue::new();
+
+/// Settings of the line discipline of the console, a subset of the `termios`
+/// of POSIX.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Termios {
+    /// Canonical mode (`ICANON`): the input is available line by line, and
+    /// can be edited by the erase and kill characters. Otherwise, each byte
+    /// is available as soon as it is received.
+    pub icanon: bool,
+    /// Echo the input characters (`ECHO`).
+    pub echo: bool,
+    /// Translate carriage return to newline on input (`ICRNL`).
+    pub icrnl: bool,
+}
+
+impl Termios {
+    /// The default settings: canonical mode with echo.
+    pub const fn new() -> Self {
+        Self {
+            icanon: true,
+            echo: true,
+            icrnl: true,
+        }
+    }
+}
+
+impl Default for Termios {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Processes the console input according to [`Termios`].
+struct LineDiscipline {
+    termios: Termios,
+    /// The line being edited in canonical mode.
+    line: Vec<u8>,
+    /// The input that can be read.
+    ready: VecDeque<u8>,
+    /// The number of end-of-file marks (`^D` on an empty line) to be read.
+    pending_eofs: usize,
+}
+
+impl LineDiscipline {
+    const fn new() -> Self {
+        Self {
+            termios: Termios::new(),
+            line: Vec::new(),
+            ready: VecDeque::new(),
+            pending_eofs: 0,
+        }
+    }
+
+    fn echo(&self, bytes: &[u8]) {
+        if self.termios.echo {
+            axhal::console::write_bytes(bytes);
+        }
+    }
+
+    fn receive(&mut self, c: u8) {
+        let c = if self.termios.icrnl && c == b'\r' {
+            b'\n'
+        } else {
+            c
+        };
+        if !self.termios.icanon {
+            self.ready.push_back(c);
+            self.echo(&[c]);
+            return;
+        }
+        match c {
+            ERASE | BACKSPACE => {
+                if self.line.pop().is_some() {
+                    self.echo(b"\x08 \x08");
+                }
+            }
+            KILL => {
+                while self.line.pop().is_some() {
+                    self.echo(b"\x08 \x08");
+                }
+            }
+            EOF if self.line.is_empty() => self.pending_eofs += 1,
+            EOF => self.ready.extend(self.line.drain(..)),
+            b'\n' => {
+                self.line.push(c);
+                self.ready.extend(self.line.drain(..));
+                self.echo(b"\n");
+            }
+            _ => {
+                self.line.push(c);
+                self.echo(&[c]);
+            }
+        }
+    }
+
+    /// Whether a read will not block.
+    fn has_input(&self) -> bool {
+        !self.ready.is_empty() || self.pending_eofs > 0
+    }
+
+    /// Reads the available input, at most one line in canonical mode.
+    ///
+    /// Returns `None` if nothing is available.
+    fn read(&mut self, buf: &mut [u8]) -> Option<usize> {
+        if self.ready.is_empty() {
+            if self.pending_eofs > 0 {
+                self.pending_eofs -= 1;
+                return Some(0);
+            }
+            return None;
+        }
+        let mut read = 0;
+        while read < buf.len() {
+            match self.ready.pop_front() {
+                Some(c) => {
+                    buf[read] = c;
+                    read += 1;
+                    if self.termios.icanon && c == b'\n' {
+                        break;
+                    }
+                }
+                None => break,
+            }
+        }
+        Some(read)
+    }
+}
+
+/// Moves the pending input of the console to the line discipline.
+fn poll_input() {
+    let mut console = CONSOLE.lock();
+    while let Some(c) = axhal::console::getchar() {
+        console.receive(c);
+    }
+}
+
+#[cfg(all(feature = "irq", feature = "multitask"))]
+fn handle_input_irq() {
+    poll_input();
+    INPUT_WQ.notify_all(false);
+}
+
+/// Lets the readers of the console sleep until the input IRQ, if the
+/// platform supports it.
+pub(crate) fn init_input_irq() {
+    #[cfg(all(feature = "irq", feature = "multitask"))]
+    if axhal::console::set_input_handler(handle_input_irq) {
+        INPUT_IRQ.store(true, Ordering::Release);
+    }
+}
+
+/// Processes `input` as if it was received from the console, e.g., the input
+/// from a remote terminal.
+pub fn receive_input(input: &[u8]) {
+    let mut console = CONSOLE.lock();
+    for &c in input {
+        console.receive(c);
+    }
+    drop(console);
+    #[cfg(all(feature = "irq", feature = "multitask"))]
+    INPUT_WQ.notify_all(false);
+}
+
+/// Returns the line discipline settings of the console.
+pub fn termios() -> Termios {
+    CONSOLE.lock().termios
+}
+
+/// Changes the line discipline settings of the console.
+///
+/// The line being edited becomes available when the canonical mode is turned
+/// off.
+pub fn set_termios(termios: Termios) {
+    let mut console = CONSOLE.lock();
+    if console.termios.icanon && !termios.icanon {
+        let line = core::mem::take(&mut console.line);
+        console.ready.extend(line);
+    }
+    console.termios = termios;
+}
+
+/// A terminal device (`/dev/console` and `/dev/tty`) on the console of
+/// [`axhal`].
+///
+/// Reads block until some input is available, which is processed by a line
+/// discipline configured by [`set_termios`].
+pub struct TtyDev;
+
+impl VfsNodeOps for TtyDev {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(0o620),
+            VfsNodeType::CharDevice,
+            0,
+            0,
+        ))
+    }
+
+    fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if let Some(read) = CONSOLE.lock().read(buf) {
+                return Ok(read);
+            }
+            if INPUT_IRQ.load(Ordering::Acquire) {
+                #[cfg(all(feature = "irq", feature = "multitask"))]
+                INPUT_WQ.wait_until(|| CONSOLE.lock().has_input());
+            } else {
+                poll_input();
+                if !CONSOLE.lock().has_input() {
+                    axtask::yield_now();
+                }
+            }
+        }
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        axhal::console::write_bytes(buf);
+        Ok(buf.len())
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Ok(())
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
@@ -0,0 +1,70 @@
+//! The device filesystem mounted on `/dev`.
+//!
+//! Besides `null` and `zero`, it contains the console (`console` and `tty`),
+//! the random number devices (`random` and `urandom`), and the block devices
+//! (`vda`, `vdb`, ...) passed to [`init_filesystems`](crate::init_filesystems).
+//! Other devices, e.g., the framebuffer `fb0`, are added by the kernel with
+//! [`add`].
+
+use alloc::sync::Arc;
+
+use axdriver::{prelude::*, AxDeviceContainer};
+use axerrno::{ax_err, AxResult};
+use axfs_vfs::VfsNodeRef;
+use axhal::mem::PhysAddr;
+use axsync::Mutex;
+use cap_access::Cap;
+use lazyinit::LazyInit;
+
+use crate::dev::SharedBlockDevice;
+use crate::fops::File;
+use crate::fs::devfs::DeviceFileSystem;
+
+pub use crate::dev::{receive_input, set_termios, termios, Termios};
+pub use crate::dev::{BlockDev, FrameBufferDev, RandomDev, TtyDev};
+
+static DEVFS: LazyInit<Arc<DeviceFileSystem>> = LazyInit::new();
+
+/// Makes `devfs` the target of [`add`].
+pub(crate) fn set_global(devfs: &Arc<DeviceFileSystem>) {
+    DEVFS.call_once(|| devfs.clone());
+}
+
+/// Adds the block devices as `vda`, `vdb`, etc., starting from the one of
+/// the root filesystem if it's on a disk.
+pub(crate) fn add_block_devices(
+    root_dev: Option<SharedBlockDevice>,
+    mut others: AxDeviceContainer<AxBlockDevice>,
+) {
+    let others = core::iter::from_fn(|| others.take_one().map(|dev| Arc::new(Mutex::new(dev))));
+    for (idx, dev) in root_dev.into_iter().chain(others).enumerate() {
+        let Some(name) = crate::dev::block_dev_name(idx) else {
+            warn!("too many block devices, only {} are added to /dev", idx);
+            break;
+        };
+        if let Err(e) = add(name, Arc::new(BlockDev::new(dev))) {
+            warn!("failed to add /dev/{}: {:?}", name, e);
+        }
+    }
+}
+
+/// Adds a device file to the mounted `/dev`.
+pub fn add(name: &'static str, node: VfsNodeRef) -> AxResult {
+    let Some(devfs) = DEVFS.get() else {
+        return ax_err!(NotFound, "devfs is not mounted");
+    };
+    devfs.add(name, node);
+    Ok(())
+}
+
+/// Returns the physical memory that the opened device file stands for, i.e.,
+/// the address and the size of the framebuffer of `/dev/fb0`.
+///
+/// Mapping the memory directly makes the updates visible immediately.
+pub fn phys_range(file: &File) -> Option<(PhysAddr, usize)> {
+    file.access_node(Cap::empty())
+        .ok()?
+        .as_any()
+        .downcast_ref::<FrameBufferDev>()
+        .map(FrameBufferDev::phys_range)
+}
@@ -1,12 +1,19 @@
 //! Low-level filesystem operations.
 
+use alloc::{string::String, vec::Vec};
 use axerrno::{ax_err, ax_err_type, AxError, AxResult};
 use axfs_vfs::{VfsError, VfsNodeRef};
 use axio::SeekFrom;
 use cap_access::{Cap, WithCap};
 use core::fmt;
+use core::time::Duration;
 
-#[cfg(feature = "myfs")]
+use crate::root::MountRef;
+
+pub use crate::lock::{LockHandle, LockType, RecordLock};
+pub use crate::xattr::{XattrMode, XATTR_NAME_MAX, XATTR_SIZE_MAX};
+
+#[cfg(any(feature = "myfs", feature = "extfs"))]
 pub use crate::dev::Disk;
 #[cfg(feature = "myfs")]
 pub use crate::fs::myfs::MyFileSystemIf;
@@ -20,11 +27,26 @@ pub type FileAttr = axfs_vfs::VfsNodeAttr;
 /// Alias of [`axfs_vfs::VfsNodePerm`].
 pub type FilePerm = axfs_vfs::VfsNodePerm;
 
+/// Timestamps of a file, as the durations since the UNIX epoch.
+///
+/// They are zeros if the filesystem does not record them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FileTimes {
+    /// The last access time (`atime`).
+    pub accessed: Duration,
+    /// The last modification time (`mtime`).
+    pub modified: Duration,
+    /// The last status change time (`ctime`).
+    pub changed: Duration,
+}
+
 /// An opened file object, with open permissions and a cursor.
 pub struct File {
     node: WithCap<VfsNodeRef>,
     is_append: bool,
     offset: u64,
+    lock: LockHandle,
+    _mount: MountRef,
 }
 
 /// An opened directory object, with open permissions and a cursor for
@@ -32,6 +54,9 @@ pub struct File {
 pub struct Directory {
     node: WithCap<VfsNodeRef>,
     entry_idx: usize,
+    /// The absolute path, for the operations relative to this directory.
+    path: String,
+    _mount: MountRef,
 }
 
 /// Options and flags which can be used to configure how a file is opened.
@@ -46,7 +71,7 @@ pub struct OpenOptions {
     create_new: bool,
     // system-specific
     _custom_flags: i32,
-    _mode: u32,
+    mode: u32,
 }
 
 impl OpenOptions {
@@ -62,7 +87,7 @@ impl OpenOptions {
             create_new: false,
             // system-specific
             _custom_flags: 0,
-            _mode: 0o666,
+            mode: 0o666,
         }
     }
     /// Sets the option for read access.
@@ -89,6 +114,11 @@ impl OpenOptions {
     pub fn create_new(&mut self, create_new: bool) {
         self.create_new = create_new;
     }
+    /// Sets the permission bits of the new file, which are masked by the
+    /// umask of the current task. The default is `0o666`.
+    pub fn mode(&mut self, mode: u32) {
+        self.mode = mode;
+    }
 
     const fn is_valid(&self) -> bool {
         if !self.read && !self.write && !self.append {
@@ -112,28 +142,32 @@ impl OpenOptions {
 }
 
 impl File {
-    fn access_node(&self, cap: Cap) -> AxResult<&VfsNodeRef> {
+    pub(crate) fn access_node(&self, cap: Cap) -> AxResult<&VfsNodeRef> {
         self.node.access_or_err(cap, AxError::PermissionDenied)
     }
 
-    fn _open_at(dir: Option<&VfsNodeRef>, path: &str, opts: &OpenOptions) -> AxResult<Self> {
+    fn _open_at(dir: Option<&str>, path: &str, opts: &OpenOptions) -> AxResult<Self> {
         debug!("open file: {} {:?}", path, opts);
         if !opts.is_valid() {
             return ax_err!(InvalidInput);
         }
 
         let node_option = crate::root::lookup(dir, path);
-        let node = if opts.create || opts.create_new {
+        let mut created = false;
+        let (node, mount) = if opts.create || opts.create_new {
             match node_option {
-                Ok(node) => {
+                Ok(found) => {
                     // already exists
                     if opts.create_new {
                         return ax_err!(AlreadyExists);
                     }
-                    node
+                    found
                 }
                 // not exists, create new
-                Err(VfsError::NotFound) => crate::root::create_file(dir, path)?,
+                Err(VfsError::NotFound) => {
+                    created = true;
+                    crate::root::create_file(dir, path, opts.mode)?
+                }
                 Err(e) => return Err(e),
             }
         } else {
@@ -148,18 +182,24 @@ impl File {
             return ax_err!(IsADirectory);
         }
         let access_cap = opts.into();
-        if !perm_to_cap(attr.perm()).contains(access_cap) {
+        // The new file can be written even if its mode does not allow it.
+        if !created && !self::access_cap(&node, attr.perm()).contains(access_cap) {
             return ax_err!(PermissionDenied);
         }
 
+        let abs_path = crate::root::resolve_path(dir, path, true)?;
         node.open()?;
         if opts.truncate {
             node.truncate(0)?;
+            crate::notify::modified(&abs_path);
         }
+        let lock = LockHandle::new(node.clone(), abs_path, access_cap);
         Ok(Self {
             node: WithCap::new(node, access_cap),
             is_append: opts.append,
             offset: 0,
+            lock,
+            _mount: mount,
         })
     }
 
@@ -172,6 +212,7 @@ impl File {
     /// Truncates the file to the specified size.
     pub fn truncate(&self, size: u64) -> AxResult {
         self.access_node(Cap::WRITE)?.truncate(size)?;
+        crate::notify::modified(self.lock.path());
         Ok(())
     }
 
@@ -209,6 +250,7 @@ impl File {
         let node = self.access_node(Cap::WRITE)?;
         let write_len = node.write_at(offset, buf)?;
         self.offset = offset + write_len as u64;
+        crate::notify::modified(self.lock.path());
         Ok(write_len)
     }
 
@@ -219,6 +261,7 @@ impl File {
     pub fn write_at(&self, offset: u64, buf: &[u8]) -> AxResult<usize> {
         let node = self.access_node(Cap::WRITE)?;
         let write_len = node.write_at(offset, buf)?;
+        crate::notify::modified(self.lock.path());
         Ok(write_len)
     }
 
@@ -228,6 +271,15 @@ impl File {
         Ok(())
     }
 
+    /// Writes back the cached data and metadata of the file to the disk.
+    ///
+    /// Unlike [`flush`](Self::flush), the file is not required to be
+    /// writable.
+    pub fn sync(&self) -> AxResult {
+        self.access_node(Cap::empty())?.fsync()?;
+        Ok(())
+    }
+
     /// Sets the cursor of the file to the specified offset. Returns the new
     /// position after the seek.
     pub fn seek(&mut self, pos: SeekFrom) -> AxResult<u64> {
@@ -246,6 +298,49 @@ impl File {
     pub fn get_attr(&self) -> AxResult<FileAttr> {
         self.access_node(Cap::empty())?.get_attr()
     }
+
+    /// Gets the timestamps of the file.
+    pub fn get_times(&self) -> AxResult<FileTimes> {
+        let node = self.access_node(Cap::empty())?;
+        Ok(crate::fs::node_times(node).unwrap_or_default())
+    }
+
+    /// Returns whether the file is opened for writing.
+    pub fn is_writable(&self) -> bool {
+        self.node.can_access(Cap::WRITE)
+    }
+
+    /// Returns the identity of the file, which is the same for all its opened
+    /// instances (e.g., to share the pages of its mappings), or `None` if the
+    /// filesystem cannot tell it (FAT).
+    pub fn id(&self) -> Option<(u64, u64)> {
+        self.lock.file_id()
+    }
+
+    /// Returns the handle to the advisory locks of the file.
+    pub fn lock_handle(&self) -> &LockHandle {
+        &self.lock
+    }
+
+    /// Returns the value of the extended attribute `name` of the file.
+    pub fn get_xattr(&self, name: &str) -> AxResult<Vec<u8>> {
+        crate::xattr::get(self.access_node(Cap::empty())?, name)
+    }
+
+    /// Returns the names of the extended attributes of the file.
+    pub fn list_xattr(&self) -> AxResult<Vec<String>> {
+        crate::xattr::list(self.access_node(Cap::empty())?)
+    }
+
+    /// Sets the value of the extended attribute `name` of the file as `mode`.
+    pub fn set_xattr(&self, name: &str, value: &[u8], mode: XattrMode) -> AxResult {
+        crate::xattr::set(self.access_node(Cap::empty())?, name, value, mode)
+    }
+
+    /// Removes the extended attribute `name` of the file.
+    pub fn remove_xattr(&self, name: &str) -> AxResult {
+        crate::xattr::remove(self.access_node(Cap::empty())?, name)
+    }
 }
 
 impl Directory {
@@ -253,7 +348,7 @@ impl Directory {
         self.node.access_or_err(cap, AxError::PermissionDenied)
     }
 
-    fn _open_dir_at(dir: Option<&VfsNodeRef>, path: &str, opts: &OpenOptions) -> AxResult<Self> {
+    fn _open_dir_at(dir: Option<&str>, path: &str, opts: &OpenOptions) -> AxResult<Self> {
         debug!("open dir: {}", path);
         if !opts.read {
             return ax_err!(InvalidInput);
@@ -262,28 +357,33 @@ impl Directory {
             return ax_err!(InvalidInput);
         }
 
-        let node = crate::root::lookup(dir, path)?;
+        let (node, mount) = crate::root::lookup(dir, path)?;
         let attr = node.get_attr()?;
         if !attr.is_dir() {
             return ax_err!(NotADirectory);
         }
         let access_cap = opts.into();
-        if !perm_to_cap(attr.perm()).contains(access_cap) {
+        let allowed = self::access_cap(&node, attr.perm());
+        if !allowed.contains(access_cap) {
             return ax_err!(PermissionDenied);
         }
 
         node.open()?;
         Ok(Self {
-            node: WithCap::new(node, access_cap),
+            // search permission for the paths relative to it
+            node: WithCap::new(node, access_cap | (allowed & Cap::EXECUTE)),
             entry_idx: 0,
+            path: crate::root::resolve_path(dir, path, true)?,
+            _mount: mount,
         })
     }
 
-    fn access_at(&self, path: &str) -> AxResult<Option<&VfsNodeRef>> {
+    fn access_at(&self, path: &str) -> AxResult<Option<&str>> {
         if path.starts_with('/') {
             Ok(None)
         } else {
-            Ok(Some(self.access_node(Cap::EXECUTE)?))
+            self.access_node(Cap::EXECUTE)?;
+            Ok(Some(&self.path))
         }
     }
 
@@ -307,12 +407,12 @@ impl Directory {
 
     /// Creates an empty file at the path relative to this directory.
     pub fn create_file(&self, path: &str) -> AxResult<VfsNodeRef> {
-        crate::root::create_file(self.access_at(path)?, path)
+        Ok(crate::root::create_file(self.access_at(path)?, path, 0o666)?.0)
     }
 
     /// Creates an empty directory at the path relative to this directory.
     pub fn create_dir(&self, path: &str) -> AxResult {
-        crate::root::create_dir(self.access_at(path)?, path)
+        crate::root::create_dir(self.access_at(path)?, path, 0o777)
     }
 
     /// Removes a file at the path relative to this directory.
@@ -349,6 +449,7 @@ impl Directory {
 
 impl Drop for File {
     fn drop(&mut self) {
+        self.lock.lock(None, false).ok();
         unsafe { self.node.access_unchecked().release().ok() };
     }
 }
@@ -397,15 +498,41 @@ impl From<&OpenOptions> for Cap {
     }
 }
 
-fn perm_to_cap(perm: FilePerm) -> Cap {
+/// Returns the access rights of the current task to `node`, whose permission
+/// bits are `perm`.
+///
+/// The bits of the owner, the group or the others apply, as the task is the
+/// owner, in the group or neither. The superuser can read and write anything,
+/// and execute it if any execute bit is set. If the filesystem does not
+/// record the owners, the owner bits apply to all tasks.
+pub(crate) fn access_cap(node: &VfsNodeRef, perm: FilePerm) -> Cap {
+    let bits = perm.bits();
+    let Some((uid, gid)) = crate::fs::node_owner(node) else {
+        return rwx_to_cap(bits >> 6);
+    };
+    let cred = axtask::current_cred();
+    if cred.is_root() {
+        let exec = if bits & 0o111 != 0 { 0o1 } else { 0 };
+        rwx_to_cap(0o6 | exec)
+    } else if cred.uid == uid {
+        rwx_to_cap(bits >> 6)
+    } else if cred.gid == gid {
+        rwx_to_cap(bits >> 3)
+    } else {
+        rwx_to_cap(bits)
+    }
+}
+
+/// Converts the lowest three permission bits to [`Cap`].
+fn rwx_to_cap(bits: u16) -> Cap {
     let mut cap = Cap::empty();
-    if perm.owner_readable() {
+    if bits & 0o4 != 0 {
         cap |= Cap::READ;
     }
-    if perm.owner_writable() {
+    if bits & 0o2 != 0 {
         cap |= Cap::WRITE;
     }
-    if perm.owner_executable() {
+    if bits & 0o1 != 0 {
         cap |= Cap::EXECUTE;
     }
     cap
@@ -0,0 +1,312 @@
+//! The data of the files, mapped by the block pointers of ext2 or the
+//! extents of ext4.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use axfs_vfs::{VfsError, VfsResult};
+
+use super::layout::*;
+use super::Volume;
+
+/// The maximum depth of the extent trees.
+const MAX_EXTENT_DEPTH: u16 = 5;
+
+impl Volume {
+    /// The number of block pointers in an indirect block.
+    fn ptrs_per_block(&self) -> u64 {
+        self.block_size as u64 / 4
+    }
+
+    /// Returns the slot in `i_block` and the indices in the indirect blocks
+    /// that map the logical block `lblk`.
+    fn block_path(&self, lblk: u64) -> VfsResult<(usize, Vec<usize>)> {
+        let per = self.ptrs_per_block();
+        if lblk < N_DIRECT as u64 {
+            return Ok((lblk as usize, Vec::new()));
+        }
+        let mut rest = lblk - N_DIRECT as u64;
+        let mut span = per;
+        for level in 1..=3 {
+            if rest < span {
+                let mut path = Vec::with_capacity(level);
+                for _ in 0..level {
+                    span /= per;
+                    path.push((rest / span) as usize);
+                    rest %= span;
+                }
+                return Ok((N_DIRECT + level - 1, path));
+            }
+            rest -= span;
+            span *= per;
+        }
+        Err(VfsError::InvalidInput)
+    }
+
+    /// Reads the block pointer at `idx` of the indirect block.
+    fn read_ptr(&mut self, block: u64, idx: usize) -> VfsResult<u32> {
+        let mut buf = [0; 4];
+        self.read_bytes(block * self.block_size as u64 + idx as u64 * 4, &mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn write_ptr(&mut self, block: u64, idx: usize, ptr: u32) -> VfsResult {
+        let pos = block * self.block_size as u64 + idx as u64 * 4;
+        self.write_bytes(pos, &ptr.to_le_bytes())
+    }
+
+    /// Returns the physical block of the logical block `lblk` of the file,
+    /// or `None` if it is a hole.
+    pub(super) fn bmap(&mut self, inode: &Inode, lblk: u64) -> VfsResult<Option<u64>> {
+        if inode.uses_extents() {
+            let root = inode.block_area().to_vec();
+            return self.extent_bmap(&root, lblk as u32, MAX_EXTENT_DEPTH);
+        }
+        let (slot, path) = self.block_path(lblk)?;
+        let mut block = inode.block(slot);
+        for idx in path {
+            if block == 0 {
+                break;
+            }
+            block = self.read_ptr(block as u64, idx)?;
+        }
+        Ok((block != 0).then_some(block as u64))
+    }
+
+    /// Looks up `lblk` in the extent tree node.
+    fn extent_bmap(&mut self, node: &[u8], lblk: u32, max_depth: u16) -> VfsResult<Option<u64>> {
+        let entries = read_u16(node, 2) as usize;
+        let depth = read_u16(node, 6);
+        if read_u16(node, 0) != EXTENT_MAGIC || depth > max_depth || (entries + 1) * 12 > node.len()
+        {
+            return Err(VfsError::InvalidData);
+        }
+        let entry = |i: usize| &node[12 + i * 12..24 + i * 12];
+        if depth == 0 {
+            for e in (0..entries).map(entry) {
+                let start = read_u32(e, 0);
+                let len = read_u16(e, 4);
+                let (len, init) = if len > EXTENT_INIT_MAX_LEN {
+                    (len - EXTENT_INIT_MAX_LEN, false)
+                } else {
+                    (len, true)
+                };
+                if lblk >= start && lblk - start < len as u32 {
+                    let pstart = read_u32(e, 8) as u64 | (read_u16(e, 6) as u64) << 32;
+                    // The uninitialized extents are read as zeros.
+                    return Ok(init.then_some(pstart + (lblk - start) as u64));
+                }
+            }
+            return Ok(None);
+        }
+        // The index entries are sorted, find the last one covering `lblk`.
+        let Some(e) = (0..entries)
+            .map(entry)
+            .take_while(|e| read_u32(e, 0) <= lblk)
+            .last()
+        else {
+            return Ok(None);
+        };
+        let child = read_u32(e, 4) as u64 | (read_u16(e, 8) as u64) << 32;
+        let mut buf = vec![0; self.block_size];
+        self.read_block(child, &mut buf)?;
+        self.extent_bmap(&buf, lblk, depth - 1)
+    }
+
+    /// Returns the physical block of the logical block `lblk` of the file,
+    /// allocating it and the indirect blocks if necessary.
+    pub(super) fn bmap_alloc(&mut self, ino: u32, inode: &mut Inode, lblk: u64) -> VfsResult<u64> {
+        if inode.uses_extents() {
+            return Err(VfsError::Unsupported);
+        }
+        let goal = self.group_of(ino);
+        let sectors_per_block = self.block_size as u64 / 512;
+        let (slot, path) = self.block_path(lblk)?;
+        let mut block = inode.block(slot) as u64;
+        if block == 0 {
+            block = self.alloc_block(goal)?;
+            inode.set_block(slot, block as u32);
+            inode.set_sectors(inode.sectors() + sectors_per_block);
+        }
+        for idx in path {
+            let next = self.read_ptr(block, idx)? as u64;
+            block = if next == 0 {
+                let new = self.alloc_block(goal)?;
+                self.write_ptr(block, idx, new as u32)?;
+                inode.set_sectors(inode.sectors() + sectors_per_block);
+                new
+            } else {
+                next
+            };
+        }
+        Ok(block)
+    }
+
+    /// Reads the file data at `offset`, the holes are read as zeros.
+    pub(super) fn read_data(
+        &mut self,
+        inode: &Inode,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> VfsResult<usize> {
+        let size = inode.size();
+        if offset >= size {
+            return Ok(0);
+        }
+        let len = buf.len().min((size - offset) as usize);
+        if inode.is_fast_symlink() {
+            let start = offset as usize;
+            buf[..len].copy_from_slice(&inode.block_area()[start..start + len]);
+            return Ok(len);
+        }
+        let bs = self.block_size as u64;
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done as u64;
+            let in_block = (pos % bs) as usize;
+            let count = (len - done).min(self.block_size - in_block);
+            let dst = &mut buf[done..done + count];
+            match self.bmap(inode, pos / bs)? {
+                Some(block) => self.read_bytes(block * bs + in_block as u64, dst)?,
+                None => dst.fill(0),
+            }
+            done += count;
+        }
+        Ok(len)
+    }
+
+    /// Writes the file data at `offset`, extending the file if necessary.
+    ///
+    /// The inode is not written back.
+    pub(super) fn write_data(
+        &mut self,
+        ino: u32,
+        inode: &mut Inode,
+        offset: u64,
+        buf: &[u8],
+    ) -> VfsResult<usize> {
+        self.check_writable()?;
+        let bs = self.block_size as u64;
+        let mut done = 0;
+        while done < buf.len() {
+            let pos = offset + done as u64;
+            let in_block = (pos % bs) as usize;
+            let count = (buf.len() - done).min(self.block_size - in_block);
+            let block = match self.bmap_alloc(ino, inode, pos / bs) {
+                Ok(block) => block,
+                Err(VfsError::StorageFull) if done > 0 => break,
+                Err(e) => return Err(e),
+            };
+            self.write_bytes(block * bs + in_block as u64, &buf[done..done + count])?;
+            done += count;
+        }
+        let end = offset + done as u64;
+        if end > inode.size() {
+            inode.set_size(end);
+        }
+        let now = super::now();
+        inode.set_mtime(now);
+        inode.set_ctime(now);
+        Ok(done)
+    }
+
+    /// Changes the size of the file, freeing the blocks beyond the new size.
+    ///
+    /// The inode is not written back.
+    pub(super) fn truncate(&mut self, inode: &mut Inode, size: u64) -> VfsResult {
+        self.check_writable()?;
+        if inode.uses_extents() || inode.is_fast_symlink() {
+            return Err(VfsError::Unsupported);
+        }
+        let bs = self.block_size as u64;
+        if size < inode.size() {
+            // Zero the tail of the last block, which is read if the file is
+            // extended again.
+            if size % bs != 0 {
+                if let Some(block) = self.bmap(inode, size / bs)? {
+                    let zeros = vec![0; (bs - size % bs) as usize];
+                    self.write_bytes(block * bs + size % bs, &zeros)?;
+                }
+            }
+            let keep = size.div_ceil(bs);
+            for slot in 0..N_DIRECT {
+                let block = inode.block(slot) as u64;
+                if slot as u64 >= keep && block != 0 {
+                    self.free_data_block(inode, block)?;
+                    inode.set_block(slot, 0);
+                }
+            }
+            let per = self.ptrs_per_block();
+            let mut base = N_DIRECT as u64;
+            let mut span = per;
+            for level in 1..=3 {
+                let slot = N_DIRECT + level as usize - 1;
+                let block = inode.block(slot) as u64;
+                if block != 0 && self.truncate_indirect(inode, block, level, base, keep)? {
+                    self.free_data_block(inode, block)?;
+                    inode.set_block(slot, 0);
+                }
+                base += span;
+                span *= per;
+            }
+        }
+        inode.set_size(size);
+        let now = super::now();
+        inode.set_mtime(now);
+        inode.set_ctime(now);
+        Ok(())
+    }
+
+    /// Frees the blocks from the logical block `keep` under the indirect
+    /// block of `level`, whose first logical block is `base`.
+    ///
+    /// Returns whether the indirect block becomes empty, which is freed by
+    /// the caller.
+    fn truncate_indirect(
+        &mut self,
+        inode: &mut Inode,
+        block: u64,
+        level: u32,
+        base: u64,
+        keep: u64,
+    ) -> VfsResult<bool> {
+        let per = self.ptrs_per_block();
+        let span = per.pow(level - 1);
+        let mut buf = vec![0; self.block_size];
+        self.read_block(block, &mut buf)?;
+        let mut empty = true;
+        let mut changed = false;
+        for idx in 0..per as usize {
+            let child = read_u32(&buf, idx * 4) as u64;
+            if child == 0 {
+                continue;
+            }
+            let child_base = base + idx as u64 * span;
+            let freed = if child_base + span <= keep {
+                false
+            } else if level == 1 {
+                true
+            } else {
+                self.truncate_indirect(inode, child, level - 1, child_base, keep)?
+            };
+            if freed {
+                self.free_data_block(inode, child)?;
+                write_u32(&mut buf, idx * 4, 0);
+                changed = true;
+            } else {
+                empty = false;
+            }
+        }
+        if changed && !empty {
+            self.write_block(block, &buf)?;
+        }
+        Ok(empty)
+    }
+
+    fn free_data_block(&mut self, inode: &mut Inode, block: u64) -> VfsResult {
+        self.free_block(block)?;
+        let sectors_per_block = self.block_size as u64 / 512;
+        inode.set_sectors(inode.sectors().saturating_sub(sectors_per_block));
+        Ok(())
+    }
+}
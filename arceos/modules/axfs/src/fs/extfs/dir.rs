@@ -0,0 +1,241 @@
+//! The directories, which are lists of the entries in the data blocks.
+//!
+//! The hash tree indices of ext3/4 are ignored, their nodes look like empty
+//! entries when the blocks are scanned linearly.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use axfs_vfs::{VfsError, VfsNodeType, VfsResult};
+
+use super::layout::*;
+use super::Volume;
+
+/// An entry in a directory.
+pub(super) struct DirEntry {
+    pub name: String,
+    pub ty: VfsNodeType,
+}
+
+/// The location of an entry in the directory.
+struct EntryPos {
+    block: u64,
+    offset: usize,
+    /// The offset of the previous entry in the same block.
+    prev: Option<usize>,
+}
+
+impl Volume {
+    /// Calls `f` with each entry in the directory, its position and the
+    /// block containing it, until `f` returns `Some`.
+    fn scan_dir<T>(
+        &mut self,
+        dir: &Inode,
+        mut f: impl FnMut(&RawDirEntry, &[u8], EntryPos) -> Option<T>,
+    ) -> VfsResult<Option<T>> {
+        let has_type = self.has_dirent_type();
+        let bs = self.block_size;
+        let mut buf = vec![0; bs];
+        for lblk in 0..dir.size().div_ceil(bs as u64) {
+            let Some(block) = self.bmap(dir, lblk)? else {
+                continue;
+            };
+            self.read_block(block, &mut buf)?;
+            let mut offset = 0;
+            let mut prev = None;
+            while offset + RawDirEntry::HEADER_SIZE <= bs {
+                let entry = RawDirEntry::parse(&buf[offset..], has_type);
+                let rec_len = entry.rec_len as usize;
+                if rec_len < RawDirEntry::HEADER_SIZE
+                    || offset + rec_len > bs
+                    || RawDirEntry::HEADER_SIZE + entry.name_len as usize > rec_len
+                {
+                    warn!("extfs: corrupted directory entry in block {}", block);
+                    return Err(VfsError::InvalidData);
+                }
+                let pos = EntryPos {
+                    block,
+                    offset,
+                    prev,
+                };
+                if let Some(res) = f(&entry, &buf, pos) {
+                    return Ok(Some(res));
+                }
+                prev = Some(offset);
+                offset += rec_len;
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the type of the entry, which is read from the inode if the
+    /// entries do not record the types.
+    fn entry_type(&mut self, ino: u32, file_type: u8) -> VfsResult<VfsNodeType> {
+        match dirent_type_to_type(file_type) {
+            Some(ty) => Ok(ty),
+            None => Ok(self.read_inode(ino)?.node_type()),
+        }
+    }
+
+    /// Lists the entries in the directory, including `.` and `..`.
+    pub(super) fn read_dir_entries(&mut self, dir: &Inode) -> VfsResult<Vec<DirEntry>> {
+        let mut raw = Vec::new();
+        self.scan_dir::<()>(dir, |entry, buf, pos| {
+            if entry.ino != 0 {
+                let name = &buf[pos.offset + RawDirEntry::HEADER_SIZE..][..entry.name_len as usize];
+                raw.push((
+                    entry.ino,
+                    String::from_utf8_lossy(name).into(),
+                    entry.file_type,
+                ));
+            }
+            None
+        })?;
+        raw.into_iter()
+            .map(|(ino, name, file_type)| {
+                let ty = self.entry_type(ino, file_type)?;
+                Ok(DirEntry { name, ty })
+            })
+            .collect()
+    }
+
+    /// Finds the entry named `name`, returns its position and inode number.
+    fn find_entry_pos(&mut self, dir: &Inode, name: &str) -> VfsResult<Option<(EntryPos, u32)>> {
+        self.scan_dir(dir, |entry, buf, pos| {
+            let entry_name =
+                &buf[pos.offset + RawDirEntry::HEADER_SIZE..][..entry.name_len as usize];
+            (entry.ino != 0 && entry_name == name.as_bytes()).then_some((pos, entry.ino))
+        })
+    }
+
+    /// Finds the entry named `name`, returns its inode number.
+    pub(super) fn find_entry(&mut self, dir: &Inode, name: &str) -> VfsResult<Option<u32>> {
+        Ok(self.find_entry_pos(dir, name)?.map(|(_, ino)| ino))
+    }
+
+    /// Adds an entry to the directory. The directory inode is written back.
+    pub(super) fn add_entry(
+        &mut self,
+        dir_ino: u32,
+        dir: &mut Inode,
+        name: &str,
+        ino: u32,
+        ty: VfsNodeType,
+    ) -> VfsResult {
+        self.check_writable()?;
+        if name.is_empty() || name.len() > 255 || name.contains('/') {
+            return Err(VfsError::InvalidInput);
+        }
+        let has_type = self.has_dirent_type();
+        let needed = RawDirEntry::used_len(name.len());
+        let new_entry = |rec_len: usize| RawDirEntry {
+            ino,
+            rec_len: rec_len as u16,
+            name_len: name.len() as u16,
+            file_type: if has_type { type_to_dirent_type(ty) } else { 0 },
+        };
+
+        // Find the free space in an entry, which is split into two.
+        let found = self.scan_dir(dir, |entry, _, pos| {
+            let used = if entry.ino == 0 {
+                0
+            } else {
+                RawDirEntry::used_len(entry.name_len as usize)
+            };
+            ((entry.rec_len as usize).saturating_sub(used) >= needed).then_some((pos, used))
+        })?;
+        let bs = self.block_size;
+        let mut buf = vec![0; bs];
+        let (block, offset) = match found {
+            Some((pos, used)) => {
+                self.read_block(pos.block, &mut buf)?;
+                let mut entry = RawDirEntry::parse(&buf[pos.offset..], has_type);
+                let rec_len = entry.rec_len as usize;
+                if used > 0 {
+                    entry.rec_len = used as u16;
+                    entry.write(&mut buf[pos.offset..], has_type);
+                }
+                let offset = pos.offset + used;
+                new_entry(rec_len - used).write(&mut buf[offset..], has_type);
+                (pos.block, offset)
+            }
+            None => {
+                // Append a new block.
+                let lblk = dir.size().div_ceil(bs as u64);
+                let block = self.bmap_alloc(dir_ino, dir, lblk)?;
+                dir.set_size((lblk + 1) * bs as u64);
+                new_entry(bs).write(&mut buf, has_type);
+                (block, 0)
+            }
+        };
+        let name_offset = offset + RawDirEntry::HEADER_SIZE;
+        buf[name_offset..name_offset + name.len()].copy_from_slice(name.as_bytes());
+        self.write_block(block, &buf)?;
+        self.dir_changed(dir_ino, dir)
+    }
+
+    /// Removes the entry named `name` from the directory, returns its inode
+    /// number. The directory inode is written back.
+    pub(super) fn remove_entry(
+        &mut self,
+        dir_ino: u32,
+        dir: &mut Inode,
+        name: &str,
+    ) -> VfsResult<u32> {
+        self.check_writable()?;
+        let has_type = self.has_dirent_type();
+        let (pos, ino) = self.find_entry_pos(dir, name)?.ok_or(VfsError::NotFound)?;
+        let mut buf = vec![0; self.block_size];
+        self.read_block(pos.block, &mut buf)?;
+        let mut entry = RawDirEntry::parse(&buf[pos.offset..], has_type);
+        match pos.prev {
+            // Merge the entry into the previous one.
+            Some(prev) => {
+                let mut prev_entry = RawDirEntry::parse(&buf[prev..], has_type);
+                prev_entry.rec_len += entry.rec_len;
+                prev_entry.write(&mut buf[prev..], has_type);
+            }
+            // The first entry in the block is marked unused.
+            None => {
+                entry.ino = 0;
+                entry.write(&mut buf[pos.offset..], has_type);
+            }
+        }
+        self.write_block(pos.block, &buf)?;
+        self.dir_changed(dir_ino, dir)?;
+        Ok(ino)
+    }
+
+    /// Changes the inode number of the entry named `name`.
+    pub(super) fn set_entry(&mut self, dir: &Inode, name: &str, ino: u32) -> VfsResult {
+        let has_type = self.has_dirent_type();
+        let (pos, _) = self.find_entry_pos(dir, name)?.ok_or(VfsError::NotFound)?;
+        let mut buf = vec![0; self.block_size];
+        self.read_block(pos.block, &mut buf)?;
+        let mut entry = RawDirEntry::parse(&buf[pos.offset..], has_type);
+        entry.ino = ino;
+        entry.write(&mut buf[pos.offset..], has_type);
+        self.write_block(pos.block, &buf)
+    }
+
+    /// Returns whether the directory has no entries other than `.` and `..`.
+    pub(super) fn is_dir_empty(&mut self, dir: &Inode) -> VfsResult<bool> {
+        let found = self.scan_dir(dir, |entry, buf, pos| {
+            let name = &buf[pos.offset + RawDirEntry::HEADER_SIZE..][..entry.name_len as usize];
+            (entry.ino != 0 && name != b"." && name != b"..").then_some(())
+        })?;
+        Ok(found.is_none())
+    }
+
+    /// Updates the timestamps of the modified directory and writes it back.
+    ///
+    /// The hash tree index is dropped, as it is not updated.
+    fn dir_changed(&mut self, dir_ino: u32, dir: &mut Inode) -> VfsResult {
+        dir.set_flags(dir.flags() & !INODE_INDEX_FL);
+        let now = super::now();
+        dir.set_mtime(now);
+        dir.set_ctime(now);
+        self.write_inode(dir_ino, dir)
+    }
+}
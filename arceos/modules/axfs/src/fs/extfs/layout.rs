@@ -0,0 +1,510 @@
+//! On-disk structures of ext2/ext4, which are all little-endian.
+//!
+//! The structures keep their raw bytes, so that the fields not interpreted
+//! here are written back unchanged.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use axfs_vfs::VfsNodeType;
+
+/// The byte offset of the primary superblock.
+pub const SUPERBLOCK_OFFSET: u64 = 1024;
+/// The size of the superblock.
+pub const SUPERBLOCK_SIZE: usize = 1024;
+/// The magic number of ext2/3/4.
+pub const EXT_MAGIC: u16 = 0xef53;
+/// The inode number of the root directory.
+pub const ROOT_INO: u32 = 2;
+/// The number of block pointers in an inode.
+pub const N_BLOCKS: usize = 15;
+/// The number of direct block pointers in an inode.
+pub const N_DIRECT: usize = 12;
+
+/// `s_feature_incompat`: directory entries record the file type.
+pub const INCOMPAT_FILETYPE: u32 = 0x2;
+/// `s_feature_incompat`: the journal needs to be replayed.
+pub const INCOMPAT_RECOVER: u32 = 0x4;
+/// `s_feature_incompat`: files may be mapped by extents.
+pub const INCOMPAT_EXTENTS: u32 = 0x40;
+/// `s_feature_incompat`: block numbers are 64-bit.
+pub const INCOMPAT_64BIT: u32 = 0x80;
+/// `s_feature_incompat`: multiple mount protection.
+pub const INCOMPAT_MMP: u32 = 0x100;
+/// `s_feature_incompat`: the metadata of groups are packed together.
+pub const INCOMPAT_FLEX_BG: u32 = 0x200;
+/// `s_feature_incompat`: the checksum seed is in the superblock.
+pub const INCOMPAT_CSUM_SEED: u32 = 0x2000;
+/// `s_feature_incompat`: directories larger than 2GB or 3-level htrees.
+pub const INCOMPAT_LARGEDIR: u32 = 0x4000;
+/// `s_feature_ro_compat`: superblock backups in some groups only.
+pub const RO_COMPAT_SPARSE_SUPER: u32 = 0x1;
+/// `s_feature_ro_compat`: files larger than 2GB.
+pub const RO_COMPAT_LARGE_FILE: u32 = 0x2;
+
+/// The features of ext2 that can be written.
+pub const INCOMPAT_RW: u32 = INCOMPAT_FILETYPE;
+/// The read-only compatible features of ext2 that can be written.
+pub const RO_COMPAT_RW: u32 = RO_COMPAT_SPARSE_SUPER | RO_COMPAT_LARGE_FILE;
+/// The features of ext4 that can be read.
+pub const INCOMPAT_RO: u32 = INCOMPAT_FILETYPE
+    | INCOMPAT_RECOVER
+    | INCOMPAT_EXTENTS
+    | INCOMPAT_64BIT
+    | INCOMPAT_MMP
+    | INCOMPAT_FLEX_BG
+    | INCOMPAT_CSUM_SEED
+    | INCOMPAT_LARGEDIR;
+
+/// `i_flags`: the directory is indexed by a hash tree.
+pub const INODE_INDEX_FL: u32 = 0x1000;
+/// `i_flags`: the file is mapped by extents.
+pub const INODE_EXTENTS_FL: u32 = 0x80000;
+
+/// The magic number of the extent tree nodes.
+pub const EXTENT_MAGIC: u16 = 0xf30a;
+/// The extents longer than this are uninitialized, and read as zeros.
+pub const EXTENT_INIT_MAX_LEN: u16 = 32768;
+
+pub fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+pub fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+pub fn write_u16(buf: &mut [u8], offset: usize, value: u16) {
+    buf[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+pub fn write_u32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// The superblock, which describes the whole filesystem.
+#[derive(Clone)]
+pub struct Superblock {
+    raw: Vec<u8>,
+}
+
+impl Superblock {
+    pub fn from_bytes(raw: &[u8]) -> Self {
+        Self { raw: raw.into() }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.raw
+    }
+
+    pub fn magic(&self) -> u16 {
+        read_u16(&self.raw, 56)
+    }
+
+    pub fn inodes_count(&self) -> u32 {
+        read_u32(&self.raw, 0)
+    }
+
+    pub fn blocks_count(&self) -> u64 {
+        let hi = if self.is_64bit() {
+            read_u32(&self.raw, 0x150)
+        } else {
+            0
+        };
+        read_u32(&self.raw, 4) as u64 | (hi as u64) << 32
+    }
+
+    pub fn free_blocks_count(&self) -> u64 {
+        let hi = if self.is_64bit() {
+            read_u32(&self.raw, 0x158)
+        } else {
+            0
+        };
+        read_u32(&self.raw, 12) as u64 | (hi as u64) << 32
+    }
+
+    pub fn set_free_blocks_count(&mut self, count: u64) {
+        write_u32(&mut self.raw, 12, count as u32);
+        if self.is_64bit() {
+            write_u32(&mut self.raw, 0x158, (count >> 32) as u32);
+        }
+    }
+
+    pub fn free_inodes_count(&self) -> u32 {
+        read_u32(&self.raw, 16)
+    }
+
+    pub fn set_free_inodes_count(&mut self, count: u32) {
+        write_u32(&mut self.raw, 16, count);
+    }
+
+    pub fn first_data_block(&self) -> u32 {
+        read_u32(&self.raw, 20)
+    }
+
+    /// The block size in bytes, `1024 << s_log_block_size`.
+    pub fn block_size(&self) -> usize {
+        1024 << read_u32(&self.raw, 24)
+    }
+
+    pub fn blocks_per_group(&self) -> u32 {
+        read_u32(&self.raw, 32)
+    }
+
+    pub fn inodes_per_group(&self) -> u32 {
+        read_u32(&self.raw, 40)
+    }
+
+    pub fn rev_level(&self) -> u32 {
+        read_u32(&self.raw, 76)
+    }
+
+    pub fn inode_size(&self) -> usize {
+        match self.rev_level() {
+            0 => 128,
+            _ => read_u16(&self.raw, 88) as usize,
+        }
+    }
+
+    pub fn feature_incompat(&self) -> u32 {
+        match self.rev_level() {
+            0 => 0,
+            _ => read_u32(&self.raw, 96),
+        }
+    }
+
+    pub fn feature_ro_compat(&self) -> u32 {
+        match self.rev_level() {
+            0 => 0,
+            _ => read_u32(&self.raw, 100),
+        }
+    }
+
+    fn is_64bit(&self) -> bool {
+        self.feature_incompat() & INCOMPAT_64BIT != 0
+    }
+
+    /// The size of the group descriptors.
+    pub fn desc_size(&self) -> usize {
+        if self.is_64bit() {
+            (read_u16(&self.raw, 254) as usize).max(32)
+        } else {
+            32
+        }
+    }
+
+    /// The number of block groups.
+    pub fn groups_count(&self) -> u32 {
+        let data_blocks = self.blocks_count() - self.first_data_block() as u64;
+        data_blocks.div_ceil(self.blocks_per_group() as u64) as u32
+    }
+
+    /// Returns whether the fields used are valid, to avoid the divisions by
+    /// zero or the overflows on a corrupted superblock.
+    pub fn is_valid(&self) -> bool {
+        self.magic() == EXT_MAGIC
+            && read_u32(&self.raw, 24) <= 6 // up to 64K blocks
+            && self.blocks_per_group() > 0
+            && self.inodes_per_group() > 0
+            && self.blocks_count() > self.first_data_block() as u64
+            && self.inode_size() >= 128
+            && self.inode_size() <= self.block_size()
+            && self.inode_size().is_power_of_two()
+    }
+}
+
+/// A block group descriptor.
+#[derive(Clone)]
+pub struct GroupDesc {
+    raw: Vec<u8>,
+}
+
+impl GroupDesc {
+    pub fn from_bytes(raw: &[u8]) -> Self {
+        Self { raw: raw.into() }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.raw
+    }
+
+    fn read_lo_hi(&self, lo: usize, hi: usize) -> u64 {
+        let hi = if self.raw.len() >= 64 {
+            read_u32(&self.raw, hi)
+        } else {
+            0
+        };
+        read_u32(&self.raw, lo) as u64 | (hi as u64) << 32
+    }
+
+    fn read_lo_hi16(&self, lo: usize, hi: usize) -> u32 {
+        let hi = if self.raw.len() >= 64 {
+            read_u16(&self.raw, hi)
+        } else {
+            0
+        };
+        read_u16(&self.raw, lo) as u32 | (hi as u32) << 16
+    }
+
+    fn write_lo_hi16(&mut self, lo: usize, hi: usize, value: u32) {
+        write_u16(&mut self.raw, lo, value as u16);
+        if self.raw.len() >= 64 {
+            write_u16(&mut self.raw, hi, (value >> 16) as u16);
+        }
+    }
+
+    pub fn block_bitmap(&self) -> u64 {
+        self.read_lo_hi(0x0, 0x20)
+    }
+
+    pub fn inode_bitmap(&self) -> u64 {
+        self.read_lo_hi(0x4, 0x24)
+    }
+
+    pub fn inode_table(&self) -> u64 {
+        self.read_lo_hi(0x8, 0x28)
+    }
+
+    pub fn free_blocks_count(&self) -> u32 {
+        self.read_lo_hi16(0xc, 0x2c)
+    }
+
+    pub fn set_free_blocks_count(&mut self, count: u32) {
+        self.write_lo_hi16(0xc, 0x2c, count)
+    }
+
+    pub fn free_inodes_count(&self) -> u32 {
+        self.read_lo_hi16(0xe, 0x2e)
+    }
+
+    pub fn set_free_inodes_count(&mut self, count: u32) {
+        self.write_lo_hi16(0xe, 0x2e, count)
+    }
+
+    pub fn used_dirs_count(&self) -> u32 {
+        self.read_lo_hi16(0x10, 0x30)
+    }
+
+    pub fn set_used_dirs_count(&mut self, count: u32) {
+        self.write_lo_hi16(0x10, 0x30, count)
+    }
+}
+
+/// An inode, which describes a file.
+#[derive(Clone)]
+pub struct Inode {
+    raw: Vec<u8>,
+}
+
+impl Inode {
+    pub fn from_bytes(raw: &[u8]) -> Self {
+        Self { raw: raw.into() }
+    }
+
+    /// Creates an inode with the mode, the timestamps and no data.
+    pub fn new(size: usize, mode: u16, now: u32) -> Self {
+        let mut inode = Self { raw: vec![0; size] };
+        write_u16(&mut inode.raw, 0, mode);
+        inode.set_atime(now);
+        inode.set_mtime(now);
+        inode.set_ctime(now);
+        if size > 128 {
+            // `i_extra_isize`, the size of the fields used beyond 128 bytes.
+            write_u16(&mut inode.raw, 128, 32.min(size as u16 - 128));
+        }
+        inode
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.raw
+    }
+
+    pub fn mode(&self) -> u16 {
+        read_u16(&self.raw, 0)
+    }
+
+    pub fn node_type(&self) -> VfsNodeType {
+        mode_to_type(self.mode())
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.node_type() == VfsNodeType::Dir
+    }
+
+    pub fn size(&self) -> u64 {
+        read_u32(&self.raw, 4) as u64 | (read_u32(&self.raw, 108) as u64) << 32
+    }
+
+    pub fn set_size(&mut self, size: u64) {
+        write_u32(&mut self.raw, 4, size as u32);
+        write_u32(&mut self.raw, 108, (size >> 32) as u32);
+    }
+
+    pub fn atime(&self) -> u32 {
+        read_u32(&self.raw, 8)
+    }
+
+    pub fn set_atime(&mut self, time: u32) {
+        write_u32(&mut self.raw, 8, time)
+    }
+
+    pub fn ctime(&self) -> u32 {
+        read_u32(&self.raw, 12)
+    }
+
+    pub fn set_ctime(&mut self, time: u32) {
+        write_u32(&mut self.raw, 12, time)
+    }
+
+    pub fn mtime(&self) -> u32 {
+        read_u32(&self.raw, 16)
+    }
+
+    pub fn set_mtime(&mut self, time: u32) {
+        write_u32(&mut self.raw, 16, time)
+    }
+
+    pub fn set_dtime(&mut self, time: u32) {
+        write_u32(&mut self.raw, 20, time)
+    }
+
+    pub fn links_count(&self) -> u16 {
+        read_u16(&self.raw, 26)
+    }
+
+    pub fn set_links_count(&mut self, count: u16) {
+        write_u16(&mut self.raw, 26, count)
+    }
+
+    /// The number of 512-byte sectors allocated, including the metadata
+    /// blocks.
+    pub fn sectors(&self) -> u64 {
+        read_u32(&self.raw, 28) as u64 | (read_u16(&self.raw, 116) as u64) << 32
+    }
+
+    pub fn set_sectors(&mut self, sectors: u64) {
+        write_u32(&mut self.raw, 28, sectors as u32);
+        write_u16(&mut self.raw, 116, (sectors >> 32) as u16);
+    }
+
+    pub fn flags(&self) -> u32 {
+        read_u32(&self.raw, 32)
+    }
+
+    pub fn set_flags(&mut self, flags: u32) {
+        write_u32(&mut self.raw, 32, flags)
+    }
+
+    pub fn uses_extents(&self) -> bool {
+        self.flags() & INODE_EXTENTS_FL != 0
+    }
+
+    /// Returns whether the inode is a symbolic link whose target is stored in
+    /// `i_block` rather than a data block.
+    pub fn is_fast_symlink(&self) -> bool {
+        self.node_type() == VfsNodeType::SymLink
+            && self.size() < (N_BLOCKS * 4) as u64
+            && !self.uses_extents()
+    }
+
+    /// The `i_block` field, the block pointers or the root of the extent
+    /// tree.
+    pub fn block_area(&self) -> &[u8] {
+        &self.raw[40..40 + N_BLOCKS * 4]
+    }
+
+    pub fn block(&self, idx: usize) -> u32 {
+        read_u32(&self.raw, 40 + idx * 4)
+    }
+
+    pub fn set_block(&mut self, idx: usize, block: u32) {
+        write_u32(&mut self.raw, 40 + idx * 4, block)
+    }
+}
+
+/// A directory entry, whose name follows at the offset 8.
+pub struct RawDirEntry {
+    pub ino: u32,
+    pub rec_len: u16,
+    pub name_len: u16,
+    pub file_type: u8,
+}
+
+impl RawDirEntry {
+    /// The size of the header of a directory entry.
+    pub const HEADER_SIZE: usize = 8;
+
+    /// Parses the entry, `has_type` is whether the `filetype` feature is on,
+    /// otherwise the name length is 16-bit.
+    pub fn parse(buf: &[u8], has_type: bool) -> Self {
+        let (name_len, file_type) = if has_type {
+            (buf[6] as u16, buf[7])
+        } else {
+            (read_u16(buf, 6), 0)
+        };
+        Self {
+            ino: read_u32(buf, 0),
+            rec_len: read_u16(buf, 4),
+            name_len,
+            file_type,
+        }
+    }
+
+    pub fn write(&self, buf: &mut [u8], has_type: bool) {
+        write_u32(buf, 0, self.ino);
+        write_u16(buf, 4, self.rec_len);
+        if has_type {
+            buf[6] = self.name_len as u8;
+            buf[7] = self.file_type;
+        } else {
+            write_u16(buf, 6, self.name_len);
+        }
+    }
+
+    /// The size actually used by an entry with the name length.
+    pub const fn used_len(name_len: usize) -> usize {
+        (Self::HEADER_SIZE + name_len).next_multiple_of(4)
+    }
+}
+
+pub const fn mode_to_type(mode: u16) -> VfsNodeType {
+    match mode & 0xf000 {
+        0x1000 => VfsNodeType::Fifo,
+        0x2000 => VfsNodeType::CharDevice,
+        0x4000 => VfsNodeType::Dir,
+        0x6000 => VfsNodeType::BlockDevice,
+        0xa000 => VfsNodeType::SymLink,
+        0xc000 => VfsNodeType::Socket,
+        _ => VfsNodeType::File,
+    }
+}
+
+pub const fn type_to_mode(ty: VfsNodeType) -> u16 {
+    (ty as u16) << 12
+}
+
+/// The file type recorded in the directory entries.
+pub const fn type_to_dirent_type(ty: VfsNodeType) -> u8 {
+    match ty {
+        VfsNodeType::File => 1,
+        VfsNodeType::Dir => 2,
+        VfsNodeType::CharDevice => 3,
+        VfsNodeType::BlockDevice => 4,
+        VfsNodeType::Fifo => 5,
+        VfsNodeType::Socket => 6,
+        VfsNodeType::SymLink => 7,
+    }
+}
+
+pub const fn dirent_type_to_type(ty: u8) -> Option<VfsNodeType> {
+    match ty {
+        1 => Some(VfsNodeType::File),
+        2 => Some(VfsNodeType::Dir),
+        3 => Some(VfsNodeType::CharDevice),
+        4 => Some(VfsNodeType::BlockDevice),
+        5 => Some(VfsNodeType::Fifo),
+        6 => Some(VfsNodeType::Socket),
+        7 => Some(VfsNodeType::SymLink),
+        _ => None,
+    }
+}
@@ -0,0 +1,156 @@
+//! Creates an empty ext2 filesystem in a disk image, e.g., for the ramdisk.
+
+use alloc::vec;
+
+use axfs_vfs::{VfsError, VfsNodeType, VfsResult};
+
+use super::layout::*;
+
+const BLOCK_SIZE: usize = 1024;
+const INODE_SIZE: usize = 128;
+const DESC_SIZE: usize = 32;
+/// Each group has as many blocks as the bits in a bitmap block.
+const BLOCKS_PER_GROUP: usize = BLOCK_SIZE * 8;
+/// The inode numbers below this are reserved.
+const FIRST_INO: u32 = 11;
+
+fn block_mut(image: &mut [u8], block: usize) -> &mut [u8] {
+    &mut image[block * BLOCK_SIZE..(block + 1) * BLOCK_SIZE]
+}
+
+fn set_bits(bitmap: &mut [u8], bits: core::ops::Range<usize>) {
+    for i in bits {
+        bitmap[i / 8] |= 1 << (i % 8);
+    }
+}
+
+/// Formats the image as an ext2 filesystem with 1K blocks, and an empty
+/// root directory.
+///
+/// Every group has a copy of the superblock and the group descriptors.
+pub fn format(image: &mut [u8]) -> VfsResult {
+    let first_data_block = 1;
+    let mut blocks_count = image.len() / BLOCK_SIZE;
+    if blocks_count < 64 {
+        return Err(VfsError::InvalidInput);
+    }
+    let mut groups = (blocks_count - first_data_block).div_ceil(BLOCKS_PER_GROUP);
+    let gdt_blocks = (groups * DESC_SIZE).div_ceil(BLOCK_SIZE);
+    let inodes_per_group = ((blocks_count / groups / 4).next_multiple_of(8)).min(BLOCKS_PER_GROUP);
+    let inode_table_blocks = inodes_per_group * INODE_SIZE / BLOCK_SIZE;
+    // The superblock, the descriptors, the bitmaps and the inode table.
+    let overhead = 1 + gdt_blocks + 2 + inode_table_blocks;
+
+    // Drop the last group if it is too small to hold anything.
+    let last_group_blocks = blocks_count - first_data_block - (groups - 1) * BLOCKS_PER_GROUP;
+    if last_group_blocks < overhead + 16 {
+        if groups == 1 {
+            return Err(VfsError::InvalidInput);
+        }
+        groups -= 1;
+        blocks_count -= last_group_blocks;
+    }
+    image[..blocks_count * BLOCK_SIZE].fill(0);
+
+    let group_start = |g: usize| first_data_block + g * BLOCKS_PER_GROUP;
+    let group_blocks = |g: usize| (blocks_count - group_start(g)).min(BLOCKS_PER_GROUP);
+    let now = super::now();
+
+    // The root directory takes the first data block of group 0.
+    let root_block = group_start(0) + overhead;
+    let mut free_blocks = 0;
+    let mut gdt = vec![0u8; gdt_blocks * BLOCK_SIZE];
+    for g in 0..groups {
+        let start = group_start(g);
+        let block_bitmap = start + 1 + gdt_blocks;
+        let inode_bitmap = block_bitmap + 1;
+        let inode_table = inode_bitmap + 1;
+
+        let mut used_blocks = overhead;
+        let mut used_inodes = 0;
+        if g == 0 {
+            used_blocks += 1; // the root directory
+            used_inodes = FIRST_INO as usize - 1;
+        }
+        let bitmap = block_mut(image, block_bitmap);
+        set_bits(bitmap, 0..used_blocks);
+        // The bits beyond the end of the last group are set.
+        set_bits(bitmap, group_blocks(g)..BLOCKS_PER_GROUP);
+        let bitmap = block_mut(image, inode_bitmap);
+        set_bits(bitmap, 0..used_inodes);
+        set_bits(bitmap, inodes_per_group..BLOCK_SIZE * 8);
+
+        let free = group_blocks(g) - used_blocks;
+        free_blocks += free;
+        let desc = &mut gdt[g * DESC_SIZE..(g + 1) * DESC_SIZE];
+        write_u32(desc, 0x0, block_bitmap as u32);
+        write_u32(desc, 0x4, inode_bitmap as u32);
+        write_u32(desc, 0x8, inode_table as u32);
+        write_u16(desc, 0xc, free as u16);
+        write_u16(desc, 0xe, (inodes_per_group - used_inodes) as u16);
+        write_u16(desc, 0x10, if g == 0 { 1 } else { 0 });
+    }
+
+    let mut sb = [0u8; SUPERBLOCK_SIZE];
+    let inodes_count = groups * inodes_per_group;
+    write_u32(&mut sb, 0, inodes_count as u32);
+    write_u32(&mut sb, 4, blocks_count as u32);
+    write_u32(&mut sb, 12, free_blocks as u32);
+    write_u32(&mut sb, 16, (inodes_count - FIRST_INO as usize + 1) as u32);
+    write_u32(&mut sb, 20, first_data_block as u32);
+    write_u32(&mut sb, 24, 0); // log2(block size) - 10
+    write_u32(&mut sb, 28, 0); // log2(fragment size) - 10
+    write_u32(&mut sb, 32, BLOCKS_PER_GROUP as u32);
+    write_u32(&mut sb, 36, BLOCKS_PER_GROUP as u32); // fragments per group
+    write_u32(&mut sb, 40, inodes_per_group as u32);
+    write_u32(&mut sb, 48, now); // write time
+    write_u16(&mut sb, 54, u16::MAX); // max mount count, no check
+    write_u16(&mut sb, 56, EXT_MAGIC);
+    write_u16(&mut sb, 58, 1); // state: clean
+    write_u16(&mut sb, 60, 1); // errors: continue
+    write_u32(&mut sb, 64, now); // last check time
+    write_u32(&mut sb, 76, 1); // revision: dynamic
+    write_u32(&mut sb, 84, FIRST_INO);
+    write_u16(&mut sb, 88, INODE_SIZE as u16);
+    write_u32(&mut sb, 96, INCOMPAT_FILETYPE);
+    write_u32(&mut sb, 100, RO_COMPAT_LARGE_FILE);
+    sb[120..126].copy_from_slice(b"arceos"); // volume name
+
+    for g in 0..groups {
+        write_u16(&mut sb, 90, g as u16); // the group of this copy
+        let start = group_start(g);
+        let sb_pos = if g == 0 {
+            SUPERBLOCK_OFFSET as usize
+        } else {
+            start * BLOCK_SIZE
+        };
+        image[sb_pos..sb_pos + SUPERBLOCK_SIZE].copy_from_slice(&sb);
+        let gdt_pos = (start + 1) * BLOCK_SIZE;
+        image[gdt_pos..gdt_pos + gdt.len()].copy_from_slice(&gdt);
+    }
+
+    // The root directory, with `.` and `..` pointing to itself.
+    let inode_table = read_u32(&gdt, 0x8) as usize;
+    let mut root = Inode::new(INODE_SIZE, type_to_mode(VfsNodeType::Dir) | 0o755, now);
+    root.set_links_count(2);
+    root.set_size(BLOCK_SIZE as u64);
+    root.set_sectors((BLOCK_SIZE / 512) as u64);
+    root.set_block(0, root_block as u32);
+    let root_pos = inode_table * BLOCK_SIZE + (ROOT_INO as usize - 1) * INODE_SIZE;
+    image[root_pos..root_pos + INODE_SIZE].copy_from_slice(root.as_bytes());
+
+    let dir_block = block_mut(image, root_block);
+    let dot_len = RawDirEntry::used_len(1);
+    for (offset, name, rec_len) in [(0, ".", dot_len), (dot_len, "..", BLOCK_SIZE - dot_len)] {
+        let entry = RawDirEntry {
+            ino: ROOT_INO,
+            rec_len: rec_len as u16,
+            name_len: name.len() as u16,
+            file_type: type_to_dirent_type(VfsNodeType::Dir),
+        };
+        entry.write(&mut dir_block[offset..], true);
+        let name_offset = offset + RawDirEntry::HEADER_SIZE;
+        dir_block[name_offset..name_offset + name.len()].copy_from_slice(name.as_bytes());
+    }
+    Ok(())
+}
@@ -0,0 +1,406 @@
+//! The ext2 and ext4 filesystems.
+//!
+//! ext2 is read-write. The filesystems with the features not in ext2, such
+//! as ext4 with extents or checksums, are mounted read-only, and the journal
+//! is ignored. Nothing is cached, each operation reads and writes the disk
+//! directly.
+
+mod data;
+mod dir;
+mod layout;
+mod mkfs;
+mod node;
+
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use axfs_vfs::{VfsError, VfsNodeRef, VfsOps, VfsResult};
+use axsync::Mutex;
+
+use self::layout::*;
+use self::node::{DirNode, FileNode};
+use crate::dev::Disk;
+use crate::fops::FileTimes;
+use crate::lock::NodeKey;
+
+pub use self::mkfs::format;
+
+/// The shared state of a mounted filesystem.
+type VolumeRef = Arc<Mutex<Volume>>;
+
+/// An ext2 or ext4 filesystem on a disk.
+pub struct ExtFileSystem {
+    volume: VolumeRef,
+}
+
+impl ExtFileSystem {
+    /// Returns whether there is an ext2/3/4 filesystem on the disk, by the
+    /// magic number in the superblock.
+    pub fn probe(disk: &mut Disk) -> bool {
+        let mut buf = [0; SUPERBLOCK_SIZE];
+        read_disk(disk, SUPERBLOCK_OFFSET, &mut buf).is_ok()
+            && Superblock::from_bytes(&buf).magic() == EXT_MAGIC
+    }
+
+    /// Mounts the filesystem on the disk.
+    ///
+    /// Fails if the filesystem uses the features that can be neither read
+    /// nor written, e.g., inline data or encryption.
+    pub fn new(disk: Disk) -> VfsResult<Self> {
+        let volume = Volume::open(disk)?;
+        Ok(Self {
+            volume: Arc::new(Mutex::new(volume)),
+        })
+    }
+}
+
+impl VfsOps for ExtFileSystem {
+    fn root_dir(&self) -> VfsNodeRef {
+        Arc::new(DirNode::new(self.volume.clone(), ROOT_INO))
+    }
+}
+
+/// Returns the timestamps of `node` if it is on an ext2/4 filesystem.
+pub(crate) fn node_times(node: &VfsNodeRef) -> Option<FileTimes> {
+    let node = node.as_any();
+    let (volume, ino) = if let Some(file) = node.downcast_ref::<FileNode>() {
+        (&file.volume, file.ino)
+    } else if let Some(dir) = node.downcast_ref::<DirNode>() {
+        (&dir.volume, dir.ino)
+    } else {
+        return None;
+    };
+    let inode = volume.lock().read_inode(ino).ok()?;
+    let secs = |t: u32| Duration::from_secs(t as u64);
+    Some(FileTimes {
+        accessed: secs(inode.atime()),
+        modified: secs(inode.mtime()),
+        changed: secs(inode.ctime()),
+    })
+}
+
+/// Returns the identity of `node` if it is on an ext2/4 filesystem.
+pub(crate) fn node_key(node: &VfsNodeRef) -> Option<NodeKey> {
+    let node = node.as_any();
+    let (volume, ino) = if let Some(file) = node.downcast_ref::<FileNode>() {
+        (&file.volume, file.ino)
+    } else if let Some(dir) = node.downcast_ref::<DirNode>() {
+        (&dir.volume, dir.ino)
+    } else {
+        return None;
+    };
+    let volume = Arc::as_ptr(volume) as *const () as usize;
+    Some(NodeKey::Inode(volume, ino as u64))
+}
+
+/// Adds the entry `name` in the directory `dir` as a hard link to `node`.
+///
+/// Returns `None` if `dir` is not on an ext2/4 filesystem.
+pub(crate) fn link_node(dir: &VfsNodeRef, name: &str, node: &VfsNodeRef) -> Option<VfsResult> {
+    let dir = dir.as_any().downcast_ref::<DirNode>()?;
+    Some(match node.as_any().downcast_ref::<FileNode>() {
+        Some(file) if Arc::ptr_eq(&dir.volume, &file.volume) => {
+            dir.volume.lock().link(dir.ino, name, file.ino)
+        }
+        // The directories cannot be linked.
+        _ => Err(VfsError::PermissionDenied),
+    })
+}
+
+/// Returns the current time for the timestamps of the inodes.
+fn now() -> u32 {
+    axhal::time::wall_time().as_secs() as u32
+}
+
+fn read_disk(disk: &mut Disk, pos: u64, mut buf: &mut [u8]) -> VfsResult {
+    disk.set_position(pos);
+    while !buf.is_empty() {
+        match disk.read_one(buf) {
+            Ok(0) => return Err(VfsError::UnexpectedEof),
+            Ok(n) => {
+                let tmp = buf;
+                buf = &mut tmp[n..];
+            }
+            Err(_) => return Err(VfsError::Io),
+        }
+    }
+    Ok(())
+}
+
+fn write_disk(disk: &mut Disk, pos: u64, mut buf: &[u8]) -> VfsResult {
+    disk.set_position(pos);
+    while !buf.is_empty() {
+        match disk.write_one(buf) {
+            Ok(0) => return Err(VfsError::WriteZero),
+            Ok(n) => buf = &buf[n..],
+            Err(_) => return Err(VfsError::Io),
+        }
+    }
+    Ok(())
+}
+
+/// The mounted filesystem: the disk, the superblock and the group
+/// descriptors.
+pub(super) struct Volume {
+    disk: Disk,
+    sb: Superblock,
+    groups: Vec<GroupDesc>,
+    block_size: usize,
+    read_only: bool,
+}
+
+impl Volume {
+    fn open(mut disk: Disk) -> VfsResult<Self> {
+        let mut buf = [0; SUPERBLOCK_SIZE];
+        read_disk(&mut disk, SUPERBLOCK_OFFSET, &mut buf)?;
+        let sb = Superblock::from_bytes(&buf);
+        if !sb.is_valid() {
+            warn!("extfs: invalid superblock");
+            return Err(VfsError::InvalidData);
+        }
+
+        let incompat = sb.feature_incompat();
+        if incompat & !INCOMPAT_RO != 0 {
+            warn!(
+                "extfs: unsupported incompatible features {:#x}",
+                incompat & !INCOMPAT_RO
+            );
+            return Err(VfsError::Unsupported);
+        }
+        let read_only = incompat & !INCOMPAT_RW != 0 || sb.feature_ro_compat() & !RO_COMPAT_RW != 0;
+        if incompat & INCOMPAT_RECOVER != 0 {
+            warn!("extfs: the journal is not replayed, the files may be stale");
+        }
+
+        let block_size = sb.block_size();
+        let desc_size = sb.desc_size();
+        let groups_count = sb.groups_count() as usize;
+        let gdt_block = sb.first_data_block() as u64 + 1;
+        let mut gdt = vec![0; groups_count * desc_size];
+        read_disk(&mut disk, gdt_block * block_size as u64, &mut gdt)?;
+        let groups = gdt
+            .chunks_exact(desc_size)
+            .map(GroupDesc::from_bytes)
+            .collect();
+
+        info!(
+            "extfs: {} blocks of {} bytes, {} inodes, {}",
+            sb.blocks_count(),
+            block_size,
+            sb.inodes_count(),
+            if read_only { "read-only" } else { "read-write" }
+        );
+        Ok(Self {
+            disk,
+            sb,
+            groups,
+            block_size,
+            read_only,
+        })
+    }
+
+    /// Writes back the cached blocks of the disk.
+    pub(super) fn sync(&mut self) -> VfsResult {
+        self.disk.sync().map_err(|_| VfsError::Io)
+    }
+
+    fn check_writable(&self) -> VfsResult {
+        if self.read_only {
+            Err(VfsError::PermissionDenied)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn has_dirent_type(&self) -> bool {
+        self.sb.feature_incompat() & INCOMPAT_FILETYPE != 0
+    }
+
+    fn read_bytes(&mut self, pos: u64, buf: &mut [u8]) -> VfsResult {
+        read_disk(&mut self.disk, pos, buf)
+    }
+
+    fn write_bytes(&mut self, pos: u64, buf: &[u8]) -> VfsResult {
+        write_disk(&mut self.disk, pos, buf)
+    }
+
+    fn read_block(&mut self, block: u64, buf: &mut [u8]) -> VfsResult {
+        self.read_bytes(block * self.block_size as u64, buf)
+    }
+
+    fn write_block(&mut self, block: u64, buf: &[u8]) -> VfsResult {
+        self.write_bytes(block * self.block_size as u64, buf)
+    }
+
+    fn inode_pos(&self, ino: u32) -> VfsResult<u64> {
+        if ino == 0 || ino > self.sb.inodes_count() {
+            return Err(VfsError::InvalidData);
+        }
+        let ipg = self.sb.inodes_per_group();
+        let group = &self.groups[((ino - 1) / ipg) as usize];
+        let index = ((ino - 1) % ipg) as u64;
+        Ok(group.inode_table() * self.block_size as u64 + index * self.sb.inode_size() as u64)
+    }
+
+    fn read_inode(&mut self, ino: u32) -> VfsResult<Inode> {
+        let pos = self.inode_pos(ino)?;
+        let mut buf = vec![0; self.sb.inode_size()];
+        self.read_bytes(pos, &mut buf)?;
+        Ok(Inode::from_bytes(&buf))
+    }
+
+    fn write_inode(&mut self, ino: u32, inode: &Inode) -> VfsResult {
+        let pos = self.inode_pos(ino)?;
+        self.write_bytes(pos, inode.as_bytes())
+    }
+
+    /// Writes back the superblock and the group descriptor `group`, after
+    /// the free counts are changed.
+    ///
+    /// The backups in other groups are left for `fsck`, as Linux does.
+    fn sync_group(&mut self, group: usize) -> VfsResult {
+        let sb = self.sb.as_bytes().to_vec();
+        self.write_bytes(SUPERBLOCK_OFFSET, &sb)?;
+        let desc_size = self.sb.desc_size();
+        let gdt_block = self.sb.first_data_block() as u64 + 1;
+        let pos = gdt_block * self.block_size as u64 + (group * desc_size) as u64;
+        let desc = self.groups[group].as_bytes().to_vec();
+        self.write_bytes(pos, &desc)
+    }
+
+    /// Finds a zero bit in the bitmap block, sets it and returns its index.
+    fn alloc_bit(&mut self, bitmap: u64, limit: u32) -> VfsResult<Option<u32>> {
+        let mut buf = vec![0u8; self.block_size];
+        self.read_block(bitmap, &mut buf)?;
+        let found = (0..limit).find(|&i| buf[i as usize / 8] & (1 << (i % 8)) == 0);
+        if let Some(i) = found {
+            buf[i as usize / 8] |= 1 << (i % 8);
+            self.write_block(bitmap, &buf)?;
+        }
+        Ok(found)
+    }
+
+    /// Clears the bit in the bitmap block.
+    fn free_bit(&mut self, bitmap: u64, i: u32) -> VfsResult {
+        let mut buf = vec![0u8; self.block_size];
+        self.read_block(bitmap, &mut buf)?;
+        if buf[i as usize / 8] & (1 << (i % 8)) == 0 {
+            warn!("extfs: freeing a free object {} in bitmap {}", i, bitmap);
+            return Err(VfsError::InvalidData);
+        }
+        buf[i as usize / 8] &= !(1 << (i % 8));
+        self.write_block(bitmap, &buf)
+    }
+
+    /// Allocates a zeroed block, preferring the group `goal`.
+    fn alloc_block(&mut self, goal: usize) -> VfsResult<u64> {
+        let groups_count = self.groups.len();
+        let bpg = self.sb.blocks_per_group();
+        let first = self.sb.first_data_block() as u64;
+        for group in (0..groups_count).map(|i| (goal + i) % groups_count) {
+            if self.groups[group].free_blocks_count() == 0 {
+                continue;
+            }
+            let group_start = first + group as u64 * bpg as u64;
+            let limit = (self.sb.blocks_count() - group_start).min(bpg as u64) as u32;
+            let bitmap = self.groups[group].block_bitmap();
+            if let Some(i) = self.alloc_bit(bitmap, limit)? {
+                let desc = &mut self.groups[group];
+                desc.set_free_blocks_count(desc.free_blocks_count() - 1);
+                let free = self.sb.free_blocks_count();
+                self.sb.set_free_blocks_count(free.saturating_sub(1));
+                self.sync_group(group)?;
+
+                let block = group_start + i as u64;
+                let zeros = vec![0; self.block_size];
+                self.write_block(block, &zeros)?;
+                return Ok(block);
+            }
+        }
+        Err(VfsError::StorageFull)
+    }
+
+    fn free_block(&mut self, block: u64) -> VfsResult {
+        let first = self.sb.first_data_block() as u64;
+        let bpg = self.sb.blocks_per_group() as u64;
+        if block < first || block >= self.sb.blocks_count() {
+            return Err(VfsError::InvalidData);
+        }
+        let group = ((block - first) / bpg) as usize;
+        let bitmap = self.groups[group].block_bitmap();
+        self.free_bit(bitmap, ((block - first) % bpg) as u32)?;
+        let desc = &mut self.groups[group];
+        desc.set_free_blocks_count(desc.free_blocks_count() + 1);
+        let free = self.sb.free_blocks_count();
+        self.sb.set_free_blocks_count(free + 1);
+        self.sync_group(group)
+    }
+
+    /// Allocates an inode, preferring the group `goal`.
+    fn alloc_inode(&mut self, goal: usize, is_dir: bool) -> VfsResult<u32> {
+        let groups_count = self.groups.len();
+        let ipg = self.sb.inodes_per_group();
+        for group in (0..groups_count).map(|i| (goal + i) % groups_count) {
+            if self.groups[group].free_inodes_count() == 0 {
+                continue;
+            }
+            let bitmap = self.groups[group].inode_bitmap();
+            if let Some(i) = self.alloc_bit(bitmap, ipg)? {
+                let desc = &mut self.groups[group];
+                desc.set_free_inodes_count(desc.free_inodes_count() - 1);
+                if is_dir {
+                    desc.set_used_dirs_count(desc.used_dirs_count() + 1);
+                }
+                let free = self.sb.free_inodes_count();
+                self.sb.set_free_inodes_count(free.saturating_sub(1));
+                self.sync_group(group)?;
+                return Ok(group as u32 * ipg + i + 1);
+            }
+        }
+        Err(VfsError::StorageFull)
+    }
+
+    fn free_inode(&mut self, ino: u32, is_dir: bool) -> VfsResult {
+        let ipg = self.sb.inodes_per_group();
+        let group = ((ino - 1) / ipg) as usize;
+        let bitmap = self.groups[group].inode_bitmap();
+        self.free_bit(bitmap, (ino - 1) % ipg)?;
+        let desc = &mut self.groups[group];
+        desc.set_free_inodes_count(desc.free_inodes_count() + 1);
+        if is_dir {
+            desc.set_used_dirs_count(desc.used_dirs_count().saturating_sub(1));
+        }
+        let free = self.sb.free_inodes_count();
+        self.sb.set_free_inodes_count(free + 1);
+        self.sync_group(group)
+    }
+
+    /// The group of the inode, where its blocks and children are preferably
+    /// allocated.
+    fn group_of(&self, ino: u32) -> usize {
+        ((ino - 1) / self.sb.inodes_per_group()) as usize
+    }
+
+    /// Releases the data and the inode whose last link is removed.
+    fn release_inode(&mut self, ino: u32, mut inode: Inode) -> VfsResult {
+        let is_dir = inode.is_dir();
+        if !inode.is_fast_symlink() {
+            self.truncate(&mut inode, 0)?;
+        }
+        inode.set_links_count(0);
+        inode.set_dtime(now());
+        self.write_inode(ino, &inode)?;
+        self.free_inode(ino, is_dir)
+    }
+}
+
+/// Creates the node for the inode, according to its type.
+fn new_node(volume: VolumeRef, ino: u32, is_dir: bool) -> VfsNodeRef {
+    if is_dir {
+        Arc::new(DirNode::new(volume, ino))
+    } else {
+        Arc::new(FileNode::new(volume, ino))
+    }
+}
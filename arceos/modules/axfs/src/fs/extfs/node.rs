@@ -0,0 +1,357 @@
+//! The files and directories of ext2/ext4 as VFS nodes.
+
+use alloc::sync::Arc;
+
+use axfs_vfs::{VfsDirEntry, VfsError, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef};
+use axfs_vfs::{VfsNodeType, VfsResult};
+
+use super::layout::*;
+use super::{new_node, now, Volume, VolumeRef};
+
+/// A regular file, or any other non-directory inode.
+pub struct FileNode {
+    pub(super) volume: VolumeRef,
+    pub(super) ino: u32,
+}
+
+/// A directory.
+pub struct DirNode {
+    pub(super) volume: VolumeRef,
+    pub(super) ino: u32,
+}
+
+/// Splits the path into the parent path and the last component.
+fn split_path(path: &str) -> (&str, &str) {
+    let path = path.trim_matches('/');
+    path.rsplit_once('/').unwrap_or(("", path))
+}
+
+fn attr_of(inode: &Inode) -> VfsNodeAttr {
+    VfsNodeAttr::new(
+        VfsNodePerm::from_bits_truncate(inode.mode() & 0o777),
+        inode.node_type(),
+        inode.size(),
+        inode.sectors(),
+    )
+}
+
+impl Volume {
+    /// Follows the path from the directory `ino`, returns the inode number
+    /// it leads to.
+    fn walk(&mut self, mut ino: u32, path: &str) -> VfsResult<u32> {
+        for name in path.split('/').filter(|&s| !s.is_empty() && s != ".") {
+            let dir = self.read_inode(ino)?;
+            if !dir.is_dir() {
+                return Err(VfsError::NotADirectory);
+            }
+            ino = self.find_entry(&dir, name)?.ok_or(VfsError::NotFound)?;
+        }
+        Ok(ino)
+    }
+
+    /// Walks to the parent directory of the path, returns its inode number
+    /// and the last component.
+    fn walk_parent<'a>(&mut self, ino: u32, path: &'a str) -> VfsResult<(u32, Inode, &'a str)> {
+        let (parent, name) = split_path(path);
+        let dir_ino = self.walk(ino, parent)?;
+        let dir = self.read_inode(dir_ino)?;
+        if !dir.is_dir() {
+            return Err(VfsError::NotADirectory);
+        }
+        Ok((dir_ino, dir, name))
+    }
+
+    fn add_links(&mut self, ino: u32, delta: i32) -> VfsResult {
+        let mut inode = self.read_inode(ino)?;
+        let links = (inode.links_count() as i32 + delta).max(0);
+        inode.set_links_count(links as u16);
+        inode.set_ctime(now());
+        self.write_inode(ino, &inode)
+    }
+
+    /// Creates a file or a directory named `name` in the directory `dir_ino`.
+    fn create_node(&mut self, dir_ino: u32, name: &str, ty: VfsNodeType) -> VfsResult {
+        let mode = match ty {
+            VfsNodeType::File => 0o644,
+            VfsNodeType::Dir => 0o755,
+            VfsNodeType::SymLink => 0o777,
+            _ => return Err(VfsError::Unsupported),
+        };
+        let is_dir = ty == VfsNodeType::Dir;
+        let goal = self.group_of(dir_ino);
+        let ino = self.alloc_inode(goal, is_dir)?;
+        let mut inode = Inode::new(self.sb.inode_size(), type_to_mode(ty) | mode, now());
+        let res = if is_dir {
+            inode.set_links_count(2);
+            self.add_entry(ino, &mut inode, ".", ino, VfsNodeType::Dir)
+                .and_then(|_| self.add_entry(ino, &mut inode, "..", dir_ino, VfsNodeType::Dir))
+                .and_then(|_| self.add_links(dir_ino, 1))
+        } else {
+            inode.set_links_count(1);
+            self.write_inode(ino, &inode)
+        };
+        let res = res.and_then(|_| {
+            let mut dir = self.read_inode(dir_ino)?;
+            self.add_entry(dir_ino, &mut dir, name, ino, ty)
+        });
+        if res.is_err() {
+            if is_dir {
+                self.add_links(dir_ino, -1).ok();
+            }
+            self.release_inode(ino, inode).ok();
+        }
+        res
+    }
+
+    /// Removes the entry named `name` in the directory `dir_ino`, and the
+    /// inode if it is the last link.
+    fn unlink(&mut self, dir_ino: u32, name: &str) -> VfsResult {
+        let mut dir = self.read_inode(dir_ino)?;
+        let ino = self.find_entry(&dir, name)?.ok_or(VfsError::NotFound)?;
+        let mut inode = self.read_inode(ino)?;
+        if inode.is_dir() && !self.is_dir_empty(&inode)? {
+            return Err(VfsError::DirectoryNotEmpty);
+        }
+        self.remove_entry(dir_ino, &mut dir, name)?;
+        if inode.is_dir() {
+            // Drop the link of `..` in the removed directory.
+            self.add_links(dir_ino, -1)?;
+            return self.release_inode(ino, inode);
+        }
+        match inode.links_count() {
+            0 | 1 => self.release_inode(ino, inode),
+            links => {
+                inode.set_links_count(links - 1);
+                inode.set_ctime(now());
+                self.write_inode(ino, &inode)
+            }
+        }
+    }
+
+    /// Adds the entry `name` in the directory `dir_ino` for the existing
+    /// inode `ino`.
+    pub(super) fn link(&mut self, dir_ino: u32, name: &str, ino: u32) -> VfsResult {
+        let mut dir = self.read_inode(dir_ino)?;
+        if self.find_entry(&dir, name)?.is_some() {
+            return Err(VfsError::AlreadyExists);
+        }
+        let inode = self.read_inode(ino)?;
+        if inode.links_count() == u16::MAX {
+            return Err(VfsError::StorageFull);
+        }
+        self.add_entry(dir_ino, &mut dir, name, ino, inode.node_type())?;
+        self.add_links(ino, 1)
+    }
+
+    /// Returns whether the directory `ino` is `ancestor` or inside it.
+    fn is_inside(&mut self, mut ino: u32, ancestor: u32) -> VfsResult<bool> {
+        loop {
+            if ino == ancestor {
+                return Ok(true);
+            }
+            if ino == ROOT_INO {
+                return Ok(false);
+            }
+            ino = self.walk(ino, "..")?;
+        }
+    }
+
+    fn rename(&mut self, root: u32, src_path: &str, dst_path: &str) -> VfsResult {
+        let (src_dir_ino, src_dir, src_name) = self.walk_parent(root, src_path)?;
+        let ino = self
+            .find_entry(&src_dir, src_name)?
+            .ok_or(VfsError::NotFound)?;
+        let inode = self.read_inode(ino)?;
+        let (dst_dir_ino, dst_dir, dst_name) = self.walk_parent(root, dst_path)?;
+        if matches!(src_name, "" | "." | "..") || matches!(dst_name, "" | "." | "..") {
+            return Err(VfsError::InvalidInput);
+        }
+        if inode.is_dir() && self.is_inside(dst_dir_ino, ino)? {
+            return Err(VfsError::InvalidInput);
+        }
+
+        if let Some(old) = self.find_entry(&dst_dir, dst_name)? {
+            if old == ino {
+                return Ok(());
+            }
+            let old_inode = self.read_inode(old)?;
+            match (inode.is_dir(), old_inode.is_dir()) {
+                (true, false) => return Err(VfsError::NotADirectory),
+                (false, true) => return Err(VfsError::IsADirectory),
+                _ => self.unlink(dst_dir_ino, dst_name)?,
+            }
+        }
+
+        let mut dst_dir = self.read_inode(dst_dir_ino)?;
+        self.add_entry(dst_dir_ino, &mut dst_dir, dst_name, ino, inode.node_type())?;
+        let mut src_dir = self.read_inode(src_dir_ino)?;
+        self.remove_entry(src_dir_ino, &mut src_dir, src_name)?;
+        if inode.is_dir() && src_dir_ino != dst_dir_ino {
+            self.set_entry(&inode, "..", dst_dir_ino)?;
+            self.add_links(src_dir_ino, -1)?;
+            self.add_links(dst_dir_ino, 1)?;
+        }
+        // Only updates the status change time.
+        self.add_links(ino, 0)
+    }
+
+    /// Writes the target of a symbolic link in `i_block` if it fits, or
+    /// moves it to a data block otherwise.
+    fn write_fast_symlink(
+        &mut self,
+        ino: u32,
+        inode: &mut Inode,
+        offset: u64,
+        buf: &[u8],
+    ) -> VfsResult<usize> {
+        self.check_writable()?;
+        let mut target = inode.block_area()[..inode.size() as usize].to_vec();
+        let end = offset as usize + buf.len();
+        if end > target.len() {
+            target.resize(end, 0);
+        }
+        target[offset as usize..end].copy_from_slice(buf);
+
+        let mut area = [0; N_BLOCKS * 4];
+        let fits = target.len() < area.len();
+        if fits {
+            area[..target.len()].copy_from_slice(&target);
+        }
+        for (idx, ptr) in area.chunks(4).enumerate() {
+            inode.set_block(idx, u32::from_le_bytes(ptr.try_into().unwrap()));
+        }
+        let res = if fits {
+            inode.set_size(target.len() as u64);
+            let now = now();
+            inode.set_mtime(now);
+            inode.set_ctime(now);
+            Ok(buf.len())
+        } else {
+            // The block pointers are cleared above.
+            inode.set_size(0);
+            self.write_data(ino, inode, 0, &target).map(|_| buf.len())
+        };
+        self.write_inode(ino, inode)?;
+        res
+    }
+}
+
+impl FileNode {
+    pub(super) fn new(volume: VolumeRef, ino: u32) -> Self {
+        Self { volume, ino }
+    }
+}
+
+impl VfsNodeOps for FileNode {
+    axfs_vfs::impl_vfs_non_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let inode = self.volume.lock().read_inode(self.ino)?;
+        Ok(attr_of(&inode))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let mut volume = self.volume.lock();
+        let inode = volume.read_inode(self.ino)?;
+        volume.read_data(&inode, offset, buf)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let mut volume = self.volume.lock();
+        let mut inode = volume.read_inode(self.ino)?;
+        if inode.is_fast_symlink() {
+            return volume.write_fast_symlink(self.ino, &mut inode, offset, buf);
+        }
+        let res = volume.write_data(self.ino, &mut inode, offset, buf);
+        // The blocks allocated before an error are recorded as well.
+        volume.write_inode(self.ino, &inode)?;
+        res
+    }
+
+    fn truncate(&self, size: u64) -> VfsResult {
+        let mut volume = self.volume.lock();
+        let mut inode = volume.read_inode(self.ino)?;
+        volume.truncate(&mut inode, size)?;
+        volume.write_inode(self.ino, &inode)
+    }
+
+    fn fsync(&self) -> VfsResult {
+        // The inodes are written to the disk cache directly.
+        self.volume.lock().sync()
+    }
+}
+
+impl DirNode {
+    pub(super) fn new(volume: VolumeRef, ino: u32) -> Self {
+        Self { volume, ino }
+    }
+}
+
+impl VfsNodeOps for DirNode {
+    axfs_vfs::impl_vfs_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let inode = self.volume.lock().read_inode(self.ino)?;
+        Ok(attr_of(&inode))
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        if self.ino == ROOT_INO {
+            return None;
+        }
+        let ino = self.volume.lock().walk(self.ino, "..").ok()?;
+        Some(Arc::new(DirNode::new(self.volume.clone(), ino)))
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        debug!("lookup at extfs: {}", path);
+        let mut volume = self.volume.lock();
+        let ino = volume.walk(self.ino, path)?;
+        let is_dir = volume.read_inode(ino)?.is_dir();
+        Ok(new_node(self.volume.clone(), ino, is_dir))
+    }
+
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        debug!("create {:?} at extfs: {}", ty, path);
+        let mut volume = self.volume.lock();
+        volume.check_writable()?;
+        let (dir_ino, dir, name) = volume.walk_parent(self.ino, path)?;
+        if name.is_empty() || name == "." {
+            return Ok(());
+        }
+        if volume.find_entry(&dir, name)?.is_some() {
+            return Err(VfsError::AlreadyExists);
+        }
+        volume.create_node(dir_ino, name, ty)
+    }
+
+    fn remove(&self, path: &str) -> VfsResult {
+        debug!("remove at extfs: {}", path);
+        let mut volume = self.volume.lock();
+        volume.check_writable()?;
+        let (dir_ino, _, name) = volume.walk_parent(self.ino, path)?;
+        if matches!(name, "" | "." | "..") {
+            return Err(VfsError::InvalidInput);
+        }
+        volume.unlink(dir_ino, name)
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let mut volume = self.volume.lock();
+        let dir = volume.read_inode(self.ino)?;
+        let entries = volume.read_dir_entries(&dir)?;
+        let mut count = 0;
+        for (entry, out) in entries.iter().skip(start_idx).zip(dirents.iter_mut()) {
+            *out = VfsDirEntry::new(&entry.name, entry.ty);
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        debug!("rename at extfs: {} -> {}", src_path, dst_path);
+        let mut volume = self.volume.lock();
+        volume.check_writable()?;
+        volume.rename(self.ino, src_path, dst_path)
+    }
+}
@@ -1,22 +1,44 @@
+use alloc::string::{String, ToString};
 use alloc::sync::Arc;
 use core::cell::UnsafeCell;
+use core::time::Duration;
 
 use axfs_vfs::{VfsDirEntry, VfsError, VfsNodePerm, VfsResult};
 use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps};
 use axsync::Mutex;
-use fatfs::{Dir, File, LossyOemCpConverter, NullTimeProvider, Read, Seek, SeekFrom, Write};
+use fatfs::{Date, DateTime, LossyOemCpConverter, Read, Seek, SeekFrom, Time, Write};
 
 use crate::dev::Disk;
+use crate::fops::FileTimes;
 
 const BLOCK_SIZE: usize = 512;
 
+type Dir<'a> = fatfs::Dir<'a, Disk, AxTimeProvider, LossyOemCpConverter>;
+type File<'a> = fatfs::File<'a, Disk, AxTimeProvider, LossyOemCpConverter>;
+
 pub struct FatFileSystem {
-    inner: fatfs::FileSystem<Disk, NullTimeProvider, LossyOemCpConverter>,
+    inner: fatfs::FileSystem<Disk, AxTimeProvider, LossyOemCpConverter>,
     root_dir: UnsafeCell<Option<VfsNodeRef>>,
 }
 
-pub struct FileWrapper<'a>(Mutex<File<'a, Disk, NullTimeProvider, LossyOemCpConverter>>);
-pub struct DirWrapper<'a>(Dir<'a, Disk, NullTimeProvider, LossyOemCpConverter>);
+/// The directory entry of a node, i.e., its parent directory and its name,
+/// where the timestamps are stored.
+///
+/// It is unknown for the root directory and the directories opened by `..`.
+struct Entry<'a> {
+    parent: Dir<'a>,
+    name: String,
+}
+
+pub struct FileWrapper<'a> {
+    file: Mutex<File<'a>>,
+    entry: Option<Entry<'a>>,
+}
+
+pub struct DirWrapper<'a> {
+    dir: Dir<'a>,
+    entry: Option<Entry<'a>>,
+}
 
 unsafe impl Sync for FatFileSystem {}
 unsafe impl Send for FatFileSystem {}
@@ -30,7 +52,7 @@ impl FatFileSystem {
     pub fn new(mut disk: Disk) -> Self {
         let opts = fatfs::FormatVolumeOptions::new();
         fatfs::format_volume(&mut disk, opts).expect("failed to format volume");
-        let inner = fatfs::FileSystem::new(disk, fatfs::FsOptions::new())
+        let inner = fatfs::FileSystem::new(disk, Self::options())
             .expect("failed to initialize FAT filesystem");
         Self {
             inner,
@@ -40,7 +62,7 @@ impl FatFileSystem {
 
     #[cfg(not(feature = "use-ramdisk"))]
     pub fn new(disk: Disk) -> Self {
-        let inner = fatfs::FileSystem::new(disk, fatfs::FsOptions::new())
+        let inner = fatfs::FileSystem::new(disk, Self::options())
             .expect("failed to initialize FAT filesystem");
         Self {
             inner,
@@ -48,25 +70,76 @@ impl FatFileSystem {
         }
     }
 
+    fn options() -> fatfs::FsOptions<AxTimeProvider, LossyOemCpConverter> {
+        fatfs::FsOptions::new()
+            .time_provider(AxTimeProvider)
+            .update_accessed_date(true)
+    }
+
     pub fn init(&'static self) {
         // must be called before later operations
-        unsafe { *self.root_dir.get() = Some(Self::new_dir(self.inner.root_dir())) }
+        unsafe { *self.root_dir.get() = Some(Self::new_dir(self.inner.root_dir(), None)) }
     }
 
-    fn new_file(file: File<'_, Disk, NullTimeProvider, LossyOemCpConverter>) -> Arc<FileWrapper> {
-        Arc::new(FileWrapper(Mutex::new(file)))
+    fn new_file<'a>(file: File<'a>, entry: Option<Entry<'a>>) -> Arc<FileWrapper<'a>> {
+        Arc::new(FileWrapper {
+            file: Mutex::new(file),
+            entry,
+        })
     }
 
-    fn new_dir(dir: Dir<'_, Disk, NullTimeProvider, LossyOemCpConverter>) -> Arc<DirWrapper> {
-        Arc::new(DirWrapper(dir))
+    fn new_dir<'a>(dir: Dir<'a>, entry: Option<Entry<'a>>) -> Arc<DirWrapper<'a>> {
+        Arc::new(DirWrapper { dir, entry })
     }
 }
 
+impl Entry<'_> {
+    /// Reads the timestamps from the directory entry.
+    fn times(&self) -> Option<FileTimes> {
+        let entry = self.parent.iter().filter_map(Result::ok).find(|e| {
+            e.file_name().eq_ignore_ascii_case(&self.name)
+                || e.short_file_name().eq_ignore_ascii_case(&self.name)
+        })?;
+        let modified = date_time_to_epoch(entry.modified());
+        Some(FileTimes {
+            accessed: date_to_epoch(entry.accessed()),
+            modified,
+            // FAT has no status change time, use the modification time as
+            // Linux does.
+            changed: modified,
+        })
+    }
+}
+
+/// Returns the timestamps of `node` if it is on a FAT filesystem.
+pub(crate) fn node_times(node: &VfsNodeRef) -> Option<FileTimes> {
+    let node = node.as_any();
+    if let Some(file) = node.downcast_ref::<FileWrapper<'static>>() {
+        // The directory entry is updated when the file is flushed.
+        file.file.lock().flush().ok()?;
+        file.entry.as_ref()?.times()
+    } else if let Some(dir) = node.downcast_ref::<DirWrapper<'static>>() {
+        dir.entry.as_ref()?.times()
+    } else {
+        None
+    }
+}
+
+/// Returns whether `node` is on a FAT filesystem.
+pub(crate) fn is_fat_node(node: &VfsNodeRef) -> bool {
+    let node = node.as_any();
+    node.is::<FileWrapper<'static>>() || node.is::<DirWrapper<'static>>()
+}
+
 impl VfsNodeOps for FileWrapper<'static> {
     axfs_vfs::impl_vfs_non_dir_default! {}
 
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        let size = self.0.lock().seek(SeekFrom::End(0)).map_err(as_vfs_err)?;
+        let size = self
+            .file
+            .lock()
+            .seek(SeekFrom::End(0))
+            .map_err(as_vfs_err)?;
         let blocks = (size + BLOCK_SIZE as u64 - 1) / BLOCK_SIZE as u64;
         // FAT fs doesn't support permissions, we just set everything to 755
         let perm = VfsNodePerm::from_bits_truncate(0o755);
@@ -74,22 +147,27 @@ impl VfsNodeOps for FileWrapper<'static> {
     }
 
     fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
-        let mut file = self.0.lock();
+        let mut file = self.file.lock();
         file.seek(SeekFrom::Start(offset)).map_err(as_vfs_err)?; // TODO: more efficient
         file.read(buf).map_err(as_vfs_err)
     }
 
     fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
-        let mut file = self.0.lock();
+        let mut file = self.file.lock();
         file.seek(SeekFrom::Start(offset)).map_err(as_vfs_err)?; // TODO: more efficient
         file.write(buf).map_err(as_vfs_err)
     }
 
     fn truncate(&self, size: u64) -> VfsResult {
-        let mut file = self.0.lock();
+        let mut file = self.file.lock();
         file.seek(SeekFrom::Start(size)).map_err(as_vfs_err)?; // TODO: more efficient
         file.truncate().map_err(as_vfs_err)
     }
+
+    fn fsync(&self) -> VfsResult {
+        // Updates the directory entry, and writes back the disk.
+        self.file.lock().flush().map_err(as_vfs_err)
+    }
 }
 
 impl VfsNodeOps for DirWrapper<'static> {
@@ -106,9 +184,9 @@ impl VfsNodeOps for DirWrapper<'static> {
     }
 
     fn parent(&self) -> Option<VfsNodeRef> {
-        self.0
+        self.dir
             .open_dir("..")
-            .map_or(None, |dir| Some(FatFileSystem::new_dir(dir)))
+            .map_or(None, |dir| Some(FatFileSystem::new_dir(dir, None)))
     }
 
     fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
@@ -122,10 +200,21 @@ impl VfsNodeOps for DirWrapper<'static> {
         }
 
         // TODO: use `fatfs::Dir::find_entry`, but it's not public.
-        if let Ok(file) = self.0.open_file(path) {
-            Ok(FatFileSystem::new_file(file))
-        } else if let Ok(dir) = self.0.open_dir(path) {
-            Ok(FatFileSystem::new_dir(dir))
+        let entry = |name: &str| {
+            let parent = match path.rsplit_once('/') {
+                Some((parent, _)) => self.dir.open_dir(parent).ok()?,
+                None => self.dir.clone(),
+            };
+            Some(Entry {
+                parent,
+                name: name.to_string(),
+            })
+        };
+        let name = path.rsplit('/').next().unwrap_or(path);
+        if let Ok(file) = self.dir.open_file(path) {
+            Ok(FatFileSystem::new_file(file, entry(name)))
+        } else if let Ok(dir) = self.dir.open_dir(path) {
+            Ok(FatFileSystem::new_dir(dir, entry(name)))
         } else {
             Err(VfsError::NotFound)
         }
@@ -143,11 +232,11 @@ impl VfsNodeOps for DirWrapper<'static> {
 
         match ty {
             VfsNodeType::File => {
-                self.0.create_file(path).map_err(as_vfs_err)?;
+                self.dir.create_file(path).map_err(as_vfs_err)?;
                 Ok(())
             }
             VfsNodeType::Dir => {
-                self.0.create_dir(path).map_err(as_vfs_err)?;
+                self.dir.create_dir(path).map_err(as_vfs_err)?;
                 Ok(())
             }
             _ => Err(VfsError::Unsupported),
@@ -161,11 +250,11 @@ impl VfsNodeOps for DirWrapper<'static> {
         if let Some(rest) = path.strip_prefix("./") {
             return self.remove(rest);
         }
-        self.0.remove(path).map_err(as_vfs_err)
+        self.dir.remove(path).map_err(as_vfs_err)
     }
 
     fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
-        let mut iter = self.0.iter().skip(start_idx);
+        let mut iter = self.dir.iter().skip(start_idx);
         for (i, out_entry) in dirents.iter_mut().enumerate() {
             let x = iter.next();
             match x {
@@ -192,8 +281,8 @@ impl VfsNodeOps for DirWrapper<'static> {
             src_path, dst_path
         );
 
-        self.0
-            .rename(src_path, &self.0, dst_path)
+        self.dir
+            .rename(src_path, &self.dir, dst_path)
             .map_err(as_vfs_err)
     }
 }
@@ -243,7 +332,7 @@ impl Write for Disk {
         Ok(write_len)
     }
     fn flush(&mut self) -> Result<(), Self::Error> {
-        Ok(())
+        self.sync().map_err(|_| ())
     }
 }
 
@@ -264,6 +353,86 @@ impl Seek for Disk {
     }
 }
 
+/// Provides the current time to fatfs from [`axhal::time::wall_time`], which
+/// is read from the RTC at boot if the `rtc` feature of `axhal` is enabled.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AxTimeProvider;
+
+impl fatfs::TimeProvider for AxTimeProvider {
+    fn get_current_date(&self) -> Date {
+        self.get_current_date_time().date
+    }
+
+    fn get_current_date_time(&self) -> DateTime {
+        epoch_to_date_time(axhal::time::wall_time())
+    }
+}
+
+/// The earliest and the latest time that FAT can record, 1980-01-01 and
+/// 2107-12-31 23:59:59, in seconds since the UNIX epoch.
+const FAT_TIME_RANGE: (u64, u64) = (315_532_800, 4_354_819_199);
+
+/// Returns the number of days since 1970-01-01 of a date in the Gregorian
+/// calendar.
+const fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let yoe = year - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Returns the date in the Gregorian calendar `days` after 1970-01-01, as
+/// `(year, month, day)`.
+const fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let days = days + 719468;
+    let era = days / 146097;
+    let doe = days - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn date_to_epoch(date: Date) -> Duration {
+    let days = days_from_civil(date.year as u64, date.month as u64, date.day as u64);
+    Duration::from_secs(days * 86400)
+}
+
+fn date_time_to_epoch(date_time: DateTime) -> Duration {
+    let time = date_time.time;
+    let secs = time.hour as u64 * 3600 + time.min as u64 * 60 + time.sec as u64;
+    date_to_epoch(date_time.date)
+        + Duration::from_secs(secs)
+        + Duration::from_millis(time.millis as u64)
+}
+
+/// Converts the time since the UNIX epoch, which is clamped to the range that
+/// FAT can record.
+fn epoch_to_date_time(time: Duration) -> DateTime {
+    let (min, max) = FAT_TIME_RANGE;
+    let (secs, millis) = match time.as_secs() {
+        secs if secs < min => (min, 0),
+        secs if secs > max => (max, 999),
+        secs => (secs, time.subsec_millis() as u64),
+    };
+    let (year, month, day) = civil_from_days(secs / 86400);
+    let secs = secs % 86400;
+    DateTime::new(
+        Date::new(year as u16, month as u16, day as u16),
+        Time::new(
+            (secs / 3600) as u16,
+            (secs / 60 % 60) as u16,
+            (secs % 60) as u16,
+            millis as u16,
+        ),
+    )
+}
+
 const fn as_vfs_err(err: fatfs::Error<()>) -> VfsError {
     use fatfs::Error::*;
     match err {
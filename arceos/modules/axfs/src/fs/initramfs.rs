@@ -0,0 +1,187 @@
+//! The initial RAM filesystem, unpacked at boot from a cpio archive in the
+//! "newc" format, e.g., made by `find . | cpio -o -H newc`.
+//!
+//! The archive is linked into the kernel if the `AX_INITRD` environment
+//! variable is set to its path at build time, otherwise it's the initial RAM
+//! disk loaded by the bootloader (see [`axhal::mem::initrd`]), e.g., by the
+//! `-initrd` option of QEMU.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::str;
+
+use axfs_ramfs::RamFileSystem;
+use axfs_vfs::{VfsError, VfsNodePerm, VfsNodeRef, VfsNodeType, VfsOps, VfsResult};
+
+const MAGIC: &[u8] = b"070701";
+/// The magic number of the archives with checksums, which are not checked.
+const MAGIC_CRC: &[u8] = b"070702";
+const HEADER_SIZE: usize = 110;
+const TRAILER: &str = "TRAILER!!!";
+
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+const S_IFLNK: u32 = 0o120000;
+
+#[cfg(builtin_initrd)]
+static BUILTIN_ARCHIVE: Option<&[u8]> = Some(include_bytes!(env!("AX_INITRD")));
+#[cfg(not(builtin_initrd))]
+static BUILTIN_ARCHIVE: Option<&[u8]> = None;
+
+/// An entry of the archive.
+struct Entry<'a> {
+    ino: u32,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    nlink: u32,
+    name: &'a str,
+    data: &'a [u8],
+}
+
+/// Parses the entry at `pos`, returns it and the position of the next one.
+fn parse_entry(archive: &[u8], pos: usize) -> VfsResult<(Entry, usize)> {
+    let header = archive
+        .get(pos..pos + HEADER_SIZE)
+        .ok_or(VfsError::InvalidData)?;
+    if &header[..6] != MAGIC && &header[..6] != MAGIC_CRC {
+        return Err(VfsError::InvalidData);
+    }
+    // The fields after the magic number are 8 hexadecimal digits each: ino,
+    // mode, uid, gid, nlink, mtime, filesize, devmajor, devminor, rdevmajor,
+    // rdevminor, namesize and check.
+    let field = |i: usize| {
+        str::from_utf8(&header[6 + i * 8..14 + i * 8])
+            .ok()
+            .and_then(|s| u32::from_str_radix(s, 16).ok())
+            .ok_or(VfsError::InvalidData)
+    };
+    let file_size = field(6)? as usize;
+    let name_size = field(11)? as usize;
+
+    // The name ends with a NUL, and the name and the data are padded to a
+    // multiple of 4 bytes.
+    let name_start = pos + HEADER_SIZE;
+    let name = archive
+        .get(name_start..name_start + name_size)
+        .and_then(|name| name.strip_suffix(b"\0"))
+        .and_then(|name| str::from_utf8(name).ok())
+        .ok_or(VfsError::InvalidData)?;
+    let data_start = (name_start + name_size).next_multiple_of(4);
+    let data = archive
+        .get(data_start..data_start + file_size)
+        .ok_or(VfsError::InvalidData)?;
+    let entry = Entry {
+        ino: field(0)?,
+        mode: field(1)?,
+        uid: field(2)?,
+        gid: field(3)?,
+        nlink: field(4)?,
+        name,
+        data,
+    };
+    Ok((entry, (data_start + file_size).next_multiple_of(4)))
+}
+
+/// Returns the node `name` in `dir`, which is created if it does not exist.
+fn create(dir: &VfsNodeRef, name: &str, ty: VfsNodeType) -> VfsResult<VfsNodeRef> {
+    match dir.create(name, ty) {
+        Ok(()) | Err(VfsError::AlreadyExists) => {}
+        Err(e) => return Err(e),
+    }
+    dir.clone().lookup(name)
+}
+
+fn set_meta(node: &VfsNodeRef, entry: &Entry) -> VfsResult {
+    let perm = VfsNodePerm::from_bits_truncate((entry.mode & 0o777) as u16);
+    match super::set_node_perm(node, perm)
+        .and_then(|_| super::set_node_owner(node, entry.uid, entry.gid))
+    {
+        Err(VfsError::Unsupported) => Ok(()),
+        res => res,
+    }
+}
+
+/// Returns the archive linked into the kernel, or the one loaded by the
+/// bootloader if there is none.
+pub fn archive() -> Option<&'static [u8]> {
+    BUILTIN_ARCHIVE.or_else(axhal::mem::initrd)
+}
+
+/// Unpacks the archive into the directory `root`.
+///
+/// The directories, regular files (including their hard links) and symbolic
+/// links are created with their permissions and owners, if the filesystem
+/// records them. The other files, e.g., the device files, are skipped.
+///
+/// Returns `InvalidData` if the archive is malformed.
+pub fn unpack(root: &VfsNodeRef, archive: &[u8]) -> VfsResult {
+    // The first node of each regular file with hard links, by inode number.
+    let mut links = BTreeMap::new();
+    let mut pos = 0;
+    loop {
+        let (entry, next) = parse_entry(archive, pos)?;
+        pos = next;
+        if entry.name == TRAILER {
+            return Ok(());
+        }
+
+        let names = entry
+            .name
+            .split('/')
+            .filter(|n| !n.is_empty() && *n != ".")
+            .collect::<Vec<_>>();
+        if names.contains(&"..") {
+            return Err(VfsError::InvalidData);
+        }
+        let Some((name, parents)) = names.split_last() else {
+            set_meta(root, &entry)?; // the root directory itself
+            continue;
+        };
+        let mut dir = root.clone();
+        for parent in parents {
+            dir = create(&dir, parent, VfsNodeType::Dir)?;
+        }
+
+        let node = match entry.mode & S_IFMT {
+            S_IFDIR => create(&dir, name, VfsNodeType::Dir)?,
+            S_IFREG => {
+                // The data is stored with the last link only.
+                let node = match links.get(&entry.ino) {
+                    Some(node) if entry.nlink > 1 => {
+                        super::link_node(&dir, name, node)?;
+                        node.clone()
+                    }
+                    _ => create(&dir, name, VfsNodeType::File)?,
+                };
+                if entry.nlink > 1 {
+                    links.entry(entry.ino).or_insert_with(|| node.clone());
+                }
+                if !entry.data.is_empty() {
+                    node.truncate(0)?;
+                    node.write_at(0, entry.data)?;
+                }
+                node
+            }
+            S_IFLNK => {
+                let node = create(&dir, name, VfsNodeType::SymLink)?;
+                node.write_at(0, entry.data)?;
+                node
+            }
+            _ => {
+                warn!("initramfs: skip special file {:?}", entry.name);
+                continue;
+            }
+        };
+        set_meta(&node, &entry)?;
+    }
+}
+
+/// Creates a RAM filesystem with the files in the archive.
+pub fn new_initramfs(archive: &[u8]) -> VfsResult<Arc<RamFileSystem>> {
+    let fs = Arc::new(RamFileSystem::new());
+    unpack(&fs.root_dir(), archive)?;
+    Ok(fs)
+}
@@ -6,8 +6,336 @@ cfg_if::cfg_if! {
     }
 }
 
+#[cfg(feature = "extfs")]
+pub mod extfs;
+
 #[cfg(feature = "devfs")]
 pub use axfs_devfs as devfs;
 
 #[cfg(feature = "ramfs")]
 pub use axfs_ramfs as ramfs;
+
+#[cfg(feature = "procfs")]
+pub mod procfs;
+
+#[cfg(feature = "sysfs")]
+pub mod sysfs;
+
+#[cfg(feature = "overlayfs")]
+pub mod overlayfs;
+
+#[cfg(feature = "initramfs")]
+pub mod initramfs;
+
+use alloc::{string::String, vec::Vec};
+use axfs_vfs::{VfsError, VfsNodePerm, VfsNodeRef, VfsNodeType, VfsResult};
+
+use crate::fops::{FileTimes, XattrMode};
+use crate::lock::NodeKey;
+
+/// Returns the identity of `node` at the absolute path `path`, by which its
+/// advisory locks are kept.
+pub(crate) fn node_key(node: &VfsNodeRef, path: &str) -> NodeKey {
+    #[cfg(all(feature = "fatfs", not(feature = "myfs")))]
+    if fatfs::is_fat_node(node) {
+        return NodeKey::Path(path.into());
+    }
+    #[cfg(feature = "extfs")]
+    if let Some(key) = extfs::node_key(node) {
+        return key;
+    }
+    // The file in the lower filesystem keeps its identity when it's copied up.
+    #[cfg(feature = "overlayfs")]
+    if let Some(node) = overlayfs::base_node(node) {
+        return node_key(&node, path);
+    }
+    let _ = path;
+    NodeKey::Node(alloc::sync::Arc::as_ptr(node) as *const () as usize)
+}
+
+/// Returns the timestamps of `node` if its filesystem records them.
+pub(crate) fn node_times(node: &VfsNodeRef) -> Option<FileTimes> {
+    #[cfg(all(feature = "fatfs", not(feature = "myfs")))]
+    if let Some(times) = fatfs::node_times(node) {
+        return Some(times);
+    }
+    #[cfg(feature = "extfs")]
+    if let Some(times) = extfs::node_times(node) {
+        return Some(times);
+    }
+    #[cfg(feature = "overlayfs")]
+    if let Some(node) = overlayfs::real_node(node) {
+        return node_times(&node);
+    }
+    let _ = node;
+    None
+}
+
+/// Adds the entry `name` in the directory `dir` as a hard link to `node`,
+/// which is on the same filesystem.
+///
+/// Returns `PermissionDenied` if the filesystem does not support hard links.
+pub(crate) fn link_node(dir: &VfsNodeRef, name: &str, node: &VfsNodeRef) -> VfsResult {
+    #[cfg(feature = "ramfs")]
+    if let Some(dir) = dir.as_any().downcast_ref::<ramfs::DirNode>() {
+        if node.get_attr()?.is_dir() {
+            return Err(VfsError::PermissionDenied);
+        }
+        return dir.add_node(name, node.clone());
+    }
+    #[cfg(feature = "extfs")]
+    if let Some(res) = extfs::link_node(dir, name, node) {
+        return res;
+    }
+    let _ = (dir, name, node);
+    Err(VfsError::PermissionDenied)
+}
+
+/// The sticky bit in the mode of a node, see [`node_sticky`].
+pub(crate) const STICKY: u32 = 0o1000;
+
+/// Creates the entry `name` of type `ty` in the directory `dir`.
+///
+/// If the filesystem records them, the permission bits and the sticky bit
+/// are set from `mode`, and the owner to `uid` and `gid`, before the entry
+/// becomes visible. Otherwise they are ignored.
+pub(crate) fn create_node(
+    dir: &VfsNodeRef,
+    name: &str,
+    ty: VfsNodeType,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+) -> VfsResult {
+    #[cfg(any(
+        feature = "ramfs",
+        feature = "procfs",
+        feature = "sysfs",
+        feature = "overlayfs"
+    ))]
+    {
+        let init = |meta: &axfs_ramfs::NodeMeta| {
+            meta.set_perm(VfsNodePerm::from_bits_truncate(mode as u16));
+            meta.set_sticky(mode & STICKY != 0);
+            meta.set_owner(uid, gid);
+        };
+        if let Some(dir) = dir.as_any().downcast_ref::<axfs_ramfs::DirNode>() {
+            return dir.create_node_with(name, ty, init);
+        }
+        #[cfg(feature = "overlayfs")]
+        if let Some(res) = overlayfs::create_node(dir, name, ty, init) {
+            return res;
+        }
+    }
+    let _ = (mode, uid, gid);
+    dir.create(name, ty)
+}
+
+/// Returns whether `node` has the sticky bit, which is never set if its
+/// filesystem does not record it.
+///
+/// Only the owners of the entries in a directory with it, the owner of the
+/// directory, or the superuser can remove or rename them.
+pub(crate) fn node_sticky(node: &VfsNodeRef) -> bool {
+    #[cfg(any(
+        feature = "ramfs",
+        feature = "procfs",
+        feature = "sysfs",
+        feature = "overlayfs"
+    ))]
+    if let Some(meta) = axfs_ramfs::node_meta(node) {
+        return meta.sticky();
+    }
+    #[cfg(feature = "overlayfs")]
+    if let Some(node) = overlayfs::real_node(node) {
+        return node_sticky(&node);
+    }
+    let _ = node;
+    false
+}
+
+/// Sets or clears the sticky bit of `node`.
+///
+/// Returns `Unsupported` if its filesystem does not record it.
+pub(crate) fn set_node_sticky(node: &VfsNodeRef, sticky: bool) -> VfsResult {
+    #[cfg(any(
+        feature = "ramfs",
+        feature = "procfs",
+        feature = "sysfs",
+        feature = "overlayfs"
+    ))]
+    if let Some(meta) = axfs_ramfs::node_meta(node) {
+        meta.set_sticky(sticky);
+        return Ok(());
+    }
+    #[cfg(feature = "overlayfs")]
+    if let Some(node) = overlayfs::upper_node(node) {
+        return set_node_sticky(&node?, sticky);
+    }
+    let _ = (node, sticky);
+    Err(VfsError::Unsupported)
+}
+
+/// Returns the user and group IDs of the owner of `node`, or `None` if its
+/// filesystem does not record the owners.
+pub(crate) fn node_owner(node: &VfsNodeRef) -> Option<(u32, u32)> {
+    #[cfg(any(
+        feature = "ramfs",
+        feature = "procfs",
+        feature = "sysfs",
+        feature = "overlayfs"
+    ))]
+    if let Some(meta) = axfs_ramfs::node_meta(node) {
+        return Some(meta.owner());
+    }
+    #[cfg(feature = "overlayfs")]
+    if let Some(node) = overlayfs::real_node(node) {
+        return node_owner(&node);
+    }
+    let _ = node;
+    None
+}
+
+/// Changes the permission bits of `node`.
+///
+/// Returns `Unsupported` if its filesystem does not record the permissions.
+pub(crate) fn set_node_perm(node: &VfsNodeRef, perm: VfsNodePerm) -> VfsResult {
+    #[cfg(any(
+        feature = "ramfs",
+        feature = "procfs",
+        feature = "sysfs",
+        feature = "overlayfs"
+    ))]
+    if let Some(meta) = axfs_ramfs::node_meta(node) {
+        meta.set_perm(perm);
+        return Ok(());
+    }
+    #[cfg(feature = "overlayfs")]
+    if let Some(node) = overlayfs::upper_node(node) {
+        return set_node_perm(&node?, perm);
+    }
+    let _ = (node, perm);
+    Err(VfsError::Unsupported)
+}
+
+/// Changes the owner of `node`.
+///
+/// Returns `Unsupported` if its filesystem does not record the owners.
+pub(crate) fn set_node_owner(node: &VfsNodeRef, uid: u32, gid: u32) -> VfsResult {
+    #[cfg(any(
+        feature = "ramfs",
+        feature = "procfs",
+        feature = "sysfs",
+        feature = "overlayfs"
+    ))]
+    if let Some(meta) = axfs_ramfs::node_meta(node) {
+        meta.set_owner(uid, gid);
+        return Ok(());
+    }
+    #[cfg(feature = "overlayfs")]
+    if let Some(node) = overlayfs::upper_node(node) {
+        return set_node_owner(&node?, uid, gid);
+    }
+    let _ = (node, uid, gid);
+    Err(VfsError::Unsupported)
+}
+
+/// Returns the value of the extended attribute `name` of `node`.
+///
+/// Returns `NotFound` if it does not exist, or `Unsupported` if its
+/// filesystem does not record the extended attributes.
+pub(crate) fn node_xattr(node: &VfsNodeRef, name: &str) -> VfsResult<Vec<u8>> {
+    #[cfg(any(
+        feature = "ramfs",
+        feature = "procfs",
+        feature = "sysfs",
+        feature = "overlayfs"
+    ))]
+    if let Some(meta) = axfs_ramfs::node_meta(node) {
+        return meta.xattr(name).ok_or(VfsError::NotFound);
+    }
+    #[cfg(feature = "overlayfs")]
+    if let Some(node) = overlayfs::real_node(node) {
+        return node_xattr(&node, name);
+    }
+    let _ = (node, name);
+    Err(VfsError::Unsupported)
+}
+
+/// Returns the names of the extended attributes of `node`.
+///
+/// Returns `Unsupported` if its filesystem does not record the extended
+/// attributes.
+pub(crate) fn node_xattr_names(node: &VfsNodeRef) -> VfsResult<Vec<String>> {
+    #[cfg(any(
+        feature = "ramfs",
+        feature = "procfs",
+        feature = "sysfs",
+        feature = "overlayfs"
+    ))]
+    if let Some(meta) = axfs_ramfs::node_meta(node) {
+        return Ok(meta.xattr_names());
+    }
+    #[cfg(feature = "overlayfs")]
+    if let Some(node) = overlayfs::real_node(node) {
+        return node_xattr_names(&node);
+    }
+    let _ = node;
+    Err(VfsError::Unsupported)
+}
+
+/// Sets the value of the extended attribute `name` of `node` as `mode`.
+///
+/// Returns `Unsupported` if its filesystem does not record the extended
+/// attributes.
+pub(crate) fn set_node_xattr(
+    node: &VfsNodeRef,
+    name: &str,
+    value: &[u8],
+    mode: XattrMode,
+) -> VfsResult {
+    #[cfg(any(
+        feature = "ramfs",
+        feature = "procfs",
+        feature = "sysfs",
+        feature = "overlayfs"
+    ))]
+    if let Some(meta) = axfs_ramfs::node_meta(node) {
+        return match mode {
+            XattrMode::Set => {
+                meta.set_xattr(name, value);
+                Ok(())
+            }
+            XattrMode::Create => meta.create_xattr(name, value),
+            XattrMode::Replace => meta.replace_xattr(name, value),
+        };
+    }
+    #[cfg(feature = "overlayfs")]
+    if let Some(node) = overlayfs::upper_node(node) {
+        return set_node_xattr(&node?, name, value, mode);
+    }
+    let _ = (node, name, value, mode);
+    Err(VfsError::Unsupported)
+}
+
+/// Removes the extended attribute `name` of `node`.
+///
+/// Returns `NotFound` if it does not exist, or `Unsupported` if its
+/// filesystem does not record the extended attributes.
+pub(crate) fn remove_node_xattr(node: &VfsNodeRef, name: &str) -> VfsResult {
+    #[cfg(any(
+        feature = "ramfs",
+        feature = "procfs",
+        feature = "sysfs",
+        feature = "overlayfs"
+    ))]
+    if let Some(meta) = axfs_ramfs::node_meta(node) {
+        return meta.remove_xattr(name);
+    }
+    #[cfg(feature = "overlayfs")]
+    if let Some(node) = overlayfs::upper_node(node) {
+        return remove_node_xattr(&node?, name);
+    }
+    let _ = (node, name);
+    Err(VfsError::Unsupported)
+}
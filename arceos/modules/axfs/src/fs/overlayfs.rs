@@ -0,0 +1,576 @@
+//! An overlay filesystem, which stacks a writable RAM filesystem (the upper)
+//! on another filesystem (the lower), e.g., a read-only disk image.
+//!
+//! The nodes of the upper filesystem hide the ones at the same paths in the
+//! lower filesystem, except that the directories of both are merged. The
+//! lower filesystem is never modified:
+//!
+//! - A lower file is copied up to the upper filesystem with its parent
+//!   directories before it's modified.
+//! - A removed lower node is hidden by a whiteout, an empty file named
+//!   `.wh.<name>` in the upper directory.
+//! - A directory created in place of a removed lower one is opaque, marked by
+//!   a `.wh..wh..opq` file in it, which hides the lower directory.
+//!
+//! The whiteouts are not listed in the merged directories, and the names
+//! starting with `.wh.` cannot be created.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use alloc::{format, vec};
+
+use axfs_ramfs::{DirNode, NodeMeta, RamFileSystem};
+use axfs_vfs::{VfsDirEntry, VfsError, VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType};
+use axfs_vfs::{VfsOps, VfsResult};
+use axsync::Mutex;
+
+use crate::fops::XattrMode;
+
+const WHITEOUT_PREFIX: &str = ".wh.";
+const OPAQUE: &str = ".wh..wh..opq";
+
+/// The overlay filesystem, see the [module-level documentation](self).
+pub struct OverlayFileSystem {
+    ov: Arc<Overlay>,
+}
+
+struct Overlay {
+    upper: VfsNodeRef,
+    lower: VfsNodeRef,
+    _upper_fs: RamFileSystem,
+    _lower_fs: Option<Arc<dyn VfsOps>>,
+    /// Serializes the modifications, e.g., the copy-up of a file.
+    lock: Mutex<()>,
+}
+
+/// The nodes of a path in the upper and the lower filesystems, at least one
+/// of them exists.
+#[derive(Clone)]
+struct Layers {
+    upper: Option<VfsNodeRef>,
+    lower: Option<VfsNodeRef>,
+}
+
+/// A node of the overlay filesystem.
+struct Entry {
+    ov: Arc<Overlay>,
+    path: Vec<String>,
+    upper: Mutex<Option<VfsNodeRef>>,
+    lower: Option<VfsNodeRef>,
+}
+
+/// A file (or another non-directory node) in the overlay filesystem.
+pub struct OverlayFile(Entry);
+
+/// A directory in the overlay filesystem.
+pub struct OverlayDir(Entry);
+
+impl OverlayFileSystem {
+    /// Creates an overlay filesystem on the `lower` filesystem, with an empty
+    /// RAM filesystem as the upper one.
+    pub fn new(lower: Arc<dyn VfsOps>) -> Self {
+        Self::create(lower.root_dir(), Some(lower))
+    }
+
+    /// Creates an overlay filesystem on the directory `lower`.
+    pub fn with_lower_dir(lower: VfsNodeRef) -> Self {
+        Self::create(lower, None)
+    }
+
+    fn create(lower: VfsNodeRef, lower_fs: Option<Arc<dyn VfsOps>>) -> Self {
+        let upper_fs = RamFileSystem::new();
+        let ov = Overlay {
+            upper: upper_fs.root_dir(),
+            lower,
+            _upper_fs: upper_fs,
+            _lower_fs: lower_fs,
+            lock: Mutex::new(()),
+        };
+        Self { ov: Arc::new(ov) }
+    }
+}
+
+impl VfsOps for OverlayFileSystem {
+    fn root_dir(&self) -> VfsNodeRef {
+        let layers = Layers {
+            upper: Some(self.ov.upper.clone()),
+            lower: Some(self.ov.lower.clone()),
+        };
+        self.ov.node(Vec::new(), layers)
+    }
+}
+
+fn is_dir(node: &VfsNodeRef) -> bool {
+    node.get_attr().is_ok_and(|attr| attr.is_dir())
+}
+
+fn exists(dir: &VfsNodeRef, name: &str) -> bool {
+    dir.clone().lookup(name).is_ok()
+}
+
+fn whiteout(name: &str) -> String {
+    format!("{}{}", WHITEOUT_PREFIX, name)
+}
+
+fn check_name(name: &str) -> VfsResult {
+    if name.starts_with(WHITEOUT_PREFIX) {
+        Err(VfsError::InvalidInput)
+    } else {
+        Ok(())
+    }
+}
+
+/// Appends the components of `path` to `base`, `..` goes up at most to the
+/// root.
+fn join(base: &[String], path: &str) -> Vec<String> {
+    let mut comps = base.to_vec();
+    for name in path.split('/') {
+        match name {
+            "" | "." => {}
+            ".." => {
+                comps.pop();
+            }
+            _ => comps.push(name.into()),
+        }
+    }
+    comps
+}
+
+/// Reads all the entries of the directory except `.` and `..`.
+fn read_all(dir: &VfsNodeRef) -> VfsResult<Vec<(String, VfsNodeType)>> {
+    let mut entries = Vec::new();
+    const EMPTY: VfsDirEntry = VfsDirEntry::default();
+    let mut buf = [EMPTY; 16];
+    let mut idx = 0;
+    loop {
+        let n = dir.read_dir(idx, &mut buf)?;
+        if n == 0 {
+            return Ok(entries);
+        }
+        idx += n;
+        for ent in &buf[..n] {
+            let name = String::from_utf8_lossy(ent.name_as_bytes());
+            if name != "." && name != ".." {
+                entries.push((name.into_owned(), ent.entry_type()));
+            }
+        }
+    }
+}
+
+/// Copies the content of the file or the symbolic link `src` to `dst`.
+fn copy_data(src: &VfsNodeRef, dst: &VfsNodeRef) -> VfsResult {
+    let mut buf = vec![0; 4096];
+    let mut offset = 0;
+    loop {
+        let n = src.read_at(offset, &mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        dst.write_at(offset, &buf[..n])?;
+        offset += n as u64;
+    }
+}
+
+impl Layers {
+    fn top(&self) -> &VfsNodeRef {
+        self.upper.as_ref().or(self.lower.as_ref()).unwrap()
+    }
+
+    fn is_dir(&self) -> bool {
+        is_dir(self.top())
+    }
+
+    fn child(&self, name: &str) -> VfsResult<Self> {
+        if name.starts_with(WHITEOUT_PREFIX) {
+            return Err(VfsError::NotFound);
+        }
+        let upper_dir = self.upper.as_ref();
+        let upper = upper_dir.and_then(|dir| dir.clone().lookup(name).ok());
+        let lower = match &upper {
+            // only the directories are merged
+            Some(node) if !is_dir(node) || exists(node, OPAQUE) => None,
+            Some(_) => self.lookup_lower(name).filter(is_dir),
+            None if upper_dir.is_some_and(|dir| exists(dir, &whiteout(name))) => None,
+            None => self.lookup_lower(name),
+        };
+        if upper.is_none() && lower.is_none() {
+            return Err(VfsError::NotFound);
+        }
+        Ok(Self { upper, lower })
+    }
+
+    fn lookup_lower(&self, name: &str) -> Option<VfsNodeRef> {
+        self.lower.as_ref()?.clone().lookup(name).ok()
+    }
+}
+
+impl Overlay {
+    fn node(self: &Arc<Self>, path: Vec<String>, layers: Layers) -> VfsNodeRef {
+        let is_dir = layers.is_dir();
+        let entry = Entry {
+            ov: self.clone(),
+            path,
+            upper: Mutex::new(layers.upper),
+            lower: layers.lower,
+        };
+        if is_dir {
+            Arc::new(OverlayDir(entry))
+        } else {
+            Arc::new(OverlayFile(entry))
+        }
+    }
+
+    fn resolve(&self, path: &[String]) -> VfsResult<Layers> {
+        let mut layers = Layers {
+            upper: Some(self.upper.clone()),
+            lower: Some(self.lower.clone()),
+        };
+        for name in path {
+            if !layers.is_dir() {
+                return Err(VfsError::NotADirectory);
+            }
+            layers = layers.child(name)?;
+        }
+        Ok(layers)
+    }
+
+    /// Lists the merged directory, sorted by the names.
+    fn read_dir(&self, layers: &Layers) -> VfsResult<Vec<(String, VfsNodeType)>> {
+        let mut entries = BTreeMap::new();
+        let mut whiteouts = BTreeSet::new();
+        if let Some(upper) = &layers.upper {
+            for (name, ty) in read_all(upper)? {
+                if let Some(name) = name.strip_prefix(WHITEOUT_PREFIX) {
+                    whiteouts.insert(String::from(name));
+                } else {
+                    entries.insert(name, ty);
+                }
+            }
+        }
+        if let Some(lower) = &layers.lower {
+            for (name, ty) in read_all(lower)? {
+                if !whiteouts.contains(&name) {
+                    entries.entry(name).or_insert(ty);
+                }
+            }
+        }
+        Ok(entries.into_iter().collect())
+    }
+
+    /// Copies up the node at `path` with its parent directories, returns the
+    /// upper node.
+    ///
+    /// The content of a directory is not copied, which is merged.
+    fn copy_up(&self, path: &[String]) -> VfsResult<VfsNodeRef> {
+        let layers = self.resolve(path)?;
+        if let Some(upper) = layers.upper {
+            return Ok(upper);
+        }
+        let (name, parent) = path.split_last().unwrap(); // the root is in the upper
+        let upper_dir = self.copy_up(parent)?;
+        let lower = layers.lower.unwrap();
+        let attr = lower.get_attr()?;
+        debug!(
+            "copy up {:?} at overlayfs: {}",
+            attr.file_type(),
+            path.join("/")
+        );
+        upper_dir.create(name, attr.file_type())?;
+        let upper = upper_dir.clone().lookup(name)?;
+        let res = match attr.file_type() {
+            VfsNodeType::Dir => Ok(()),
+            _ => copy_data(&lower, &upper),
+        };
+        let res = res
+            .and_then(|_| super::set_node_perm(&upper, attr.perm()))
+            .and_then(|_| super::set_node_sticky(&upper, super::node_sticky(&lower)));
+        if let Err(e) = res {
+            upper_dir.remove(name).ok();
+            return Err(e);
+        }
+        if let Some((uid, gid)) = super::node_owner(&lower) {
+            super::set_node_owner(&upper, uid, gid)?;
+        }
+        if let Ok(names) = super::node_xattr_names(&lower) {
+            for name in names {
+                let value = super::node_xattr(&lower, &name)?;
+                super::set_node_xattr(&upper, &name, &value, XattrMode::Set)?;
+            }
+        }
+        Ok(upper)
+    }
+
+    /// Creates a node at `path` in the upper filesystem, `init` sets up its
+    /// metadata before it becomes visible.
+    fn create(&self, path: &[String], ty: VfsNodeType, init: impl FnOnce(&NodeMeta)) -> VfsResult {
+        let _guard = self.lock.lock();
+        let Some((name, parent)) = path.split_last() else {
+            return Ok(()); // the root already exists
+        };
+        check_name(name)?;
+        match self.resolve(path) {
+            Ok(_) => return Err(VfsError::AlreadyExists),
+            Err(VfsError::NotFound) => {}
+            Err(e) => return Err(e),
+        }
+        let upper_dir = self.copy_up(parent)?;
+        let whiteout = whiteout(name);
+        let whited_out = exists(&upper_dir, &whiteout);
+        if whited_out {
+            upper_dir.remove(&whiteout)?;
+        }
+        let upper_ramfs = upper_dir.as_any().downcast_ref::<DirNode>().unwrap();
+        upper_ramfs.create_node_with(name, ty, init)?;
+        if whited_out && ty == VfsNodeType::Dir {
+            // hide the removed lower directory
+            upper_dir.lookup(name)?.create(OPAQUE, VfsNodeType::File)?;
+        }
+        Ok(())
+    }
+
+    fn remove(&self, path: &[String]) -> VfsResult {
+        let _guard = self.lock.lock();
+        self.remove_locked(path)
+    }
+
+    fn remove_locked(&self, path: &[String]) -> VfsResult {
+        let Some((name, parent)) = path.split_last() else {
+            return Err(VfsError::InvalidInput); // cannot remove the root
+        };
+        let layers = self.resolve(path)?;
+        if layers.is_dir() && !self.read_dir(&layers)?.is_empty() {
+            return Err(VfsError::DirectoryNotEmpty);
+        }
+        if let Some(upper) = &layers.upper {
+            if is_dir(upper) {
+                // only the whiteouts are left
+                for (name, _) in read_all(upper)? {
+                    upper.remove(&name)?;
+                }
+            }
+            self.upper.remove(&path.join("/"))?;
+        }
+        if layers.lower.is_some() {
+            let upper_dir = self.copy_up(parent)?;
+            upper_dir.create(&whiteout(name), VfsNodeType::File)?;
+        }
+        Ok(())
+    }
+
+    fn rename(&self, src: &[String], dst: &[String]) -> VfsResult {
+        let _guard = self.lock.lock();
+        let (Some((src_name, src_parent)), Some((dst_name, dst_parent))) =
+            (src.split_last(), dst.split_last())
+        else {
+            return Err(VfsError::InvalidInput);
+        };
+        check_name(dst_name)?;
+        if src == dst {
+            return Ok(());
+        }
+        if dst.starts_with(src) {
+            return Err(VfsError::InvalidInput); // cannot move into itself
+        }
+        let src_layers = self.resolve(src)?;
+        let src_is_dir = src_layers.is_dir();
+        if src_is_dir && src_layers.lower.is_some() {
+            // As Linux does, the merged directories cannot be renamed.
+            return Err(VfsError::Unsupported);
+        }
+        if src_is_dir && src_parent != dst_parent {
+            // The parent of the directory cannot be changed in the RAM
+            // filesystem.
+            return Err(VfsError::Unsupported);
+        }
+        // Nothing is removed until all the checks are done.
+        match self.resolve(dst) {
+            Ok(dst_layers) => {
+                match (src_is_dir, dst_layers.is_dir()) {
+                    (false, true) => return Err(VfsError::IsADirectory),
+                    (true, false) => return Err(VfsError::NotADirectory),
+                    _ => {}
+                }
+                self.remove_locked(dst)?;
+            }
+            Err(VfsError::NotFound) => {}
+            Err(e) => return Err(e),
+        }
+
+        let node = self.copy_up(src)?;
+        let src_dir = self.copy_up(src_parent)?;
+        let dst_dir = self.copy_up(dst_parent)?;
+        let whiteout = whiteout(dst_name);
+        let whited_out = exists(&dst_dir, &whiteout);
+        if whited_out {
+            dst_dir.remove(&whiteout)?;
+        }
+        let src_ramfs = src_dir.as_any().downcast_ref::<DirNode>().unwrap();
+        let dst_ramfs = dst_dir.as_any().downcast_ref::<DirNode>().unwrap();
+        if src_parent == dst_parent {
+            src_ramfs.rename_node(src_name, dst_name)?;
+        } else {
+            dst_ramfs.add_node(dst_name, node.clone())?;
+            src_ramfs.remove_node(src_name)?;
+        }
+        if whited_out && src_is_dir {
+            node.create(OPAQUE, VfsNodeType::File)?;
+        }
+        if src_layers.lower.is_some() {
+            src_dir.create(&whiteout(src_name), VfsNodeType::File)?;
+        }
+        Ok(())
+    }
+}
+
+impl Entry {
+    /// Returns the upper node, which may be copied up by another node of the
+    /// same path.
+    fn upper(&self) -> Option<VfsNodeRef> {
+        let mut upper = self.upper.lock();
+        if upper.is_none() {
+            *upper = self.ov.upper.clone().lookup(&self.path.join("/")).ok();
+        }
+        upper.clone()
+    }
+
+    fn top(&self) -> VfsNodeRef {
+        self.upper().or_else(|| self.lower.clone()).unwrap()
+    }
+
+    fn copy_up(&self) -> VfsResult<VfsNodeRef> {
+        if let Some(upper) = self.upper() {
+            return Ok(upper);
+        }
+        let _guard = self.ov.lock.lock();
+        let upper = self.ov.copy_up(&self.path)?;
+        *self.upper.lock() = Some(upper.clone());
+        Ok(upper)
+    }
+}
+
+/// Returns the node in the upper filesystem if `node` is in an overlay
+/// filesystem, or the one in the lower filesystem if it's not copied up.
+pub(crate) fn real_node(node: &VfsNodeRef) -> Option<VfsNodeRef> {
+    let node = node.as_any();
+    if let Some(file) = node.downcast_ref::<OverlayFile>() {
+        Some(file.0.top())
+    } else {
+        node.downcast_ref::<OverlayDir>().map(|dir| dir.0.top())
+    }
+}
+
+/// Returns the node in the lower filesystem if `node` is in an overlay
+/// filesystem, or the one in the upper filesystem if it's not in the lower.
+pub(crate) fn base_node(node: &VfsNodeRef) -> Option<VfsNodeRef> {
+    let node = node.as_any();
+    let entry = if let Some(file) = node.downcast_ref::<OverlayFile>() {
+        &file.0
+    } else {
+        &node.downcast_ref::<OverlayDir>()?.0
+    };
+    entry.lower.clone().or_else(|| entry.upper())
+}
+
+/// Copies up `node` if it's in an overlay filesystem, returns the node in the
+/// upper filesystem.
+pub(crate) fn upper_node(node: &VfsNodeRef) -> Option<VfsResult<VfsNodeRef>> {
+    let node = node.as_any();
+    if let Some(file) = node.downcast_ref::<OverlayFile>() {
+        Some(file.0.copy_up())
+    } else {
+        node.downcast_ref::<OverlayDir>().map(|dir| dir.0.copy_up())
+    }
+}
+
+/// Creates the entry `name` in `dir` if it's a directory in an overlay
+/// filesystem, `init` sets up its metadata before it becomes visible.
+pub(crate) fn create_node(
+    dir: &VfsNodeRef,
+    name: &str,
+    ty: VfsNodeType,
+    init: impl FnOnce(&NodeMeta),
+) -> Option<VfsResult> {
+    let dir = dir.as_any().downcast_ref::<OverlayDir>()?;
+    Some(dir.0.ov.create(&join(&dir.0.path, name), ty, init))
+}
+
+impl VfsNodeOps for OverlayFile {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        self.0.top().get_attr()
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        self.0.top().read_at(offset, buf)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        self.0.copy_up()?.write_at(offset, buf)
+    }
+
+    fn truncate(&self, size: u64) -> VfsResult {
+        self.0.copy_up()?.truncate(size)
+    }
+
+    fn fsync(&self) -> VfsResult {
+        self.0.top().fsync()
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
+
+impl VfsNodeOps for OverlayDir {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        self.0.top().get_attr()
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        let (_, parent) = self.0.path.split_last()?;
+        let layers = self.0.ov.resolve(parent).ok()?;
+        Some(self.0.ov.node(parent.to_vec(), layers))
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        debug!("lookup at overlayfs: {}", path);
+        let path = join(&self.0.path, path);
+        let layers = self.0.ov.resolve(&path)?;
+        Ok(self.0.ov.node(path, layers))
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let layers = self.0.ov.resolve(&self.0.path)?;
+        let entries = self.0.ov.read_dir(&layers)?;
+        let mut entries = entries.iter().skip(start_idx.max(2) - 2);
+        for (i, ent) in dirents.iter_mut().enumerate() {
+            match i + start_idx {
+                0 => *ent = VfsDirEntry::new(".", VfsNodeType::Dir),
+                1 => *ent = VfsDirEntry::new("..", VfsNodeType::Dir),
+                _ => match entries.next() {
+                    Some((name, ty)) => *ent = VfsDirEntry::new(name, *ty),
+                    None => return Ok(i),
+                },
+            }
+        }
+        Ok(dirents.len())
+    }
+
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        debug!("create {:?} at overlayfs: {}", ty, path);
+        self.0.ov.create(&join(&self.0.path, path), ty, |_| {})
+    }
+
+    fn remove(&self, path: &str) -> VfsResult {
+        debug!("remove at overlayfs: {}", path);
+        self.0.ov.remove(&join(&self.0.path, path))
+    }
+
+    fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        debug!("rename at overlayfs: {} -> {}", src_path, dst_path);
+        let src = join(&self.0.path, src_path);
+        let dst = join(&self.0.path, dst_path);
+        self.0.ov.rename(&src, &dst)
+    }
+
+    axfs_vfs::impl_vfs_dir_default! {}
+}
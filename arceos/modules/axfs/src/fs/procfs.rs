@@ -0,0 +1,485 @@
+//! The process filesystem mounted on `/proc`.
+//!
+//! Static entries (e.g., `/proc/sys/...`) are kept in a RAM filesystem, while
+//! the per-process directories `/proc/<pid>` and `/proc/self` are generated
+//! from the [`ProcessInfo`] registered by the kernel. If the kernel does not
+//! register one, each task of [`axtask`] gets a directory `/proc/<tid>` with
+//! its `stat` (see [`task_stat`]) when the `multitask` feature is enabled.
+//!
+//! Files like `/proc/meminfo` are [`ProcFile`]s, whose content is generated
+//! on each read. Writable ones (tunables) call back into the kernel on each
+//! write. More of them can be added with [`add_file`].
+
+#[cfg(feature = "multitask")]
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+
+use axfs_ramfs::{DirNode, RamFileSystem};
+use axfs_vfs::{VfsDirEntry, VfsError, VfsNodeAttr, VfsNodePerm, VfsResult};
+use axfs_vfs::{VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps};
+use lazyinit::LazyInit;
+
+static PROCESS_INFO: LazyInit<&'static dyn ProcessInfo> = LazyInit::new();
+static STATIC_ROOT: LazyInit<Arc<DirNode>> = LazyInit::new();
+
+/// Information about the processes, provided by the kernel which manages
+/// them.
+pub trait ProcessInfo: Send + Sync {
+    /// Returns the ID of the current process, which `/proc/self` refers to.
+    fn current_pid(&self) -> u64;
+
+    /// Returns the IDs of all processes.
+    fn pids(&self) -> Vec<u64>;
+
+    /// Returns the names of the files in each `/proc/<pid>` directory.
+    fn file_names(&self) -> &'static [&'static str];
+
+    /// Generates the content of the file `name` in `/proc/<pid>`.
+    ///
+    /// Returns `None` if the process does not exist.
+    fn read_file(&self, pid: u64, name: &str) -> Option<String>;
+
+    /// Returns whether the process `pid` exists.
+    fn has_pid(&self, pid: u64) -> bool {
+        self.pids().contains(&pid)
+    }
+}
+
+/// Registers the source of the per-process directories in `/proc`.
+///
+/// It can only be called once.
+pub fn register_process_info(info: &'static dyn ProcessInfo) {
+    PROCESS_INFO.init_once(info);
+}
+
+fn process_info() -> Option<&'static dyn ProcessInfo> {
+    let info = PROCESS_INFO.get().copied();
+    #[cfg(feature = "multitask")]
+    let info = info.or(Some(&TaskInfo));
+    info
+}
+
+/// The directories `/proc/<tid>` of the tasks, used if the kernel does not
+/// register its [`ProcessInfo`].
+#[cfg(feature = "multitask")]
+struct TaskInfo;
+
+#[cfg(feature = "multitask")]
+impl ProcessInfo for TaskInfo {
+    fn current_pid(&self) -> u64 {
+        axtask::current().id().as_u64()
+    }
+
+    fn pids(&self) -> Vec<u64> {
+        axtask::all_tasks()
+            .iter()
+            .map(|task| task.id().as_u64())
+            .collect()
+    }
+
+    fn file_names(&self) -> &'static [&'static str] {
+        &["stat"]
+    }
+
+    fn read_file(&self, tid: u64, name: &str) -> Option<String> {
+        match name {
+            "stat" => task_stat(tid),
+            _ => None,
+        }
+    }
+
+    fn has_pid(&self, tid: u64) -> bool {
+        axtask::find_task(tid).is_some()
+    }
+}
+
+/// Generates `/proc/<tid>/stat` of the task `tid`, or returns `None` if the
+/// task has been dropped.
+///
+/// Each task is shown as a process of its own. The state, the scheduling
+/// priority, the CPU and the policy are filled in, the other fields are
+/// zeros.
+#[cfg(feature = "multitask")]
+pub fn task_stat(tid: u64) -> Option<String> {
+    use axtask::{SchedPolicy, TaskState};
+
+    let task = axtask::find_task(tid)?;
+    let state = match task.state() {
+        TaskState::Running | TaskState::Ready => 'R',
+        TaskState::Blocked => 'S',
+        TaskState::Exited => 'Z',
+    };
+    // As in Linux, the priority of a real-time task is `-1 - rt_priority`.
+    let (priority, rt_priority, policy) = match task.sched_policy() {
+        SchedPolicy::Normal => (20, 0, 0),
+        SchedPolicy::Fifo(prio) => (-1 - prio as i32, prio, 1),
+        SchedPolicy::RoundRobin(prio) => (-1 - prio as i32, prio, 2),
+        SchedPolicy::Deadline(_) => (-101, 0, 6),
+    };
+    // pid (comm) state ppid pgrp session tty_nr tpgid flags minflt cminflt
+    // majflt cmajflt utime stime cutime cstime priority nice num_threads
+    // itrealvalue starttime vsize rss rsslim startcode endcode startstack
+    // kstkesp kstkeip signal blocked sigignore sigcatch wchan nswap cnswap
+    // exit_signal processor rt_priority policy
+    Some(format!(
+        "{tid} ({}) {state} 0 {tid} {tid} 0 0 0 0 0 0 0 0 0 0 0 {priority} 0 1 0 0 0 0 \
+         0 0 0 0 0 0 0 0 0 0 0 0 0 0 {} {rt_priority} {policy}\n",
+        task.name(),
+        task.cpu_id(),
+    ))
+}
+
+/// Adds a generated file to the mounted `/proc`, e.g., `sys/vm/foo`.
+///
+/// The parent directories are created if they do not exist.
+pub fn add_file(path: &str, file: ProcFile) -> VfsResult {
+    STATIC_ROOT
+        .get()
+        .ok_or(VfsError::NotFound)
+        .and_then(|root| add_file_at(root, path, file))
+}
+
+fn add_file_at(root: &Arc<DirNode>, path: &str, file: ProcFile) -> VfsResult {
+    let path = path.trim_matches('/');
+    let (dir, name) = match path.rfind('/') {
+        Some(n) => (&path[..n], &path[n + 1..]),
+        None => ("", path),
+    };
+    let mut parent: VfsNodeRef = root.clone();
+    for component in dir.split('/').filter(|c| !c.is_empty()) {
+        match parent.create(component, VfsNodeType::Dir) {
+            Ok(()) | Err(VfsError::AlreadyExists) => {}
+            Err(e) => return Err(e),
+        }
+        parent = parent.lookup(component)?;
+    }
+    parent
+        .as_any()
+        .downcast_ref::<DirNode>()
+        .ok_or(VfsError::NotADirectory)?
+        .add_node(name, Arc::new(file))
+}
+
+fn read_content(content: &str, offset: u64, buf: &mut [u8]) -> usize {
+    let content = content.as_bytes();
+    let start = content.len().min(offset as usize);
+    let end = content.len().min(offset as usize + buf.len());
+    let src = &content[start..end];
+    buf[..src.len()].copy_from_slice(src);
+    src.len()
+}
+
+fn split_path(path: &str) -> (&str, Option<&str>) {
+    let trimmed_path = path.trim_start_matches('/');
+    trimmed_path.find('/').map_or((trimmed_path, None), |n| {
+        (&trimmed_path[..n], Some(&trimmed_path[n + 1..]))
+    })
+}
+
+fn fill_dirents(
+    entries: &[(String, VfsNodeType)],
+    start_idx: usize,
+    dirents: &mut [VfsDirEntry],
+) -> usize {
+    let mut entries = entries.iter().skip(start_idx);
+    for (i, ent) in dirents.iter_mut().enumerate() {
+        match entries.next() {
+            Some((name, ty)) => *ent = VfsDirEntry::new(name, *ty),
+            None => return i,
+        }
+    }
+    dirents.len()
+}
+
+/// The process filesystem, see the [module-level documentation](self).
+pub struct ProcFileSystem {
+    inner: RamFileSystem,
+    root: Arc<ProcRootDir>,
+}
+
+impl ProcFileSystem {
+    /// Creates a new instance with no static entries.
+    pub fn new() -> Self {
+        let inner = RamFileSystem::new();
+        let root = Arc::new_cyclic(|this| ProcRootDir {
+            this: this.clone(),
+            static_root: inner.root_dir_node(),
+        });
+        Self { inner, root }
+    }
+
+    /// Returns the directory holding the static entries.
+    pub fn static_root(&self) -> Arc<DirNode> {
+        self.inner.root_dir_node()
+    }
+
+    /// Adds a generated file at `path`, relative to `/proc`.
+    ///
+    /// The parent directories are created if they do not exist.
+    pub fn add_file(&self, path: &str, file: ProcFile) -> VfsResult {
+        add_file_at(&self.static_root(), path, file)
+    }
+
+    /// Makes this instance the target of [`add_file`].
+    pub(crate) fn set_global(&self) {
+        STATIC_ROOT.call_once(|| self.static_root());
+    }
+}
+
+impl VfsOps for ProcFileSystem {
+    fn mount(&self, path: &str, mount_point: VfsNodeRef) -> VfsResult {
+        self.inner.mount(path, mount_point)
+    }
+
+    fn root_dir(&self) -> VfsNodeRef {
+        self.root.clone()
+    }
+}
+
+impl Default for ProcFileSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The root directory `/proc`.
+struct ProcRootDir {
+    this: Weak<ProcRootDir>,
+    static_root: Arc<DirNode>,
+}
+
+impl ProcRootDir {
+    /// Returns the process directory named `name`, if it is a process ID or
+    /// `self`.
+    fn pid_dir(&self, name: &str) -> Option<VfsNodeRef> {
+        let info = process_info()?;
+        let pid = match name {
+            "self" => info.current_pid(),
+            _ => name.parse().ok().filter(|&pid| info.has_pid(pid))?,
+        };
+        Some(Arc::new(ProcPidDir {
+            pid,
+            parent: self.this.clone(),
+        }))
+    }
+}
+
+impl VfsNodeOps for ProcRootDir {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        self.static_root.get_attr()
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        self.static_root.parent()
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        let (name, rest) = split_path(path);
+        match self.pid_dir(name) {
+            Some(dir) => match rest {
+                Some(rest) => dir.lookup(rest),
+                None => Ok(dir),
+            },
+            None => self.static_root.clone().lookup(path),
+        }
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let mut entries = Vec::new();
+        entries.push((".".to_string(), VfsNodeType::Dir));
+        entries.push(("..".to_string(), VfsNodeType::Dir));
+        for name in self.static_root.get_entries() {
+            if process_info().is_some() && name == "self" {
+                continue; // Overridden by the dynamic one.
+            }
+            let ty = self
+                .static_root
+                .clone()
+                .lookup(&name)?
+                .get_attr()?
+                .file_type();
+            entries.push((name, ty));
+        }
+        if let Some(info) = process_info() {
+            entries.push(("self".to_string(), VfsNodeType::Dir));
+            for pid in info.pids() {
+                entries.push((pid.to_string(), VfsNodeType::Dir));
+            }
+        }
+        Ok(fill_dirents(&entries, start_idx, dirents))
+    }
+
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        match self.pid_dir(split_path(path).0) {
+            Some(_) => Err(VfsError::PermissionDenied),
+            None => self.static_root.create(path, ty),
+        }
+    }
+
+    fn remove(&self, path: &str) -> VfsResult {
+        match self.pid_dir(split_path(path).0) {
+            Some(_) => Err(VfsError::PermissionDenied),
+            None => self.static_root.remove(path),
+        }
+    }
+
+    axfs_vfs::impl_vfs_dir_default! {}
+}
+
+/// The directory `/proc/<pid>`.
+struct ProcPidDir {
+    pid: u64,
+    parent: Weak<ProcRootDir>,
+}
+
+impl VfsNodeOps for ProcPidDir {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(0o555),
+            VfsNodeType::Dir,
+            0,
+            0,
+        ))
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        self.parent.upgrade().map(|dir| dir as VfsNodeRef)
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        let (name, rest) = split_path(path);
+        let node: VfsNodeRef = match name {
+            "" | "." => self.clone(),
+            ".." => self.parent().ok_or(VfsError::NotFound)?,
+            _ => {
+                let info = process_info().ok_or(VfsError::NotFound)?;
+                let name = info
+                    .file_names()
+                    .iter()
+                    .find(|&&n| n == name)
+                    .ok_or(VfsError::NotFound)?;
+                Arc::new(ProcPidFile {
+                    pid: self.pid,
+                    name: *name,
+                })
+            }
+        };
+        match rest {
+            Some(rest) => node.lookup(rest),
+            None => Ok(node),
+        }
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let mut entries = Vec::new();
+        entries.push((".".to_string(), VfsNodeType::Dir));
+        entries.push(("..".to_string(), VfsNodeType::Dir));
+        if let Some(info) = process_info() {
+            for name in info.file_names() {
+                entries.push((name.to_string(), VfsNodeType::File));
+            }
+        }
+        Ok(fill_dirents(&entries, start_idx, dirents))
+    }
+
+    axfs_vfs::impl_vfs_dir_default! {}
+}
+
+/// A read-only file in `/proc/<pid>`, whose content is generated on each
+/// access.
+struct ProcPidFile {
+    pid: u64,
+    name: &'static str,
+}
+
+impl ProcPidFile {
+    fn content(&self) -> VfsResult<String> {
+        process_info()
+            .and_then(|info| info.read_file(self.pid, self.name))
+            .ok_or(VfsError::NotFound) // The process has gone.
+    }
+}
+
+impl VfsNodeOps for ProcPidFile {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let size = self.content()?.len() as u64;
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(0o444),
+            VfsNodeType::File,
+            size,
+            0,
+        ))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        Ok(read_content(&self.content()?, offset, buf))
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
+
+/// A file whose content is generated on each read.
+///
+/// If it is writable, each write must contain the whole new value, which is
+/// passed to the write callback.
+pub struct ProcFile {
+    read: fn() -> String,
+    write: Option<fn(&str) -> VfsResult>,
+}
+
+impl ProcFile {
+    /// Creates a read-only file.
+    pub const fn new(read: fn() -> String) -> Self {
+        Self { read, write: None }
+    }
+
+    /// Creates a writable file, e.g., a tunable in `/proc/sys`.
+    pub const fn new_writable(read: fn() -> String, write: fn(&str) -> VfsResult) -> Self {
+        Self {
+            read,
+            write: Some(write),
+        }
+    }
+}
+
+impl VfsNodeOps for ProcFile {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let perm = if self.write.is_some() { 0o644 } else { 0o444 };
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(perm),
+            VfsNodeType::File,
+            (self.read)().len() as u64,
+            0,
+        ))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        Ok(read_content(&(self.read)(), offset, buf))
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let write = self.write.ok_or(VfsError::PermissionDenied)?;
+        if offset != 0 {
+            return Err(VfsError::InvalidInput);
+        }
+        let value = core::str::from_utf8(buf).map_err(|_| VfsError::InvalidData)?;
+        write(value.trim())?;
+        Ok(buf.len())
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        // Opening with `O_TRUNC` is allowed, the value is replaced on write.
+        match self.write {
+            Some(_) => Ok(()),
+            None => Err(VfsError::PermissionDenied),
+        }
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
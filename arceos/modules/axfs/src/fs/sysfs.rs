@@ -0,0 +1,52 @@
+//! The system filesystem mounted on `/sys`.
+//!
+//! It is a RAM filesystem describing the hardware, e.g., the devices probed
+//! by the kernel. The attributes do not change after boot, so their values
+//! are written once when they are added by [`add_file`].
+
+use alloc::sync::Arc;
+
+use axfs_ramfs::{DirNode, RamFileSystem};
+use axfs_vfs::{VfsError, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsResult};
+use lazyinit::LazyInit;
+
+static SYS_ROOT: LazyInit<Arc<DirNode>> = LazyInit::new();
+
+/// Makes the root of `fs` the target of [`add_file`].
+pub(crate) fn set_global(fs: &RamFileSystem) {
+    SYS_ROOT.call_once(|| fs.root_dir_node());
+}
+
+/// Adds a file with the given content to the mounted `/sys`, e.g.,
+/// `block/vda/size`.
+///
+/// The parent directories are created if they do not exist, and the file is
+/// overwritten if it exists.
+pub fn add_file(path: &str, content: &str) -> VfsResult {
+    let root = SYS_ROOT.get().ok_or(VfsError::NotFound)?;
+    add_file_at(root.clone(), path, content)
+}
+
+pub(crate) fn add_file_at(root: VfsNodeRef, path: &str, content: &str) -> VfsResult {
+    let path = path.trim_matches('/');
+    let (dir, name) = match path.rfind('/') {
+        Some(n) => (&path[..n], &path[n + 1..]),
+        None => ("", path),
+    };
+    let mut parent = root;
+    for component in dir.split('/').filter(|c| !c.is_empty()) {
+        match parent.create(component, VfsNodeType::Dir) {
+            Ok(()) | Err(VfsError::AlreadyExists) => {}
+            Err(e) => return Err(e),
+        }
+        parent = parent.lookup(component)?;
+    }
+    match parent.create(name, VfsNodeType::File) {
+        Ok(()) | Err(VfsError::AlreadyExists) => {}
+        Err(e) => return Err(e),
+    }
+    let file = parent.lookup(name)?;
+    file.truncate(0)?;
+    file.write_at(0, content.as_bytes())?;
+    Ok(())
+}
@@ -1,15 +1,55 @@
 //! [ArceOS](https://github.com/arceos-org/arceos) filesystem module.
 //!
 //! It provides unified filesystem operations for various filesystems.
+//! Besides the ones mounted at initialization, filesystems can be mounted and
+//! unmounted at runtime with [`api::mount`] and [`api::umount`].
+//!
+//! The disks are accessed through a page cache shared by the filesystems on
+//! them, see [`cache`]. The written data is kept in the cache until the file
+//! is synchronized ([`api::File::sync_all`]), [`api::sync`] is called, or it
+//! is evicted.
 //!
 //! # Cargo Features
 //!
 //! - `fatfs`: Use [FAT] as the main filesystem and mount it on `/`. This feature
 //!    is **enabled** by default.
-//! - `devfs`: Mount [`axfs_devfs::DeviceFileSystem`] on `/dev`. This feature is
-//!    **enabled** by default.
+//! - `extfs`: Use [ext2] (read-write) or ext4 (read-only) as the main
+//!    filesystem if the disk has one, otherwise fall back to `fatfs`. See
+//!    [`extfs::format`] to create an image. This feature is **disabled** by
+//!    default.
+//! - `initramfs`: Unpack a cpio archive into a [`axfs_ramfs::RamFileSystem`]
+//!    and mount it on `/` instead of the filesystem on the disk. The archive
+//!    is linked into the kernel if the `AX_INITRD` environment variable is
+//!    set to its path at build time, or loaded by the bootloader, see
+//!    [`initramfs`]. This feature is **disabled** by default.
+//! - `overlayfs`: Provide [`overlayfs::OverlayFileSystem`], which stacks a
+//!    writable RAM filesystem on another filesystem. This feature is
+//!    **disabled** by default.
+//! - `overlay-root`: Mount the main filesystem on `/` through an overlay
+//!    filesystem, so that it is writable even if the disk is a read-only
+//!    image, and the changes are lost after reboot. This feature is
+//!    **disabled** by default.
+//! - `devfs`: Mount [`axfs_devfs::DeviceFileSystem`] on `/dev`, with the
+//!    console, random number and block devices, see [`devfs`]. This feature
+//!    is **enabled** by default.
 //! - `ramfs`: Mount [`axfs_ramfs::RamFileSystem`] on `/tmp`. This feature is
 //!    **enabled** by default.
+//! - `procfs`: Mount the process filesystem on `/proc`. The per-process
+//!    directories are provided by the kernel through
+//!    [`procfs::register_process_info`], or generated for each task if the
+//!    `multitask` feature is enabled. This feature is **enabled** by default.
+//! - `sysfs`: Mount the system filesystem on `/sys`. The kernel adds the
+//!    attributes of the probed devices through [`sysfs::add_file`]. This
+//!    feature is **enabled** by default.
+//! - `multitask`: Block the tasks waiting for the advisory file locks (see
+//!    [`fops::LockHandle`]) or the change notifications (see [`notify`]),
+//!    otherwise they fail with `WouldBlock`. This
+//!    feature is **disabled** by default, and enabled with the `multitask`
+//!    feature of the system.
+//! - `irq`: Block the readers of the console (`/dev/tty`) until the input IRQ
+//!    if the platform supports it, otherwise the input is polled. This feature
+//!    is **disabled** by default, and enabled with the `irq` feature of the
+//!    system.
 //! - `myfs`: Allow users to define their custom filesystems to override the
 //!    default. In this case, [`MyFileSystemIf`] is required to be implemented
 //!    to create and initialize other filesystems. This feature is **disabled** by
@@ -17,6 +57,7 @@
 //!    both are enabled.
 //!
 //! [FAT]: https://en.wikipedia.org/wiki/File_Allocation_Table
+//! [ext2]: https://en.wikipedia.org/wiki/Ext2
 //! [`MyFileSystemIf`]: fops::MyFileSystemIf
 
 #![cfg_attr(all(not(test), not(doc)), no_std)]
@@ -28,19 +69,57 @@ extern crate alloc;
 
 mod dev;
 mod fs;
+mod lock;
 mod mounts;
 mod root;
+mod xattr;
 
 pub mod api;
+#[cfg(feature = "devfs")]
+pub mod devfs;
 pub mod fops;
+pub mod notify;
+
+pub use self::dev::{block_dev_name, cache};
+
+#[cfg(feature = "extfs")]
+pub use self::fs::extfs;
+#[cfg(feature = "initramfs")]
+pub use self::fs::initramfs;
+#[cfg(feature = "overlayfs")]
+pub use self::fs::overlayfs;
+#[cfg(feature = "procfs")]
+pub use self::fs::procfs;
+#[cfg(feature = "sysfs")]
+pub use self::fs::sysfs;
 
 use axdriver::{prelude::*, AxDeviceContainer};
 
 /// Initializes filesystems by block devices.
+///
+/// The root filesystem is on the first block device, unless it's unpacked
+/// from an initramfs archive (see [`initramfs`]).
 pub fn init_filesystems(mut blk_devs: AxDeviceContainer<AxBlockDevice>) {
     info!("Initialize filesystems...");
+    axalloc::register_reclaimer(self::dev::cache::reclaim);
+
+    #[cfg(feature = "initramfs")]
+    if let Some(archive) = self::initramfs::archive() {
+        info!("  use initramfs: {} bytes", archive.len());
+        let main_fs = self::initramfs::new_initramfs(archive).expect("failed to unpack initramfs");
+        self::root::init_rootfs(main_fs);
+        #[cfg(feature = "devfs")]
+        self::devfs::add_block_devices(None, blk_devs);
+        return;
+    }
 
     let dev = blk_devs.take_one().expect("No block device found!");
     info!("  use block device 0: {:?}", dev.device_name());
-    self::root::init_rootfs(self::dev::Disk::new(dev));
+    let disk = self::dev::Disk::new(dev);
+    #[cfg(feature = "devfs")]
+    let root_dev = disk.device().clone();
+    self::root::init_rootfs(self::root::new_main_fs(disk));
+
+    #[cfg(feature = "devfs")]
+    self::devfs::add_block_devices(Some(root_dev), blk_devs);
 }
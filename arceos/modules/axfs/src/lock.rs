@@ -0,0 +1,297 @@
+//! Advisory file locks: the whole-file locks of `flock` and the byte-range
+//! (record) locks of `fcntl`.
+//!
+//! The locks only conflict with each other, and do not prevent a file from
+//! being read or written. The two kinds of locks are independent.
+//!
+//! They are kept by the identity of the file (see [`NodeKey`]), so the hard
+//! links of a file share the locks, and the locks follow the file when it is
+//! renamed.
+
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use axerrno::{ax_err, AxResult};
+use axfs_vfs::VfsNodeRef;
+use axsync::spin::SpinNoIrq;
+use cap_access::Cap;
+
+/// The type of an advisory lock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockType {
+    /// A shared (read) lock, which can be held by several owners.
+    Shared,
+    /// An exclusive (write) lock, which can be held by one owner only.
+    Exclusive,
+}
+
+/// A byte-range lock on a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordLock {
+    /// The type of the lock.
+    pub ty: LockType,
+    /// The offset of the first byte locked.
+    pub start: u64,
+    /// The offset after the last byte locked, `u64::MAX` if it extends to
+    /// the end of the file, however it grows.
+    pub end: u64,
+    /// The owner of the lock, e.g., the process ID.
+    pub owner: u64,
+}
+
+/// The locks on a file.
+#[derive(Default)]
+struct FileLocks {
+    /// The whole-file locks, with the IDs of the opened files holding them.
+    whole: Vec<(u64, LockType)>,
+    records: Vec<RecordLock>,
+}
+
+/// The identity of a file, by which its locks are kept.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum NodeKey {
+    /// An inode number with the address of the volume it's on, for the
+    /// filesystems which create a new node on each lookup (ext2/4).
+    Inode(usize, u64),
+    /// A node kept in memory, by its address.
+    Node(usize),
+    /// A file of a filesystem which creates a new node on each lookup and
+    /// has no inode numbers, by its absolute path. It has no hard links, but
+    /// the locks stay with the old path if it is renamed.
+    Path(String),
+}
+
+static LOCKS: SpinNoIrq<BTreeMap<NodeKey, FileLocks>> = SpinNoIrq::new(BTreeMap::new());
+
+/// The tasks waiting for a lock on any file.
+#[cfg(feature = "multitask")]
+static WAIT_QUEUE: axtask::WaitQueue = axtask::WaitQueue::new();
+
+const fn conflicts(a: LockType, b: LockType) -> bool {
+    matches!(a, LockType::Exclusive) || matches!(b, LockType::Exclusive)
+}
+
+const fn overlaps(lock: &RecordLock, start: u64, end: u64) -> bool {
+    lock.start < end && start < lock.end
+}
+
+/// Runs `f` on the locks of the file `key`, the entry of the file is dropped
+/// if there are no locks left.
+fn with_locks<R>(key: &NodeKey, f: impl FnOnce(&mut FileLocks) -> R) -> R {
+    let mut table = LOCKS.lock();
+    let locks = table.entry(key.clone()).or_default();
+    let res = f(locks);
+    if locks.whole.is_empty() && locks.records.is_empty() {
+        table.remove(key);
+    }
+    res
+}
+
+/// Runs `try_lock` on the locks of the file `key` until it takes the lock,
+/// i.e., returns `true`.
+///
+/// If the lock is held by others, the current task is blocked until it is
+/// released if `wait` is set, otherwise `WouldBlock` is returned.
+fn acquire(key: &NodeKey, wait: bool, try_lock: impl Fn(&mut FileLocks) -> bool) -> AxResult {
+    if with_locks(key, &try_lock) {
+        return Ok(());
+    }
+    // Without `multitask`, no one else can release the lock.
+    if !wait || cfg!(not(feature = "multitask")) {
+        return ax_err!(WouldBlock);
+    }
+    #[cfg(feature = "multitask")]
+    WAIT_QUEUE.wait_until(|| with_locks(key, &try_lock));
+    Ok(())
+}
+
+/// Runs `f` to release some locks of the file `key`, and wakes up the waiting
+/// tasks.
+fn release(key: &NodeKey, f: impl FnOnce(&mut FileLocks)) {
+    with_locks(key, f);
+    #[cfg(feature = "multitask")]
+    WAIT_QUEUE.notify_all(false);
+}
+
+/// Removes the range `start..end` from the record locks of `owner`, the ones
+/// partially in it are split.
+fn remove_range(records: &mut Vec<RecordLock>, owner: u64, start: u64, end: u64) {
+    let mut rest = Vec::new();
+    records.retain(|r| {
+        if r.owner != owner || !overlaps(r, start, end) {
+            return true;
+        }
+        if r.start < start {
+            rest.push(RecordLock { end: start, ..*r });
+        }
+        if r.end > end {
+            rest.push(RecordLock { start: end, ..*r });
+        }
+        false
+    });
+    records.extend(rest);
+}
+
+/// Adds the record lock in place of the ones of the same owner in its range,
+/// and merges it with the adjacent ones of the same type.
+fn insert_record(records: &mut Vec<RecordLock>, mut lock: RecordLock) {
+    remove_range(records, lock.owner, lock.start, lock.end);
+    records.retain(|r| {
+        let adjacent = r.end == lock.start || r.start == lock.end;
+        if r.owner == lock.owner && r.ty == lock.ty && adjacent {
+            lock.start = lock.start.min(r.start);
+            lock.end = lock.end.max(r.end);
+            false
+        } else {
+            true
+        }
+    });
+    records.push(lock);
+}
+
+/// A handle to the advisory locks of an opened file, returned by
+/// [`File::lock_handle`](crate::fops::File::lock_handle).
+///
+/// It can be used without holding the file, e.g., to wait for a lock while
+/// the file is read or written by other tasks. The whole-file lock is held by
+/// the opened file, and released when the file is closed.
+#[derive(Clone)]
+pub struct LockHandle {
+    /// The unique ID of the opened file.
+    id: u64,
+    /// The identity of the file.
+    key: NodeKey,
+    /// The node of the file, which keeps its address from being reused while
+    /// it is locked.
+    _node: VfsNodeRef,
+    /// The absolute path of the file.
+    path: String,
+    /// The access rights of the opened file.
+    cap: Cap,
+}
+
+impl LockHandle {
+    pub(crate) fn new(node: VfsNodeRef, path: String, cap: Cap) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            key: crate::fs::node_key(&node, &path),
+            _node: node,
+            path,
+            cap,
+        }
+    }
+
+    /// Returns the absolute path of the file.
+    pub(crate) fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the identity of the file as numbers, or `None` if it's only
+    /// known by its path.
+    pub(crate) fn file_id(&self) -> Option<(u64, u64)> {
+        match self.key {
+            NodeKey::Inode(volume, ino) => Some((volume as u64, ino)),
+            // The addresses of the volumes are never 0.
+            NodeKey::Node(addr) => Some((0, addr as u64)),
+            NodeKey::Path(_) => None,
+        }
+    }
+
+    /// Takes a lock of the type `ty` on the whole file, or releases it if
+    /// `ty` is `None`. A lock already held by the opened file is converted.
+    ///
+    /// If another opened file holds a conflicting lock, it blocks until the
+    /// lock is released if `wait` is set, otherwise returns `WouldBlock`.
+    pub fn lock(&self, ty: Option<LockType>, wait: bool) -> AxResult {
+        let Some(ty) = ty else {
+            release(&self.key, |locks| {
+                locks.whole.retain(|(id, _)| *id != self.id)
+            });
+            return Ok(());
+        };
+        acquire(&self.key, wait, |locks| {
+            let busy = locks
+                .whole
+                .iter()
+                .any(|(id, held)| *id != self.id && conflicts(*held, ty));
+            if !busy {
+                locks.whole.retain(|(id, _)| *id != self.id);
+                locks.whole.push((self.id, ty));
+            }
+            !busy
+        })
+    }
+
+    /// Takes a lock of the type `ty` on the bytes `start..end` of the file
+    /// for `owner`, or releases it if `ty` is `None`. `end` is `u64::MAX` to
+    /// lock up to the end of the file, however it grows.
+    ///
+    /// The locks of `owner` in the range are replaced, and split if they are
+    /// partially in it. The file must be opened for reading to take a shared
+    /// lock, and for writing to take an exclusive one.
+    ///
+    /// If another owner holds a conflicting lock, it blocks until the lock is
+    /// released if `wait` is set, otherwise returns `WouldBlock`.
+    pub fn lock_range(
+        &self,
+        owner: u64,
+        ty: Option<LockType>,
+        start: u64,
+        end: u64,
+        wait: bool,
+    ) -> AxResult {
+        if start >= end {
+            return ax_err!(InvalidInput);
+        }
+        let Some(ty) = ty else {
+            release(&self.key, |locks| {
+                remove_range(&mut locks.records, owner, start, end)
+            });
+            return Ok(());
+        };
+        let cap = match ty {
+            LockType::Shared => Cap::READ,
+            LockType::Exclusive => Cap::WRITE,
+        };
+        if !self.cap.contains(cap) {
+            return ax_err!(PermissionDenied);
+        }
+        acquire(&self.key, wait, |locks| {
+            let busy = locks
+                .records
+                .iter()
+                .any(|r| r.owner != owner && overlaps(r, start, end) && conflicts(r.ty, ty));
+            if !busy {
+                let lock = RecordLock {
+                    ty,
+                    start,
+                    end,
+                    owner,
+                };
+                insert_record(&mut locks.records, lock);
+            }
+            !busy
+        })
+    }
+
+    /// Returns the first lock of another owner which prevents `owner` from
+    /// locking the bytes `start..end` of the file with the type `ty`, or
+    /// `None` if it can.
+    pub fn test_lock(&self, owner: u64, ty: LockType, start: u64, end: u64) -> Option<RecordLock> {
+        let table = LOCKS.lock();
+        let locks = table.get(&self.key)?;
+        locks
+            .records
+            .iter()
+            .find(|r| r.owner != owner && overlaps(r, start, end) && conflicts(r.ty, ty))
+            .copied()
+    }
+
+    /// Releases all the byte-range locks of `owner` on the file.
+    pub fn unlock_all(&self, owner: u64) {
+        release(&self.key, |locks| {
+            locks.records.retain(|r| r.owner != owner)
+        });
+    }
+}
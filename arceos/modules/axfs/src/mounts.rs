@@ -1,8 +1,39 @@
 use alloc::sync::Arc;
+use axerrno::{ax_err, AxResult};
 use axfs_vfs::{VfsNodeType, VfsOps, VfsResult};
 
 use crate::fs;
 
+/// Creates a filesystem of the type `fstype` to be mounted at runtime.
+///
+/// The disk-based filesystems are created on the block device file `source`,
+/// which is ignored by the others.
+#[allow(unused_variables)]
+pub(crate) fn new_fs(source: &str, fstype: &str) -> AxResult<Arc<dyn VfsOps>> {
+    match fstype {
+        #[cfg(feature = "ramfs")]
+        "tmpfs" | "ramfs" => Ok(ramfs()),
+        #[cfg(all(feature = "extfs", feature = "devfs"))]
+        "ext2" | "ext3" | "ext4" => {
+            let (node, _) = crate::root::lookup(None, source)?;
+            let Some(dev) = node.as_any().downcast_ref::<crate::dev::BlockDev>() else {
+                return ax_err!(InvalidInput, "not a block device");
+            };
+            Ok(Arc::new(fs::extfs::ExtFileSystem::new(dev.disk())?))
+        }
+        #[cfg(feature = "overlayfs")]
+        "overlay" => {
+            let (lower, _) = crate::root::lookup(None, source)?;
+            if !lower.get_attr()?.is_dir() {
+                return ax_err!(NotADirectory);
+            }
+            let overlay = fs::overlayfs::OverlayFileSystem::with_lower_dir(lower);
+            Ok(Arc::new(overlay))
+        }
+        _ => ax_err!(Unsupported, "unknown filesystem type"),
+    }
+}
+
 #[cfg(feature = "devfs")]
 pub(crate) fn devfs() -> Arc<fs::devfs::DeviceFileSystem> {
     let null = fs::devfs::NullDev;
@@ -13,7 +44,18 @@ pub(crate) fn devfs() -> Arc<fs::devfs::DeviceFileSystem> {
     devfs.add("null", Arc::new(null));
     devfs.add("zero", Arc::new(zero));
     foo_dir.add("bar", Arc::new(bar));
-    Arc::new(devfs)
+
+    crate::dev::init_input_irq();
+    let tty = Arc::new(crate::devfs::TtyDev);
+    devfs.add("console", tty.clone());
+    devfs.add("tty", tty);
+    let random = Arc::new(crate::devfs::RandomDev);
+    devfs.add("random", random.clone());
+    devfs.add("urandom", random);
+
+    let devfs = Arc::new(devfs);
+    crate::devfs::set_global(&devfs);
+    devfs
 }
 
 #[cfg(feature = "ramfs")]
@@ -22,59 +64,141 @@ pub(crate) fn ramfs() -> Arc<fs::ramfs::RamFileSystem> {
 }
 
 #[cfg(feature = "procfs")]
-pub(crate) fn procfs() -> VfsResult<Arc<fs::ramfs::RamFileSystem>> {
-    let procfs = fs::ramfs::RamFileSystem::new();
-    let proc_root = procfs.root_dir();
-
-    // Create /proc/sys/net/core/somaxconn
-    proc_root.create("sys", VfsNodeType::Dir)?;
-    proc_root.create("sys/net", VfsNodeType::Dir)?;
-    proc_root.create("sys/net/core", VfsNodeType::Dir)?;
-    proc_root.create("sys/net/core/somaxconn", VfsNodeType::File)?;
-    let file_somaxconn = proc_root.clone().lookup("./sys/net/core/somaxconn")?;
-    file_somaxconn.write_at(0, b"4096\n")?;
-
-    // Create /proc/sys/vm/overcommit_memory
-    proc_root.create("sys/vm", VfsNodeType::Dir)?;
-    proc_root.create("sys/vm/overcommit_memory", VfsNodeType::File)?;
-    let file_over = proc_root.clone().lookup("./sys/vm/overcommit_memory")?;
-    file_over.write_at(0, b"0\n")?;
-
-    // Create /proc/self/stat
+pub(crate) fn procfs() -> VfsResult<Arc<fs::procfs::ProcFileSystem>> {
+    let procfs = fs::procfs::ProcFileSystem::new();
+    let proc_root = procfs.static_root();
+
+    // Create /proc/self/stat, which is replaced by the per-process directory
+    // if the kernel registers `ProcessInfo`.
     proc_root.create("self", VfsNodeType::Dir)?;
     proc_root.create("self/stat", VfsNodeType::File)?;
 
+    // Files generated from the kernel state.
+    use fs::procfs::ProcFile;
+    procfs.add_file("meminfo", ProcFile::new(procfs_files::meminfo))?;
+    procfs.add_file("cpuinfo", ProcFile::new(procfs_files::cpuinfo))?;
+    procfs.add_file("uptime", ProcFile::new(procfs_files::uptime))?;
+    procfs.add_file(
+        "sys/kernel/printk",
+        ProcFile::new_writable(procfs_files::printk, procfs_files::set_printk),
+    )?;
+
+    procfs.set_global();
     Ok(Arc::new(procfs))
 }
 
+#[cfg(feature = "procfs")]
+mod procfs_files {
+    use alloc::format;
+    use alloc::string::String;
+    use axfs_vfs::{VfsError, VfsResult};
+    use axhal::mem::PAGE_SIZE_4K;
+    use log::LevelFilter;
+
+    /// Generates `/proc/meminfo`.
+    pub fn meminfo() -> String {
+        let allocator = axalloc::global_allocator();
+        let pages = allocator.used_pages() + allocator.available_pages();
+        let total = pages * PAGE_SIZE_4K / 1024;
+        // Free bytes in the heap can be used as well.
+        let free =
+            (allocator.available_pages() * PAGE_SIZE_4K + allocator.available_bytes()) / 1024;
+        let (cached, dirty) = crate::cache::stats();
+        let cached = cached * crate::cache::PAGE_SIZE / 1024;
+        let dirty = dirty * crate::cache::PAGE_SIZE / 1024;
+        // The clean pages in the cache are dropped on memory pressure.
+        let available = free + cached - dirty;
+        format!(
+            "MemTotal:       {:8} kB\nMemFree:        {:8} kB\nMemAvailable:   {:8} kB\n\
+             Cached:         {:8} kB\nDirty:          {:8} kB\n",
+            total, free, available, cached, dirty
+        )
+    }
+
+    /// Generates `/proc/cpuinfo`.
+    pub fn cpuinfo() -> String {
+        let mut out = String::new();
+        for cpu in 0..axconfig::SMP {
+            out += &format!(
+                "processor\t: {}\narch\t\t: {}\nplatform\t: {}\n\n",
+                cpu,
+                axconfig::ARCH,
+                axconfig::PLATFORM
+            );
+        }
+        out
+    }
+
+    /// Generates `/proc/uptime`. The idle time is not accounted.
+    pub fn uptime() -> String {
+        let uptime = axhal::time::monotonic_time();
+        format!(
+            "{}.{:02} 0.00\n",
+            uptime.as_secs(),
+            uptime.subsec_millis() / 10
+        )
+    }
+
+    /// Generates `/proc/sys/kernel/printk`, the console log level: messages
+    /// with a lower Linux log level (error 3, warning 4, info 6, debug 7,
+    /// trace 8) are printed.
+    pub fn printk() -> String {
+        let level = match log::max_level() {
+            LevelFilter::Off => 0,
+            LevelFilter::Error => 4,
+            LevelFilter::Warn => 5,
+            LevelFilter::Info => 7,
+            LevelFilter::Debug => 8,
+            LevelFilter::Trace => 9,
+        };
+        format!("{}\n", level)
+    }
+
+    /// Sets the console log level from `/proc/sys/kernel/printk`.
+    pub fn set_printk(value: &str) -> VfsResult {
+        let level: u32 = value
+            .split_whitespace()
+            .next()
+            .and_then(|s| s.parse().ok())
+            .ok_or(VfsError::InvalidInput)?;
+        log::set_max_level(match level {
+            0 => LevelFilter::Off,
+            1..=4 => LevelFilter::Error,
+            5..=6 => LevelFilter::Warn,
+            7 => LevelFilter::Info,
+            8 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        });
+        Ok(())
+    }
+}
+
 #[cfg(feature = "sysfs")]
-pub(crate) fn sysfs() -> VfsResult<Arc<fs::ramfs::RamFileSystem>> {
-    let sysfs = fs::ramfs::RamFileSystem::new();
+pub(crate) fn sysfs() -> VfsResult<Arc<axfs_ramfs::RamFileSystem>> {
+    use fs::sysfs::add_file_at;
+
+    let sysfs = axfs_ramfs::RamFileSystem::new();
     let sys_root = sysfs.root_dir();
 
-    // Create /sys/kernel/mm/transparent_hugepage/enabled
-    sys_root.create("kernel", VfsNodeType::Dir)?;
-    sys_root.create("kernel/mm", VfsNodeType::Dir)?;
-    sys_root.create("kernel/mm/transparent_hugepage", VfsNodeType::Dir)?;
-    sys_root.create("kernel/mm/transparent_hugepage/enabled", VfsNodeType::File)?;
-    let file_hp = sys_root
-        .clone()
-        .lookup("./kernel/mm/transparent_hugepage/enabled")?;
-    file_hp.write_at(0, b"always [madvise] never\n")?;
-
-    // Create /sys/devices/system/clocksource/clocksource0/current_clocksource
-    sys_root.create("devices", VfsNodeType::Dir)?;
-    sys_root.create("devices/system", VfsNodeType::Dir)?;
-    sys_root.create("devices/system/clocksource", VfsNodeType::Dir)?;
-    sys_root.create("devices/system/clocksource/clocksource0", VfsNodeType::Dir)?;
-    sys_root.create(
+    add_file_at(
+        sys_root.clone(),
+        "kernel/mm/transparent_hugepage/enabled",
+        "always [madvise] never\n",
+    )?;
+
+    // The clock source of axhal, rather than the one of the host.
+    let clock_source = alloc::format!("{}\n", axhal::time::CLOCK_SOURCE);
+    add_file_at(
+        sys_root.clone(),
         "devices/system/clocksource/clocksource0/current_clocksource",
-        VfsNodeType::File,
+        &clock_source,
+    )?;
+    add_file_at(
+        sys_root,
+        "devices/system/clocksource/clocksource0/available_clocksource",
+        &clock_source,
     )?;
-    let file_cc = sys_root
-        .clone()
-        .lookup("devices/system/clocksource/clocksource0/current_clocksource")?;
-    file_cc.write_at(0, b"tsc\n")?;
 
+    fs::sysfs::set_global(&sysfs);
     Ok(Arc::new(sysfs))
 }
@@ -0,0 +1,373 @@
+//! Notifications of the changes of files and directories, like `inotify` of
+//! Linux.
+//!
+//! A [`Watcher`] watches files and directories by their paths, and queues
+//! the events on them. The events on a file are reported to the watches on
+//! it, and to the watches on its parent directory with the name of the file.
+//!
+//! The events are emitted by the changes made through this crate, e.g.,
+//! [`api::write`](crate::api::write) and [`api::rename`](crate::api::rename).
+//! As the files are watched by their paths, the hard links of a watched file
+//! are not watched.
+
+use alloc::{collections::VecDeque, string::String, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicI32, AtomicU32, Ordering};
+
+use axerrno::{ax_err, AxResult};
+use axsync::spin::SpinNoIrq;
+use bitflags::bitflags;
+use cap_access::Cap;
+
+use crate::fops::access_cap;
+
+/// The maximum number of events queued in a watcher, the later ones are
+/// dropped and reported by an event with [`EventMask::Q_OVERFLOW`].
+pub const MAX_QUEUED_EVENTS: usize = 16384;
+
+bitflags! {
+    /// The kinds of events to watch and the flags of an event, which are the
+    /// same as the `IN_*` constants of Linux.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct EventMask: u32 {
+        /// The file was modified, i.e., written or truncated.
+        const MODIFY = 0x0000_0002;
+        /// The metadata of the file changed, e.g., the permissions.
+        const ATTRIB = 0x0000_0004;
+        /// The file was moved out of the watched directory.
+        const MOVED_FROM = 0x0000_0040;
+        /// The file was moved into the watched directory.
+        const MOVED_TO = 0x0000_0080;
+        /// The file was created in the watched directory.
+        const CREATE = 0x0000_0100;
+        /// The file was deleted from the watched directory.
+        const DELETE = 0x0000_0200;
+        /// The watched file itself was deleted.
+        const DELETE_SELF = 0x0000_0400;
+        /// The watched file itself was moved.
+        const MOVE_SELF = 0x0000_0800;
+        /// Both [`MOVED_FROM`](Self::MOVED_FROM) and [`MOVED_TO`](Self::MOVED_TO).
+        const MOVE = Self::MOVED_FROM.bits() | Self::MOVED_TO.bits();
+        /// All the events above.
+        const ALL_EVENTS = 0x0000_0fc6;
+
+        /// The events were dropped as the queue was full.
+        const Q_OVERFLOW = 0x0000_4000;
+        /// The watch was removed, explicitly or as the file was deleted.
+        const IGNORED = 0x0000_8000;
+        /// The file of the event is a directory.
+        const ISDIR = 0x4000_0000;
+
+        /// Only watches the path if it's a directory.
+        const ONLYDIR = 0x0100_0000;
+        /// Does not follow the path if it's a symbolic link.
+        const DONT_FOLLOW = 0x0200_0000;
+        /// Adds the events to the watch of the path if there is one, instead
+        /// of replacing them.
+        const MASK_ADD = 0x2000_0000;
+        /// Removes the watch after one event.
+        const ONESHOT = 0x8000_0000;
+    }
+}
+
+/// An event on a watched file or directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    /// The watch descriptor, or -1 for [`EventMask::Q_OVERFLOW`].
+    pub wd: i32,
+    /// The kind of the event and its flags.
+    pub mask: EventMask,
+    /// The same nonzero number for the [`EventMask::MOVED_FROM`] and
+    /// [`EventMask::MOVED_TO`] events of a rename, otherwise 0.
+    pub cookie: u32,
+    /// The name of the file in the watched directory, or empty if the event
+    /// is on the watched file itself.
+    pub name: String,
+}
+
+/// The event queue of a watcher.
+struct Queue {
+    events: SpinNoIrq<VecDeque<Event>>,
+    #[cfg(feature = "multitask")]
+    wait_queue: axtask::WaitQueue,
+}
+
+struct Watch {
+    wd: i32,
+    /// The absolute path of the watched file.
+    path: String,
+    mask: EventMask,
+    queue: Arc<Queue>,
+}
+
+static WATCHES: SpinNoIrq<Vec<Watch>> = SpinNoIrq::new(Vec::new());
+
+impl Queue {
+    fn push(&self, event: Event) {
+        let mut events = self.events.lock();
+        if events.back() == Some(&event) {
+            return; // merged with the same one not read yet
+        }
+        if events.len() < MAX_QUEUED_EVENTS {
+            events.push_back(event);
+        } else if events.back().map(|e| e.mask) != Some(EventMask::Q_OVERFLOW) {
+            events.push_back(Event {
+                wd: -1,
+                mask: EventMask::Q_OVERFLOW,
+                cookie: 0,
+                name: String::new(),
+            });
+        }
+        drop(events);
+        #[cfg(feature = "multitask")]
+        self.wait_queue.notify_all(false);
+    }
+}
+
+/// A watcher of the changes of files and directories.
+///
+/// The watches are removed when it's dropped.
+pub struct Watcher {
+    queue: Arc<Queue>,
+}
+
+impl Watcher {
+    /// Creates a watcher without any watches.
+    pub fn new() -> Self {
+        Self {
+            queue: Arc::new(Queue {
+                events: SpinNoIrq::new(VecDeque::new()),
+                #[cfg(feature = "multitask")]
+                wait_queue: axtask::WaitQueue::new(),
+            }),
+        }
+    }
+
+    /// Watches the events in `mask` on the file or directory at the path,
+    /// which must be readable by the current task. Returns the watch
+    /// descriptor, which is in the events of the watch.
+    ///
+    /// If the path is already watched by this watcher, the events of its
+    /// watch are replaced, or added if `mask` contains
+    /// [`EventMask::MASK_ADD`]. The path is resolved as `mask` contains
+    /// [`EventMask::ONLYDIR`] and [`EventMask::DONT_FOLLOW`].
+    pub fn add_watch(&self, path: &str, mask: EventMask) -> AxResult<i32> {
+        let follow = !mask.contains(EventMask::DONT_FOLLOW);
+        let (abs_path, node, _) = crate::root::lookup_at(None, path, follow)?;
+        let attr = node.get_attr()?;
+        if mask.contains(EventMask::ONLYDIR) && !attr.is_dir() {
+            return ax_err!(NotADirectory);
+        }
+        if !access_cap(&node, attr.perm()).contains(Cap::READ) {
+            return ax_err!(PermissionDenied);
+        }
+        let events = mask & (EventMask::ALL_EVENTS | EventMask::ONESHOT);
+        if events.difference(EventMask::ONESHOT).is_empty() {
+            return ax_err!(InvalidInput);
+        }
+
+        let mut watches = WATCHES.lock();
+        let existing = watches
+            .iter_mut()
+            .find(|w| Arc::ptr_eq(&w.queue, &self.queue) && w.path == abs_path);
+        if let Some(watch) = existing {
+            if mask.contains(EventMask::MASK_ADD) {
+                watch.mask |= events;
+            } else {
+                watch.mask = events;
+            }
+            return Ok(watch.wd);
+        }
+        static NEXT_WD: AtomicI32 = AtomicI32::new(1);
+        let wd = NEXT_WD.fetch_add(1, Ordering::Relaxed);
+        watches.push(Watch {
+            wd,
+            path: abs_path,
+            mask: events,
+            queue: self.queue.clone(),
+        });
+        Ok(wd)
+    }
+
+    /// Removes the watch `wd`, an [`EventMask::IGNORED`] event is queued.
+    pub fn remove_watch(&self, wd: i32) -> AxResult {
+        let mut watches = WATCHES.lock();
+        let idx = watches
+            .iter()
+            .position(|w| w.wd == wd && Arc::ptr_eq(&w.queue, &self.queue))
+            .ok_or(axerrno::AxError::InvalidInput)?;
+        watches.remove(idx);
+        drop(watches);
+        self.queue.push(ignored(wd));
+        Ok(())
+    }
+
+    /// Returns whether there are events to read.
+    pub fn has_events(&self) -> bool {
+        !self.queue.events.lock().is_empty()
+    }
+
+    /// Reads the queued events in order, as long as `fits` returns `true`
+    /// for them, e.g., while they fit in a buffer.
+    ///
+    /// If there are no events, it blocks until an event is queued if `wait`
+    /// is set, otherwise returns `WouldBlock`.
+    pub fn read_events(
+        &self,
+        wait: bool,
+        mut fits: impl FnMut(&Event) -> bool,
+    ) -> AxResult<Vec<Event>> {
+        if !self.has_events() {
+            // Without `multitask`, no one else can queue an event.
+            if !wait || cfg!(not(feature = "multitask")) {
+                return ax_err!(WouldBlock);
+            }
+            #[cfg(feature = "multitask")]
+            self.queue.wait_queue.wait_until(|| self.has_events());
+        }
+        let mut events = self.queue.events.lock();
+        let mut read = Vec::new();
+        while let Some(event) = events.front() {
+            if !fits(event) {
+                break;
+            }
+            read.extend(events.pop_front());
+        }
+        Ok(read)
+    }
+}
+
+impl Default for Watcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        WATCHES
+            .lock()
+            .retain(|w| !Arc::ptr_eq(&w.queue, &self.queue));
+    }
+}
+
+fn ignored(wd: i32) -> Event {
+    Event {
+        wd,
+        mask: EventMask::IGNORED,
+        cookie: 0,
+        name: String::new(),
+    }
+}
+
+/// Splits an absolute path into the parent directory and the name.
+fn split_path(path: &str) -> (&str, &str) {
+    match path.rsplit_once('/') {
+        Some(("", name)) => ("/", name),
+        Some((parent, name)) => (parent, name),
+        None => ("", path),
+    }
+}
+
+/// Reports an event on the file at the absolute path `path`, which is
+/// `self_mask` to the watches on the file, and `parent_mask` to the watches
+/// on its parent directory. The watches on the file are removed if `removed`
+/// is set.
+fn emit(path: &str, self_mask: EventMask, parent_mask: EventMask, cookie: u32, removed: bool) {
+    let (parent, name) = split_path(path);
+    let mut events = Vec::new();
+    let mut watches = WATCHES.lock();
+    watches.retain(|w| {
+        let (mask, name) = if w.path == path {
+            (self_mask, "")
+        } else if w.path == parent {
+            (parent_mask, name)
+        } else {
+            return true;
+        };
+        if w.mask.intersects(mask) {
+            let event = Event {
+                wd: w.wd,
+                mask,
+                cookie: if name.is_empty() { 0 } else { cookie },
+                name: name.into(),
+            };
+            events.push((w.queue.clone(), event));
+            if w.mask.contains(EventMask::ONESHOT) {
+                events.push((w.queue.clone(), ignored(w.wd)));
+                return false;
+            }
+        }
+        if removed && w.path == path {
+            events.push((w.queue.clone(), ignored(w.wd)));
+            return false;
+        }
+        true
+    });
+    drop(watches);
+    for (queue, event) in events {
+        queue.push(event);
+    }
+}
+
+fn dir_flag(is_dir: bool) -> EventMask {
+    if is_dir {
+        EventMask::ISDIR
+    } else {
+        EventMask::empty()
+    }
+}
+
+/// Reports that the file at the absolute path was created.
+pub(crate) fn created(path: &str, is_dir: bool) {
+    let mask = EventMask::CREATE | dir_flag(is_dir);
+    emit(path, EventMask::empty(), mask, 0, false);
+}
+
+/// Reports that the file at the absolute path was modified.
+pub(crate) fn modified(path: &str) {
+    emit(path, EventMask::MODIFY, EventMask::MODIFY, 0, false);
+}
+
+/// Reports that the metadata of the file at the absolute path changed.
+pub(crate) fn attrib_changed(path: &str, is_dir: bool) {
+    let mask = EventMask::ATTRIB | dir_flag(is_dir);
+    emit(path, mask, mask, 0, false);
+}
+
+/// Reports that the file at the absolute path was deleted, and removes the
+/// watches on it.
+pub(crate) fn deleted(path: &str, is_dir: bool) {
+    let mask = EventMask::DELETE | dir_flag(is_dir);
+    emit(path, EventMask::DELETE_SELF, mask, 0, true);
+}
+
+/// Reports that the file at the absolute path `old` was moved to `new`, and
+/// moves the watches on it and in it to the new path.
+pub(crate) fn renamed(old: &str, new: &str, is_dir: bool) {
+    static NEXT_COOKIE: AtomicU32 = AtomicU32::new(1);
+    let cookie = NEXT_COOKIE.fetch_add(1, Ordering::Relaxed);
+    let flag = dir_flag(is_dir);
+    // the destination is replaced
+    emit(new, EventMask::DELETE_SELF, EventMask::empty(), 0, true);
+    emit(
+        old,
+        EventMask::MOVE_SELF,
+        EventMask::MOVED_FROM | flag,
+        cookie,
+        false,
+    );
+    for watch in WATCHES.lock().iter_mut() {
+        if let Some(rest) = watch.path.strip_prefix(old) {
+            if rest.is_empty() || rest.starts_with('/') {
+                watch.path = String::from(new) + rest;
+            }
+        }
+    }
+    emit(
+        new,
+        EventMask::empty(),
+        EventMask::MOVED_TO | flag,
+        cookie,
+        false,
+    );
+}
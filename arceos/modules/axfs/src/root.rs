@@ -1,250 +1,564 @@
-//! Root directory of the filesystem
+//! Root directory of the filesystem, which is a tree of the mounted
+//! filesystems.
 //!
-//! TODO: it doesn't work very well if the mount points have containment relationships.
+//! A path is first made absolute and normalized, then it is handled by the
+//! filesystem of the deepest mount point containing it, which is found by
+//! matching the path component by component.
 
-use alloc::{string::String, sync::Arc, vec::Vec};
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec, vec::Vec};
 use axerrno::{ax_err, AxError, AxResult};
-use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps, VfsResult};
+use axfs_vfs::{VfsNodeRef, VfsNodeType, VfsOps};
 use axsync::Mutex;
+use cap_access::Cap;
 use lazyinit::LazyInit;
 
-use crate::{api::FileType, fs, mounts};
+use crate::fops::{access_cap, FilePerm};
+use crate::{fs, mounts, notify};
 
-static CURRENT_DIR_PATH: Mutex<String> = Mutex::new(String::new());
-static CURRENT_DIR: LazyInit<Mutex<VfsNodeRef>> = LazyInit::new();
-
-struct MountPoint {
-    path: &'static str,
+/// A mounted filesystem.
+///
+/// The opened files, the opened directories and the current directory hold
+/// a reference to the mount containing them, which keeps it busy.
+pub(crate) struct Mount {
     fs: Arc<dyn VfsOps>,
 }
 
+/// A shared reference to a [`Mount`].
+pub(crate) type MountRef = Arc<Mount>;
+
+/// A node of the mount tree, for a path component leading to mount points.
+#[derive(Default)]
+struct MountNode {
+    mount: Option<MountRef>,
+    children: BTreeMap<String, MountNode>,
+}
+
 struct RootDirectory {
-    main_fs: Arc<dyn VfsOps>,
-    mounts: Vec<MountPoint>,
+    tree: Mutex<MountNode>,
+}
+
+struct CurrentDir {
+    /// The absolute path, ends with `/`.
+    path: String,
+    mount: Option<MountRef>,
+}
+
+static CURRENT_DIR: Mutex<CurrentDir> = Mutex::new(CurrentDir {
+    path: String::new(),
+    mount: None,
+});
+
+static ROOT_DIR: LazyInit<RootDirectory> = LazyInit::new();
+
+/// Iterates over the components of a normalized path.
+fn components(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|s| !s.is_empty())
 }
 
-static ROOT_DIR: LazyInit<Arc<RootDirectory>> = LazyInit::new();
+impl Mount {
+    fn new(path: &str, fs: Arc<dyn VfsOps>, mount_point: VfsNodeRef) -> AxResult<Self> {
+        fs.mount(path, mount_point)?;
+        Ok(Self { fs })
+    }
 
-impl MountPoint {
-    pub fn new(path: &'static str, fs: Arc<dyn VfsOps>) -> Self {
-        Self { path, fs }
+    /// Looks up the path relative to the root of the mounted filesystem.
+    fn lookup(&self, path: &str) -> AxResult<VfsNodeRef> {
+        let root = self.fs.root_dir();
+        if path.is_empty() {
+            Ok(root)
+        } else {
+            root.lookup(path)
+        }
     }
 }
 
-impl Drop for MountPoint {
+impl Drop for Mount {
     fn drop(&mut self) {
         self.fs.umount().ok();
     }
 }
 
+impl MountNode {
+    /// Finds the deepest mount containing the normalized absolute path,
+    /// returns it and the rest of the path inside it.
+    fn find<'a>(&self, path: &'a str) -> (MountRef, &'a str) {
+        let mut node = self;
+        let mut rest = path.trim_start_matches('/');
+        let mut found = (self.mount.as_ref().unwrap(), rest);
+        while !rest.is_empty() {
+            let (name, next) = rest.split_once('/').unwrap_or((rest, ""));
+            match node.children.get(name) {
+                Some(child) => node = child,
+                None => break,
+            }
+            rest = next;
+            if let Some(mount) = &node.mount {
+                found = (mount, rest);
+            }
+        }
+        (found.0.clone(), found.1)
+    }
+
+    fn get(&self, path: &str) -> Option<&MountNode> {
+        components(path).try_fold(self, |node, name| node.children.get(name))
+    }
+
+    fn insert(&mut self, path: &str, mount: MountRef) {
+        let node = components(path).fold(self, |node, name| {
+            node.children.entry(name.into()).or_default()
+        });
+        node.mount = Some(mount);
+    }
+
+    /// Removes the mount at the path from the tree, and the nodes that no
+    /// longer lead to any mount points.
+    ///
+    /// If `detach` is set, it is removed even if it is busy, together with
+    /// the mounts inside it.
+    fn remove(&mut self, names: &[&str], detach: bool) -> AxResult<MountRef> {
+        let Some((name, names)) = names.split_first() else {
+            let Some(mount) = &self.mount else {
+                return ax_err!(InvalidInput, "not a mount point");
+            };
+            if !detach && (!self.children.is_empty() || Arc::strong_count(mount) > 1) {
+                return ax_err!(ResourceBusy);
+            }
+            self.children.clear();
+            return Ok(self.mount.take().unwrap());
+        };
+        let child = self.children.get_mut(*name).ok_or(AxError::InvalidInput)?;
+        let mount = child.remove(names, detach)?;
+        if child.mount.is_none() && child.children.is_empty() {
+            self.children.remove(*name);
+        }
+        Ok(mount)
+    }
+}
+
 impl RootDirectory {
-    pub const fn new(main_fs: Arc<dyn VfsOps>) -> Self {
+    fn new(main_fs: Arc<dyn VfsOps>) -> Self {
+        let root = MountNode {
+            mount: Some(Arc::new(Mount { fs: main_fs })),
+            children: BTreeMap::new(),
+        };
         Self {
-            main_fs,
-            mounts: Vec::new(),
+            tree: Mutex::new(root),
         }
     }
 
-    pub fn mount(&mut self, path: &'static str, fs: Arc<dyn VfsOps>) -> AxResult {
+    /// Mounts `fs` on the directory at the normalized absolute path.
+    fn mount(&self, path: &str, fs: Arc<dyn VfsOps>) -> AxResult {
         if path == "/" {
             return ax_err!(InvalidInput, "cannot mount root filesystem");
         }
-        if !path.starts_with('/') {
-            return ax_err!(InvalidInput, "mount path must start with '/'");
+        let mut tree = self.tree.lock();
+        let (parent, rest) = tree.find(path);
+        if rest.is_empty() {
+            return ax_err!(ResourceBusy, "mount point already exists");
         }
-        if self.mounts.iter().any(|mp| mp.path == path) {
-            return ax_err!(InvalidInput, "mount point already exists");
+        if tree.get(path).is_some() {
+            // Mounting it would hide the mount points inside.
+            return ax_err!(ResourceBusy, "mount point contains other mount points");
         }
-        // create the mount point in the main filesystem if it does not exist
-        self.main_fs.root_dir().create(path, FileType::Dir)?;
-        fs.mount(path, self.main_fs.root_dir().lookup(path)?)?;
-        self.mounts.push(MountPoint::new(path, fs));
+        let mount_point = parent.lookup(rest)?;
+        if !mount_point.get_attr()?.is_dir() {
+            return ax_err!(NotADirectory);
+        }
+        let mount = Mount::new(path, fs, mount_point)?;
+        tree.insert(path, Arc::new(mount));
         Ok(())
     }
 
-    pub fn _umount(&mut self, path: &str) {
-        self.mounts.retain(|mp| mp.path != path);
+    /// Unmounts the filesystem at the normalized absolute path.
+    fn umount(&self, path: &str, detach: bool) -> AxResult {
+        if path == "/" {
+            return ax_err!(ResourceBusy, "cannot unmount root filesystem");
+        }
+        let names = components(path).collect::<Vec<_>>();
+        let mount = self.tree.lock().remove(&names, detach)?;
+        // The filesystem is unmounted when the last reference is dropped.
+        drop(mount);
+        if crate::dev::cache::sync_all().is_err() {
+            warn!("failed to write back the disks after unmounting {}", path);
+        }
+        Ok(())
     }
 
-    pub fn contains(&self, path: &str) -> bool {
-        self.mounts.iter().any(|mp| mp.path == path)
+    fn is_mount_point(&self, path: &str) -> bool {
+        self.tree
+            .lock()
+            .get(path)
+            .is_some_and(|node| node.mount.is_some())
     }
 
-    fn lookup_mounted_fs<F, T>(&self, path: &str, f: F) -> AxResult<T>
-    where
-        F: FnOnce(Arc<dyn VfsOps>, &str) -> AxResult<T>,
-    {
-        debug!("lookup at root: {}", path);
-        let path = path.trim_matches('/');
-        if let Some(rest) = path.strip_prefix("./") {
-            return self.lookup_mounted_fs(rest, f);
-        }
+    fn find(&self, path: &str) -> (MountRef, String) {
+        let (mount, rest) = self.tree.lock().find(path);
+        (mount, rest.into())
+    }
 
-        let mut idx = 0;
-        let mut max_len = 0;
+    fn lookup(&self, path: &str) -> AxResult<(VfsNodeRef, MountRef)> {
+        debug!("lookup at root: {}", path);
+        let (mount, rest) = self.find(path);
+        Ok((mount.lookup(&rest)?, mount))
+    }
 
-        // Find the filesystem that has the longest mounted path match
-        // TODO: more efficient, e.g. trie
-        for (i, mp) in self.mounts.iter().enumerate() {
-            // skip the first '/'
-            if path.starts_with(&mp.path[1..]) && mp.path.len() - 1 > max_len {
-                max_len = mp.path.len() - 1;
-                idx = i;
-            }
+    /// Creates a node at `path` with the mode and the owner, see
+    /// [`fs::create_node`].
+    fn create(&self, path: &str, ty: VfsNodeType, mode: u32, uid: u32, gid: u32) -> AxResult {
+        let (mount, rest) = self.find(path);
+        if rest.is_empty() {
+            return Ok(()); // already exists
         }
+        let (dir, name) = match rest.rsplit_once('/') {
+            Some((parent, name)) => (mount.lookup(parent)?, name),
+            None => (mount.fs.root_dir(), rest.as_str()),
+        };
+        fs::create_node(&dir, name, ty, mode, uid, gid)
+    }
 
-        if max_len == 0 {
-            f(self.main_fs.clone(), path) // not matched any mount point
+    fn remove(&self, path: &str) -> AxResult {
+        let (mount, rest) = self.find(path);
+        if rest.is_empty() {
+            ax_err!(PermissionDenied) // cannot remove mount points
         } else {
-            f(self.mounts[idx].fs.clone(), &path[max_len..]) // matched at `idx`
+            mount.fs.root_dir().remove(&rest)
         }
     }
-}
-
-impl VfsNodeOps for RootDirectory {
-    axfs_vfs::impl_vfs_dir_default! {}
-
-    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        self.main_fs.root_dir().get_attr()
-    }
 
-    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
-        self.lookup_mounted_fs(path, |fs, rest_path| fs.root_dir().lookup(rest_path))
+    fn rename(&self, src_path: &str, dst_path: &str) -> AxResult {
+        let (src_mount, src_rest) = self.find(src_path);
+        let (dst_mount, dst_rest) = self.find(dst_path);
+        if src_rest.is_empty() {
+            ax_err!(PermissionDenied) // cannot rename mount points
+        } else if dst_rest.is_empty() {
+            ax_err!(ResourceBusy)
+        } else if !Arc::ptr_eq(&src_mount, &dst_mount) {
+            ax_err!(Unsupported, "cannot rename across filesystems")
+        } else {
+            src_mount.fs.root_dir().rename(&src_rest, &dst_rest)
+        }
     }
+}
 
-    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
-        self.lookup_mounted_fs(path, |fs, rest_path| {
-            if rest_path.is_empty() {
-                Ok(()) // already exists
-            } else {
-                fs.root_dir().create(rest_path, ty)
+/// Creates the filesystem on the disk, which is detected by the magic number
+/// in the superblock.
+///
+/// If the ext2/4 filesystem on it cannot be mounted, e.g., it uses features
+/// not supported, an empty RAM filesystem is used instead.
+#[cfg(not(feature = "myfs"))]
+#[allow(unused_mut, unused_variables)]
+fn new_disk_fs(mut disk: crate::dev::Disk) -> Arc<dyn VfsOps> {
+    #[cfg(feature = "extfs")]
+    if fs::extfs::ExtFileSystem::probe(&mut disk) {
+        match fs::extfs::ExtFileSystem::new(disk) {
+            Ok(ext_fs) => return Arc::new(ext_fs),
+            Err(e) => {
+                error!("failed to mount ext2/ext4 on the disk: {:?}", e);
+                cfg_if::cfg_if! {
+                    if #[cfg(feature = "ramfs")] {
+                        warn!("use an empty RAM filesystem as the root");
+                        return mounts::ramfs();
+                    } else {
+                        panic!("no filesystem to mount on the root");
+                    }
+                }
             }
-        })
+        }
     }
-
-    fn remove(&self, path: &str) -> VfsResult {
-        self.lookup_mounted_fs(path, |fs, rest_path| {
-            if rest_path.is_empty() {
-                ax_err!(PermissionDenied) // cannot remove mount points
-            } else {
-                fs.root_dir().remove(rest_path)
-            }
-        })
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "fatfs")] {
+            new_fat_fs(disk)
+        } else {
+            panic!("no supported filesystem found on the disk")
+        }
     }
+}
 
-    fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
-        self.lookup_mounted_fs(src_path, |fs, rest_path| {
-            if rest_path.is_empty() {
-                ax_err!(PermissionDenied) // cannot rename mount points
-            } else {
-                fs.root_dir().rename(rest_path, dst_path)
-            }
-        })
-    }
+#[cfg(all(feature = "fatfs", not(feature = "myfs")))]
+fn new_fat_fs(disk: crate::dev::Disk) -> Arc<dyn VfsOps> {
+    static FAT_FS: LazyInit<Arc<fs::fatfs::FatFileSystem>> = LazyInit::new();
+    FAT_FS.init_once(Arc::new(fs::fatfs::FatFileSystem::new(disk)));
+    FAT_FS.init();
+    FAT_FS.clone()
 }
 
-pub(crate) fn init_rootfs(disk: crate::dev::Disk) {
+/// Creates the main filesystem on the disk, which is mounted on `/`.
+#[allow(clippy::let_and_return)] // if not wrapped in an overlay
+pub(crate) fn new_main_fs(disk: crate::dev::Disk) -> Arc<dyn VfsOps> {
     cfg_if::cfg_if! {
         if #[cfg(feature = "myfs")] { // override the default filesystem
             let main_fs = fs::myfs::new_myfs(disk);
-        } else if #[cfg(feature = "fatfs")] {
-            static FAT_FS: LazyInit<Arc<fs::fatfs::FatFileSystem>> = LazyInit::new();
-            FAT_FS.init_once(Arc::new(fs::fatfs::FatFileSystem::new(disk)));
-            FAT_FS.init();
-            let main_fs = FAT_FS.clone();
+        } else {
+            let main_fs = new_disk_fs(disk);
         }
     }
 
-    let mut root_dir = RootDirectory::new(main_fs);
+    #[cfg(feature = "overlay-root")]
+    let main_fs: Arc<dyn VfsOps> = Arc::new(fs::overlayfs::OverlayFileSystem::new(main_fs));
+
+    main_fs
+}
+
+pub(crate) fn init_rootfs(main_fs: Arc<dyn VfsOps>) {
+    let root_dir = RootDirectory::new(main_fs.clone());
+    // create the mount point in the main filesystem if it does not exist
+    #[allow(unused_variables)]
+    let mount = |path: &str, fs: Arc<dyn VfsOps>| {
+        match main_fs.root_dir().create(path, VfsNodeType::Dir) {
+            Ok(()) | Err(AxError::AlreadyExists) => {}
+            Err(e) => return Err(e),
+        }
+        root_dir.mount(path, fs)
+    };
 
     #[cfg(feature = "devfs")]
-    root_dir
-        .mount("/dev", mounts::devfs())
-        .expect("failed to mount devfs at /dev");
+    mount("/dev", mounts::devfs()).expect("failed to mount devfs at /dev");
 
     #[cfg(feature = "ramfs")]
-    root_dir
-        .mount("/tmp", mounts::ramfs())
-        .expect("failed to mount ramfs at /tmp");
+    mount("/tmp", mounts::ramfs()).expect("failed to mount ramfs at /tmp");
 
     // Mount another ramfs as procfs
     #[cfg(feature = "procfs")]
-    root_dir // should not fail
-        .mount("/proc", mounts::procfs().unwrap())
+    mount("/proc", mounts::procfs().unwrap()) // should not fail
         .expect("fail to mount procfs at /proc");
 
     // Mount another ramfs as sysfs
     #[cfg(feature = "sysfs")]
-    root_dir // should not fail
-        .mount("/sys", mounts::sysfs().unwrap())
+    mount("/sys", mounts::sysfs().unwrap()) // should not fail
         .expect("fail to mount sysfs at /sys");
 
-    ROOT_DIR.init_once(Arc::new(root_dir));
-    CURRENT_DIR.init_once(Mutex::new(ROOT_DIR.clone()));
-    *CURRENT_DIR_PATH.lock() = "/".into();
+    ROOT_DIR.init_once(root_dir);
+    let mut cwd = CURRENT_DIR.lock();
+    cwd.path = "/".into();
+    cwd.mount = Some(ROOT_DIR.find("/").0);
 }
 
-fn parent_node_of(dir: Option<&VfsNodeRef>, path: &str) -> VfsNodeRef {
-    if path.starts_with('/') {
-        ROOT_DIR.clone()
+/// The maximum number of symbolic links followed in resolving a path.
+const MAX_SYMLINKS: usize = 40;
+
+fn join_names(names: &[String]) -> String {
+    String::from("/") + &names.join("/")
+}
+
+/// Reads the target of the symbolic link.
+fn read_symlink(node: &VfsNodeRef) -> AxResult<String> {
+    let mut buf = vec![0; node.get_attr()?.size() as usize];
+    let mut read = 0;
+    while read < buf.len() {
+        match node.read_at(read as u64, &mut buf[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    buf.truncate(read);
+    String::from_utf8(buf).map_err(|_| AxError::InvalidData)
+}
+
+/// Joins the path to the directory `dir` (the current directory if `None`),
+/// and resolves it to an absolute path without `.`, `..`, redundant slashes
+/// and symbolic links.
+///
+/// The last component is kept if it is a symbolic link, unless `follow` is
+/// set or the path ends with a slash. It may not exist, e.g., to be created.
+///
+/// Returns `NotFound` if `..` goes beyond the root, as the filesystems do.
+pub(crate) fn resolve_path(dir: Option<&str>, path: &str, follow: bool) -> AxResult<String> {
+    let cwd;
+    let base = if path.starts_with('/') {
+        ""
+    } else if let Some(dir) = dir {
+        dir
     } else {
-        dir.cloned().unwrap_or_else(|| CURRENT_DIR.lock().clone())
+        cwd = CURRENT_DIR.lock().path.clone();
+        &cwd
+    };
+    let follow = follow || path.ends_with('/');
+    let mut names: Vec<String> = components(base).map(String::from).collect();
+    // The components to be resolved, in reverse order.
+    let mut pending: Vec<String> = components(path).rev().map(String::from).collect();
+    let mut links = 0;
+    while let Some(name) = pending.pop() {
+        match name.as_str() {
+            "." => continue,
+            ".." => {
+                names.pop().ok_or(AxError::NotFound)?;
+                continue;
+            }
+            _ => names.push(name),
+        }
+        if pending.is_empty() && !follow {
+            break;
+        }
+        let node = match ROOT_DIR.lookup(&join_names(&names)) {
+            Ok((node, _)) => node,
+            Err(AxError::NotFound) if pending.is_empty() => break,
+            Err(e) => return Err(e),
+        };
+        let attr = node.get_attr()?;
+        if attr.is_dir() {
+            // search permission to go on
+            if !pending.is_empty() && !access_cap(&node, attr.perm()).contains(Cap::EXECUTE) {
+                return ax_err!(PermissionDenied);
+            }
+            continue;
+        } else if attr.file_type() != VfsNodeType::SymLink {
+            continue;
+        }
+        links += 1;
+        if links > MAX_SYMLINKS {
+            return ax_err!(InvalidInput, "too many levels of symbolic links");
+        }
+        let target = read_symlink(&node)?;
+        if target.is_empty() {
+            return ax_err!(NotFound);
+        }
+        names.pop();
+        if target.starts_with('/') {
+            names.clear();
+        }
+        pending.extend(components(&target).rev().map(String::from));
     }
+    Ok(join_names(&names))
 }
 
 pub(crate) fn absolute_path(path: &str) -> AxResult<String> {
     if path.starts_with('/') {
         Ok(axfs_vfs::path::canonicalize(path))
     } else {
-        let path = CURRENT_DIR_PATH.lock().clone() + path;
+        let path = CURRENT_DIR.lock().path.clone() + path;
         Ok(axfs_vfs::path::canonicalize(&path))
     }
 }
 
-pub(crate) fn lookup(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRef> {
+/// Resolves the path relative to `dir` and looks it up, returns the resolved
+/// path, the node and the mount containing it.
+pub(crate) fn lookup_at(
+    dir: Option<&str>,
+    path: &str,
+    follow: bool,
+) -> AxResult<(String, VfsNodeRef, MountRef)> {
     if path.is_empty() {
         return ax_err!(NotFound);
     }
-    let node = parent_node_of(dir, path).lookup(path)?;
+    let abs_path = resolve_path(dir, path, follow)?;
+    let (node, mount) = ROOT_DIR.lookup(&abs_path)?;
     if path.ends_with('/') && !node.get_attr()?.is_dir() {
         ax_err!(NotADirectory)
     } else {
-        Ok(node)
+        Ok((abs_path, node, mount))
     }
 }
 
-pub(crate) fn create_file(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRef> {
+/// Looks up the path relative to the directory `dir`, which is an absolute
+/// path (the current directory if `None`). Returns the node and the mount
+/// containing it.
+///
+/// The symbolic links are followed, including the last component.
+pub(crate) fn lookup(dir: Option<&str>, path: &str) -> AxResult<(VfsNodeRef, MountRef)> {
+    let (_, node, mount) = lookup_at(dir, path, true)?;
+    Ok((node, mount))
+}
+
+/// Same as [`lookup`], but returns the symbolic link itself if the last
+/// component is one.
+pub(crate) fn lookup_link(dir: Option<&str>, path: &str) -> AxResult<(VfsNodeRef, MountRef)> {
+    let (_, node, mount) = lookup_at(dir, path, false)?;
+    Ok((node, mount))
+}
+
+/// Checks whether the current task can add or remove entries in the
+/// directory containing `path`, which is an absolute path. Returns the
+/// directory.
+fn check_parent_writable(path: &str) -> AxResult<VfsNodeRef> {
+    let (parent, _) = path.rsplit_once('/').unwrap();
+    let (dir, _) = ROOT_DIR.lookup(if parent.is_empty() { "/" } else { parent })?;
+    let attr = dir.get_attr()?;
+    if !attr.is_dir() {
+        ax_err!(NotADirectory)
+    } else if !access_cap(&dir, attr.perm()).contains(Cap::WRITE | Cap::EXECUTE) {
+        ax_err!(PermissionDenied)
+    } else {
+        Ok(dir)
+    }
+}
+
+/// Checks whether the current task can remove or rename `node` at `path`,
+/// which is an absolute path.
+///
+/// As Linux does, the permissions of `node` itself do not matter, but the
+/// directory containing it must be writable and searchable. If the directory
+/// has the sticky bit, only the owner of `node` or the directory, or the
+/// superuser, can do it.
+fn check_removable(path: &str, node: &VfsNodeRef) -> AxResult {
+    let dir = check_parent_writable(path)?;
+    let cred = axtask::current_cred();
+    if cred.is_root() || !fs::node_sticky(&dir) {
+        return Ok(());
+    }
+    let owned = |node: &VfsNodeRef| fs::node_owner(node).is_some_and(|(uid, _)| uid == cred.uid);
+    if owned(node) || owned(&dir) {
+        Ok(())
+    } else {
+        ax_err!(PermissionDenied)
+    }
+}
+
+/// Creates a node at `path`, which is an absolute path, with the permission
+/// bits `mode` except the ones in the umask of the current task (but for the
+/// symbolic links), and owned by the current task if the filesystem records
+/// the owners.
+fn create_node(path: &str, ty: VfsNodeType, mode: u32) -> AxResult {
+    check_parent_writable(path)?;
+    let cred = axtask::current_cred();
+    let mode = if ty == VfsNodeType::SymLink {
+        mode
+    } else {
+        mode & !cred.umask
+    };
+    ROOT_DIR.create(path, ty, mode, cred.uid, cred.gid)?;
+    notify::created(path, ty == VfsNodeType::Dir);
+    Ok(())
+}
+
+pub(crate) fn create_file(
+    dir: Option<&str>,
+    path: &str,
+    mode: u32,
+) -> AxResult<(VfsNodeRef, MountRef)> {
     if path.is_empty() {
         return ax_err!(NotFound);
     } else if path.ends_with('/') {
         return ax_err!(NotADirectory);
     }
-    let parent = parent_node_of(dir, path);
-    parent.create(path, VfsNodeType::File)?;
-    parent.lookup(path)
+    let path = resolve_path(dir, path, true)?;
+    create_node(&path, VfsNodeType::File, mode)?;
+    ROOT_DIR.lookup(&path)
 }
 
-pub(crate) fn create_dir(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
-    match lookup(dir, path) {
+pub(crate) fn create_dir(dir: Option<&str>, path: &str, mode: u32) -> AxResult {
+    match lookup_at(dir, path, false) {
         Ok(_) => ax_err!(AlreadyExists),
-        Err(AxError::NotFound) => parent_node_of(dir, path).create(path, VfsNodeType::Dir),
+        Err(AxError::NotFound) => {
+            create_node(&resolve_path(dir, path, false)?, VfsNodeType::Dir, mode)
+        }
         Err(e) => Err(e),
     }
 }
 
-pub(crate) fn remove_file(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
-    let node = lookup(dir, path)?;
+pub(crate) fn remove_file(dir: Option<&str>, path: &str) -> AxResult {
+    let (abs_path, node, _) = lookup_at(dir, path, false)?;
     let attr = node.get_attr()?;
     if attr.is_dir() {
         ax_err!(IsADirectory)
-    } else if !attr.perm().owner_writable() {
-        ax_err!(PermissionDenied)
     } else {
-        parent_node_of(dir, path).remove(path)
+        check_removable(&abs_path, &node)?;
+        ROOT_DIR.remove(&abs_path)?;
+        notify::deleted(&abs_path, attr.is_dir());
+        Ok(())
     }
 }
 
-pub(crate) fn remove_dir(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
+pub(crate) fn remove_dir(dir: Option<&str>, path: &str) -> AxResult {
     if path.is_empty() {
         return ax_err!(NotFound);
     }
@@ -258,53 +572,166 @@ pub(crate) fn remove_dir(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
     {
         return ax_err!(InvalidInput);
     }
-    if ROOT_DIR.contains(&absolute_path(path)?) {
+    if ROOT_DIR.is_mount_point(&resolve_path(dir, path, false)?) {
         return ax_err!(PermissionDenied);
     }
 
-    let node = lookup(dir, path)?;
+    let (abs_path, node, _) = lookup_at(dir, path, false)?;
     let attr = node.get_attr()?;
     if !attr.is_dir() {
         ax_err!(NotADirectory)
-    } else if !attr.perm().owner_writable() {
-        ax_err!(PermissionDenied)
     } else {
-        parent_node_of(dir, path).remove(path)
+        check_removable(&abs_path, &node)?;
+        ROOT_DIR.remove(&abs_path)?;
+        notify::deleted(&abs_path, attr.is_dir());
+        Ok(())
     }
 }
 
 pub(crate) fn current_dir() -> AxResult<String> {
-    Ok(CURRENT_DIR_PATH.lock().clone())
+    Ok(CURRENT_DIR.lock().path.clone())
 }
 
 pub(crate) fn set_current_dir(path: &str) -> AxResult {
-    let mut abs_path = absolute_path(path)?;
+    // `..` is applied before the symbolic links are followed, as shells do.
+    let (mut abs_path, node, mount) = lookup_at(None, &absolute_path(path)?, true)?;
     if !abs_path.ends_with('/') {
         abs_path += "/";
     }
-    if abs_path == "/" {
-        *CURRENT_DIR.lock() = ROOT_DIR.clone();
-        *CURRENT_DIR_PATH.lock() = "/".into();
-        return Ok(());
-    }
 
-    let node = lookup(None, &abs_path)?;
     let attr = node.get_attr()?;
     if !attr.is_dir() {
         ax_err!(NotADirectory)
-    } else if !attr.perm().owner_executable() {
+    } else if abs_path != "/" && !access_cap(&node, attr.perm()).contains(Cap::EXECUTE) {
         ax_err!(PermissionDenied)
     } else {
-        *CURRENT_DIR.lock() = node;
-        *CURRENT_DIR_PATH.lock() = abs_path;
+        let mut cwd = CURRENT_DIR.lock();
+        cwd.path = abs_path;
+        cwd.mount = Some(mount);
         Ok(())
     }
 }
 
 pub(crate) fn rename(old: &str, new: &str) -> AxResult {
-    if parent_node_of(None, new).lookup(new).is_ok() {
+    let old = resolve_path(None, old, false)?;
+    let new = resolve_path(None, new, false)?;
+    let (node, _) = ROOT_DIR.lookup(&old)?;
+    check_removable(&old, &node)?;
+    check_parent_writable(&new)?;
+    if ROOT_DIR.lookup(&new).is_ok() {
         warn!("dst file already exist, now remove it");
-        remove_file(None, new)?;
+        remove_file(None, &new)?;
+    }
+    ROOT_DIR.rename(&old, &new)?;
+    let is_dir = ROOT_DIR.lookup(&new)?.0.get_attr()?.is_dir();
+    notify::renamed(&old, &new, is_dir);
+    Ok(())
+}
+
+/// Creates a symbolic link at `path` pointing to `target`.
+pub(crate) fn symlink(target: &str, path: &str) -> AxResult {
+    if target.is_empty() {
+        return ax_err!(NotFound);
+    }
+    let path = resolve_path(None, path, false)?;
+    if ROOT_DIR.lookup(&path).is_ok() {
+        return ax_err!(AlreadyExists);
+    }
+    create_node(&path, VfsNodeType::SymLink, 0o777).map_err(|e| match e {
+        // as Linux does for the filesystems without symbolic links
+        AxError::Unsupported => AxError::PermissionDenied,
+        e => e,
+    })?;
+    let (node, _) = ROOT_DIR.lookup(&path)?;
+    if let Err(e) = node.write_at(0, target.as_bytes()) {
+        ROOT_DIR.remove(&path).ok();
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Returns the target of the symbolic link at `path`.
+pub(crate) fn read_link(path: &str) -> AxResult<String> {
+    let (_, node, _) = lookup_at(None, path, false)?;
+    if node.get_attr()?.file_type() != VfsNodeType::SymLink {
+        return ax_err!(InvalidInput, "not a symbolic link");
+    }
+    read_symlink(&node)
+}
+
+/// Creates a hard link at `new` to the file at `old`.
+pub(crate) fn link(old: &str, new: &str) -> AxResult {
+    let (_, node, old_mount) = lookup_at(None, old, false)?;
+    let new = resolve_path(None, new, false)?;
+    if ROOT_DIR.lookup(&new).is_ok() {
+        return ax_err!(AlreadyExists);
+    }
+    let (parent, name) = new.rsplit_once('/').unwrap();
+    let (dir, new_mount) = ROOT_DIR.lookup(if parent.is_empty() { "/" } else { parent })?;
+    if !Arc::ptr_eq(&old_mount, &new_mount) {
+        return ax_err!(Unsupported, "cannot link across filesystems");
+    }
+    check_parent_writable(&new)?;
+    fs::link_node(&dir, name, &node)?;
+    notify::created(&new, false);
+    Ok(())
+}
+
+/// Mounts `fs` on the directory at the path.
+pub(crate) fn mount(path: &str, fs: Arc<dyn VfsOps>) -> AxResult {
+    ROOT_DIR.mount(&resolve_path(None, path, true)?, fs)
+}
+
+/// Unmounts the filesystem at the path. It fails if the filesystem is busy,
+/// unless `detach` is set.
+pub(crate) fn umount(path: &str, detach: bool) -> AxResult {
+    ROOT_DIR.umount(&resolve_path(None, path, true)?, detach)
+}
+
+/// Checks whether the current task can access the file at the path with
+/// `cap`, the symbolic links are followed.
+pub(crate) fn access(path: &str, cap: Cap) -> AxResult {
+    let (node, _) = lookup(None, path)?;
+    if access_cap(&node, node.get_attr()?.perm()).contains(cap) {
+        Ok(())
+    } else {
+        ax_err!(PermissionDenied)
+    }
+}
+
+/// Changes the permission bits and the sticky bit of the file at the path to
+/// the ones in `mode`, the symbolic links are followed. Only the owner or the
+/// superuser can do it.
+pub(crate) fn set_mode(path: &str, mode: u32) -> AxResult {
+    let (abs_path, node, _) = lookup_at(None, path, true)?;
+    let cred = axtask::current_cred();
+    if let Some((uid, _)) = fs::node_owner(&node) {
+        if !cred.is_root() && cred.uid != uid {
+            return ax_err!(PermissionDenied);
+        }
+    }
+    fs::set_node_perm(&node, FilePerm::from_bits_truncate(mode as u16))?;
+    fs::set_node_sticky(&node, mode & fs::STICKY != 0)?;
+    notify::attrib_changed(&abs_path, node.get_attr()?.is_dir());
+    Ok(())
+}
+
+/// Changes the owner of the file at the path, the symbolic links are
+/// followed. The IDs given as `None` are not changed.
+///
+/// Only the superuser can change the user, and the owner can only change the
+/// group to its own.
+pub(crate) fn set_owner(path: &str, uid: Option<u32>, gid: Option<u32>) -> AxResult {
+    let (abs_path, node, _) = lookup_at(None, path, true)?;
+    let (old_uid, old_gid) = fs::node_owner(&node).ok_or(AxError::Unsupported)?;
+    let (uid, gid) = (uid.unwrap_or(old_uid), gid.unwrap_or(old_gid));
+    let cred = axtask::current_cred();
+    if !cred.is_root()
+        && (uid != old_uid || old_uid != cred.uid || (gid != old_gid && gid != cred.gid))
+    {
+        return ax_err!(PermissionDenied);
     }
-    parent_node_of(None, old).rename(old, new)
+    fs::set_node_owner(&node, uid, gid)?;
+    notify::attrib_changed(&abs_path, node.get_attr()?.is_dir());
+    Ok(())
 }
@@ -0,0 +1,92 @@
+//! Extended attributes, the name-value pairs attached to files in addition to
+//! the data.
+//!
+//! The names are in one of the namespaces:
+//!
+//! - `user.*`: readable and writable as the file itself, only for the regular
+//!   files and directories.
+//! - `trusted.*`: only accessible by the superuser.
+//! - `security.*` and `system.*`: readable by all, but only writable by the
+//!   superuser.
+
+use alloc::{string::String, vec::Vec};
+use axerrno::{ax_err, AxResult};
+use axfs_vfs::{VfsNodeRef, VfsNodeType};
+use cap_access::Cap;
+
+use crate::fops::access_cap;
+use crate::fs;
+
+/// The maximum length of the name of an extended attribute.
+pub const XATTR_NAME_MAX: usize = 255;
+/// The maximum size of the value of an extended attribute.
+pub const XATTR_SIZE_MAX: usize = 65536;
+
+/// How to set an extended attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XattrMode {
+    /// Creates the attribute, or replaces its value if it exists.
+    Set,
+    /// Creates the attribute, fails with `AlreadyExists` if it exists.
+    Create,
+    /// Replaces the value, fails with `NotFound` if it does not exist.
+    Replace,
+}
+
+/// Checks whether the current task can read (or write if `write` is set) the
+/// extended attribute `name` of `node`.
+fn check_access(node: &VfsNodeRef, name: &str, write: bool) -> AxResult {
+    if name.is_empty() || name.len() > XATTR_NAME_MAX {
+        return ax_err!(InvalidInput);
+    }
+    let (namespace, _) = name.split_once('.').unwrap_or_default();
+    let is_root = axtask::current_cred().is_root();
+    match namespace {
+        "user" => {
+            let attr = node.get_attr()?;
+            if !matches!(attr.file_type(), VfsNodeType::File | VfsNodeType::Dir) {
+                return ax_err!(PermissionDenied);
+            }
+            let cap = if write { Cap::WRITE } else { Cap::READ };
+            if !access_cap(node, attr.perm()).contains(cap) {
+                return ax_err!(PermissionDenied);
+            }
+        }
+        "trusted" if !is_root => return ax_err!(PermissionDenied),
+        "security" | "system" if write && !is_root => return ax_err!(PermissionDenied),
+        "trusted" | "security" | "system" => {}
+        _ => return ax_err!(Unsupported),
+    }
+    Ok(())
+}
+
+/// Returns the value of the extended attribute `name` of `node`.
+pub(crate) fn get(node: &VfsNodeRef, name: &str) -> AxResult<Vec<u8>> {
+    check_access(node, name, false)?;
+    fs::node_xattr(node, name)
+}
+
+/// Returns the names of the extended attributes of `node` which the current
+/// task can read, i.e., `trusted.*` is only listed for the superuser.
+pub(crate) fn list(node: &VfsNodeRef) -> AxResult<Vec<String>> {
+    let mut names = fs::node_xattr_names(node)?;
+    if !axtask::current_cred().is_root() {
+        names.retain(|name| !name.starts_with("trusted."));
+    }
+    Ok(names)
+}
+
+/// Sets the value of the extended attribute `name` of `node` as `mode`.
+pub(crate) fn set(node: &VfsNodeRef, name: &str, value: &[u8], mode: XattrMode) -> AxResult {
+    check_access(node, name, true)?;
+    if value.len() > XATTR_SIZE_MAX {
+        return ax_err!(InvalidInput);
+    }
+    fs::set_node_xattr(node, name, value, mode)
+}
+
+/// Removes the extended attribute `name` of `node`.
+pub(crate) fn remove(node: &VfsNodeRef, name: &str) -> AxResult {
+    check_access(node, name, true)?;
+    fs::remove_node_xattr(node, name)
+}
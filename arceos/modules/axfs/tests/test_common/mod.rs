@@ -1,7 +1,7 @@
 use axfs::api as fs;
 use axio as io;
 
-use fs::{File, FileType, OpenOptions};
+use fs::{File, FileType, OpenOptions, Permissions};
 use io::{prelude::*, Error, Result};
 
 macro_rules! assert_err {
@@ -198,6 +198,15 @@ fn test_devfs_ramfs() -> Result<()> {
         .collect::<Vec<_>>();
     assert!(dirents.contains(&"null".into()));
     assert!(dirents.contains(&"zero".into()));
+    assert!(dirents.contains(&"tty".into()));
+
+    // read /dev/urandom
+    let mut file = File::open("/dev/urandom")?;
+    assert_eq!(file.read(&mut buf)?, N);
+    assert_eq!(file.metadata()?.file_type(), FileType::CharDevice);
+
+    // stat the block device of the root filesystem
+    assert_eq!(fs::metadata("/dev/vda")?.file_type(), FileType::BlockDevice);
 
     // stat /dev
     let dname = "/dev";
@@ -242,7 +251,7 @@ fn test_devfs_ramfs() -> Result<()> {
     assert_eq!(fs::read_dir("tmp").unwrap().count(), 1);
     assert_eq!(fs::write(".///tmp///dir//.///test.txt", "test"), Ok(()));
     assert_eq!(fs::read("tmp//././/dir//.///test.txt"), Ok("test".into()));
-    // assert_err!(fs::remove_dir("dev/../tmp//dir"), DirectoryNotEmpty); // TODO
+    assert_err!(fs::remove_dir("dev/../tmp//dir"), DirectoryNotEmpty);
     assert_err!(fs::remove_dir("/tmp/dir/../dir"), DirectoryNotEmpty);
     assert_eq!(fs::remove_file("./tmp//dir//test.txt"), Ok(()));
     assert_eq!(fs::remove_dir("tmp/dir/.././dir///"), Ok(()));
@@ -252,6 +261,498 @@ fn test_devfs_ramfs() -> Result<()> {
     Ok(())
 }
 
+fn test_char_devices() -> Result<()> {
+    use axfs::devfs::{receive_input, set_termios, termios, Termios};
+
+    // /dev/random and /dev/urandom never block, and discard the writes
+    for path in ["/dev/random", "/dev/urandom"] {
+        let mut file = File::options().read(true).write(true).open(path)?;
+        assert_eq!(file.metadata()?.file_type(), FileType::CharDevice);
+        let mut buf1 = [0; 64];
+        let mut buf2 = [0; 64];
+        assert_eq!(file.read(&mut buf1)?, 64);
+        assert_eq!(file.read(&mut buf2)?, 64);
+        assert_ne!(buf1, buf2);
+        assert_eq!(file.write(&buf1)?, 64);
+    }
+
+    // /dev/tty, without echo since the console cannot be written here
+    let orig = termios();
+    set_termios(Termios {
+        echo: false,
+        ..orig
+    });
+    let mut tty = File::open("/dev/tty")?;
+    assert_eq!(tty.metadata()?.file_type(), FileType::CharDevice);
+    let mut buf = [0; 32];
+    let mut read_tty = |len: usize| tty.read(&mut buf[..len]).map(|n| buf[..n].to_vec());
+
+    // canonical mode: line by line, edited by the erase and kill characters
+    receive_input(b"hello\rworlx\x7fd\n");
+    assert_eq!(read_tty(32)?, b"hello\n");
+    assert_eq!(read_tty(32)?, b"world\n");
+    receive_input(b"garbage\x15ok\n");
+    assert_eq!(read_tty(32)?, b"ok\n");
+    receive_input(b"long line\n");
+    assert_eq!(read_tty(4)?, b"long");
+    assert_eq!(read_tty(32)?, b" line\n");
+    // ^D ends the line without a newline, or reads end-of-file on an empty one
+    receive_input(b"abc\x04\x04");
+    assert_eq!(read_tty(32)?, b"abc");
+    assert_eq!(read_tty(32)?, b"");
+
+    // non-canonical mode: the line being edited becomes available
+    receive_input(b"pend");
+    set_termios(Termios {
+        icanon: false,
+        echo: false,
+        icrnl: true,
+    });
+    assert_eq!(read_tty(32)?, b"pend");
+    receive_input(b"x\x7f\r");
+    assert_eq!(read_tty(32)?, b"x\x7f\n");
+
+    set_termios(orig);
+    println!("test_char_devices() OK!");
+    Ok(())
+}
+
+fn test_sysfs() -> Result<()> {
+    // attributes added by the kernel
+    assert_eq!(axfs::sysfs::add_file("block/vdz/size", "8\n"), Ok(()));
+    assert_eq!(fs::read_to_string("/sys/block/vdz/size")?, "8\n");
+    assert_eq!(axfs::sysfs::add_file("/block/vdz/size", "16\n"), Ok(()));
+    assert_eq!(fs::read_to_string("/sys/block/vdz/size")?, "16\n");
+    let dirents = fs::read_dir("/sys/block")?
+        .map(|e| e.unwrap().file_name())
+        .collect::<Vec<_>>();
+    assert!(dirents.contains(&"vdz".into()));
+
+    // clock source of axhal
+    let clock_source =
+        fs::read_to_string("/sys/devices/system/clocksource/clocksource0/current_clocksource")?;
+    assert_eq!(clock_source.trim(), axhal::time::CLOCK_SOURCE);
+
+    println!("test_sysfs() OK!");
+    Ok(())
+}
+
+fn test_mount() -> Result<()> {
+    // mount a new filesystem inside /tmp
+    fs::create_dir("/tmp/mnt")?;
+    fs::create_dir("/tmp/mntfoo")?;
+    assert_eq!(fs::mount("", "/tmp/mnt", "tmpfs"), Ok(()));
+    assert_eq!(fs::read_dir("/tmp/mnt")?.count(), 0);
+    assert_eq!(fs::write("/tmp/mnt/test.txt", "mounted"), Ok(()));
+    assert_eq!(fs::read_to_string("/dev/../tmp/mnt/./test.txt")?, "mounted");
+    assert_eq!(fs::read_dir("/tmp/mntfoo")?.count(), 0);
+    assert_err!(fs::metadata("/tmp/mntfoo/test.txt"), NotFound);
+
+    // error cases
+    assert_err!(fs::mount("", "/tmp/mnt", "tmpfs"), ResourceBusy);
+    assert_err!(fs::mount("", "/tmp/none", "tmpfs"), NotFound);
+    assert_err!(fs::mount("", "/tmp/mnt/test.txt", "tmpfs"), NotADirectory);
+    assert_err!(fs::mount("", "/tmp/mntfoo", "unknown"), Unsupported);
+    assert_err!(
+        fs::rename("/tmp/mnt/test.txt", "/tmp/test.txt"),
+        Unsupported
+    );
+    assert_err!(fs::remove_dir("/tmp/mnt"), PermissionDenied);
+    assert_err!(fs::umount("/tmp/mntfoo"), InvalidInput);
+
+    // busy filesystems
+    let file = File::open("/tmp/mnt/test.txt")?;
+    assert_err!(fs::umount("/tmp/mnt"), ResourceBusy);
+    drop(file);
+    fs::set_current_dir("/tmp/mnt")?;
+    assert_err!(fs::umount("/tmp/mnt"), ResourceBusy);
+    fs::set_current_dir("/")?;
+    assert_err!(fs::umount("/tmp"), ResourceBusy); // has nested mounts
+    assert_eq!(fs::umount("/tmp/mnt"), Ok(()));
+    assert_err!(fs::metadata("/tmp/mnt/test.txt"), NotFound);
+    assert_err!(fs::umount("/tmp/mnt"), InvalidInput);
+
+    // detach a busy filesystem
+    assert_eq!(fs::mount("", "/tmp/mnt", "ramfs"), Ok(()));
+    let mut file = File::create("/tmp/mnt/test.txt")?;
+    assert_eq!(fs::umount_lazy("/tmp/mnt"), Ok(()));
+    assert_err!(fs::metadata("/tmp/mnt/test.txt"), NotFound);
+    assert_eq!(file.write(b"detached")?, 8);
+    drop(file);
+
+    assert_eq!(fs::remove_dir("/tmp/mnt"), Ok(()));
+    assert_eq!(fs::remove_dir("/tmp/mntfoo"), Ok(()));
+    println!("test_mount() OK!");
+    Ok(())
+}
+
+fn test_links() -> Result<()> {
+    fs::create_dir("/tmp/links")?;
+    fs::write("/tmp/links/file.txt", "linked")?;
+
+    // symbolic links, relative to the directory containing the link
+    assert_eq!(fs::symlink("file.txt", "/tmp/links/rel"), Ok(()));
+    assert_eq!(fs::symlink("/tmp/links", "/tmp/links/dir"), Ok(()));
+    assert_eq!(fs::read_to_string("/tmp/links/rel")?, "linked");
+    assert_eq!(fs::read_to_string("/tmp/links/dir/dir/rel")?, "linked");
+    assert_eq!(fs::read_link("/tmp/links/rel")?, "file.txt");
+    assert!(fs::symlink_metadata("/tmp/links/rel")?.is_symlink());
+    assert!(fs::metadata("/tmp/links/rel")?.is_file());
+    assert_err!(fs::read_link("/tmp/links/file.txt"), InvalidInput);
+    assert_err!(fs::symlink("file.txt", "/tmp/links/rel"), AlreadyExists);
+
+    fs::set_current_dir("/tmp/links/dir")?;
+    assert_eq!(fs::current_dir()?, "/tmp/links/");
+    assert_eq!(fs::read_to_string("rel")?, "linked");
+    fs::set_current_dir("/")?;
+
+    // dangling links and loops
+    assert_eq!(fs::symlink("none", "/tmp/links/dangling"), Ok(()));
+    assert_err!(fs::metadata("/tmp/links/dangling"), NotFound);
+    assert_eq!(fs::write("/tmp/links/dangling", "created"), Ok(()));
+    assert_eq!(fs::read_to_string("/tmp/links/none")?, "created");
+    assert_eq!(fs::symlink("loop2", "/tmp/links/loop1"), Ok(()));
+    assert_eq!(fs::symlink("loop1", "/tmp/links/loop2"), Ok(()));
+    assert_err!(fs::metadata("/tmp/links/loop1"), InvalidInput);
+
+    // hard links share the contents
+    assert_eq!(
+        fs::hard_link("/tmp/links/file.txt", "/tmp/links/hard"),
+        Ok(())
+    );
+    fs::write("/tmp/links/hard", "changed")?;
+    assert_eq!(fs::read_to_string("/tmp/links/file.txt")?, "changed");
+    assert_err!(
+        fs::hard_link("/tmp/links", "/tmp/dirlink"),
+        PermissionDenied
+    );
+    assert_err!(
+        fs::hard_link("/tmp/links/file.txt", "/dev/file.txt"),
+        Unsupported
+    );
+
+    // removing a link does not remove the target
+    assert_eq!(fs::remove_file("/tmp/links/dir"), Ok(()));
+    assert_eq!(fs::remove_file("/tmp/links/file.txt"), Ok(()));
+    assert_eq!(fs::read_to_string("/tmp/links/hard")?, "changed");
+    assert_err!(fs::read_to_string("/tmp/links/rel"), NotFound);
+    for name in ["rel", "dangling", "none", "loop1", "loop2", "hard"] {
+        fs::remove_file(&format!("/tmp/links/{}", name))?;
+    }
+    assert_eq!(fs::remove_dir("/tmp/links"), Ok(()));
+    println!("test_links() OK!");
+    Ok(())
+}
+
+fn test_ownership() -> Result<()> {
+    let user = axtask::Cred {
+        uid: 1000,
+        gid: 1000,
+        umask: 0o022,
+    };
+    let perm = Permissions::from_bits_truncate;
+    fs::create_dir("/tmp/perm")?;
+    fs::write("/tmp/perm/secret", "secret")?;
+    assert_eq!(fs::set_permissions("/tmp/perm/secret", perm(0o600)), Ok(()));
+    assert_eq!(fs::metadata("/tmp/perm/secret")?.permissions(), perm(0o600));
+    assert_eq!(fs::metadata("/tmp/perm")?.permissions(), perm(0o755));
+
+    // the others can read nothing and create nothing
+    axtask::set_current_cred(user);
+    assert_err!(File::open("/tmp/perm/secret"), PermissionDenied);
+    assert_err!(fs::write("/tmp/perm/new", "new"), PermissionDenied);
+    assert_err!(fs::remove_file("/tmp/perm/secret"), PermissionDenied);
+    assert_err!(
+        fs::access("/tmp/perm/secret", perm(0o400)),
+        PermissionDenied
+    );
+    assert_eq!(fs::access("/tmp/perm", perm(0o500)), Ok(()));
+    assert_err!(
+        fs::set_permissions("/tmp/perm/secret", perm(0o666)),
+        PermissionDenied
+    );
+    assert_err!(
+        fs::chown("/tmp/perm/secret", Some(1000), None),
+        PermissionDenied
+    );
+    axtask::set_current_cred(axtask::Cred::ROOT);
+
+    // the owner can
+    assert_eq!(
+        fs::chown("/tmp/perm/secret", Some(1000), Some(1000)),
+        Ok(())
+    );
+    assert_eq!(fs::set_permissions("/tmp/perm", perm(0o777)), Ok(()));
+    axtask::set_current_cred(user);
+    assert_eq!(fs::read_to_string("/tmp/perm/secret")?, "secret");
+    assert_eq!(fs::write("/tmp/perm/mine", "mine"), Ok(()));
+    assert_eq!(fs::metadata("/tmp/perm/mine")?.permissions(), perm(0o644));
+    assert_eq!(fs::set_permissions("/tmp/perm/mine", perm(0o400)), Ok(()));
+    assert_err!(fs::write("/tmp/perm/mine", "changed"), PermissionDenied);
+    assert_err!(fs::chown("/tmp/perm/mine", None, Some(0)), PermissionDenied);
+    axtask::set_current_cred(axtask::Cred::ROOT);
+
+    // search permission of the directories
+    assert_eq!(fs::set_permissions("/tmp/perm", perm(0o700)), Ok(()));
+    axtask::set_current_cred(user);
+    assert_err!(fs::metadata("/tmp/perm/mine"), PermissionDenied);
+    assert_err!(fs::set_current_dir("/tmp/perm"), PermissionDenied);
+    axtask::set_current_cred(axtask::Cred::ROOT);
+
+    // removing needs the permissions of the directory, not of the file, and
+    // the entries in a sticky directory can only be removed by their owners
+    assert_eq!(fs::set_mode("/tmp/perm", 0o1777), Ok(()));
+    assert_eq!(fs::metadata("/tmp/perm")?.permissions(), perm(0o777));
+    fs::write("/tmp/perm/root", "root")?;
+    axtask::set_current_cred(user);
+    assert_err!(fs::remove_file("/tmp/perm/root"), PermissionDenied);
+    assert_err!(
+        fs::rename("/tmp/perm/root", "/tmp/perm/stolen"),
+        PermissionDenied
+    );
+    assert_eq!(fs::remove_file("/tmp/perm/mine"), Ok(()));
+    axtask::set_current_cred(axtask::Cred::ROOT);
+    assert_eq!(fs::set_mode("/tmp/perm", 0o777), Ok(()));
+    axtask::set_current_cred(user);
+    assert_eq!(fs::remove_file("/tmp/perm/root"), Ok(()));
+    axtask::set_current_cred(axtask::Cred::ROOT);
+
+    // the superuser can do anything
+    assert_eq!(fs::write("/tmp/perm/mine", "root"), Ok(()));
+    assert_eq!(fs::remove_file("/tmp/perm/mine"), Ok(()));
+    assert_eq!(fs::remove_file("/tmp/perm/secret"), Ok(()));
+    assert_eq!(fs::remove_dir("/tmp/perm"), Ok(()));
+    println!("test_ownership() OK!");
+    Ok(())
+}
+
+fn test_page_cache() -> Result<()> {
+    let fname = "/cached.bin";
+    let data: Vec<u8> = (0..64 * 1024).map(|i| (i % 253) as u8).collect();
+    let capacity = axfs::cache::capacity();
+
+    // the dirty pages are written back when evicted
+    assert_eq!(axfs::cache::set_capacity(4), Ok(()));
+    let mut file = File::create(fname)?;
+    file.write_all(&data)?;
+    assert_eq!(file.sync_all(), Ok(()));
+    drop(file);
+    assert_eq!(fs::read(fname)?, data);
+    assert!(axfs::cache::stats().0 <= 4);
+    assert_eq!(axfs::cache::set_capacity(capacity), Ok(()));
+
+    // nothing is dirty after sync, and the clean pages can be dropped
+    fs::write(fname, &data[..5000])?;
+    assert_eq!(fs::sync(), Ok(()));
+    assert_eq!(axfs::cache::stats().1, 0);
+    let (cached, _) = axfs::cache::stats();
+    assert_eq!(axfs::cache::reclaim(usize::MAX), cached);
+    assert_eq!(axfs::cache::stats(), (0, 0));
+    assert_eq!(fs::read(fname)?, data[..5000]);
+
+    fs::remove_file(fname)?;
+    println!("test_page_cache() OK!");
+    Ok(())
+}
+
+fn test_file_locks() -> Result<()> {
+    use axfs::fops::{LockType, RecordLock};
+    let fname = "/tmp/locked.db";
+    let file1 = File::create(fname)?;
+    let file2 = File::open(fname)?;
+
+    // the shared locks are compatible, but not with an exclusive one
+    assert_eq!(file1.try_lock_shared(), Ok(()));
+    assert_eq!(file2.try_lock_shared(), Ok(()));
+    assert_err!(file2.try_lock(), WouldBlock);
+    assert_eq!(file1.unlock(), Ok(()));
+    assert_eq!(file2.try_lock(), Ok(()));
+    assert_err!(file1.try_lock_shared(), WouldBlock);
+
+    // the lock is released when the file is closed
+    drop(file2);
+    assert_eq!(file1.try_lock(), Ok(()));
+    drop(file1);
+
+    // the byte-range locks of different owners
+    let mut opts = axfs::fops::OpenOptions::new();
+    opts.read(true);
+    opts.write(true);
+    let file = axfs::fops::File::open(fname, &opts)?;
+    let locks = file.lock_handle();
+    assert_eq!(
+        locks.lock_range(1, Some(LockType::Exclusive), 0, 100, false),
+        Ok(())
+    );
+    assert_eq!(
+        locks.lock_range(2, Some(LockType::Shared), 100, u64::MAX, false),
+        Ok(())
+    );
+    assert_err!(
+        locks.lock_range(2, Some(LockType::Shared), 50, 60, false),
+        WouldBlock
+    );
+    // unlocking the middle splits the lock
+    assert_eq!(locks.lock_range(1, None, 40, 70, false), Ok(()));
+    assert_eq!(
+        locks.lock_range(2, Some(LockType::Exclusive), 50, 60, false),
+        Ok(())
+    );
+    assert_eq!(
+        locks.test_lock(2, LockType::Shared, 0, 100),
+        Some(RecordLock {
+            ty: LockType::Exclusive,
+            start: 0,
+            end: 40,
+            owner: 1,
+        })
+    );
+    assert_eq!(locks.test_lock(1, LockType::Shared, 200, 300), None);
+    assert_eq!(
+        locks
+            .test_lock(1, LockType::Exclusive, 200, 300)
+            .map(|l| l.owner),
+        Some(2)
+    );
+    // the read-only file cannot be locked exclusively
+    let mut opts = axfs::fops::OpenOptions::new();
+    opts.read(true);
+    let ro = axfs::fops::File::open(fname, &opts)?;
+    assert_err!(
+        ro.lock_handle()
+            .lock_range(3, Some(LockType::Exclusive), 0, 1, false),
+        PermissionDenied
+    );
+    locks.unlock_all(1);
+    locks.unlock_all(2);
+    assert_eq!(locks.test_lock(3, LockType::Exclusive, 0, u64::MAX), None);
+    drop((file, ro));
+
+    // the locks are kept by the file, not by its path
+    let link = "/tmp/locked.link";
+    let renamed = "/tmp/locked.old";
+    fs::hard_link(fname, link)?;
+    let file1 = File::open(fname)?;
+    let file2 = File::open(link)?;
+    assert_eq!(file1.try_lock(), Ok(()));
+    assert_err!(file2.try_lock_shared(), WouldBlock);
+    fs::rename(fname, renamed)?;
+    let file3 = File::open(renamed)?;
+    assert_err!(file3.try_lock_shared(), WouldBlock);
+    drop(file1);
+    assert_eq!(file3.try_lock_shared(), Ok(()));
+    drop((file2, file3));
+
+    fs::remove_file(link)?;
+    fs::remove_file(renamed)?;
+    println!("test_file_locks() OK!");
+    Ok(())
+}
+
+fn test_xattrs() -> Result<()> {
+    use fs::XattrMode;
+    let fname = "/tmp/xattr.txt";
+    fs::write(fname, "data")?;
+    assert_eq!(fs::set_xattr(fname, "user.b", b"2", XattrMode::Set), Ok(()));
+    assert_eq!(
+        fs::set_xattr(fname, "user.a", b"1", XattrMode::Create),
+        Ok(())
+    );
+    assert_err!(
+        fs::set_xattr(fname, "user.a", b"3", XattrMode::Create),
+        AlreadyExists
+    );
+    assert_err!(
+        fs::set_xattr(fname, "user.c", b"3", XattrMode::Replace),
+        NotFound
+    );
+    assert_eq!(fs::get_xattr(fname, "user.a")?, b"1");
+    assert_eq!(fs::list_xattr(fname)?, ["user.a", "user.b"]);
+    assert_err!(fs::get_xattr(fname, "unknown.a"), Unsupported);
+    assert_err!(fs::get_xattr(fname, "user.c"), NotFound);
+
+    // the trusted ones are hidden from the others
+    fs::set_xattr(fname, "trusted.t", b"root", XattrMode::Set)?;
+    fs::set_permissions(fname, Permissions::from_bits_truncate(0o644))?;
+    axtask::set_current_cred(axtask::Cred {
+        uid: 1000,
+        gid: 1000,
+        umask: 0o022,
+    });
+    assert_eq!(fs::get_xattr(fname, "user.b")?, b"2");
+    assert_eq!(fs::list_xattr(fname)?, ["user.a", "user.b"]);
+    assert_err!(fs::get_xattr(fname, "trusted.t"), PermissionDenied);
+    assert_err!(
+        fs::set_xattr(fname, "user.a", b"x", XattrMode::Set),
+        PermissionDenied
+    );
+    axtask::set_current_cred(axtask::Cred::ROOT);
+
+    assert_eq!(fs::remove_xattr(fname, "user.a"), Ok(()));
+    assert_err!(fs::remove_xattr(fname, "user.a"), NotFound);
+    assert_eq!(fs::list_xattr(fname)?, ["trusted.t", "user.b"]);
+    fs::remove_file(fname)?;
+    println!("test_xattrs() OK!");
+    Ok(())
+}
+
+fn test_notify() -> Result<()> {
+    use axfs::notify::{EventMask, Watcher};
+    let dir = "/tmp/watched";
+    fs::create_dir(dir)?;
+    let watcher = Watcher::new();
+    let mask = EventMask::CREATE | EventMask::DELETE | EventMask::MODIFY | EventMask::MOVE;
+    let wd = watcher.add_watch(dir, mask | EventMask::DELETE_SELF)?;
+    assert_err!(watcher.add_watch("/tmp/not-exist", mask), NotFound);
+    assert!(!watcher.has_events());
+
+    fs::write("/tmp/watched/a.txt", "data")?;
+    fs::rename("/tmp/watched/a.txt", "/tmp/watched/b.txt")?;
+    fs::create_dir("/tmp/watched/sub")?;
+    fs::remove_file("/tmp/watched/b.txt")?;
+    assert!(watcher.has_events());
+    let events = watcher.read_events(false, |_| true)?;
+    assert!(events.iter().all(|e| e.wd == wd));
+    let kinds = events
+        .iter()
+        .map(|e| (e.mask, e.name.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [
+            (EventMask::CREATE, "a.txt"),
+            (EventMask::MODIFY, "a.txt"),
+            (EventMask::MOVED_FROM, "a.txt"),
+            (EventMask::MOVED_TO, "b.txt"),
+            (EventMask::CREATE | EventMask::ISDIR, "sub"),
+            (EventMask::DELETE, "b.txt"),
+        ]
+    );
+    // the two events of a rename share the cookie
+    assert_ne!(events[2].cookie, 0);
+    assert_eq!(events[2].cookie, events[3].cookie);
+    assert_err!(watcher.read_events(false, |_| true), WouldBlock);
+
+    // the events are read as long as they fit
+    fs::remove_dir("/tmp/watched/sub")?;
+    fs::remove_dir(dir)?;
+    let mut count = 0;
+    let events = watcher.read_events(false, |_| {
+        count += 1;
+        count <= 2
+    })?;
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].mask, EventMask::DELETE | EventMask::ISDIR);
+    assert_eq!(events[1].mask, EventMask::DELETE_SELF);
+    let events = watcher.read_events(false, |_| true)?;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].mask, EventMask::IGNORED);
+    // the watch is removed with the directory
+    assert_err!(watcher.remove_watch(wd), InvalidInput);
+    println!("test_notify() OK!");
+    Ok(())
+}
+
 pub fn test_all() {
     test_read_write_file().expect("test_read_write_file() failed");
     test_read_dir().expect("test_read_dir() failed");
@@ -259,4 +760,13 @@ pub fn test_all() {
     test_create_file_dir().expect("test_create_file_dir() failed");
     test_remove_file_dir().expect("test_remove_file_dir() failed");
     test_devfs_ramfs().expect("test_devfs_ramfs() failed");
+    test_char_devices().expect("test_char_devices() failed");
+    test_sysfs().expect("test_sysfs() failed");
+    test_mount().expect("test_mount() failed");
+    test_links().expect("test_links() failed");
+    test_ownership().expect("test_ownership() failed");
+    test_page_cache().expect("test_page_cache() failed");
+    test_file_locks().expect("test_file_locks() failed");
+    test_xattrs().expect("test_xattrs() failed");
+    test_notify().expect("test_notify() failed");
 }
@@ -0,0 +1,126 @@
+#![cfg(all(feature = "extfs", not(feature = "myfs")))]
+
+mod test_common;
+
+use std::sync::Arc;
+
+use axdriver::AxDeviceContainer;
+use axdriver_block::ramdisk::RamDisk;
+use axfs::api::{self as fs, File};
+use axio::{Error, Result, Write};
+
+const IMG_SIZE: usize = 8 * 1024 * 1024;
+const EXT4_IMG_PATH: &str = "resources/ext4.img";
+const EXT4_IMG_SCRIPT: &str = "resources/create_ext4_img.sh";
+
+fn make_disk() -> RamDisk {
+    let mut image = vec![0; IMG_SIZE];
+    axfs::extfs::format(&mut image).expect("failed to format the image");
+    RamDisk::from(&image)
+}
+
+fn create_init_files() -> Result<()> {
+    fs::write("./short.txt", "Rust is cool!\n")?;
+    let mut file = File::create_new("/long.txt")?;
+    for _ in 0..100 {
+        file.write_fmt(format_args!("Rust is cool!\n"))?;
+    }
+
+    fs::create_dir("very-long-dir-name")?;
+    fs::write(
+        "very-long-dir-name/very-long-file-name.txt",
+        "Rust is cool!\n",
+    )?;
+
+    fs::create_dir("very")?;
+    fs::create_dir("//very/long")?;
+    fs::create_dir("/./very/long/path")?;
+    fs::write(".//very/long/path/test.txt", "Rust is cool!\n")?;
+    Ok(())
+}
+
+fn test_large_file() -> Result<()> {
+    // Large enough to use the double indirect blocks.
+    let fname = "/large.bin";
+    let data: Vec<u8> = (0..300 * 1024).map(|i| (i % 251) as u8).collect();
+    fs::write(fname, &data)?;
+    assert_eq!(fs::read(fname)?, data);
+
+    let file = File::options().write(true).open(fname)?;
+    file.set_len(5000)?;
+    file.set_len(8000)?;
+    drop(file);
+    let contents = fs::read(fname)?;
+    assert_eq!(contents[..5000], data[..5000]);
+    assert!(contents[5000..].iter().all(|&b| b == 0));
+
+    fs::remove_file(fname)?;
+    println!("test_large_file() OK!");
+    Ok(())
+}
+
+/// Loads the ext4 image, which is created by `create_ext4_img.sh` if it does
+/// not exist.
+fn load_ext4_image() -> std::io::Result<Vec<u8>> {
+    let dir = std::env::current_dir()?;
+    let path = dir.join(EXT4_IMG_PATH);
+    if !path.exists() {
+        println!("Creating disk image {:?} ...", path);
+        let status = std::process::Command::new("sh")
+            .arg(dir.join(EXT4_IMG_SCRIPT))
+            .status()?;
+        assert!(status.success(), "failed to create the ext4 image");
+    }
+    println!("Loading disk image from {:?} ...", path);
+    std::fs::read(path)
+}
+
+fn test_ext4_extents() -> Result<()> {
+    let image = load_ext4_image().expect("failed to load the ext4 image");
+    let disk = axfs::fops::Disk::new(RamDisk::from(&image));
+    let ext4 = axfs::extfs::ExtFileSystem::new(disk)?;
+    fs::create_dir("/ext4")?;
+    fs::mount_fs("/ext4", Arc::new(ext4))?;
+
+    assert_eq!(fs::read_to_string("/ext4/hello.txt")?, "Hello, ext4!\n");
+    assert_eq!(
+        fs::read_to_string("/ext4/dir/nested.txt")?,
+        "Rust is cool!\n"
+    );
+    assert_eq!(fs::read_dir("/ext4/fill")?.count(), 55);
+
+    // the fragmented file is mapped by an extent tree of depth 1
+    let data: Vec<u8> = (0..64 * 1024).map(|i| (i % 251) as u8).collect();
+    assert_eq!(fs::read("/ext4/large.bin")?, data);
+
+    // the holes are read as zeros
+    let sparse = fs::read("/ext4/sparse.bin")?;
+    assert_eq!(sparse.len(), 44 * 1024);
+    assert!(sparse[..4096].iter().all(|&b| b == b'x'));
+    assert!(sparse[4096..40 * 1024].iter().all(|&b| b == 0));
+    assert!(sparse[40 * 1024..].iter().all(|&b| b == b'x'));
+
+    // ext4 is read-only
+    assert_eq!(
+        fs::write("/ext4/hello.txt", "changed").err(),
+        Some(Error::PermissionDenied)
+    );
+
+    fs::umount("/ext4")?;
+    fs::remove_dir("/ext4")?;
+    println!("test_ext4_extents() OK!");
+    Ok(())
+}
+
+#[test]
+fn test_extfs() {
+    println!("Testing extfs with ramdisk ...");
+
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    axfs::init_filesystems(AxDeviceContainer::from_one(make_disk()));
+
+    create_init_files().expect("failed to create init files");
+    test_common::test_all();
+    test_large_file().expect("test_large_file() failed");
+    test_ext4_extents().expect("test_ext4_extents() failed");
+}
@@ -24,4 +24,20 @@ fn test_fatfs() {
     axfs::init_filesystems(AxDeviceContainer::from_one(disk));
 
     test_common::test_all();
+    test_times();
+}
+
+fn test_times() {
+    // 1980-01-01, the earliest time that FAT can record, which is used when
+    // there is no RTC.
+    const FAT_EPOCH: u64 = 315_532_800;
+
+    let fname = "/times.txt";
+    axfs::api::write(fname, "timestamps").unwrap();
+    let metadata = axfs::api::metadata(fname).unwrap();
+    println!("times of {:?}: {:?}", fname, metadata);
+    assert!(metadata.modified().as_secs() >= FAT_EPOCH);
+    assert!(metadata.accessed() <= metadata.modified());
+    assert_eq!(metadata.changed(), metadata.modified());
+    axfs::api::remove_file(fname).unwrap();
 }
@@ -0,0 +1,115 @@
+#![cfg(feature = "initramfs")]
+
+use axfs::initramfs;
+use axfs_vfs::{VfsError, VfsNodeRef, VfsNodeType, VfsOps, VfsResult};
+
+const UID: u32 = 1000;
+const GID: u32 = 100;
+
+/// Appends an entry in the "newc" format to the archive.
+fn push_entry(archive: &mut Vec<u8>, ino: u32, mode: u32, nlink: u32, name: &str, data: &[u8]) {
+    let fields = [
+        ino,
+        mode,
+        UID,
+        GID,
+        nlink,
+        0, // mtime
+        data.len() as u32,
+        0, // devmajor
+        0, // devminor
+        0, // rdevmajor
+        0, // rdevminor
+        name.len() as u32 + 1,
+        0, // check
+    ];
+    archive.extend_from_slice(b"070701");
+    for field in fields {
+        archive.extend_from_slice(format!("{:08x}", field).as_bytes());
+    }
+    archive.extend_from_slice(name.as_bytes());
+    archive.push(0);
+    archive.resize(archive.len().next_multiple_of(4), 0);
+    archive.extend_from_slice(data);
+    archive.resize(archive.len().next_multiple_of(4), 0);
+}
+
+fn make_archive() -> Vec<u8> {
+    let mut archive = Vec::new();
+    push_entry(&mut archive, 1, 0o040755, 3, ".", b"");
+    push_entry(&mut archive, 2, 0o040700, 2, "./bin", b"");
+    push_entry(&mut archive, 3, 0o100755, 1, "./bin/hello", b"Hello!\n");
+    push_entry(&mut archive, 4, 0o120777, 1, "./hello", b"bin/hello");
+    // the parent directory is not in the archive
+    push_entry(&mut archive, 5, 0o100644, 1, "etc/motd", b"Rust is cool!\n");
+    // the data of a file with hard links is stored with the last link
+    push_entry(&mut archive, 6, 0o100600, 2, "./link1", b"");
+    push_entry(&mut archive, 6, 0o100600, 2, "./link2", b"shared");
+    // the device files are skipped
+    push_entry(&mut archive, 7, 0o020666, 1, "./null", b"");
+    push_entry(&mut archive, 0, 0, 1, "TRAILER!!!", b"");
+    archive
+}
+
+fn lookup(root: &VfsNodeRef, path: &str) -> VfsResult<VfsNodeRef> {
+    root.clone().lookup(path)
+}
+
+fn read(node: &VfsNodeRef) -> VfsResult<String> {
+    let mut buf = vec![0; node.get_attr()?.size() as usize];
+    let len = node.read_at(0, &mut buf)?;
+    buf.truncate(len);
+    Ok(String::from_utf8(buf).unwrap())
+}
+
+#[test]
+fn test_initramfs() {
+    let fs = initramfs::new_initramfs(&make_archive()).expect("failed to unpack");
+    let root = fs.root_dir();
+
+    let bin = lookup(&root, "bin").unwrap();
+    assert!(bin.get_attr().unwrap().is_dir());
+    assert_eq!(bin.get_attr().unwrap().perm().bits(), 0o700);
+
+    let hello = lookup(&root, "bin/hello").unwrap();
+    assert_eq!(read(&hello).unwrap(), "Hello!\n");
+    assert_eq!(hello.get_attr().unwrap().perm().bits(), 0o755);
+    let meta = axfs_ramfs::node_meta(&hello).unwrap();
+    assert_eq!(meta.owner(), (UID, GID));
+
+    let link = lookup(&root, "hello").unwrap();
+    assert_eq!(link.get_attr().unwrap().file_type(), VfsNodeType::SymLink);
+    assert_eq!(read(&link).unwrap(), "bin/hello");
+
+    let motd = lookup(&root, "etc/motd").unwrap();
+    assert_eq!(read(&motd).unwrap(), "Rust is cool!\n");
+
+    let link1 = lookup(&root, "link1").unwrap();
+    let link2 = lookup(&root, "link2").unwrap();
+    assert_eq!(read(&link1).unwrap(), "shared");
+    link2.write_at(0, b"SHARED").unwrap();
+    assert_eq!(read(&link1).unwrap(), "SHARED");
+
+    assert_eq!(lookup(&root, "null").err(), Some(VfsError::NotFound));
+    println!("test_initramfs() OK!");
+}
+
+#[test]
+fn test_invalid_archive() {
+    // truncated
+    let archive = make_archive();
+    let res = initramfs::new_initramfs(&archive[..archive.len() - 4]);
+    assert_eq!(res.err(), Some(VfsError::InvalidData));
+
+    // bad magic number
+    let res = initramfs::new_initramfs(b"not an archive");
+    assert_eq!(res.err(), Some(VfsError::InvalidData));
+
+    // out of the root directory
+    let mut archive = Vec::new();
+    push_entry(&mut archive, 1, 0o100644, 1, "../escape", b"");
+    push_entry(&mut archive, 0, 0, 1, "TRAILER!!!", b"");
+    let res = initramfs::new_initramfs(&archive);
+    assert_eq!(res.err(), Some(VfsError::InvalidData));
+    println!("test_invalid_archive() OK!");
+}
@@ -0,0 +1,122 @@
+#![cfg(all(feature = "overlayfs", feature = "fatfs", not(feature = "myfs")))]
+
+use axdriver::AxDeviceContainer;
+use axdriver_block::ramdisk::RamDisk;
+use axfs::api as fs;
+use axio::{Error, Result};
+
+const IMG_PATH: &str = "resources/fat16.img";
+
+fn make_disk() -> std::io::Result<RamDisk> {
+    let path = std::env::current_dir()?.join(IMG_PATH);
+    println!("Loading disk image from {:?} ...", path);
+    let data = std::fs::read(path)?;
+    println!("size = {} bytes", data.len());
+    Ok(RamDisk::from(&data))
+}
+
+fn list(path: &str) -> Result<Vec<String>> {
+    let mut names = fs::read_dir(path)?
+        .map(|e| Ok(e?.file_name()))
+        .collect::<Result<Vec<_>>>()?;
+    names.sort();
+    Ok(names)
+}
+
+fn test_copy_up() -> Result<()> {
+    // the files of the lower directory are visible
+    let fname = "/ov/long/path/test.txt";
+    assert_eq!(fs::read_to_string(fname)?, "Rust is cool!\n");
+
+    // and copied up on write, the lower one is unchanged
+    fs::write(fname, "Overlay is cool!\n")?;
+    assert_eq!(fs::read_to_string(fname)?, "Overlay is cool!\n");
+    assert_eq!(
+        fs::read_to_string("/very/long/path/test.txt")?,
+        "Rust is cool!\n"
+    );
+
+    // the new files are only in the upper
+    fs::create_dir("/ov/long/new-dir")?;
+    fs::write("/ov/long/new-dir/new.txt", "new")?;
+    assert_eq!(list("/ov/long")?, ["new-dir", "path"]);
+    assert_eq!(list("/very/long")?, ["path"]);
+    println!("test_copy_up() OK!");
+    Ok(())
+}
+
+fn test_whiteout() -> Result<()> {
+    // the merged directory must be empty to remove
+    assert_eq!(fs::remove_dir("/ov/long"), Err(Error::DirectoryNotEmpty));
+    fs::remove_file("/ov/long/path/test.txt")?;
+    fs::remove_dir("/ov/long/path")?;
+    fs::remove_file("/ov/long/new-dir/new.txt")?;
+    fs::remove_dir("/ov/long/new-dir")?;
+    fs::remove_dir("/ov/long")?;
+
+    // the whiteouts hide the lower files, and are not listed
+    assert!(list("/ov")?.is_empty());
+    assert_eq!(fs::metadata("/ov/long").err(), Some(Error::NotFound));
+    assert_eq!(
+        fs::write("/ov/.wh.long", "hidden"),
+        Err(Error::InvalidInput)
+    );
+    assert_eq!(list("/very/long/path")?, ["test.txt"]);
+
+    // a new directory in place of a removed one is opaque
+    fs::create_dir("/ov/long")?;
+    assert!(list("/ov/long")?.is_empty());
+    assert_eq!(fs::metadata("/ov/long/path").err(), Some(Error::NotFound));
+    println!("test_whiteout() OK!");
+    Ok(())
+}
+
+fn test_rename() -> Result<()> {
+    fs::create_dir("/ov2")?;
+    fs::mount("/", "/ov2", "overlay")?;
+
+    // a lower file is moved to the upper, and hidden by a whiteout
+    fs::rename("/ov2/short.txt", "/ov2/renamed.txt")?;
+    assert_eq!(fs::read_to_string("/ov2/renamed.txt")?, "Rust is cool!\n");
+    assert_eq!(fs::metadata("/ov2/short.txt").err(), Some(Error::NotFound));
+    assert_eq!(fs::read_to_string("/short.txt")?, "Rust is cool!\n");
+
+    // the merged directories cannot be renamed
+    assert_eq!(
+        fs::rename("/ov2/very", "/ov2/other"),
+        Err(Error::Unsupported)
+    );
+
+    // nor moved to another directory, and the target is kept
+    fs::create_dir("/ov2/dir")?;
+    fs::create_dir("/ov2/new-dir")?;
+    fs::create_dir("/ov2/new-dir/dir")?;
+    assert_eq!(
+        fs::rename("/ov2/dir", "/ov2/new-dir/dir"),
+        Err(Error::Unsupported)
+    );
+    assert!(fs::metadata("/ov2/new-dir/dir")?.is_dir());
+
+    fs::umount("/ov2")?;
+    fs::remove_dir("/ov2")?;
+    println!("test_rename() OK!");
+    Ok(())
+}
+
+#[test]
+fn test_overlayfs() {
+    println!("Testing overlayfs on fatfs with ramdisk ...");
+
+    let disk = make_disk().expect("failed to load disk image");
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    axfs::init_filesystems(AxDeviceContainer::from_one(disk));
+
+    fs::create_dir("/ov").unwrap();
+    fs::mount("/very", "/ov", "overlay").unwrap();
+    test_copy_up().expect("test_copy_up() failed");
+    test_whiteout().expect("test_whiteout() failed");
+    fs::umount("/ov").unwrap();
+    assert_eq!(fs::remove_dir("/ov"), Ok(()));
+
+    test_rename().expect("test_rename() failed");
+}
@@ -0,0 +1,93 @@
+//! A minimal parser of the flattened device tree (FDT) passed by the
+//! bootloader, to read the boot parameters in `/chosen` at boot.
+
+use core::slice;
+
+use memory_addr::PhysAddr;
+
+use crate::mem::phys_to_virt;
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_HEADER_SIZE: usize = 40;
+
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+
+fn be32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+/// Reads a property value of one or two cells as a number.
+fn be_cells(value: &[u8]) -> Option<usize> {
+    match value.len() {
+        4 => Some(u32::from_be_bytes(value.try_into().unwrap()) as usize),
+        8 => Some(u64::from_be_bytes(value.try_into().unwrap()) as usize),
+        _ => None,
+    }
+}
+
+/// Returns the value of the property `name` of the `/chosen` node in the
+/// device tree blob at the physical address `dtb`.
+fn chosen_property(dtb: usize, name: &str) -> Option<&'static [u8]> {
+    if dtb == 0 {
+        return None;
+    }
+    let ptr = phys_to_virt(pa!(dtb)).as_ptr();
+    let header = unsafe { slice::from_raw_parts(ptr, FDT_HEADER_SIZE) };
+    if be32(header, 0)? != FDT_MAGIC {
+        return None;
+    }
+    let data = unsafe { slice::from_raw_parts(ptr, be32(header, 4)? as usize) };
+    let strings = be32(data, 12)? as usize;
+
+    let mut pos = be32(data, 8)? as usize;
+    // The root node is at depth 1, and `/chosen` is at depth 2.
+    let mut depth = 0;
+    let mut in_chosen = false;
+    loop {
+        let token = be32(data, pos)?;
+        pos += 4;
+        match token {
+            FDT_BEGIN_NODE => {
+                let len = data.get(pos..)?.iter().position(|&b| b == 0)?;
+                depth += 1;
+                if depth == 2 {
+                    in_chosen = &data[pos..pos + len] == b"chosen";
+                }
+                pos = (pos + len + 1).next_multiple_of(4);
+            }
+            FDT_END_NODE => {
+                if depth == 2 && in_chosen {
+                    return None;
+                }
+                depth -= 1;
+            }
+            FDT_PROP => {
+                let len = be32(data, pos)? as usize;
+                let name_off = be32(data, pos + 4)? as usize;
+                let value = data.get(pos + 8..pos + 8 + len)?;
+                pos = (pos + 8 + len).next_multiple_of(4);
+                if depth == 2 && in_chosen {
+                    let prop_name = data.get(strings + name_off..)?;
+                    let rest = prop_name.strip_prefix(name.as_bytes());
+                    if rest.and_then(|s| s.first()) == Some(&0) {
+                        return Some(value);
+                    }
+                }
+            }
+            FDT_NOP => {}
+            _ => return None, // `FDT_END` or invalid
+        }
+    }
+}
+
+/// Returns the physical address and the size of the initial RAM disk given
+/// by the `linux,initrd-start` and `linux,initrd-end` properties.
+pub(crate) fn initrd_region(dtb: usize) -> Option<(PhysAddr, usize)> {
+    let start = be_cells(chosen_property(dtb, "linux,initrd-start")?)?;
+    let end = be_cells(chosen_property(dtb, "linux,initrd-end")?)?;
+    (start < end).then(|| (pa!(start), end - start))
+}
@@ -41,6 +41,10 @@ extern crate memory_addr;
 
 mod platform;
 
+#[cfg(any(target_arch = "riscv64", target_arch = "aarch64"))]
+#[allow(dead_code)] // unused on the dummy platform
+mod dtb;
+
 #[macro_use]
 pub mod trap;
 
@@ -62,12 +66,35 @@ pub mod paging;
 pub mod console {
     pub use super::platform::console::*;
 
+    #[cfg(feature = "irq")]
+    static INPUT_HANDLER: lazyinit::LazyInit<crate::irq::IrqHandler> = lazyinit::LazyInit::new();
+
     /// Write a slice of bytes to the console.
     pub fn write_bytes(bytes: &[u8]) {
         for c in bytes {
             putchar(*c);
         }
     }
+
+    /// Sets the handler called in the IRQ context when some console input
+    /// arrives, the input is read by [`getchar`].
+    ///
+    /// Returns `false` if the console input does not raise IRQs on this
+    /// platform, or the handler has been set.
+    #[cfg(feature = "irq")]
+    pub fn set_input_handler(handler: crate::irq::IrqHandler) -> bool {
+        super::platform::console::HAS_INPUT_IRQ && INPUT_HANDLER.call_once(|| handler).is_some()
+    }
+
+    /// Calls the handler set by [`set_input_handler`], from the IRQ handler
+    /// of the console device.
+    #[cfg(feature = "irq")]
+    #[allow(dead_code)]
+    pub(crate) fn handle_input() {
+        if let Some(handler) = INPUT_HANDLER.get() {
+            handler();
+        }
+    }
 }
 
 /// Miscellaneous operation, e.g. terminate the system.
@@ -2,6 +2,8 @@
 
 use core::fmt;
 
+use lazyinit::LazyInit;
+
 #[doc(no_inline)]
 pub use memory_addr::{MemoryAddr, PhysAddr, VirtAddr, PAGE_SIZE_4K};
 
@@ -70,9 +72,48 @@ pub const fn phys_to_virt(paddr: PhysAddr) -> VirtAddr {
     va!(paddr.as_usize() + axconfig::PHYS_VIRT_OFFSET)
 }
 
+/// The physical address and the size of the initial RAM disk.
+static INITRD: LazyInit<(PhysAddr, usize)> = LazyInit::new();
+
+/// Records the initial RAM disk loaded by the bootloader. It's called at
+/// boot before the memory regions are used.
+#[allow(dead_code)]
+pub(crate) fn set_initrd(paddr: PhysAddr, size: usize) {
+    INITRD.init_once((paddr, size));
+}
+
+/// Returns the initial RAM disk loaded by the bootloader (e.g., by the
+/// `-initrd` option of QEMU), if any.
+///
+/// Its memory is a reserved region in [`memory_regions`], so it's never
+/// allocated.
+pub fn initrd() -> Option<&'static [u8]> {
+    let &(paddr, size) = INITRD.get()?;
+    Some(unsafe { core::slice::from_raw_parts(phys_to_virt(paddr).as_ptr(), size) })
+}
+
+/// Returns the page-aligned physical range of the initial RAM disk.
+fn initrd_range() -> Option<(PhysAddr, PhysAddr)> {
+    let &(paddr, size) = INITRD.get()?;
+    Some((paddr.align_down_4k(), (paddr + size).align_up_4k()))
+}
+
 /// Returns an iterator over all physical memory regions.
 pub fn memory_regions() -> impl Iterator<Item = MemRegion> {
-    kernel_image_regions().chain(crate::platform::mem::platform_regions())
+    kernel_image_regions()
+        .chain(initrd_region())
+        .chain(crate::platform::mem::platform_regions())
+}
+
+/// Returns the memory region of the initial RAM disk, if any.
+fn initrd_region() -> Option<MemRegion> {
+    let (start, end) = initrd_range()?;
+    Some(MemRegion {
+        paddr: start,
+        size: end.as_usize() - start.as_usize(),
+        flags: MemRegionFlags::RESERVED | MemRegionFlags::READ,
+        name: "initrd",
+    })
 }
 
 /// Returns the memory regions of the kernel image (code and data sections).
@@ -127,16 +168,25 @@ pub(crate) fn default_mmio_regions() -> impl Iterator<Item = MemRegion> {
 }
 
 /// Returns the default free memory regions (kernel image end to physical memory end).
+///
+/// The initial RAM disk is left out if it's loaded there.
 #[allow(dead_code)]
 pub(crate) fn default_free_regions() -> impl Iterator<Item = MemRegion> {
     let start = virt_to_phys((_ekernel as usize).into()).align_up_4k();
     let end = pa!(axconfig::PHYS_MEMORY_END).align_down_4k();
-    core::iter::once(MemRegion {
-        paddr: start,
-        size: end.as_usize() - start.as_usize(),
-        flags: MemRegionFlags::FREE | MemRegionFlags::READ | MemRegionFlags::WRITE,
-        name: "free memory",
-    })
+    let (hole_start, hole_end) = match initrd_range() {
+        Some((s, e)) => (s.max(start).min(end), e.max(start).min(end)),
+        None => (end, end),
+    };
+    [(start, hole_start), (hole_end, end)]
+        .into_iter()
+        .filter(|(s, e)| s < e)
+        .map(|(s, e)| MemRegion {
+            paddr: s,
+            size: e.as_usize() - s.as_usize(),
+            flags: MemRegionFlags::FREE | MemRegionFlags::READ | MemRegionFlags::WRITE,
+            name: "free memory",
+        })
 }
 
 /// Fills the `.bss` section with zeros.
@@ -9,7 +9,9 @@ const RAND_MAX: u64 = 2_147_483_647;
 pub fn random() -> u128 {
 	let mut seed = PARK_MILLER_LEHMER_SEED.lock();
     if *seed == 0 {
-        *seed = time::current_ticks() as u32;
+        // A zero seed would always generate zeros, e.g., before the clock
+        // starts ticking.
+        *seed = (time::current_ticks() % RAND_MAX).max(1) as u32;
     }
 
     let mut ret: u128 = 0;
@@ -38,7 +38,13 @@ pub fn init_irq() {
     crate::irq::register_handler(crate::platform::irq::UART_IRQ_NUM, handle);
 }
 
+/// Whether the console input raises IRQs.
+#[cfg(feature = "irq")]
+pub(crate) const HAS_INPUT_IRQ: bool = true;
+
 /// UART IRQ Handler
 pub fn handle() {
     trace!("Uart IRQ Handler");
+    #[cfg(feature = "irq")]
+    crate::console::handle_input();
 }
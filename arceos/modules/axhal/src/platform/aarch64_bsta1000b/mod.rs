@@ -28,6 +28,9 @@ extern "C" {
 
 pub(crate) unsafe extern "C" fn rust_entry(cpu_id: usize, dtb: usize) {
     crate::mem::clear_bss();
+    if let Some((paddr, size)) = crate::dtb::initrd_region(dtb) {
+        crate::mem::set_initrd(paddr, size);
+    }
     crate::arch::set_exception_vector_base(exception_vector_base as usize);
     crate::cpu::init_primary(cpu_id);
     dw_apb_uart::init_early();
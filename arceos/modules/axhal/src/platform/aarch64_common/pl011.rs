@@ -33,19 +33,22 @@ pub fn init_early() {
     UART.lock().init();
 }
 
+/// Whether the console input raises IRQs.
+#[cfg(feature = "irq")]
+pub(crate) const HAS_INPUT_IRQ: bool = true;
+
 /// Set UART IRQ Enable
 pub fn init() {
     #[cfg(feature = "irq")]
-    crate::irq::set_enable(crate::platform::irq::UART_IRQ_NUM, true);
+    crate::irq::register_handler(crate::platform::irq::UART_IRQ_NUM, handle);
 }
 
 /// UART IRQ Handler
+#[cfg(feature = "irq")]
 pub fn handle() {
     let is_receive_interrupt = UART.lock().is_receive_interrupt();
     UART.lock().ack_interrupts();
     if is_receive_interrupt {
-        while let Some(c) = getchar() {
-            putchar(c);
-        }
+        crate::console::handle_input();
     }
 }
@@ -29,6 +29,9 @@ extern "C" {
 
 pub(crate) unsafe extern "C" fn rust_entry(cpu_id: usize, dtb: usize) {
     crate::mem::clear_bss();
+    if let Some((paddr, size)) = crate::dtb::initrd_region(dtb) {
+        crate::mem::set_initrd(paddr, size);
+    }
     crate::arch::set_exception_vector_base(exception_vector_base as usize);
     crate::arch::write_page_table_root0(0.into()); // disable low address access
     crate::cpu::init_primary(cpu_id);
@@ -1,3 +1,7 @@
+/// Whether the console input raises IRQs.
+#[cfg(feature = "irq")]
+pub(crate) const HAS_INPUT_IRQ: bool = false;
+
 /// Writes a byte to the console.
 pub fn putchar(c: u8) {
     #[allow(deprecated)]
@@ -20,6 +20,9 @@ extern "C" {
 
 unsafe extern "C" fn rust_entry(cpu_id: usize, dtb: usize) {
     crate::mem::clear_bss();
+    if let Some((paddr, size)) = crate::dtb::initrd_region(dtb) {
+        crate::mem::set_initrd(paddr, size);
+    }
     crate::cpu::init_primary(cpu_id);
     crate::arch::set_trap_vector_base(trap_vector_base as usize);
     self::time::init_early();
@@ -4,11 +4,13 @@ use x86_64::registers::control::{Cr0Flags, Cr4Flags};
 use x86_64::registers::model_specific::EferFlags;
 
 use axconfig::{PHYS_VIRT_OFFSET, TASK_STACK_SIZE};
+use memory_addr::PhysAddr;
 
 /// Flags set in the ’flags’ member of the multiboot header.
 ///
-/// (bits 1, 16: memory information, address fields in header)
-const MULTIBOOT_HEADER_FLAGS: usize = 0x0001_0002;
+/// (bits 0, 1, 16: page-aligned modules, memory information, address fields
+/// in header)
+const MULTIBOOT_HEADER_FLAGS: usize = 0x0001_0003;
 
 /// The magic field should contain this.
 const MULTIBOOT_HEADER_MAGIC: usize = 0x1BADB002;
@@ -16,6 +18,10 @@ const MULTIBOOT_HEADER_MAGIC: usize = 0x1BADB002;
 /// This should be in EAX.
 pub(super) const MULTIBOOT_BOOTLOADER_MAGIC: usize = 0x2BADB002;
 
+/// The bit in the ’flags’ member of the multiboot info, set if the boot
+/// modules are loaded.
+const MULTIBOOT_INFO_MODS: u32 = 1 << 3;
+
 const CR0: u64 = Cr0Flags::PROTECTED_MODE_ENABLE.bits()
     | Cr0Flags::MONITOR_COPROCESSOR.bits()
     | Cr0Flags::NUMERIC_ERROR.bits()
@@ -50,3 +56,19 @@ global_asm!(
     efer_msr = const x86::msr::IA32_EFER,
     efer = const EFER,
 );
+
+/// Returns the physical address and the size of the first boot module in the
+/// multiboot info at the physical address `mbi`, which is the initial RAM
+/// disk (e.g., given by the `-initrd` option of QEMU).
+pub(super) fn multiboot_initrd(mbi: usize) -> Option<(PhysAddr, usize)> {
+    let info = crate::mem::phys_to_virt(pa!(mbi)).as_ptr() as *const u32;
+    // flags: offset 0, mods_count: offset 20, mods_addr: offset 24
+    let (flags, mods_count, mods_addr) = unsafe { (*info, *info.add(5), *info.add(6)) };
+    if flags & MULTIBOOT_INFO_MODS == 0 || mods_count == 0 {
+        return None;
+    }
+    // mod_start: offset 0, mod_end: offset 4
+    let module = crate::mem::phys_to_virt(pa!(mods_addr as usize)).as_ptr() as *const u32;
+    let (start, end) = unsafe { (*module as usize, *module.add(1) as usize) };
+    (start < end).then(|| (pa!(start), end - start))
+}
@@ -32,10 +32,13 @@ fn current_cpu_id() -> usize {
     }
 }
 
-unsafe extern "C" fn rust_entry(magic: usize, _mbi: usize) {
-    // TODO: handle multiboot info
+unsafe extern "C" fn rust_entry(magic: usize, mbi: usize) {
+    // TODO: handle the memory map in the multiboot info
     if magic == self::boot::MULTIBOOT_BOOTLOADER_MAGIC {
         crate::mem::clear_bss();
+        if let Some((paddr, size)) = self::boot::multiboot_initrd(mbi) {
+            crate::mem::set_initrd(paddr, size);
+        }
         crate::cpu::init_primary(current_cpu_id());
         self::uart16550::init();
         self::dtables::init_primary();
@@ -14,6 +14,29 @@ pub use crate::platform::irq::TIMER_IRQ_NUM;
 pub use crate::platform::time::set_oneshot_timer;
 pub use crate::platform::time::{current_ticks, epochoffset_nanos, nanos_to_ticks, ticks_to_nanos};
 
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "x86_64")] {
+        /// The name of the hardware counter that [`current_ticks`] reads, as
+        /// called by Linux.
+        pub const CLOCK_SOURCE: &str = "tsc";
+    } else if #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))] {
+        /// The name of the hardware counter that [`current_ticks`] reads, as
+        /// called by Linux.
+        pub const CLOCK_SOURCE: &str = "riscv_clocksource";
+    } else if #[cfg(target_arch = "aarch64")] {
+        /// The name of the hardware counter that [`current_ticks`] reads, as
+        /// called by Linux.
+        pub const CLOCK_SOURCE: &str = "arch_sys_counter";
+    } else if #[cfg(target_arch = "loongarch64")] {
+        /// The name of the hardware counter that [`current_ticks`] reads, as
+        /// called by Linux.
+        pub const CLOCK_SOURCE: &str = "Constant";
+    } else {
+        /// The name of the hardware counter that [`current_ticks`] reads.
+        pub const CLOCK_SOURCE: &str = "dummy";
+    }
+}
+
 /// Number of milliseconds in a second.
 pub const MILLIS_PER_SEC: u64 = 1_000;
 /// Number of microseconds in a second.
@@ -6,19 +6,87 @@ use axhal::{
     paging::{MappingFlags, PageTable},
 };
 use memory_addr::{
-    is_aligned_4k, pa, MemoryAddr, PageIter4K, PhysAddr, VirtAddr, VirtAddrRange, PAGE_SIZE_4K,
+    is_aligned_4k, pa, va, MemoryAddr, PageIter4K, PhysAddr, VirtAddr, VirtAddrRange, PAGE_SIZE_4K,
 };
 use memory_set::{MemoryArea, MemorySet};
-use crate::backend::Backend;
+use crate::backend::{resident_pages, Backend, MmapFile, SharedPages};
+use crate::huge_page::{map_linear_region, split_huge_pages_at};
 use crate::paging_err_to_ax_err;
 use crate::mapping_err_to_ax_err;
+use crate::swap::{self, SWAP_BATCH_PAGES};
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 
+/// The kind of the backend of a memory area, see [`Backend`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AreaKind {
+    /// Linear mapping to contiguous physical memory.
+    Linear,
+    /// Anonymous memory allocated from the global allocator.
+    Alloc,
+    /// File mapping, whose start is mapped to `offset` of the file.
+    File {
+        /// The file offset mapped at the start of the area.
+        offset: u64,
+        /// Whether the modifications are visible in the file.
+        shared: bool,
+    },
+    /// Shared memory object.
+    Shared,
+}
+
+/// Information about a memory area in an address space.
+#[derive(Debug, Clone)]
+pub struct AreaInfo {
+    /// The address range of the area.
+    pub va_range: VirtAddrRange,
+    /// The mapping flags of the area.
+    pub flags: MappingFlags,
+    /// The kind of the backend.
+    pub kind: AreaKind,
+    /// The number of pages of the area in memory.
+    pub resident_pages: usize,
+}
+
+/// The identity of the memory at an address, which is the same in all the
+/// address spaces sharing the memory, see [`AddrSpace::shared_key`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SharedKey {
+    /// Physical memory mapped linearly.
+    Phys(PhysAddr),
+    /// The data at `offset` of the file identified by [`MmapFile::id`].
+    File {
+        /// The identity of the file.
+        id: (u64, u64),
+        /// The offset in the file.
+        offset: u64,
+    },
+    /// The data at `offset` of a shared memory object, or of a file without
+    /// an identity, identified by the address of the object.
+    Object {
+        /// The address of the object.
+        addr: usize,
+        /// The offset in the object.
+        offset: u64,
+    },
+}
+
+/// Memory usage statistics of an address space.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AddrSpaceStats {
+    /// The total size of the memory areas in bytes.
+    pub vm_size: usize,
+    /// The size of the pages in memory in bytes.
+    pub vm_rss: usize,
+}
+
 /// The virtual memory address space.
 pub struct AddrSpace {
     va_range: VirtAddrRange,
     areas: MemorySet<Backend>,
     pt: PageTable,
+    /// Where the page reclamation continues from.
+    clock_hand: VirtAddr,
 }
 
 impl AddrSpace {
@@ -47,6 +115,40 @@ impl AddrSpace {
         self.pt.root_paddr()
     }
 
+    /// Returns the information of all memory areas, in ascending order of
+    /// their addresses.
+    ///
+    /// Mappings that are not managed as memory areas (e.g., mapped by
+    /// [`AddrSpace::map_linear`]) are not included.
+    pub fn areas(&self) -> Vec<AreaInfo> {
+        self.areas
+            .iter()
+            .map(|area| AreaInfo {
+                va_range: area.va_range(),
+                flags: area.flags(),
+                kind: area.backend().kind(area.start()),
+                resident_pages: resident_pages(&self.pt, area.start(), area.size()),
+            })
+            .collect()
+    }
+
+    /// Returns the memory usage statistics of the memory areas.
+    pub fn stats(&self) -> AddrSpaceStats {
+        self.areas()
+            .iter()
+            .fold(AddrSpaceStats::default(), |stats, area| AddrSpaceStats {
+                vm_size: stats.vm_size + area.va_range.size(),
+                vm_rss: stats.vm_rss + area.resident_pages * PAGE_SIZE_4K,
+            })
+    }
+
+    /// Returns the identity of the memory at `vaddr` if it's shared with the
+    /// other address spaces mapping it (shared file mappings, shared memory
+    /// objects and linear mappings), or `None` if it's private.
+    pub fn shared_key(&self, vaddr: VirtAddr) -> Option<SharedKey> {
+        self.areas.find(vaddr)?.backend().shared_key(vaddr)
+    }
+
     /// Checks if the address space contains the given address range.
     pub fn contains_range(&self, start: VirtAddr, size: usize) -> bool {
         self.va_range
@@ -59,6 +161,7 @@ impl AddrSpace {
             va_range: VirtAddrRange::from_start_size(base, size),
             areas: MemorySet::new(),
             pt: PageTable::try_new().map_err(|_| AxError::NoMemory)?,
+            clock_hand: base,
         })
     }
 
@@ -77,6 +180,68 @@ impl AddrSpace {
         Ok(())
     }
 
+    /// Creates a copy of the address space for a forked process.
+    ///
+    /// All memory areas are recreated in a new page table. Linear areas are
+    /// mapped to the same physical memory. The populated pages of allocation
+    /// areas are shared by both address spaces and made read-only, each side
+    /// gets its own copy of a page on the first write to it (copy-on-write,
+    /// see [`AddrSpace::handle_page_fault`]). The kernel portion of the page
+    /// table is shared as it is in this address space.
+    pub fn try_clone(&mut self) -> AxResult<Self> {
+        let mut new_aspace = Self::new_empty(self.base(), self.size())?;
+        let kernel_range = VirtAddrRange::from_start_size(
+            va!(axconfig::KERNEL_ASPACE_BASE),
+            axconfig::KERNEL_ASPACE_SIZE,
+        );
+        if !self.va_range.overlaps(kernel_range) {
+            new_aspace
+                .pt
+                .copy_from(&self.pt, kernel_range.start, kernel_range.size());
+        }
+
+        for area in self.areas.iter() {
+            let backend = area.backend();
+            let new_area = MemoryArea::new(
+                area.start(),
+                area.size(),
+                area.flags(),
+                backend.clone_for_fork(),
+            );
+            new_aspace
+                .areas
+                .map(new_area, &mut new_aspace.pt, false)
+                .map_err(mapping_err_to_ax_err)?;
+            if !backend.clone_map(area.start(), area.size(), &mut self.pt, &mut new_aspace.pt) {
+                return ax_err!(NoMemory, "failed to share pages");
+            }
+        }
+        Ok(new_aspace)
+    }
+
+    /// Removes all memory areas and frees the frames they own.
+    ///
+    /// Page table mappings that are not tracked as memory areas (e.g., the
+    /// shared kernel portion) are left untouched.
+    ///
+    /// It's also called on drop, so the errors are only logged. If an area
+    /// fails to be unmapped, the remaining ones are still unmapped.
+    pub fn clear(&mut self) {
+        if let Err(e) = self.areas.clear(&mut self.pt) {
+            warn!("failed to clear the address space: {:?}", e);
+            let areas: Vec<_> = self
+                .areas
+                .iter()
+                .map(|area| (area.start(), area.size()))
+                .collect();
+            for (start, size) in areas {
+                if let Err(e) = self.areas.unmap(start, size, &mut self.pt) {
+                    warn!("failed to unmap the area at {:#x}: {:?}", start, e);
+                }
+            }
+        }
+    }
+
     /// Finds a free area that can accommodate the given size.
     ///
     /// The search starts from the given hint address, and the area should be within the given limit range.
@@ -115,17 +280,46 @@ impl AddrSpace {
         }
 
         let offset = start_vaddr.as_usize() - start_paddr.as_usize();
-        self.pt
-            .map_region(
-                start_vaddr,
-                |va| pa!(va.as_usize() - offset),
-                size,
-                flags,
-                false, // allow_huge
-                false, // flush_tlb_by_page
-            )
-            .map_err(paging_err_to_ax_err)?
-            .flush_all();
+        map_linear_region(
+            &mut self.pt,
+            start_vaddr,
+            |va| pa!(va.as_usize() - offset),
+            size,
+            flags,
+        )
+        .map_err(paging_err_to_ax_err)?;
+        axhal::arch::flush_tlb(None);
+        Ok(())
+    }
+
+    /// Add a new linear mapping managed as a memory area, e.g., to map the
+    /// device memory into user space.
+    ///
+    /// Unlike [`AddrSpace::map_linear`], the mapping can be found by
+    /// [`AddrSpace::find_free_area`] and is kept by [`AddrSpace::try_clone`]
+    /// (sharing the same physical memory).
+    ///
+    /// Returns an error if the address range is out of the address space or not
+    /// aligned.
+    pub fn map_linear_area(
+        &mut self,
+        start_vaddr: VirtAddr,
+        start_paddr: PhysAddr,
+        size: usize,
+        flags: MappingFlags,
+    ) -> AxResult {
+        if !self.contains_range(start_vaddr, size) {
+            return ax_err!(InvalidInput, "address out of range");
+        }
+        if !start_vaddr.is_aligned_4k() || !start_paddr.is_aligned_4k() || !is_aligned_4k(size) {
+            return ax_err!(InvalidInput, "address not aligned");
+        }
+
+        let offset = start_vaddr.as_usize().wrapping_sub(start_paddr.as_usize());
+        let area = MemoryArea::new(start_vaddr, size, flags, Backend::new_linear(offset));
+        self.areas
+            .map(area, &mut self.pt, false)
+            .map_err(mapping_err_to_ax_err)?;
         Ok(())
     }
 
@@ -158,8 +352,78 @@ impl AddrSpace {
         Ok(())
     }
 
+    /// Add a new file mapping.
+    ///
+    /// The page at `start` is mapped to the data at `offset` of `file`, which
+    /// should be aligned to the page size. The pages are read from the file on
+    /// demand. If `shared` is `true`, the modifications are written back to the
+    /// file when the pages are unmapped or synchronized by [`AddrSpace::sync`],
+    /// and seen by the other shared mappings of the file at once if it tells
+    /// its identity (see [`MmapFile::id`]). Otherwise they are private to this
+    /// address space.
+    ///
+    /// Returns an error if the address range is out of the address space or not
+    /// aligned.
+    pub fn map_file(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+        file: Arc<dyn MmapFile>,
+        offset: u64,
+        shared: bool,
+    ) -> AxResult {
+        if !self.contains_range(start, size) {
+            return ax_err!(InvalidInput, "address out of range");
+        }
+        if !start.is_aligned_4k() || !is_aligned_4k(size) || !is_aligned_4k(offset as usize) {
+            return ax_err!(InvalidInput, "address not aligned");
+        }
+
+        let backend = Backend::new_file(file, start, offset, shared);
+        let area = MemoryArea::new(start, size, flags, backend);
+        self.areas
+            .map(area, &mut self.pt, false)
+            .map_err(mapping_err_to_ax_err)?;
+        Ok(())
+    }
+
+    /// Add a new shared memory mapping.
+    ///
+    /// The page at `start` is mapped to the data at `offset` of the shared
+    /// memory object `pages`, which should be aligned to the page size. Other
+    /// address spaces mapping the same object see the modifications.
+    ///
+    /// Returns an error if the address range is out of the address space or not
+    /// aligned.
+    pub fn map_shared(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+        pages: Arc<SharedPages>,
+        offset: usize,
+    ) -> AxResult {
+        if !self.contains_range(start, size) {
+            return ax_err!(InvalidInput, "address out of range");
+        }
+        if !start.is_aligned_4k() || !is_aligned_4k(size) || !is_aligned_4k(offset) {
+            return ax_err!(InvalidInput, "address not aligned");
+        }
+
+        let backend = Backend::new_shared(pages, start, offset);
+        let area = MemoryArea::new(start, size, flags, backend);
+        self.areas
+            .map(area, &mut self.pt, false)
+            .map_err(mapping_err_to_ax_err)?;
+        Ok(())
+    }
+
     /// Removes mappings within the specified virtual address range.
     ///
+    /// Ranges not managed as memory areas (e.g., mapped by
+    /// [`AddrSpace::map_linear`]) are unmapped from the page table directly.
+    ///
     /// Returns an error if the address range is out of the address space or not
     /// aligned.
     pub fn unmap(&mut self, start: VirtAddr, size: usize) -> AxResult {
@@ -170,10 +434,64 @@ impl AddrSpace {
             return ax_err!(InvalidInput, "address not aligned");
         }
 
-        self.pt
-            .unmap_region(start, size, true)
-            .map_err(paging_err_to_ax_err)?
-            .ignore();
+        if self
+            .areas
+            .overlaps(VirtAddrRange::from_start_size(start, size))
+        {
+            // Let the backends release (or drop the references to) the frames.
+            self.areas
+                .unmap(start, size, &mut self.pt)
+                .map_err(mapping_err_to_ax_err)?;
+        } else {
+            self.split_huge_pages_around(start, size)?;
+            self.pt
+                .unmap_region(start, size, true)
+                .map_err(paging_err_to_ax_err)?
+                .ignore();
+        }
+        Ok(())
+    }
+
+    /// Splits the huge pages across the boundaries of the given range, so that
+    /// the range can be unmapped or protected separately.
+    fn split_huge_pages_around(&mut self, start: VirtAddr, size: usize) -> AxResult {
+        if !split_huge_pages_at(&mut self.pt, start)
+            || !split_huge_pages_at(&mut self.pt, start + size)
+        {
+            return ax_err!(NoMemory, "failed to split huge pages");
+        }
+        Ok(())
+    }
+
+    /// Writes the modifications of the shared file mappings within the specified
+    /// virtual address range back to the files.
+    ///
+    /// Only the pages written since the last write-back are written, they are
+    /// mapped read-only again to catch the next modification.
+    ///
+    /// Returns an error if the address range is out of the address space or not
+    /// aligned, or the files cannot be written.
+    pub fn sync(&mut self, start: VirtAddr, size: usize) -> AxResult {
+        if !self.contains_range(start, size) {
+            return ax_err!(InvalidInput, "address out of range");
+        }
+        if !start.is_aligned_4k() || !is_aligned_4k(size) {
+            return ax_err!(InvalidInput, "address not aligned");
+        }
+
+        let range = VirtAddrRange::from_start_size(start, size);
+        for area in self.areas.iter() {
+            let area_range = area.va_range();
+            if !area_range.overlaps(range) {
+                continue;
+            }
+            let sync_start = area_range.start.max(range.start);
+            let sync_end = area_range.end.min(range.end);
+            let sync_size = sync_end.as_usize() - sync_start.as_usize();
+            if !area.backend().sync(sync_start, sync_size, &mut self.pt) {
+                return ax_err!(Io, "failed to write back the mapped file");
+            }
+        }
         Ok(())
     }
 
@@ -211,13 +529,49 @@ impl AddrSpace {
         Ok(())
     }
 
+    /// Populates the pages in the given range (and swaps them in), so that the
+    /// data can be accessed through the physical frames. If `write` is `true`,
+    /// copy-on-write sharing is broken as well.
+    fn populate_area(&mut self, start: VirtAddr, size: usize, write: bool) -> AxResult {
+        let end_align_up = (start + size).align_up_4k();
+        for vaddr in PageIter4K::new(start.align_down_4k(), end_align_up)
+            .expect("Failed to create page iterator")
+        {
+            let Some(area) = self.areas.find(vaddr) else {
+                continue;
+            };
+            let pte_flags = match self.pt.query(vaddr) {
+                Ok((_, flags, _)) => flags,
+                Err(_) => continue,
+            };
+            let mapped = !pte_flags.is_empty();
+            if mapped && (!write || pte_flags.contains(MappingFlags::WRITE)) {
+                continue;
+            }
+            let access_flags = if write {
+                MappingFlags::WRITE
+            } else {
+                MappingFlags::READ
+            };
+            if !area
+                .backend()
+                .handle_page_fault(vaddr, area.flags(), access_flags, &mut self.pt)
+                && !mapped
+            {
+                return ax_err!(BadAddress, "failed to populate page");
+            }
+        }
+        Ok(())
+    }
+
     /// To read data from the address space.
     ///
     /// # Arguments
     ///
     /// * `start` - The start virtual address to read.
     /// * `buf` - The buffer to store the data.
-    pub fn read(&self, start: VirtAddr, buf: &mut [u8]) -> AxResult {
+    pub fn read(&mut self, start: VirtAddr, buf: &mut [u8]) -> AxResult {
+        self.populate_area(start, buf.len(), false)?;
         self.process_area_data(start, buf.len(), |src, offset, read_size| unsafe {
             core::ptr::copy_nonoverlapping(src.as_ptr(), buf.as_mut_ptr().add(offset), read_size);
         })
@@ -229,7 +583,8 @@ impl AddrSpace {
     ///
     /// * `start_vaddr` - The start virtual address to write.
     /// * `buf` - The buffer to write to the address space.
-    pub fn write(&self, start: VirtAddr, buf: &[u8]) -> AxResult {
+    pub fn write(&mut self, start: VirtAddr, buf: &[u8]) -> AxResult {
+        self.populate_area(start, buf.len(), true)?;
         self.process_area_data(start, buf.len(), |dst, offset, write_size| unsafe {
             core::ptr::copy_nonoverlapping(buf.as_ptr().add(offset), dst.as_mut_ptr(), write_size);
         })
@@ -237,6 +592,9 @@ impl AddrSpace {
 
     /// Updates mapping within the specified virtual address range.
     ///
+    /// Ranges not managed as memory areas (e.g., mapped by
+    /// [`AddrSpace::map_linear`]) are updated in the page table directly.
+    ///
     /// Returns an error if the address range is out of the address space or not
     /// aligned.
     pub fn protect(&mut self, start: VirtAddr, size: usize, flags: MappingFlags) -> AxResult {
@@ -247,10 +605,21 @@ impl AddrSpace {
             return ax_err!(InvalidInput, "address not aligned");
         }
 
-        self.pt
-            .protect_region(start, size, flags, true)
-            .map_err(paging_err_to_ax_err)?
-            .ignore();
+        if self
+            .areas
+            .overlaps(VirtAddrRange::from_start_size(start, size))
+        {
+            // Copy-on-write pages must not become writable here.
+            self.areas
+                .protect(start, size, |_| Some(flags), &mut self.pt)
+                .map_err(mapping_err_to_ax_err)?;
+        } else {
+            self.split_huge_pages_around(start, size)?;
+            self.pt
+                .protect_region(start, size, flags, true)
+                .map_err(paging_err_to_ax_err)?
+                .ignore();
+        }
         Ok(())
     }
 
@@ -264,17 +633,68 @@ impl AddrSpace {
         if !self.va_range.contains(vaddr) {
             return false;
         }
+        if swap::memory_low() {
+            // Take the pages of this address space first. It's locked by the
+            // caller, so it will be skipped when reclaiming from the others.
+            let reclaimed = self.reclaim(SWAP_BATCH_PAGES);
+            if reclaimed < SWAP_BATCH_PAGES {
+                swap::reclaim_others(SWAP_BATCH_PAGES - reclaimed);
+            }
+        }
         if let Some(area) = self.areas.find(vaddr) {
             let orig_flags = area.flags();
             if orig_flags.contains(access_flags) {
-                return area
-                    .backend()
-                    .handle_page_fault(vaddr, orig_flags, &mut self.pt);
+                return area.backend().handle_page_fault(
+                    vaddr,
+                    orig_flags,
+                    access_flags,
+                    &mut self.pt,
+                );
             }
         }
         false
     }
 
+    /// Reclaims at most `nr_pages` pages of the lazy allocation mappings by
+    /// swapping them out, returns the number of pages reclaimed.
+    ///
+    /// Victim pages are selected by the clock algorithm, the clock hand sweeps
+    /// over the swappable pages at most twice, see [`Backend::clock_page`].
+    /// Nothing is reclaimed if the swap area is not initialized.
+    pub fn reclaim(&mut self, nr_pages: usize) -> usize {
+        let ranges: Vec<VirtAddrRange> = self
+            .areas
+            .iter()
+            .filter(|area| area.backend().is_swappable())
+            .map(|area| area.va_range())
+            .collect();
+        if ranges.is_empty() || !swap::is_enabled() {
+            return 0;
+        }
+        let total_pages: usize = ranges.iter().map(|r| r.size() / PAGE_SIZE_4K).sum();
+
+        let mut reclaimed = 0;
+        let mut hand = self.clock_hand;
+        for _ in 0..total_pages * 2 {
+            if reclaimed >= nr_pages {
+                break;
+            }
+            // Move the hand to the next swappable page, wrapping around.
+            hand = match ranges.iter().find(|r| r.end > hand) {
+                Some(r) => hand.max(r.start),
+                None => ranges[0].start,
+            };
+            let area = self.areas.find(hand).unwrap();
+            if area.backend().clock_page(hand, &mut self.pt) {
+                reclaimed += 1;
+            }
+            hand += PAGE_SIZE_4K;
+        }
+        self.clock_hand = hand;
+        debug!("reclaimed {} pages in {:?}", reclaimed, self);
+        reclaimed
+    }
+
     pub fn translated_byte_buffer(
         &self,
         vaddr: VirtAddr,
@@ -324,6 +744,12 @@ impl AddrSpace {
     }
 }
 
+impl Drop for AddrSpace {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
 impl fmt::Debug for AddrSpace {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("AddrSpace")
@@ -1,11 +1,28 @@
+use alloc::collections::BTreeMap;
 use axalloc::global_allocator;
 use axhal::mem::{phys_to_virt, virt_to_phys};
 use axhal::paging::{MappingFlags, PageSize, PageTable};
-use memory_addr::{PageIter4K, PhysAddr, VirtAddr, PAGE_SIZE_4K};
+use kspin::SpinNoIrq;
+use memory_addr::{MemoryAddr, PageIter4K, PhysAddr, VirtAddr, PAGE_SIZE_4K};
 
 use super::Backend;
+use crate::huge_page::{huge_page_sizes, split_huge_pages, split_huge_pages_at};
+use crate::swap;
 
-fn alloc_frame(zeroed: bool) -> Option<PhysAddr> {
+/// Reference counts of the frames shared by copy-on-write mappings.
+///
+/// A frame that is not in the table is owned by exactly one mapping.
+static SHARED_FRAMES: SpinNoIrq<BTreeMap<PhysAddr, usize>> = SpinNoIrq::new(BTreeMap::new());
+
+/// The blocks of contiguous frames allocated for huge pages, by the start
+/// address, with the total and the remaining numbers of 4K frames in them.
+///
+/// A huge page may be split, and its frames are then freed in smaller pages.
+/// The block is given back to the allocator as a whole after all of them are
+/// freed.
+static HUGE_BLOCKS: SpinNoIrq<BTreeMap<PhysAddr, (usize, usize)>> = SpinNoIrq::new(BTreeMap::new());
+
+pub(super) fn alloc_frame(zeroed: bool) -> Option<PhysAddr> {
     let vaddr = VirtAddr::from(global_allocator().alloc_pages(1, PAGE_SIZE_4K).ok()?);
     if zeroed {
         unsafe { core::ptr::write_bytes(vaddr.as_mut_ptr(), 0, PAGE_SIZE_4K) };
@@ -14,9 +31,144 @@ fn alloc_frame(zeroed: bool) -> Option<PhysAddr> {
     Some(paddr)
 }
 
-fn dealloc_frame(frame: PhysAddr) {
-    let vaddr = phys_to_virt(frame);
-    global_allocator().dealloc_pages(vaddr.as_usize(), 1);
+/// Allocates contiguous frames for a page of `page_size`, aligned to the page
+/// size. They are freed as a block, see [`HUGE_BLOCKS`].
+fn alloc_page_frames(page_size: PageSize, zeroed: bool) -> Option<PhysAddr> {
+    let size = page_size as usize;
+    let num_pages = size / PAGE_SIZE_4K;
+    let vaddr = VirtAddr::from(global_allocator().alloc_pages(num_pages, size).ok()?);
+    if zeroed {
+        unsafe { core::ptr::write_bytes(vaddr.as_mut_ptr(), 0, size) };
+    }
+    let paddr = virt_to_phys(vaddr);
+    HUGE_BLOCKS.lock().insert(paddr, (num_pages, num_pages));
+    Some(paddr)
+}
+
+/// Deallocates the frames of a huge page, which are never shared.
+fn dealloc_page_frames(frame: PhysAddr, page_size: PageSize) {
+    free_frames(frame, page_size as usize / PAGE_SIZE_4K);
+}
+
+/// Gives back `num_pages` frames starting from `frame` to the allocator.
+///
+/// If they are in a block allocated for huge pages, the block is deallocated
+/// after all the frames in it are given back.
+fn free_frames(frame: PhysAddr, num_pages: usize) {
+    let mut blocks = HUGE_BLOCKS.lock();
+    let (start, num_pages) = match blocks.range_mut(..=frame).next_back() {
+        Some((&start, (total, left))) if frame < start + *total * PAGE_SIZE_4K => {
+            *left -= num_pages;
+            if *left > 0 {
+                return;
+            }
+            let total = *total;
+            blocks.remove(&start);
+            (start, total)
+        }
+        _ => (frame, num_pages),
+    };
+    drop(blocks);
+    global_allocator().dealloc_pages(phys_to_virt(start).as_usize(), num_pages);
+}
+
+/// Drops a reference to the frame, and deallocates it if it was the last one.
+pub(super) fn dealloc_frame(frame: PhysAddr) {
+    {
+        let mut shared = SHARED_FRAMES.lock();
+        if let Some(count) = shared.get_mut(&frame) {
+            *count -= 1;
+            if *count == 1 {
+                shared.remove(&frame);
+            }
+            return;
+        }
+    }
+    free_frames(frame, 1);
+}
+
+/// Adds a reference to the frame.
+pub(super) fn share_frame(frame: PhysAddr) {
+    *SHARED_FRAMES.lock().entry(frame).or_insert(1) += 1;
+}
+
+/// Whether the frame is referenced by more than one mapping.
+pub(super) fn is_shared_frame(frame: PhysAddr) -> bool {
+    SHARED_FRAMES.lock().contains_key(&frame)
+}
+
+/// Returns the frame mapped at `vaddr` and the page table entry flags, or
+/// `None` if no frame has been allocated for it yet.
+pub(super) fn query_frame(pt: &PageTable, vaddr: VirtAddr) -> Option<(PhysAddr, MappingFlags)> {
+    match pt.query(vaddr) {
+        Ok((frame, flags, _)) if !flags.is_empty() => Some((frame, flags)),
+        _ => None,
+    }
+}
+
+/// Marks the address of an inaccessible page table entry as a swap slot
+/// rather than a frame. It is beyond the physical memory of any platform.
+const SWAP_ENTRY_TAG: usize = 1 << 40;
+
+/// The state of a page in a lazy allocation mapping.
+///
+/// Inaccessible page table entries (with empty flags) tell the states other
+/// than [`PageState::Mapped`] apart by their addresses.
+enum PageState {
+    /// No frame has been allocated for the page yet.
+    Unpopulated,
+    /// The page is mapped to the frame with the flags.
+    Mapped(PhysAddr, MappingFlags),
+    /// The page is in the frame, but made inaccessible by the clock hand to
+    /// see if it is accessed again (see [`Backend::clock_page_alloc`]).
+    Idle(PhysAddr),
+    /// The page has been swapped out to the slot.
+    Swapped(usize),
+}
+
+fn page_state(pt: &PageTable, vaddr: VirtAddr) -> PageState {
+    match pt.query(vaddr) {
+        Ok((frame, flags, _)) if !flags.is_empty() => PageState::Mapped(frame, flags),
+        Ok((paddr, _, _)) if paddr.as_usize() & SWAP_ENTRY_TAG != 0 => {
+            PageState::Swapped((paddr.as_usize() & !SWAP_ENTRY_TAG) / PAGE_SIZE_4K)
+        }
+        Ok((paddr, _, _)) if paddr.as_usize() != 0 => PageState::Idle(paddr),
+        _ => PageState::Unpopulated,
+    }
+}
+
+/// Returns the number of pages in `[start, start + size)` that are in memory,
+/// including the idle ones.
+pub(crate) fn resident_pages(pt: &PageTable, start: VirtAddr, size: usize) -> usize {
+    let end = start + size;
+    let mut count = 0;
+    let mut addr = start;
+    while addr < end {
+        let mut page_end = addr + PAGE_SIZE_4K;
+        match page_state(pt, addr) {
+            PageState::Mapped(..) | PageState::Idle(_) => {
+                if let Ok((_, _, page_size)) = pt.query(addr) {
+                    page_end = addr.align_down(page_size as usize) + page_size as usize;
+                }
+                count += (page_end.min(end).as_usize() - addr.as_usize()) / PAGE_SIZE_4K;
+            }
+            PageState::Swapped(_) | PageState::Unpopulated => {}
+        }
+        addr = page_end;
+    }
+    count
+}
+
+fn swap_entry(slot: usize) -> PhysAddr {
+    PhysAddr::from(SWAP_ENTRY_TAG | (slot * PAGE_SIZE_4K))
+}
+
+/// Maps the page at `vaddr` to `paddr`, which may also be a swap entry or an
+/// idle frame if `flags` is empty.
+fn set_entry(pt: &mut PageTable, vaddr: VirtAddr, paddr: PhysAddr, flags: MappingFlags) -> bool {
+    pt.remap(vaddr, paddr, flags)
+        .map(|(_, tlb)| tlb.flush())
+        .is_ok()
 }
 
 impl Backend {
@@ -42,14 +194,25 @@ impl Backend {
         );
         if populate {
             // allocate all possible physical frames for populated mapping.
-            for addr in PageIter4K::new(start, start + size).unwrap() {
-                if let Some(frame) = alloc_frame(true) {
-                    if let Ok(tlb) = pt.map(addr, frame, PageSize::Size4K, flags) {
+            let end = start + size;
+            let mut addr = start;
+            while addr < end {
+                // Use the largest page that fits, and fall back to smaller ones
+                // if there are not enough contiguous frames.
+                let huge = huge_page_sizes(addr, end - addr)
+                    .find_map(|page_size| Some((alloc_page_frames(page_size, true)?, page_size)));
+                let (frame, page_size) = match huge {
+                    Some((frame, page_size)) => (Some(frame), page_size),
+                    None => (alloc_frame(true), PageSize::Size4K),
+                };
+                if let Some(frame) = frame {
+                    if let Ok(tlb) = pt.map(addr, frame, page_size, flags) {
                         tlb.ignore(); // TLB flush on map is unnecessary, as there are no outdated mappings.
                     } else {
                         return false;
                     }
                 }
+                addr += page_size as usize;
             }
             true
         } else {
@@ -69,18 +232,69 @@ impl Backend {
         _populate: bool,
     ) -> bool {
         debug!("unmap_alloc: [{:#x}, {:#x})", start, start + size);
-        for addr in PageIter4K::new(start, start + size).unwrap() {
-            if let Ok((frame, page_size, tlb)) = pt.unmap(addr) {
-                // Deallocate the physical frame if there is a mapping in the
-                // page table.
-                if page_size.is_huge() {
-                    return false;
-                }
+        let end = start + size;
+        if !split_huge_pages_at(pt, start) || !split_huge_pages_at(pt, end) {
+            return false;
+        }
+        let mut addr = start;
+        while addr < end {
+            let state = page_state(pt, addr);
+            let mut page_size = PageSize::Size4K;
+            if let Ok((_, size, tlb)) = pt.unmap(addr) {
                 tlb.flush();
-                dealloc_frame(frame);
-            } else {
-                // Deallocation is needn't if the page is not mapped.
+                page_size = size;
+            }
+            // Deallocate the physical frame or the swap slot of the page.
+            match state {
+                PageState::Mapped(frame, _) if page_size.is_huge() => {
+                    dealloc_page_frames(frame, page_size)
+                }
+                PageState::Mapped(frame, _) | PageState::Idle(frame) => dealloc_frame(frame),
+                PageState::Swapped(slot) => swap::free_slot(slot),
+                PageState::Unpopulated => {}
+            }
+            addr += page_size as usize;
+        }
+        true
+    }
+
+    pub(crate) fn protect_alloc(
+        &self,
+        start: VirtAddr,
+        size: usize,
+        new_flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> bool {
+        debug!(
+            "protect_alloc: [{:#x}, {:#x}) {:?}",
+            start,
+            start + size,
+            new_flags
+        );
+        let end = start + size;
+        if !split_huge_pages_at(pt, start) || !split_huge_pages_at(pt, end) {
+            return false;
+        }
+        let mut addr = start;
+        while addr < end {
+            let mut page_size = PageSize::Size4K;
+            // Pages not mapped now get the new flags when they are faulted in.
+            if let PageState::Mapped(frame, _) = page_state(pt, addr) {
+                // Shared frames must stay read-only until they are copied.
+                let flags = if is_shared_frame(frame) {
+                    new_flags - MappingFlags::WRITE
+                } else {
+                    new_flags
+                };
+                match pt.protect(addr, flags) {
+                    Ok((size, tlb)) => {
+                        tlb.flush();
+                        page_size = size;
+                    }
+                    Err(_) => return false,
+                }
             }
+            addr += page_size as usize;
         }
         true
     }
@@ -92,17 +306,358 @@ impl Backend {
         pt: &mut PageTable,
         populate: bool,
     ) -> bool {
-        if populate {
-            false // Populated mappings should not trigger page faults.
-        } else if let Some(frame) = alloc_frame(true) {
-            // Allocate a physical frame lazily and map it to the fault address.
-            // `vaddr` does not need to be aligned. It will be automatically
-            // aligned during `pt.remap` regardless of the page size.
-            pt.remap(vaddr, frame, orig_flags)
+        // The fault address may be anywhere in the page, but the frames are
+        // looked up and copied by their starts.
+        let vaddr = vaddr.align_down_4k();
+        match page_state(pt, vaddr) {
+            PageState::Mapped(frame, flags) => {
+                // The page is present, so it can only be a write to a
+                // copy-on-write page.
+                if flags.contains(MappingFlags::WRITE) || !orig_flags.contains(MappingFlags::WRITE)
+                {
+                    return false;
+                }
+                Self::copy_on_write(vaddr, frame, orig_flags, pt)
+            }
+            PageState::Idle(frame) => {
+                // Accessed again before being swapped out, just map it back.
+                let flags = if is_shared_frame(frame) {
+                    orig_flags - MappingFlags::WRITE
+                } else {
+                    orig_flags
+                };
+                set_entry(pt, vaddr, frame, flags)
+            }
+            PageState::Swapped(slot) => {
+                let Some(frame) = alloc_frame(false) else {
+                    return false;
+                };
+                if !swap::swap_in(slot, frame) {
+                    dealloc_frame(frame);
+                    return false;
+                }
+                set_entry(pt, vaddr, frame, orig_flags)
+            }
+            PageState::Unpopulated if populate => {
+                false // Populated mappings should not trigger page faults.
+            }
+            PageState::Unpopulated => {
+                // Allocate a physical frame lazily and map it to the fault
+                // address. `vaddr` does not need to be aligned. It will be
+                // automatically aligned during `pt.remap` regardless of the
+                // page size.
+                let Some(frame) = alloc_frame(true) else {
+                    return false;
+                };
+                set_entry(pt, vaddr, frame, orig_flags)
+            }
+        }
+    }
+
+    /// Advances the clock hand of page reclamation over the page at `vaddr`,
+    /// returns `true` if the page is swapped out.
+    ///
+    /// [`MappingFlags`] carry no accessed bits, so a mapped page is made
+    /// inaccessible (idle) when the hand passes it, and it is mapped back if
+    /// it is accessed before the hand comes again. Otherwise it is swapped
+    /// out at that time. Frames shared by copy-on-write mappings are left
+    /// alone, as only one of the page tables referring to them is at hand.
+    pub(crate) fn clock_page_alloc(vaddr: VirtAddr, pt: &mut PageTable) -> bool {
+        match page_state(pt, vaddr) {
+            PageState::Mapped(frame, _) if !is_shared_frame(frame) => {
+                set_entry(pt, vaddr, frame, MappingFlags::empty());
+                false
+            }
+            PageState::Idle(frame) if !is_shared_frame(frame) => {
+                let Some(slot) = swap::swap_out(frame) else {
+                    return false;
+                };
+                if !set_entry(pt, vaddr, swap_entry(slot), MappingFlags::empty()) {
+                    swap::free_slot(slot);
+                    return false;
+                }
+                dealloc_frame(frame);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Gives the page at `vaddr` a private copy of the shared `frame`.
+    pub(super) fn copy_on_write(
+        vaddr: VirtAddr,
+        frame: PhysAddr,
+        orig_flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> bool {
+        if !is_shared_frame(frame) {
+            // Other mappings have already got their own copies, just restore
+            // the write permission.
+            return pt
+                .protect(vaddr, orig_flags)
                 .map(|(_, tlb)| tlb.flush())
-                .is_ok()
-        } else {
-            false
+                .is_ok();
         }
+        let Some(new_frame) = alloc_frame(false) else {
+            return false;
+        };
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                phys_to_virt(frame).as_ptr(),
+                phys_to_virt(new_frame).as_mut_ptr(),
+                PAGE_SIZE_4K,
+            );
+        }
+        match pt.remap(vaddr, new_frame, orig_flags) {
+            Ok((_, tlb)) => {
+                tlb.flush();
+                dealloc_frame(frame);
+                true
+            }
+            Err(_) => {
+                dealloc_frame(new_frame);
+                false
+            }
+        }
+    }
+
+    /// Shares the populated pages in `[start, start + size)` of `src_pt` with
+    /// `dst_pt` in a copy-on-write manner, where the area has already been
+    /// mapped in `dst_pt` without populating.
+    ///
+    /// Writable pages become read-only in both page tables, the first write
+    /// on either side copies the frame in [`Self::handle_page_fault_alloc`].
+    pub(crate) fn clone_map_alloc(
+        &self,
+        start: VirtAddr,
+        size: usize,
+        src_pt: &mut PageTable,
+        dst_pt: &mut PageTable,
+    ) -> bool {
+        debug!("clone_map_alloc: [{:#x}, {:#x})", start, start + size);
+        // Frames are shared and copied in 4K pages.
+        if !split_huge_pages(src_pt, start, size) {
+            return false;
+        }
+        for addr in PageIter4K::new(start, start + size).unwrap() {
+            let (frame, flags) = match page_state(src_pt, addr) {
+                PageState::Mapped(frame, flags) => (frame, flags),
+                PageState::Idle(frame) => (frame, MappingFlags::empty()),
+                PageState::Swapped(slot) => {
+                    // Both sides read the slot into their own frames.
+                    swap::dup_slot(slot);
+                    if dst_pt
+                        .remap(addr, swap_entry(slot), MappingFlags::empty())
+                        .is_err()
+                    {
+                        swap::free_slot(slot);
+                        return false;
+                    }
+                    continue;
+                }
+                // Not populated yet, leave it to the page fault handler.
+                PageState::Unpopulated => continue,
+            };
+            let cow_flags = flags - MappingFlags::WRITE;
+            if flags.contains(MappingFlags::WRITE) {
+                match src_pt.protect(addr, cow_flags) {
+                    Ok((_, tlb)) => tlb.flush(),
+                    Err(_) => return false,
+                }
+            }
+            share_frame(frame);
+            match dst_pt.remap(addr, frame, cow_flags) {
+                Ok((_, tlb)) => tlb.ignore(), // The new page table is not in use.
+                Err(_) => {
+                    dealloc_frame(frame);
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    extern crate std;
+
+    use std::sync::{Mutex, MutexGuard, Once};
+
+    use axalloc::global_allocator;
+    use axhal::paging::{MappingFlags, PageSize};
+    use memory_addr::{va, PhysAddr, VirtAddr, PAGE_SIZE_4K};
+
+    use super::{HUGE_BLOCKS, SHARED_FRAMES};
+    use crate::AddrSpace;
+
+    pub(crate) const BASE: usize = 0x1000_0000;
+    pub(crate) const SIZE: usize = 0x1000_0000;
+    pub(crate) const RW: MappingFlags = MappingFlags::READ.union(MappingFlags::WRITE);
+    const SIZE_2M: usize = PageSize::Size2M as usize;
+
+    /// Initializes the global allocator, from which the frames and the page
+    /// tables are allocated. The frames are accessed by `phys_to_virt`, which
+    /// is the identity on the host.
+    ///
+    /// The tests count the allocated pages, so they are run one at a time.
+    pub(crate) fn setup() -> MutexGuard<'static, ()> {
+        const MEMORY_SIZE: usize = 16 * 1024 * 1024;
+        static INIT: Once = Once::new();
+        static LOCK: Mutex<()> = Mutex::new(());
+        INIT.call_once(|| {
+            let layout = std::alloc::Layout::from_size_align(MEMORY_SIZE, 0x20_0000).unwrap();
+            let start = unsafe { std::alloc::alloc(layout) };
+            assert!(!start.is_null());
+            axalloc::global_init(start as usize, MEMORY_SIZE);
+        });
+        LOCK.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub(crate) fn used_pages() -> usize {
+        global_allocator().used_pages()
+    }
+
+    fn query(aspace: &AddrSpace, vaddr: VirtAddr) -> (PhysAddr, MappingFlags) {
+        let (frame, flags, _) = aspace.page_table().query(vaddr).unwrap();
+        (frame, flags)
+    }
+
+    fn refs(frame: PhysAddr) -> Option<usize> {
+        SHARED_FRAMES.lock().get(&frame).copied()
+    }
+
+    #[test]
+    fn test_cow_share() {
+        let _guard = setup();
+        let used = used_pages();
+        let start = va!(BASE);
+        let mut parent = AddrSpace::new_empty(start, SIZE).unwrap();
+        parent
+            .map_alloc(start, 2 * PAGE_SIZE_4K, RW, false)
+            .unwrap();
+        parent.write(start, b"parent").unwrap();
+        let (frame, _) = query(&parent, start);
+        assert_eq!(refs(frame), None);
+
+        // The populated page is shared read-only, the other one is left to be
+        // populated on each side.
+        let mut child = parent.try_clone().unwrap();
+        assert_eq!(query(&parent, start), (frame, MappingFlags::READ));
+        assert_eq!(query(&child, start), (frame, MappingFlags::READ));
+        assert!(query(&child, start + PAGE_SIZE_4K).1.is_empty());
+        assert_eq!(refs(frame), Some(2));
+        let grandchild = child.try_clone().unwrap();
+        assert_eq!(refs(frame), Some(3));
+
+        // The frame is freed after the last one sharing it exits.
+        drop(grandchild);
+        assert_eq!(refs(frame), Some(2));
+        drop(parent);
+        assert_eq!(refs(frame), None);
+        let mut buf = [0; 6];
+        child.read(start, &mut buf).unwrap();
+        assert_eq!(&buf, b"parent");
+        drop(child);
+        assert_eq!(used_pages(), used);
+    }
+
+    #[test]
+    fn test_cow_write_fault() {
+        let _guard = setup();
+        let used = used_pages();
+        let start = va!(BASE);
+        let mut parent = AddrSpace::new_empty(start, SIZE).unwrap();
+        parent.map_alloc(start, PAGE_SIZE_4K, RW, true).unwrap();
+        parent.write(start, b"parent").unwrap();
+        parent.write(start + PAGE_SIZE_4K - 4, b"tail").unwrap();
+        let (frame, _) = query(&parent, start);
+        let mut child = parent.try_clone().unwrap();
+
+        // The first write copies the frame, wherever it is in the page.
+        assert!(child.handle_page_fault(start + 0x123, MappingFlags::WRITE));
+        let (new_frame, flags) = query(&child, start);
+        assert_ne!(new_frame, frame);
+        assert_eq!(flags, RW);
+        assert_eq!(refs(frame), None);
+        let mut buf = [0; 6];
+        child.read(start, &mut buf).unwrap();
+        assert_eq!(&buf, b"parent");
+        child.read(start + PAGE_SIZE_4K - 4, &mut buf[..4]).unwrap();
+        assert_eq!(&buf[..4], b"tail");
+
+        // The other side owns the frame alone now, it's just made writable.
+        assert!(parent.handle_page_fault(start + 0x123, MappingFlags::WRITE));
+        assert_eq!(query(&parent, start), (frame, RW));
+
+        child.write(start, b"child!").unwrap();
+        parent.read(start, &mut buf).unwrap();
+        assert_eq!(&buf, b"parent");
+        child.read(start, &mut buf).unwrap();
+        assert_eq!(&buf, b"child!");
+
+        drop(parent);
+        drop(child);
+        assert_eq!(used_pages(), used);
+    }
+
+    #[test]
+    fn test_huge_page_split() {
+        let _guard = setup();
+        let used = used_pages();
+        let start = va!(BASE);
+        let mut aspace = AddrSpace::new_empty(start, SIZE).unwrap();
+        aspace.map_alloc(start, SIZE_2M, RW, true).unwrap();
+        let (frame, _, page_size) = aspace.page_table().query(start).unwrap();
+        assert_eq!(page_size, PageSize::Size2M);
+        assert_eq!(HUGE_BLOCKS.lock().get(&frame), Some(&(512, 512)));
+        let mapped = used_pages();
+
+        // The frames split from the huge page are kept in the block until all
+        // of them are freed. Splitting takes a new page table.
+        aspace.unmap(start, PAGE_SIZE_4K).unwrap();
+        let (_, _, page_size) = aspace.page_table().query(start + PAGE_SIZE_4K).unwrap();
+        assert_eq!(page_size, PageSize::Size4K);
+        assert_eq!(HUGE_BLOCKS.lock().get(&frame), Some(&(512, 511)));
+        assert_eq!(used_pages(), mapped + 1);
+
+        aspace
+            .unmap(start + PAGE_SIZE_4K, SIZE_2M - PAGE_SIZE_4K)
+            .unwrap();
+        assert_eq!(HUGE_BLOCKS.lock().get(&frame), None);
+        assert_eq!(used_pages(), mapped + 1 - 512);
+        drop(aspace);
+        assert_eq!(used_pages(), used);
+    }
+
+    #[test]
+    fn test_huge_page_fork() {
+        let _guard = setup();
+        let used = used_pages();
+        let start = va!(BASE);
+        let mut parent = AddrSpace::new_empty(start, SIZE).unwrap();
+        parent.map_alloc(start, SIZE_2M, RW, true).unwrap();
+        parent.write(start, b"parent").unwrap();
+        let (frame, _, _) = parent.page_table().query(start).unwrap();
+
+        // The huge page is shared in 4K pages, and the copied one is freed
+        // alone.
+        let mut child = parent.try_clone().unwrap();
+        let (_, _, page_size) = parent.page_table().query(start).unwrap();
+        assert_eq!(page_size, PageSize::Size4K);
+        assert_eq!(refs(frame + PAGE_SIZE_4K), Some(2));
+        child.write(start, b"child!").unwrap();
+        assert_eq!(refs(frame), None);
+        assert_eq!(HUGE_BLOCKS.lock().get(&frame), Some(&(512, 512)));
+
+        // The block is freed after both sides exit.
+        drop(parent);
+        assert_eq!(HUGE_BLOCKS.lock().get(&frame), Some(&(512, 511)));
+        let mut buf = [0; 6];
+        child.read(start, &mut buf).unwrap();
+        assert_eq!(&buf, b"child!");
+        drop(child);
+        assert_eq!(HUGE_BLOCKS.lock().get(&frame), None);
+        assert_eq!(used_pages(), used);
     }
 }
@@ -0,0 +1,569 @@
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use axerrno::AxResult;
+use axhal::mem::phys_to_virt;
+use axhal::paging::{MappingFlags, PageTable};
+use axsync::Mutex;
+use memory_addr::{MemoryAddr, PageIter4K, PhysAddr, VirtAddr, PAGE_SIZE_4K};
+
+use super::alloc::{alloc_frame, dealloc_frame, is_shared_frame, query_frame, share_frame};
+use super::Backend;
+
+/// The frames of the pages in the shared file mappings, by the identity of
+/// the file (see [`MmapFile::id`]) and the file offset.
+///
+/// Each entry holds a reference to the frame besides the ones of the
+/// mappings, it's dropped after the page is unmapped from all of them.
+static SHARED_FILE_PAGES: Mutex<BTreeMap<((u64, u64), u64), PhysAddr>> =
+    Mutex::new(BTreeMap::new());
+
+/// A file that can be mapped into an address space.
+///
+/// It is implemented by the users of this crate for their file objects (e.g.,
+/// `axfs::fops::File`), so that the memory management does not depend on the
+/// filesystem.
+pub trait MmapFile: Send + Sync {
+    /// Reads the data at `offset` into `buf` without changing the file
+    /// position, returns the number of bytes read.
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> AxResult<usize>;
+
+    /// Writes `buf` to the file at `offset` without changing the file
+    /// position, returns the number of bytes written.
+    fn write_at(&self, offset: u64, buf: &[u8]) -> AxResult<usize>;
+
+    /// Returns the current size of the file in bytes.
+    fn size(&self) -> AxResult<u64>;
+
+    /// Returns the identity of the file, which is the same for all its open
+    /// instances (e.g., the volume and the inode number).
+    ///
+    /// The shared mappings of the same file share the frames of its pages, so
+    /// that the writes through one are seen by the others. If it's `None`
+    /// (the default), each shared mapping has its own frames, which only see
+    /// the writes of the others after they are written back to the file and
+    /// the pages are read again.
+    fn id(&self) -> Option<(u64, u64)> {
+        None
+    }
+}
+
+/// Fills the `frame` with the file data at `offset`, the part beyond the end
+/// of the file is left as zeros.
+fn read_page(file: &dyn MmapFile, offset: u64, frame: PhysAddr) -> bool {
+    let buf =
+        unsafe { core::slice::from_raw_parts_mut(phys_to_virt(frame).as_mut_ptr(), PAGE_SIZE_4K) };
+    let mut read = 0;
+    while read < PAGE_SIZE_4K {
+        match file.read_at(offset + read as u64, &mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) => {
+                warn!("failed to read mapped file at {:#x}: {:?}", offset, e);
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Allocates a frame filled with the file data at `offset`.
+fn new_page(file: &dyn MmapFile, offset: u64) -> Option<PhysAddr> {
+    let frame = alloc_frame(true)?;
+    if !read_page(file, offset, frame) {
+        dealloc_frame(frame);
+        return None;
+    }
+    Some(frame)
+}
+
+/// Returns the frame of the page at `offset` of the file `id` for a shared
+/// mapping, which is read from the file if no other mapping has it.
+fn get_shared_page(file: &dyn MmapFile, id: (u64, u64), offset: u64) -> Option<PhysAddr> {
+    let mut pages = SHARED_FILE_PAGES.lock();
+    let frame = match pages.get(&(id, offset)) {
+        Some(&frame) => frame,
+        None => {
+            let frame = new_page(file, offset)?;
+            pages.insert((id, offset), frame);
+            frame
+        }
+    };
+    share_frame(frame);
+    Some(frame)
+}
+
+/// Drops the reference of a mapping to the `frame` of the page at `offset` of
+/// the file `id` (`None` if the frame is private to the mapping), the frame is
+/// deallocated if no other mapping has it.
+fn put_page(id: Option<(u64, u64)>, offset: u64, frame: PhysAddr) {
+    let Some(id) = id else {
+        dealloc_frame(frame);
+        return;
+    };
+    let mut pages = SHARED_FILE_PAGES.lock();
+    dealloc_frame(frame);
+    // Only the reference of the entry is left.
+    if !is_shared_frame(frame) && pages.get(&(id, offset)) == Some(&frame) {
+        pages.remove(&(id, offset));
+        dealloc_frame(frame);
+    }
+}
+
+/// Writes the `frame` back to the file at `offset`.
+///
+/// The file is never extended, the part of the page beyond the end of the file
+/// is discarded.
+fn write_page(file: &dyn MmapFile, offset: u64, frame: PhysAddr) -> bool {
+    let file_size = match file.size() {
+        Ok(size) => size,
+        Err(_) => return false,
+    };
+    if offset >= file_size {
+        return true;
+    }
+    let len = (file_size - offset).min(PAGE_SIZE_4K as u64) as usize;
+    let buf = unsafe { core::slice::from_raw_parts(phys_to_virt(frame).as_ptr(), len) };
+    let mut written = 0;
+    while written < len {
+        match file.write_at(offset + written as u64, &buf[written..]) {
+            Ok(0) => return false,
+            Ok(n) => written += n,
+            Err(e) => {
+                warn!("failed to write back mapped file at {:#x}: {:?}", offset, e);
+                return false;
+            }
+        }
+    }
+    true
+}
+
+impl Backend {
+    /// Creates a new file mapping backend.
+    ///
+    /// The page at `start` is mapped to the data at `offset` of the file.
+    pub fn new_file(file: Arc<dyn MmapFile>, start: VirtAddr, offset: u64, shared: bool) -> Self {
+        Self::File {
+            file,
+            start,
+            offset,
+            shared,
+        }
+    }
+
+    /// Returns the mapped file and the file offset of the page at `vaddr`.
+    fn file_page(&self, vaddr: VirtAddr) -> (&dyn MmapFile, u64) {
+        match self {
+            Self::File {
+                file,
+                start,
+                offset,
+                ..
+            } => (
+                file.as_ref(),
+                offset + (vaddr.align_down_4k().as_usize() - start.as_usize()) as u64,
+            ),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the identity of the file if the pages are shared with the other
+    /// shared mappings of it, see [`MmapFile::id`].
+    fn shared_file_id(&self, shared: bool) -> Option<(u64, u64)> {
+        match self {
+            Self::File { file, .. } if shared => file.id(),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn map_file(
+        &self,
+        start: VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> bool {
+        debug!("map_file: [{:#x}, {:#x}) {:?}", start, start + size, flags);
+        // Map to a empty entry for on-demand mapping.
+        pt.map_region(
+            start,
+            |_| 0.into(),
+            size,
+            MappingFlags::empty(),
+            false,
+            false,
+        )
+        .map(|tlb| tlb.ignore())
+        .is_ok()
+    }
+
+    pub(crate) fn unmap_file(
+        &self,
+        start: VirtAddr,
+        size: usize,
+        pt: &mut PageTable,
+        shared: bool,
+    ) -> bool {
+        debug!("unmap_file: [{:#x}, {:#x})", start, start + size);
+        let id = self.shared_file_id(shared);
+        for addr in PageIter4K::new(start, start + size).unwrap() {
+            let populated = query_frame(pt, addr);
+            if let Ok((frame, page_size, tlb)) = pt.unmap(addr) {
+                if page_size.is_huge() {
+                    return false;
+                }
+                tlb.flush();
+                if let Some((_, flags)) = populated {
+                    let (file, offset) = self.file_page(addr);
+                    // Only the modified pages are writable.
+                    if shared && flags.contains(MappingFlags::WRITE) {
+                        // Errors are ignored as the mapping is going away anyway.
+                        write_page(file, offset, frame);
+                    }
+                    put_page(id, offset, frame);
+                }
+            }
+        }
+        true
+    }
+
+    pub(crate) fn protect_file(
+        &self,
+        start: VirtAddr,
+        size: usize,
+        new_flags: MappingFlags,
+        pt: &mut PageTable,
+        shared: bool,
+    ) -> bool {
+        if !shared {
+            // Private pages are anonymous copies of the file data.
+            return self.protect_alloc(start, size, new_flags, pt);
+        }
+        debug!(
+            "protect_file: [{:#x}, {:#x}) {:?}",
+            start,
+            start + size,
+            new_flags
+        );
+        for addr in PageIter4K::new(start, start + size).unwrap() {
+            let Some((frame, flags)) = query_frame(pt, addr) else {
+                continue;
+            };
+            // Keep the clean pages read-only to catch the writes. A modified
+            // page is written back first if it becomes read-only, as it
+            // looks clean afterwards.
+            let dirty = flags.contains(MappingFlags::WRITE);
+            let flags = if dirty {
+                new_flags
+            } else {
+                new_flags - MappingFlags::WRITE
+            };
+            match pt.protect(addr, flags) {
+                Ok((_, tlb)) => tlb.flush(),
+                Err(_) => return false,
+            }
+            if dirty && !flags.contains(MappingFlags::WRITE) {
+                let (file, offset) = self.file_page(addr);
+                if !write_page(file, offset, frame) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    pub(crate) fn handle_page_fault_file(
+        &self,
+        vaddr: VirtAddr,
+        orig_flags: MappingFlags,
+        access_flags: MappingFlags,
+        pt: &mut PageTable,
+        shared: bool,
+    ) -> bool {
+        // The fault address may be anywhere in the page, but the frames are
+        // looked up and copied by their starts.
+        let vaddr = vaddr.align_down_4k();
+        if let Some((frame, flags)) = query_frame(pt, vaddr) {
+            // Only a write to a read-only page of a writable mapping can get
+            // here.
+            if flags.contains(MappingFlags::WRITE)
+                || !orig_flags.contains(MappingFlags::WRITE)
+                || !access_flags.contains(MappingFlags::WRITE)
+            {
+                return false;
+            }
+            if !shared {
+                return Self::copy_on_write(vaddr, frame, orig_flags, pt);
+            }
+            // The first write to a clean shared page, which is writable and
+            // written back from now on.
+            return match pt.protect(vaddr, orig_flags) {
+                Ok((_, tlb)) => {
+                    tlb.flush();
+                    true
+                }
+                Err(_) => false,
+            };
+        }
+        let (file, offset) = self.file_page(vaddr);
+        let id = self.shared_file_id(shared);
+        let frame = match id {
+            Some(id) => get_shared_page(file, id, offset),
+            None => new_page(file, offset),
+        };
+        let Some(frame) = frame else {
+            return false;
+        };
+        // A shared page is read-only until it is written, so that the clean
+        // pages are not written back.
+        let flags = if shared && !access_flags.contains(MappingFlags::WRITE) {
+            orig_flags - MappingFlags::WRITE
+        } else {
+            orig_flags
+        };
+        match pt.remap(vaddr, frame, flags) {
+            Ok((_, tlb)) => {
+                tlb.flush();
+                true
+            }
+            Err(_) => {
+                put_page(id, offset, frame);
+                false
+            }
+        }
+    }
+
+    /// Writes the modified pages in `[start, start + size)` back to the file.
+    ///
+    /// The modified pages are the writable ones, they become read-only (clean)
+    /// before written back, so that the writes during the write-back are not
+    /// missed.
+    pub(crate) fn sync_file(&self, start: VirtAddr, size: usize, pt: &mut PageTable) -> bool {
+        debug!("sync_file: [{:#x}, {:#x})", start, start + size);
+        let mut ok = true;
+        for addr in PageIter4K::new(start, start + size).unwrap() {
+            let Some((frame, flags)) = query_frame(pt, addr) else {
+                continue;
+            };
+            if !flags.contains(MappingFlags::WRITE) {
+                continue;
+            }
+            match pt.protect(addr, flags - MappingFlags::WRITE) {
+                Ok((_, tlb)) => tlb.flush(),
+                Err(_) => {
+                    ok = false;
+                    continue;
+                }
+            }
+            let (file, offset) = self.file_page(addr);
+            if !write_page(file, offset, frame) {
+                // Still dirty, try again next time.
+                let _ = pt.protect(addr, flags).map(|(_, tlb)| tlb.flush());
+                ok = false;
+            }
+        }
+        ok
+    }
+
+    /// Shares the populated pages in `[start, start + size)` of `src_pt` with
+    /// `dst_pt`, where the area has already been mapped in `dst_pt`.
+    ///
+    /// Both sides keep writing to the same frames for shared mappings, while
+    /// private mappings are copied on write as [`Backend::clone_map_alloc`].
+    pub(crate) fn clone_map_file(
+        &self,
+        start: VirtAddr,
+        size: usize,
+        src_pt: &mut PageTable,
+        dst_pt: &mut PageTable,
+        shared: bool,
+    ) -> bool {
+        if !shared {
+            return self.clone_map_alloc(start, size, src_pt, dst_pt);
+        }
+        debug!("clone_map_file: [{:#x}, {:#x})", start, start + size);
+        for addr in PageIter4K::new(start, start + size).unwrap() {
+            let Some((frame, flags)) = query_frame(src_pt, addr) else {
+                continue;
+            };
+            share_frame(frame);
+            match dst_pt.remap(addr, frame, flags) {
+                Ok((_, tlb)) => tlb.ignore(), // The new page table is not in use.
+                Err(_) => {
+                    dealloc_frame(frame);
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::sync::Arc;
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use axerrno::AxResult;
+    use axhal::paging::MappingFlags;
+    use kspin::SpinNoIrq;
+    use memory_addr::{va, PhysAddr, PAGE_SIZE_4K};
+
+    use super::{read_page, write_page, MmapFile, SHARED_FILE_PAGES};
+    use crate::backend::alloc::tests::{setup, used_pages, BASE, RW, SIZE};
+    use crate::{AddrSpace, SharedKey};
+
+    /// A file in memory, which reads and writes at most `chunk` bytes at once.
+    struct MemFile {
+        data: SpinNoIrq<Vec<u8>>,
+        chunk: usize,
+    }
+
+    impl MemFile {
+        fn new(data: Vec<u8>, chunk: usize) -> Self {
+            Self {
+                data: SpinNoIrq::new(data),
+                chunk,
+            }
+        }
+    }
+
+    impl MmapFile for MemFile {
+        fn read_at(&self, offset: u64, buf: &mut [u8]) -> AxResult<usize> {
+            let data = self.data.lock();
+            let start = (offset as usize).min(data.len());
+            let len = buf.len().min(data.len() - start).min(self.chunk);
+            buf[..len].copy_from_slice(&data[start..start + len]);
+            Ok(len)
+        }
+
+        fn write_at(&self, offset: u64, buf: &[u8]) -> AxResult<usize> {
+            let mut data = self.data.lock();
+            let start = offset as usize;
+            let len = buf.len().min(self.chunk);
+            if data.len() < start + len {
+                data.resize(start + len, 0);
+            }
+            data[start..start + len].copy_from_slice(&buf[..len]);
+            Ok(len)
+        }
+
+        fn size(&self) -> AxResult<u64> {
+            Ok(self.data.lock().len() as u64)
+        }
+
+        fn id(&self) -> Option<(u64, u64)> {
+            Some((0, self as *const Self as u64))
+        }
+    }
+
+    /// The frames are accessed by `phys_to_virt`, which is the identity on the
+    /// host.
+    fn frame_of(page: &[u8]) -> PhysAddr {
+        PhysAddr::from(page.as_ptr() as usize)
+    }
+
+    #[test]
+    fn test_read_page() {
+        let data: Vec<u8> = (0..PAGE_SIZE_4K + 100).map(|i| i as u8).collect();
+        let file = MemFile::new(data.clone(), 1000);
+
+        let mut page = vec![0xff; PAGE_SIZE_4K];
+        assert!(read_page(&file, 0, frame_of(&page)));
+        assert_eq!(page, data[..PAGE_SIZE_4K]);
+
+        // the part beyond the end of the file is left as zeros
+        page.fill(0);
+        assert!(read_page(&file, PAGE_SIZE_4K as u64, frame_of(&page)));
+        assert_eq!(page[..100], data[PAGE_SIZE_4K..]);
+        assert!(page[100..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_write_page() {
+        let file = MemFile::new(vec![0; PAGE_SIZE_4K + 100], 1000);
+        let page = vec![0x5a; PAGE_SIZE_4K];
+
+        assert!(write_page(&file, 0, frame_of(&page)));
+        assert!(file.data.lock()[..PAGE_SIZE_4K].iter().all(|&b| b == 0x5a));
+
+        // the file is never extended
+        assert!(write_page(&file, PAGE_SIZE_4K as u64, frame_of(&page)));
+        assert_eq!(file.size(), Ok(PAGE_SIZE_4K as u64 + 100));
+        assert!(file.data.lock().iter().all(|&b| b == 0x5a));
+        assert!(write_page(&file, 2 * PAGE_SIZE_4K as u64, frame_of(&page)));
+        assert_eq!(file.size(), Ok(PAGE_SIZE_4K as u64 + 100));
+    }
+
+    #[test]
+    fn test_shared_mappings() {
+        let _guard = setup();
+        let used = used_pages();
+        let mem = Arc::new(MemFile::new(vec![0; PAGE_SIZE_4K], PAGE_SIZE_4K));
+        let file: Arc<dyn MmapFile> = mem.clone();
+        let start = va!(BASE);
+        let mut aspace1 = AddrSpace::new_empty(start, SIZE).unwrap();
+        let mut aspace2 = AddrSpace::new_empty(start, SIZE).unwrap();
+        for aspace in [&mut aspace1, &mut aspace2] {
+            aspace
+                .map_file(start, PAGE_SIZE_4K, RW, file.clone(), 0, true)
+                .unwrap();
+        }
+
+        // The mappings of the same file share the frames, the writes through
+        // one are seen by the other before they are written back.
+        aspace1.write(start + 0x10, b"shared").unwrap();
+        let mut buf = [0; 6];
+        aspace2.read(start + 0x10, &mut buf).unwrap();
+        assert_eq!(&buf, b"shared");
+        let frame = aspace1.page_table().query(start).unwrap().0;
+        assert_eq!(aspace2.page_table().query(start).unwrap().0, frame);
+        assert!(mem.data.lock().iter().all(|&b| b == 0));
+        let key = Some(SharedKey::File {
+            id: mem.id().unwrap(),
+            offset: 0x10,
+        });
+        assert_eq!(aspace1.shared_key(start + 0x10), key);
+        assert_eq!(aspace2.shared_key(start + 0x10), key);
+
+        // The modified page is written back when it's unmapped, and the frame
+        // is freed after the last mapping of it is gone.
+        drop(aspace1);
+        assert_eq!(&mem.data.lock()[0x10..0x16], b"shared");
+        assert_eq!(SHARED_FILE_PAGES.lock().len(), 1);
+        drop(aspace2);
+        assert!(SHARED_FILE_PAGES.lock().is_empty());
+        assert_eq!(used_pages(), used);
+    }
+
+    #[test]
+    fn test_private_mapping_fork() {
+        let _guard = setup();
+        let used = used_pages();
+        let mem = Arc::new(MemFile::new(vec![0x5a; PAGE_SIZE_4K], PAGE_SIZE_4K));
+        let start = va!(BASE);
+        let mut parent = AddrSpace::new_empty(start, SIZE).unwrap();
+        parent
+            .map_file(start, PAGE_SIZE_4K, RW, mem.clone(), 0, false)
+            .unwrap();
+        let mut buf = [0; 4];
+        parent.read(start, &mut buf).unwrap();
+        let frame = parent.page_table().query(start).unwrap().0;
+        assert_eq!(parent.shared_key(start), None);
+        let mut child = parent.try_clone().unwrap();
+
+        // The first write copies the page, wherever it is in the page.
+        assert!(child.handle_page_fault(start + 0x123, MappingFlags::WRITE));
+        assert_ne!(child.page_table().query(start).unwrap().0, frame);
+        child.read(start + PAGE_SIZE_4K - 4, &mut buf).unwrap();
+        assert_eq!(buf, [0x5a; 4]);
+        child.write(start, b"copy").unwrap();
+        parent.read(start, &mut buf).unwrap();
+        assert_eq!(buf, [0x5a; 4]);
+
+        // Private pages are never written back.
+        drop(parent);
+        drop(child);
+        assert!(mem.data.lock().iter().all(|&b| b == 0x5a));
+        assert_eq!(used_pages(), used);
+    }
+}
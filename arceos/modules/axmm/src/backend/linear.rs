@@ -2,6 +2,7 @@ use axhal::paging::{MappingFlags, PageTable};
 use memory_addr::{PhysAddr, VirtAddr};
 
 use super::Backend;
+use crate::huge_page::{map_linear_region, split_huge_pages_at};
 
 impl Backend {
     /// Creates a new linear mapping backend.
@@ -17,7 +18,8 @@ impl Backend {
         pt: &mut PageTable,
         pa_va_offset: usize,
     ) -> bool {
-        let va_to_pa = |va: VirtAddr| PhysAddr::from(va.as_usize() - pa_va_offset);
+        // The offset wraps around if the physical address is higher.
+        let va_to_pa = |va: VirtAddr| PhysAddr::from(va.as_usize().wrapping_sub(pa_va_offset));
         debug!(
             "map_linear: [{:#x}, {:#x}) -> [{:#x}, {:#x}) {:?}",
             start,
@@ -26,9 +28,8 @@ impl Backend {
             va_to_pa(start + size),
             flags
         );
-        pt.map_region(start, va_to_pa, size, flags, false, false)
-            .map(|tlb| tlb.ignore()) // TLB flush on map is unnecessary, as there are no outdated mappings.
-            .is_ok()
+        // TLB flush on map is unnecessary, as there are no outdated mappings.
+        map_linear_region(pt, start, va_to_pa, size, flags).is_ok()
     }
 
     pub(crate) fn unmap_linear(
@@ -39,8 +40,73 @@ impl Backend {
         _pa_va_offset: usize,
     ) -> bool {
         debug!("unmap_linear: [{:#x}, {:#x})", start, start + size);
+        if !split_huge_pages_at(pt, start) || !split_huge_pages_at(pt, start + size) {
+            return false;
+        }
         pt.unmap_region(start, size, true)
             .map(|tlb| tlb.ignore()) // flush each page on unmap, do not flush the entire TLB.
             .is_ok()
     }
+
+    pub(crate) fn protect_linear(
+        &self,
+        start: VirtAddr,
+        size: usize,
+        new_flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> bool {
+        debug!(
+            "protect_linear: [{:#x}, {:#x}) {:?}",
+            start,
+            start + size,
+            new_flags
+        );
+        if !split_huge_pages_at(pt, start) || !split_huge_pages_at(pt, start + size) {
+            return false;
+        }
+        pt.protect_region(start, size, new_flags, true)
+            .map(|tlb| tlb.ignore())
+            .is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axhal::paging::PageSize;
+    use memory_addr::{pa, va, PAGE_SIZE_4K};
+
+    use crate::backend::alloc::tests::{setup, used_pages, BASE, RW, SIZE};
+    use crate::AddrSpace;
+
+    const SIZE_2M: usize = PageSize::Size2M as usize;
+
+    #[test]
+    fn test_map_linear_huge_pages() {
+        let _guard = setup();
+        let used = used_pages();
+        let start = va!(BASE);
+        let mut aspace = AddrSpace::new_empty(start, SIZE).unwrap();
+        let page_size = |aspace: &AddrSpace, vaddr| aspace.page_table().query(vaddr).unwrap().2;
+
+        // Huge pages are used where both addresses are aligned.
+        aspace
+            .map_linear_area(start, pa!(0x4000_0000), SIZE_2M + PAGE_SIZE_4K, RW)
+            .unwrap();
+        assert_eq!(page_size(&aspace, start), PageSize::Size2M);
+        assert_eq!(page_size(&aspace, start + SIZE_2M), PageSize::Size4K);
+
+        let start = start + 2 * SIZE_2M;
+        aspace
+            .map_linear_area(start, pa!(0x4000_1000), SIZE_2M, RW)
+            .unwrap();
+        assert_eq!(page_size(&aspace, start), PageSize::Size4K);
+        assert_eq!(
+            aspace.page_table().query(start + SIZE_2M - 1).unwrap().0,
+            pa!(0x4020_0fff)
+        );
+
+        // The frames are not freed, only the page tables.
+        drop(aspace);
+        assert_eq!(used_pages(), used);
+    }
 }
@@ -1,21 +1,36 @@
 //! Memory mapping backends.
 #![allow(dead_code)]
 
+use ::alloc::sync::Arc;
 use axhal::paging::{MappingFlags, PageTable};
-use memory_addr::VirtAddr;
+use memory_addr::{PhysAddr, VirtAddr};
 use memory_set::MappingBackend;
 
+use crate::aspace::{AreaKind, SharedKey};
+
 mod alloc;
+mod file;
 mod linear;
+mod shared;
+
+pub(crate) use self::alloc::resident_pages;
+pub use self::file::MmapFile;
+pub use self::shared::SharedPages;
 
 /// A unified enum type for different memory mapping backends.
 ///
-/// Currently, two backends are implemented:
+/// Currently, four backends are implemented:
 ///
 /// - **Linear**: used for linear mappings. The target physical frames are
 ///   contiguous and their addresses should be known when creating the mapping.
 /// - **Allocation**: used in general, or for lazy mappings. The target physical
-///   frames are obtained from the global allocator.
+///   frames are obtained from the global allocator. Frames can be shared by
+///   several address spaces after cloning, and are copied on write.
+/// - **File**: used for file mappings. The physical frames are allocated and
+///   filled with the file data on demand. The shared mappings of the same
+///   file share the frames, see [`MmapFile::id`].
+/// - **Shared**: used for shared memory. The physical frames belong to a
+///   [`SharedPages`] object, which can be mapped by several address spaces.
 #[derive(Clone)]
 pub enum Backend {
     /// Linear mapping backend.
@@ -23,6 +38,8 @@ pub enum Backend {
     /// The offset between the virtual address and the physical address is
     /// constant, which is specified by `pa_va_offset`. For example, the virtual
     /// address `vaddr` is mapped to the physical address `vaddr - pa_va_offset`.
+    ///
+    /// Huge pages are used wherever the addresses are aligned.
     Linear {
         /// `vaddr - paddr`.
         pa_va_offset: usize,
@@ -33,10 +50,46 @@ pub enum Backend {
     /// mapping is created, and no page faults are triggered during the memory
     /// access. Otherwise, the physical frames are allocated on demand (by
     /// handling page faults).
+    ///
+    /// Populated mappings are backed by huge pages if possible, which are
+    /// split into 4K pages before they are shared with a cloned address space.
     Alloc {
         /// Whether to populate the physical frames when creating the mapping.
         populate: bool,
     },
+    /// File mapping backend.
+    ///
+    /// The page at `start` holds the data at `offset` of `file`, and the
+    /// following pages hold the following data. `start` is kept as the start
+    /// of the original mapping even if the area is split later.
+    ///
+    /// If `shared` is `true`, the modifications are written back to the file
+    /// when the pages are unmapped or synchronized. The pages are mapped
+    /// read-only until they are written, so that only the modified pages are
+    /// written back. Otherwise, the pages are private copies of the file data.
+    File {
+        /// The mapped file.
+        file: Arc<dyn MmapFile>,
+        /// The virtual address mapped to `offset` of the file.
+        start: VirtAddr,
+        /// The file offset mapped at `start`.
+        offset: u64,
+        /// Whether the modifications are visible in the file.
+        shared: bool,
+    },
+    /// Shared memory backend.
+    ///
+    /// The page at `start` is mapped to the frame at `offset` of `pages`, and
+    /// the following pages are mapped to the following frames. Like the file
+    /// mapping backend, `start` is kept as the start of the original mapping.
+    Shared {
+        /// The shared memory object.
+        pages: Arc<SharedPages>,
+        /// The virtual address mapped to `offset` of the object.
+        start: VirtAddr,
+        /// The offset in bytes in the object mapped at `start`.
+        offset: usize,
+    },
 }
 
 impl MappingBackend for Backend {
@@ -47,6 +100,8 @@ impl MappingBackend for Backend {
         match *self {
             Self::Linear { pa_va_offset } => self.map_linear(start, size, flags, pt, pa_va_offset),
             Self::Alloc { populate } => self.map_alloc(start, size, flags, pt, populate),
+            Self::File { .. } => self.map_file(start, size, flags, pt),
+            Self::Shared { .. } => self.map_shared(start, size, flags, pt),
         }
     }
 
@@ -54,6 +109,8 @@ impl MappingBackend for Backend {
         match *self {
             Self::Linear { pa_va_offset } => self.unmap_linear(start, size, pt, pa_va_offset),
             Self::Alloc { populate } => self.unmap_alloc(start, size, pt, populate),
+            Self::File { shared, .. } => self.unmap_file(start, size, pt, shared),
+            Self::Shared { .. } => self.unmap_shared(start, size, pt),
         }
     }
 
@@ -64,10 +121,14 @@ impl MappingBackend for Backend {
         new_flags: Self::Flags,
         page_table: &mut Self::PageTable,
     ) -> bool {
-        page_table
-            .protect_region(start, size, new_flags, true)
-            .map(|tlb| tlb.ignore())
-            .is_ok()
+        match *self {
+            Self::Linear { .. } => self.protect_linear(start, size, new_flags, page_table),
+            Self::Alloc { .. } => self.protect_alloc(start, size, new_flags, page_table),
+            Self::File { shared, .. } => {
+                self.protect_file(start, size, new_flags, page_table, shared)
+            }
+            Self::Shared { .. } => self.protect_shared(start, size, new_flags, page_table),
+        }
     }
 }
 
@@ -76,13 +137,131 @@ impl Backend {
         &self,
         vaddr: VirtAddr,
         orig_flags: MappingFlags,
+        access_flags: MappingFlags,
         page_table: &mut PageTable,
     ) -> bool {
         match *self {
-            Self::Linear { .. } => false, // Linear mappings should not trigger page faults.
+            // Linear mappings should not trigger page faults, unless the page
+            // was being split into smaller pages at that time.
+            Self::Linear { .. } => page_table
+                .query(vaddr)
+                .is_ok_and(|(_, flags, _)| flags.contains(orig_flags)),
             Self::Alloc { populate } => {
                 self.handle_page_fault_alloc(vaddr, orig_flags, page_table, populate)
             }
+            Self::File { shared, .. } => {
+                self.handle_page_fault_file(vaddr, orig_flags, access_flags, page_table, shared)
+            }
+            Self::Shared { .. } => self.handle_page_fault_shared(vaddr, orig_flags, page_table),
+        }
+    }
+
+    /// Whether the pages of the mapping can be swapped out.
+    ///
+    /// Only the lazy allocation mappings are swappable, as the pages of the
+    /// populated ones should never trigger page faults.
+    pub(crate) fn is_swappable(&self) -> bool {
+        matches!(*self, Self::Alloc { populate: false })
+    }
+
+    /// Advances the clock hand of page reclamation over the page at `vaddr`
+    /// of a swappable mapping, returns `true` if the page is swapped out.
+    pub(crate) fn clock_page(&self, vaddr: VirtAddr, page_table: &mut PageTable) -> bool {
+        match *self {
+            Self::Alloc { populate: false } => Self::clock_page_alloc(vaddr, page_table),
+            _ => false,
+        }
+    }
+
+    /// Writes the modifications in `[start, start + size)` back to the mapped
+    /// file, if it is a shared file mapping.
+    pub(crate) fn sync(&self, start: VirtAddr, size: usize, page_table: &mut PageTable) -> bool {
+        match *self {
+            Self::File { shared: true, .. } => self.sync_file(start, size, page_table),
+            _ => true,
+        }
+    }
+
+    /// Returns the kind of the backend, for the area starting at `area_start`.
+    pub(crate) fn kind(&self, area_start: VirtAddr) -> AreaKind {
+        match *self {
+            Self::Linear { .. } => AreaKind::Linear,
+            Self::Alloc { .. } => AreaKind::Alloc,
+            Self::File {
+                start,
+                offset,
+                shared,
+                ..
+            } => AreaKind::File {
+                offset: offset + (area_start.as_usize() - start.as_usize()) as u64,
+                shared,
+            },
+            Self::Shared { .. } => AreaKind::Shared,
+        }
+    }
+
+    /// Returns the identity of the memory at `vaddr` if it's shared, see
+    /// [`AddrSpace::shared_key`](crate::AddrSpace::shared_key).
+    pub(crate) fn shared_key(&self, vaddr: VirtAddr) -> Option<SharedKey> {
+        match *self {
+            Self::Linear { pa_va_offset } => Some(SharedKey::Phys(PhysAddr::from(
+                vaddr.as_usize() - pa_va_offset,
+            ))),
+            Self::Alloc { .. } | Self::File { shared: false, .. } => None,
+            Self::File {
+                ref file,
+                start,
+                offset,
+                ..
+            } => {
+                let offset = offset + (vaddr.as_usize() - start.as_usize()) as u64;
+                Some(match file.id() {
+                    Some(id) => SharedKey::File { id, offset },
+                    // The pages are shared by the copies of the mapping only.
+                    None => SharedKey::Object {
+                        addr: Arc::as_ptr(file) as *const () as usize,
+                        offset,
+                    },
+                })
+            }
+            Self::Shared {
+                ref pages,
+                start,
+                offset,
+            } => Some(SharedKey::Object {
+                addr: Arc::as_ptr(pages) as usize,
+                offset: (offset + vaddr.as_usize() - start.as_usize()) as u64,
+            }),
+        }
+    }
+
+    /// Returns the backend for the copy of an area in a cloned address space.
+    ///
+    /// Allocation areas are not populated in the copy, as their frames are
+    /// shared with the original area by [`Backend::clone_map`].
+    pub(crate) fn clone_for_fork(&self) -> Self {
+        match *self {
+            Self::Linear { pa_va_offset } => Self::new_linear(pa_va_offset),
+            Self::Alloc { .. } => Self::new_alloc(false),
+            Self::File { .. } | Self::Shared { .. } => self.clone(),
+        }
+    }
+
+    /// Makes the mapped pages in `[start, start + size)` of `src_pt` visible
+    /// in `dst_pt`, where the area has already been mapped with the backend
+    /// returned by [`Backend::clone_for_fork`].
+    pub(crate) fn clone_map(
+        &self,
+        start: VirtAddr,
+        size: usize,
+        src_pt: &mut PageTable,
+        dst_pt: &mut PageTable,
+    ) -> bool {
+        match *self {
+            Self::Linear { .. } => true, // Linear mappings share the same physical memory.
+            Self::Alloc { .. } => self.clone_map_alloc(start, size, src_pt, dst_pt),
+            Self::File { shared, .. } => self.clone_map_file(start, size, src_pt, dst_pt, shared),
+            Self::Shared { .. } => self.clone_map_shared(start, size, src_pt, dst_pt),
         }
     }
 }
@@ -0,0 +1,249 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use axerrno::{ax_err, AxResult};
+use axhal::mem::phys_to_virt;
+use axhal::paging::{MappingFlags, PageTable};
+use kspin::SpinNoIrq;
+use memory_addr::{align_up_4k, MemoryAddr, PageIter4K, PhysAddr, VirtAddr, PAGE_SIZE_4K};
+
+use super::alloc::{alloc_frame, dealloc_frame, query_frame};
+use super::Backend;
+
+struct SharedPagesInner {
+    size: usize,
+    frames: Vec<Option<PhysAddr>>,
+}
+
+/// A shared memory object, i.e., a group of physical frames that can be mapped
+/// into several address spaces at the same time by [`Backend::Shared`].
+///
+/// The frames are allocated on the first access, and are not freed until the
+/// object itself is dropped, which happens when it is no longer mapped or
+/// referenced by its owner (e.g., a System V shared memory segment).
+pub struct SharedPages {
+    inner: SpinNoIrq<SharedPagesInner>,
+}
+
+impl SharedPages {
+    /// Creates a new shared memory object of `size` bytes.
+    pub fn new(size: usize) -> Self {
+        let mut frames = Vec::new();
+        frames.resize(align_up_4k(size) / PAGE_SIZE_4K, None);
+        Self {
+            inner: SpinNoIrq::new(SharedPagesInner { size, frames }),
+        }
+    }
+
+    /// Returns the size of the object in bytes.
+    pub fn size(&self) -> usize {
+        self.inner.lock().size
+    }
+
+    /// Extends the object to `size` bytes.
+    ///
+    /// The object cannot be shrunk, as the frames may still be mapped.
+    pub fn grow(&self, size: usize) -> AxResult {
+        let mut inner = self.inner.lock();
+        if size < inner.size {
+            return ax_err!(InvalidInput, "shared memory cannot be shrunk");
+        }
+        inner.size = size;
+        inner.frames.resize(align_up_4k(size) / PAGE_SIZE_4K, None);
+        Ok(())
+    }
+
+    /// Returns the frame of the page at `index`, and allocates it if it is
+    /// accessed for the first time.
+    ///
+    /// Returns `None` if the page is beyond the end of the object or there is
+    /// no free memory.
+    pub(crate) fn frame(&self, index: usize) -> Option<PhysAddr> {
+        let mut inner = self.inner.lock();
+        let frame = inner.frames.get_mut(index)?;
+        if frame.is_none() {
+            *frame = Some(alloc_frame(true)?);
+        }
+        *frame
+    }
+
+    /// Copies the data between `buf` and the object at `offset`, returns the
+    /// number of bytes copied.
+    fn copy_at(
+        &self,
+        offset: usize,
+        len: usize,
+        mut f: impl FnMut(*mut u8, usize, usize),
+    ) -> usize {
+        let len = len.min(self.size().saturating_sub(offset));
+        let mut copied = 0;
+        while copied < len {
+            let pos = offset + copied;
+            let Some(frame) = self.frame(pos / PAGE_SIZE_4K) else {
+                break;
+            };
+            let page_offset = pos % PAGE_SIZE_4K;
+            let n = (PAGE_SIZE_4K - page_offset).min(len - copied);
+            let ptr = unsafe { phys_to_virt(frame).as_mut_ptr().add(page_offset) };
+            f(ptr, copied, n);
+            copied += n;
+        }
+        copied
+    }
+
+    /// Reads the data at `offset` into `buf`, returns the number of bytes read.
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        self.copy_at(offset, buf.len(), |src, pos, n| unsafe {
+            core::ptr::copy_nonoverlapping(src, buf.as_mut_ptr().add(pos), n);
+        })
+    }
+
+    /// Writes `buf` to the object at `offset`, returns the number of bytes
+    /// written. The object is not extended.
+    pub fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+        self.copy_at(offset, buf.len(), |dst, pos, n| unsafe {
+            core::ptr::copy_nonoverlapping(buf.as_ptr().add(pos), dst, n);
+        })
+    }
+}
+
+impl Drop for SharedPages {
+    fn drop(&mut self) {
+        for frame in self.inner.lock().frames.iter().flatten() {
+            dealloc_frame(*frame);
+        }
+    }
+}
+
+impl Backend {
+    /// Creates a new shared memory mapping backend.
+    ///
+    /// The page at `start` is mapped to the data at `offset` of the shared
+    /// memory object.
+    pub fn new_shared(pages: Arc<SharedPages>, start: VirtAddr, offset: usize) -> Self {
+        Self::Shared {
+            pages,
+            start,
+            offset,
+        }
+    }
+
+    /// Returns the shared memory object and the page index in it of the page
+    /// at `vaddr`.
+    fn shared_page(&self, vaddr: VirtAddr) -> (&SharedPages, usize) {
+        match self {
+            Self::Shared {
+                pages,
+                start,
+                offset,
+            } => (
+                pages.as_ref(),
+                (offset + (vaddr.align_down_4k().as_usize() - start.as_usize())) / PAGE_SIZE_4K,
+            ),
+            _ => unreachable!(),
+        }
+    }
+
+    pub(crate) fn map_shared(
+        &self,
+        start: VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> bool {
+        debug!(
+            "map_shared: [{:#x}, {:#x}) {:?}",
+            start,
+            start + size,
+            flags
+        );
+        // Map to a empty entry for on-demand mapping.
+        pt.map_region(
+            start,
+            |_| 0.into(),
+            size,
+            MappingFlags::empty(),
+            false,
+            false,
+        )
+        .map(|tlb| tlb.ignore())
+        .is_ok()
+    }
+
+    pub(crate) fn unmap_shared(&self, start: VirtAddr, size: usize, pt: &mut PageTable) -> bool {
+        debug!("unmap_shared: [{:#x}, {:#x})", start, start + size);
+        for addr in PageIter4K::new(start, start + size).unwrap() {
+            if let Ok((_, page_size, tlb)) = pt.unmap(addr) {
+                if page_size.is_huge() {
+                    return false;
+                }
+                // The frames are owned by the shared memory object.
+                tlb.flush();
+            }
+        }
+        true
+    }
+
+    pub(crate) fn protect_shared(
+        &self,
+        start: VirtAddr,
+        size: usize,
+        new_flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> bool {
+        debug!(
+            "protect_shared: [{:#x}, {:#x}) {:?}",
+            start,
+            start + size,
+            new_flags
+        );
+        for addr in PageIter4K::new(start, start + size).unwrap() {
+            if query_frame(pt, addr).is_some() {
+                match pt.protect(addr, new_flags) {
+                    Ok((_, tlb)) => tlb.flush(),
+                    Err(_) => return false,
+                }
+            }
+        }
+        true
+    }
+
+    pub(crate) fn handle_page_fault_shared(
+        &self,
+        vaddr: VirtAddr,
+        orig_flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> bool {
+        if query_frame(pt, vaddr).is_some() {
+            return false; // Shared pages are always mapped with the original flags.
+        }
+        let (pages, index) = self.shared_page(vaddr);
+        match pages.frame(index) {
+            Some(frame) => pt
+                .remap(vaddr, frame, orig_flags)
+                .map(|(_, tlb)| tlb.flush())
+                .is_ok(),
+            None => false, // Beyond the end of the object.
+        }
+    }
+
+    /// Maps the populated pages in `[start, start + size)` of `src_pt` to the
+    /// same frames in `dst_pt`, where the area has already been mapped.
+    pub(crate) fn clone_map_shared(
+        &self,
+        start: VirtAddr,
+        size: usize,
+        src_pt: &PageTable,
+        dst_pt: &mut PageTable,
+    ) -> bool {
+        debug!("clone_map_shared: [{:#x}, {:#x})", start, start + size);
+        for addr in PageIter4K::new(start, start + size).unwrap() {
+            if let Some((frame, flags)) = query_frame(src_pt, addr) {
+                match dst_pt.remap(addr, frame, flags) {
+                    Ok((_, tlb)) => tlb.ignore(), // The new page table is not in use.
+                    Err(_) => return false,
+                }
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,161 @@
+//! Helpers for the huge page (2M/1G) mappings.
+//!
+//! Huge pages are used when both the virtual and physical addresses are
+//! aligned to the page size, and the mapping is large enough. They are split
+//! into smaller pages when only part of them is unmapped or protected.
+
+use axhal::paging::{MappingFlags, PageSize, PageTable, PagingResult};
+use memory_addr::{is_aligned, MemoryAddr, PhysAddr, VirtAddr};
+
+/// Page sizes to try for huge page mappings, from the largest.
+const HUGE_PAGE_SIZES: [PageSize; 2] = [PageSize::Size1G, PageSize::Size2M];
+
+/// Returns the next smaller page size.
+const fn smaller_page_size(page_size: PageSize) -> PageSize {
+    match page_size {
+        PageSize::Size1G => PageSize::Size2M,
+        _ => PageSize::Size4K,
+    }
+}
+
+/// Returns whether the CPU supports the page size. 1G pages are optional on
+/// x86_64 (`pdpe1gb`, bit 26 of EDX of CPUID leaf `0x8000_0001`).
+#[cfg(target_arch = "x86_64")]
+fn is_supported(page_size: PageSize) -> bool {
+    use core::sync::atomic::{AtomicU8, Ordering};
+    /// 0 if not checked yet, 1 if 1G pages are not supported, 2 otherwise.
+    static HAS_1G_PAGES: AtomicU8 = AtomicU8::new(0);
+
+    if !matches!(page_size, PageSize::Size1G) {
+        return true;
+    }
+    let mut has = HAS_1G_PAGES.load(Ordering::Relaxed);
+    if has == 0 {
+        let supported = raw_cpuid::CpuId::new()
+            .get_extended_processor_and_feature_identifiers()
+            .is_some_and(|info| info.has_1gib_pages());
+        has = if supported { 2 } else { 1 };
+        HAS_1G_PAGES.store(has, Ordering::Relaxed);
+    }
+    has == 2
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+const fn is_supported(_page_size: PageSize) -> bool {
+    true
+}
+
+/// Returns the huge page sizes that can map `vaddr` with `size` bytes left,
+/// from the largest. The physical address must be aligned as well.
+pub(crate) fn huge_page_sizes(vaddr: VirtAddr, size: usize) -> impl Iterator<Item = PageSize> {
+    HUGE_PAGE_SIZES.into_iter().filter(move |&page_size| {
+        let page_size_bytes = page_size as usize;
+        vaddr.is_aligned(page_size_bytes) && size >= page_size_bytes && is_supported(page_size)
+    })
+}
+
+/// Maps `[start, start + size)` to the physical addresses given by
+/// `va_to_pa`, which must be linear. The largest pages supported are used
+/// wherever the addresses are aligned.
+///
+/// The TLB is not flushed.
+pub(crate) fn map_linear_region(
+    pt: &mut PageTable,
+    start: VirtAddr,
+    va_to_pa: impl Fn(VirtAddr) -> PhysAddr,
+    size: usize,
+    flags: MappingFlags,
+) -> PagingResult {
+    let end = start + size;
+    let mut vaddr = start;
+    while vaddr < end {
+        let paddr = va_to_pa(vaddr);
+        let page_size = huge_page_sizes(vaddr, end - vaddr)
+            .find(|&page_size| paddr.is_aligned(page_size as usize))
+            .unwrap_or(PageSize::Size4K);
+        pt.map(vaddr, paddr, page_size, flags)?.ignore();
+        vaddr += page_size as usize;
+    }
+    Ok(())
+}
+
+/// Splits the huge page mapped at `vaddr` into pages of the next smaller
+/// size, which are mapped to the same frames with the same flags.
+///
+/// The page is inaccessible while it is being split, faults on it are
+/// handled after the page table is updated, as the address space is locked
+/// during the split.
+fn split_huge_page(
+    pt: &mut PageTable,
+    vaddr: VirtAddr,
+    paddr: PhysAddr,
+    flags: MappingFlags,
+    page_size: PageSize,
+) -> bool {
+    let sub_size = smaller_page_size(page_size);
+    let start = vaddr.align_down(page_size as usize);
+    let start_paddr = paddr.align_down(page_size as usize);
+    debug!(
+        "split_huge_page: [{:#x}, {:#x}) {:?} -> {:?}",
+        start,
+        start + page_size as usize,
+        page_size,
+        sub_size
+    );
+    match pt.unmap(start) {
+        Ok((_, _, tlb)) => tlb.flush(),
+        Err(_) => return false,
+    }
+    for offset in (0..page_size as usize).step_by(sub_size as usize) {
+        if let Err(e) = pt.map(start + offset, start_paddr + offset, sub_size, flags) {
+            warn!("failed to split huge page at {:#x}: {:?}", start, e);
+            // Restore the huge page, the page table frames just allocated are
+            // freed with the page table.
+            let _ = pt.unmap_region(start, offset, false);
+            if let Ok(tlb) = pt.map(start, start_paddr, page_size, flags) {
+                tlb.flush();
+            }
+            return false;
+        }
+        // TLB flush on map is unnecessary, as the huge page has been flushed.
+    }
+    true
+}
+
+/// Splits the huge pages containing `vaddr`, until `vaddr` is the start of a
+/// page, so that the mappings before and after it can be changed separately.
+pub(crate) fn split_huge_pages_at(pt: &mut PageTable, vaddr: VirtAddr) -> bool {
+    loop {
+        match pt.query(vaddr) {
+            Ok((paddr, flags, page_size))
+                if page_size.is_huge() && !is_aligned(vaddr.as_usize(), page_size as usize) =>
+            {
+                if !split_huge_page(pt, vaddr, paddr, flags, page_size) {
+                    return false;
+                }
+            }
+            _ => return true,
+        }
+    }
+}
+
+/// Splits all the huge pages in `[start, start + size)` into 4K pages.
+///
+/// The range must not be in the middle of a huge page.
+pub(crate) fn split_huge_pages(pt: &mut PageTable, start: VirtAddr, size: usize) -> bool {
+    let end = start + size;
+    let mut vaddr = start;
+    while vaddr < end {
+        match pt.query(vaddr) {
+            Ok((paddr, flags, page_size)) if page_size.is_huge() => {
+                if !split_huge_page(pt, vaddr, paddr, flags, page_size) {
+                    return false;
+                }
+                // Split the smaller huge pages in the next rounds.
+            }
+            Ok((_, _, page_size)) => vaddr += page_size as usize,
+            Err(_) => vaddr += PageSize::Size4K as usize,
+        }
+    }
+    true
+}
@@ -8,8 +8,14 @@ extern crate alloc;
 
 mod aspace;
 mod backend;
-
-pub use self::aspace::AddrSpace;
+mod huge_page;
+mod swap;
+
+pub use self::aspace::{AddrSpace, AddrSpaceStats, AreaInfo, AreaKind, SharedKey};
+pub use self::backend::{MmapFile, SharedPages};
+pub use self::swap::{
+    init_swap, low_watermark, register_reclaim_source, set_low_watermark, ReclaimSource, SwapDevice,
+};
 
 use axerrno::{AxError, AxResult};
 use axhal::mem::phys_to_virt;
@@ -0,0 +1,329 @@
+//! Swap area for the pages of the lazy allocation mappings.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::boxed::Box;
+use alloc::sync::Weak;
+use alloc::vec::Vec;
+use axalloc::global_allocator;
+use axerrno::{ax_err, AxResult};
+use axhal::mem::phys_to_virt;
+use axsync::Mutex;
+use kspin::SpinNoIrq;
+use lazyinit::LazyInit;
+use memory_addr::{PhysAddr, PAGE_SIZE_4K};
+
+/// Reclaim memory when the number of free pages drops below this, by
+/// default.
+const DEFAULT_LOW_WATERMARK: usize = 256;
+/// The number of pages to reclaim at a time.
+pub(crate) const SWAP_BATCH_PAGES: usize = 32;
+
+static SWAP_AREA: LazyInit<SwapArea> = LazyInit::new();
+static LOW_WATERMARK: AtomicUsize = AtomicUsize::new(DEFAULT_LOW_WATERMARK);
+
+static RECLAIM_SOURCES: SpinNoIrq<Vec<Weak<dyn ReclaimSource>>> = SpinNoIrq::new(Vec::new());
+/// Which source to reclaim from first, rotated so that the pages are taken
+/// from all the address spaces in turn.
+static NEXT_SOURCE: AtomicUsize = AtomicUsize::new(0);
+
+/// A block device that can be used as a swap area.
+///
+/// It mirrors the block device operations of the drivers, so that the device
+/// drivers can be wrapped easily.
+pub trait SwapDevice: Send + Sync {
+    /// The size of a block in bytes.
+    fn block_size(&self) -> usize;
+
+    /// The number of blocks of the device.
+    fn num_blocks(&self) -> u64;
+
+    /// Reads the block `block_id` into `buf`.
+    fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> AxResult;
+
+    /// Writes `buf` to the block `block_id`.
+    fn write_block(&mut self, block_id: u64, buf: &[u8]) -> AxResult;
+}
+
+/// An address space whose pages can be reclaimed when another address space
+/// is short of memory, see [`register_reclaim_source`].
+pub trait ReclaimSource: Send + Sync {
+    /// Reclaims at most `nr_pages` pages by swapping them out, returns the
+    /// number of pages reclaimed.
+    ///
+    /// It must not wait if the address space is in use, since the caller is
+    /// holding the lock of another address space.
+    fn try_reclaim(&self, nr_pages: usize) -> usize;
+}
+
+/// A swap area on a block device, divided into page-sized slots.
+///
+/// The slot table is protected by a spinlock, while the device has its own
+/// lock, so that the block I/O is done without holding the spinlock.
+pub(crate) struct SwapArea {
+    dev: Mutex<Box<dyn SwapDevice>>,
+    block_size: usize,
+    slots: SpinNoIrq<SwapSlots>,
+}
+
+struct SwapSlots {
+    /// The number of page table entries referring to each slot.
+    refs: Vec<u16>,
+    /// Where to start searching for a free slot.
+    next_free: usize,
+}
+
+impl SwapArea {
+    pub(crate) fn new(dev: Box<dyn SwapDevice>) -> AxResult<Self> {
+        let block_size = dev.block_size();
+        if block_size == 0 || PAGE_SIZE_4K % block_size != 0 {
+            return ax_err!(InvalidInput, "unsupported block size of swap device");
+        }
+        let num_slots = (dev.num_blocks() * block_size as u64 / PAGE_SIZE_4K as u64) as usize;
+        let mut refs = Vec::new();
+        refs.resize(num_slots, 0);
+        Ok(Self {
+            dev: Mutex::new(dev),
+            block_size,
+            slots: SpinNoIrq::new(SwapSlots { refs, next_free: 0 }),
+        })
+    }
+
+    /// Returns the number of slots.
+    pub(crate) fn num_slots(&self) -> usize {
+        self.slots.lock().refs.len()
+    }
+
+    /// Returns the number of free slots.
+    pub(crate) fn free_slots(&self) -> usize {
+        let slots = self.slots.lock();
+        slots.refs.iter().filter(|&&refs| refs == 0).count()
+    }
+
+    fn alloc_slot(&self) -> Option<usize> {
+        let mut slots = self.slots.lock();
+        let num_slots = slots.refs.len();
+        let slot = (0..num_slots)
+            .map(|i| (slots.next_free + i) % num_slots)
+            .find(|&slot| slots.refs[slot] == 0)?;
+        slots.refs[slot] = 1;
+        slots.next_free = (slot + 1) % num_slots;
+        Some(slot)
+    }
+
+    /// Adds a reference to the slot.
+    pub(crate) fn dup_slot(&self, slot: usize) {
+        self.slots.lock().refs[slot] += 1;
+    }
+
+    /// Drops a reference to the slot, and frees it if it was the last one.
+    pub(crate) fn free_slot(&self, slot: usize) {
+        self.slots.lock().refs[slot] -= 1;
+    }
+
+    fn first_block(&self, slot: usize) -> u64 {
+        (slot * (PAGE_SIZE_4K / self.block_size)) as u64
+    }
+
+    /// Writes the page `data` to a free slot, returns the slot.
+    pub(crate) fn write_page(&self, data: &[u8]) -> Option<usize> {
+        // The slot is reserved first, the spinlock is released before the I/O.
+        let slot = self.alloc_slot()?;
+        let first_block = self.first_block(slot);
+        let mut dev = self.dev.lock();
+        for (i, block) in data.chunks(self.block_size).enumerate() {
+            if let Err(e) = dev.write_block(first_block + i as u64, block) {
+                drop(dev);
+                warn!("failed to write swap slot {}: {:?}", slot, e);
+                self.free_slot(slot);
+                return None;
+            }
+        }
+        Some(slot)
+    }
+
+    /// Reads the page in the slot into `data`.
+    pub(crate) fn read_page(&self, slot: usize, data: &mut [u8]) -> AxResult {
+        let first_block = self.first_block(slot);
+        let mut dev = self.dev.lock();
+        for (i, block) in data.chunks_mut(self.block_size).enumerate() {
+            dev.read_block(first_block + i as u64, block)?;
+        }
+        Ok(())
+    }
+}
+
+/// Initializes the swap area on the given device.
+///
+/// The pages of the lazy allocation mappings can be swapped out afterwards.
+pub fn init_swap(dev: Box<dyn SwapDevice>) {
+    match SwapArea::new(dev) {
+        Ok(area) => {
+            info!("Initialize swap area: {} pages", area.num_slots());
+            SWAP_AREA.init_once(area);
+        }
+        Err(e) => warn!("failed to initialize swap area: {:?}", e),
+    }
+}
+
+/// Whether the swap area has been initialized.
+pub(crate) fn is_enabled() -> bool {
+    SWAP_AREA.is_inited()
+}
+
+/// Returns the number of free pages below which memory is reclaimed.
+pub fn low_watermark() -> usize {
+    LOW_WATERMARK.load(Ordering::Relaxed)
+}
+
+/// Sets the number of free pages below which memory is reclaimed.
+pub fn set_low_watermark(pages: usize) {
+    LOW_WATERMARK.store(pages, Ordering::Relaxed);
+}
+
+/// Whether memory is short and the pages should be swapped out.
+pub(crate) fn memory_low() -> bool {
+    is_enabled() && global_allocator().available_pages() < low_watermark()
+}
+
+/// Registers an address space to reclaim the pages from, when any address
+/// space is short of memory.
+///
+/// The source is unregistered automatically after it is dropped.
+pub fn register_reclaim_source(src: Weak<dyn ReclaimSource>) {
+    RECLAIM_SOURCES.lock().push(src);
+}
+
+/// Reclaims at most `nr_pages` pages from the registered address spaces,
+/// returns the number of pages reclaimed.
+pub(crate) fn reclaim_others(nr_pages: usize) -> usize {
+    // Reclaim without holding the spinlock, the swap I/O may block.
+    let sources: Vec<_> = {
+        let mut sources = RECLAIM_SOURCES.lock();
+        sources.retain(|src| src.strong_count() > 0);
+        sources.iter().filter_map(Weak::upgrade).collect()
+    };
+    if sources.is_empty() {
+        return 0;
+    }
+    let first = NEXT_SOURCE.fetch_add(1, Ordering::Relaxed) % sources.len();
+    let mut reclaimed = 0;
+    for i in 0..sources.len() {
+        if reclaimed >= nr_pages {
+            break;
+        }
+        let src = &sources[(first + i) % sources.len()];
+        reclaimed += src.try_reclaim(nr_pages - reclaimed);
+    }
+    reclaimed
+}
+
+/// Writes the content of the frame to the swap area, returns the slot.
+pub(crate) fn swap_out(frame: PhysAddr) -> Option<usize> {
+    if !is_enabled() {
+        return None;
+    }
+    let data = unsafe { core::slice::from_raw_parts(phys_to_virt(frame).as_ptr(), PAGE_SIZE_4K) };
+    SWAP_AREA.write_page(data)
+}
+
+/// Reads the page in the slot into the frame, and drops the reference to the
+/// slot.
+pub(crate) fn swap_in(slot: usize, frame: PhysAddr) -> bool {
+    let data =
+        unsafe { core::slice::from_raw_parts_mut(phys_to_virt(frame).as_mut_ptr(), PAGE_SIZE_4K) };
+    match SWAP_AREA.read_page(slot, data) {
+        Ok(()) => {
+            SWAP_AREA.free_slot(slot);
+            true
+        }
+        Err(e) => {
+            warn!("failed to read swap slot {}: {:?}", slot, e);
+            false
+        }
+    }
+}
+
+/// Adds a reference to the slot, when the swapped out page is shared by a
+/// cloned address space.
+pub(crate) fn dup_slot(slot: usize) {
+    SWAP_AREA.dup_slot(slot);
+}
+
+/// Drops a reference to the slot.
+pub(crate) fn free_slot(slot: usize) {
+    SWAP_AREA.free_slot(slot);
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    use axdriver_block::{ramdisk::RamDisk, BlockDriverOps};
+    use axerrno::{AxError, AxResult};
+    use memory_addr::PAGE_SIZE_4K;
+
+    use super::{SwapArea, SwapDevice};
+
+    struct RamSwap(RamDisk);
+
+    impl SwapDevice for RamSwap {
+        fn block_size(&self) -> usize {
+            self.0.block_size()
+        }
+
+        fn num_blocks(&self) -> u64 {
+            self.0.num_blocks()
+        }
+
+        fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> AxResult {
+            self.0.read_block(block_id, buf).map_err(|_| AxError::Io)
+        }
+
+        fn write_block(&mut self, block_id: u64, buf: &[u8]) -> AxResult {
+            self.0.write_block(block_id, buf).map_err(|_| AxError::Io)
+        }
+    }
+
+    fn new_swap_area(num_pages: usize) -> SwapArea {
+        let disk = RamDisk::new(num_pages * PAGE_SIZE_4K);
+        SwapArea::new(Box::new(RamSwap(disk))).unwrap()
+    }
+
+    #[test]
+    fn test_swap_pages() {
+        let area = new_swap_area(4);
+        assert_eq!(area.num_slots(), 4);
+
+        let pages: [[u8; PAGE_SIZE_4K]; 3] = [
+            [0x11; PAGE_SIZE_4K],
+            [0x22; PAGE_SIZE_4K],
+            [0x33; PAGE_SIZE_4K],
+        ];
+        let slots: [usize; 3] = core::array::from_fn(|i| area.write_page(&pages[i]).unwrap());
+        assert_eq!(area.free_slots(), 1);
+
+        let mut buf = [0; PAGE_SIZE_4K];
+        for (slot, page) in slots.iter().zip(pages.iter()).rev() {
+            area.read_page(*slot, &mut buf).unwrap();
+            assert_eq!(&buf, page);
+        }
+    }
+
+    #[test]
+    fn test_swap_slot_refs() {
+        let area = new_swap_area(2);
+        let page = [0x5a; PAGE_SIZE_4K];
+        let slot0 = area.write_page(&page).unwrap();
+        let slot1 = area.write_page(&page).unwrap();
+        assert_ne!(slot0, slot1);
+        assert!(area.write_page(&page).is_none());
+
+        // A shared slot is freed after all references are dropped.
+        area.dup_slot(slot0);
+        area.free_slot(slot0);
+        assert_eq!(area.free_slots(), 0);
+        area.free_slot(slot0);
+        assert_eq!(area.free_slots(), 1);
+        assert_eq!(area.write_page(&page), Some(slot0));
+    }
+}
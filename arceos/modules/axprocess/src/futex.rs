@@ -0,0 +1,542 @@
+//! Fast user-space locking ([futex]).
+//!
+//! The waiters are kept in a table keyed by the futex. A private futex
+//! (`FUTEX_PRIVATE_FLAG`) is identified by the address space and the user
+//! virtual address of the futex word, so it's shared by the threads of a
+//! process. Other futexes in shared memory (shared file mappings and shared
+//! memory objects) are identified by the memory of the futex word, so that
+//! they work across processes; they are private otherwise. Each waiter blocks
+//! on its own [`WaitQueue`], so that the waiters can be woken up selectively
+//! by the bitset, and be moved to another futex by `FUTEX_REQUEUE`.
+//!
+//! [futex]: https://man7.org/linux/man-pages/man2/futex.2.html
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::mem::VirtAddr;
+use axmm::{AddrSpace, SharedKey};
+use axsync::Mutex;
+use axtask::WaitQueue;
+
+const FUTEX_WAIT: u32 = 0;
+const FUTEX_WAKE: u32 = 1;
+const FUTEX_REQUEUE: u32 = 3;
+const FUTEX_CMP_REQUEUE: u32 = 4;
+const FUTEX_WAIT_BITSET: u32 = 9;
+const FUTEX_WAKE_BITSET: u32 = 10;
+/// The futex is not shared with other processes.
+const FUTEX_PRIVATE_FLAG: u32 = 128;
+/// The absolute timeout of `FUTEX_WAIT_BITSET` is measured against
+/// `CLOCK_REALTIME` instead of `CLOCK_MONOTONIC`.
+const FUTEX_CLOCK_REALTIME: u32 = 256;
+/// A bitset that matches any waiter.
+const FUTEX_BITSET_MATCH_ANY: u32 = u32::MAX;
+
+/// Identifies a futex.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum FutexKey {
+    /// A private futex, by the address of the address space and the user
+    /// virtual address of the futex word.
+    Private(usize, usize),
+    /// A futex in shared memory, by the memory of the futex word.
+    Shared(SharedKey),
+}
+
+/// A thread waiting on a futex.
+struct FutexWaiter {
+    /// The process of the thread.
+    pid: u64,
+    /// Only the wakeups with an intersecting bitset wake it up.
+    bitset: u32,
+    /// The futex it's waiting on, changed by requeue. It's accessed with
+    /// [`FUTEX_TABLE`] locked.
+    key: Mutex<FutexKey>,
+    woken: AtomicBool,
+    wq: WaitQueue,
+}
+
+impl FutexWaiter {
+    fn wake(&self) {
+        self.woken.store(true, Ordering::Release);
+        self.wq.notify_one(false);
+    }
+}
+
+/// The waiters of each futex, in FIFO order.
+///
+/// The futex words are read with the table locked, so a thread changing the
+/// word and then waking up the waiters never misses one.
+static FUTEX_TABLE: Mutex<BTreeMap<FutexKey, VecDeque<Arc<FutexWaiter>>>> =
+    Mutex::new(BTreeMap::new());
+
+/// Returns the key of the futex at `uaddr` in `aspace`.
+///
+/// A futex that is not `private` is keyed by the memory of the futex word if
+/// the memory is shared, so it's the same futex in all the address spaces
+/// sharing the memory.
+fn futex_key(aspace: &Mutex<AddrSpace>, uaddr: usize, private: bool) -> FutexKey {
+    if !private {
+        if let Some(key) = aspace.lock().shared_key(VirtAddr::from(uaddr)) {
+            return FutexKey::Shared(key);
+        }
+    }
+    FutexKey::Private(aspace as *const _ as usize, uaddr)
+}
+
+fn read_user<const N: usize>(aspace: &Mutex<AddrSpace>, uaddr: usize) -> LinuxResult<[u8; N]> {
+    let mut buf = [0; N];
+    aspace
+        .lock()
+        .read(VirtAddr::from(uaddr), &mut buf)
+        .map_err(|_| LinuxError::EFAULT)?;
+    Ok(buf)
+}
+
+fn read_futex_word(aspace: &Mutex<AddrSpace>, uaddr: usize) -> LinuxResult<u32> {
+    read_user(aspace, uaddr).map(u32::from_ne_bytes)
+}
+
+/// Reads a `struct timespec` from the user space.
+fn read_timespec(aspace: &Mutex<AddrSpace>, uaddr: usize) -> LinuxResult<Duration> {
+    let buf: [u8; 16] = read_user(aspace, uaddr)?;
+    let tv_sec = i64::from_ne_bytes(buf[..8].try_into().unwrap());
+    let tv_nsec = i64::from_ne_bytes(buf[8..].try_into().unwrap());
+    if tv_sec < 0 || !(0..1_000_000_000).contains(&tv_nsec) {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(Duration::new(tv_sec as u64, tv_nsec as u32))
+}
+
+/// Blocks the current thread of the process `pid` if the word of the futex
+/// `key`, read by `read_word`, still contains `val`, until it's woken up with
+/// an intersecting `bitset`, or the `timeout` has elapsed.
+fn futex_wait(
+    pid: u64,
+    key: FutexKey,
+    read_word: impl FnOnce() -> LinuxResult<u32>,
+    val: u32,
+    bitset: u32,
+    timeout: Option<Duration>,
+) -> LinuxResult {
+    if bitset == 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let waiter = Arc::new(FutexWaiter {
+        pid,
+        bitset,
+        key: Mutex::new(key),
+        woken: AtomicBool::new(false),
+        wq: WaitQueue::new(),
+    });
+    {
+        let mut table = FUTEX_TABLE.lock();
+        if read_word()? != val {
+            return Err(LinuxError::EAGAIN);
+        }
+        table.entry(key).or_default().push_back(waiter.clone());
+    }
+
+    let is_woken = || waiter.woken.load(Ordering::Acquire);
+    let timed_out = match timeout {
+        Some(dur) => waiter.wq.wait_timeout_until(dur, is_woken),
+        None => {
+            waiter.wq.wait_until(is_woken);
+            false
+        }
+    };
+    if timed_out {
+        let mut table = FUTEX_TABLE.lock();
+        // It may be woken up before we get the lock.
+        if !is_woken() {
+            let key = *waiter.key.lock();
+            if let Some(waiters) = table.get_mut(&key) {
+                waiters.retain(|w| !Arc::ptr_eq(w, &waiter));
+                if waiters.is_empty() {
+                    table.remove(&key);
+                }
+            }
+            return Err(LinuxError::ETIMEDOUT);
+        }
+    }
+    Ok(())
+}
+
+/// Wakes up at most `max_wake` waiters of the futex `key` with an
+/// intersecting `bitset`.
+///
+/// Returns the number of the woken waiters.
+fn futex_wake(key: FutexKey, max_wake: usize, bitset: u32) -> usize {
+    let mut table = FUTEX_TABLE.lock();
+    let Some(waiters) = table.get_mut(&key) else {
+        return 0;
+    };
+    let mut woken = 0;
+    waiters.retain(|w| {
+        if woken < max_wake && w.bitset & bitset != 0 {
+            w.wake();
+            woken += 1;
+            false
+        } else {
+            true
+        }
+    });
+    if waiters.is_empty() {
+        table.remove(&key);
+    }
+    woken
+}
+
+/// Wakes up at most `max_wake` waiters of the futex `key`, and moves at most
+/// `max_requeue` of the remaining ones to the futex `key2`.
+///
+/// If `cmp_val` is given, fails with `EAGAIN` unless the word of the futex
+/// `key`, read by `read_word`, contains it.
+///
+/// Returns the number of the woken waiters and the number of the requeued
+/// ones.
+fn futex_requeue(
+    key: FutexKey,
+    max_wake: usize,
+    key2: FutexKey,
+    max_requeue: usize,
+    cmp_val: Option<u32>,
+    read_word: impl FnOnce() -> LinuxResult<u32>,
+) -> LinuxResult<(usize, usize)> {
+    let mut table = FUTEX_TABLE.lock();
+    if let Some(val) = cmp_val {
+        if read_word()? != val {
+            return Err(LinuxError::EAGAIN);
+        }
+    }
+    let Some(mut waiters) = table.remove(&key) else {
+        return Ok((0, 0));
+    };
+    let woken = waiters.len().min(max_wake);
+    for waiter in waiters.drain(..woken) {
+        waiter.wake();
+    }
+    let requeued = waiters.len().min(max_requeue);
+    let moved: Vec<_> = waiters.drain(..requeued).collect();
+    if !waiters.is_empty() {
+        table.insert(key, waiters);
+    }
+    if !moved.is_empty() {
+        for waiter in &moved {
+            *waiter.key.lock() = key2;
+        }
+        table.entry(key2).or_default().extend(moved);
+    }
+    Ok((woken, requeued))
+}
+
+/// The `futex` syscall on the address space `aspace` of the process `pid`.
+///
+/// `timeout` is a pointer to `struct timespec` for the waiting operations,
+/// but it's the maximum number of the waiters to requeue (`val2`) for
+/// `FUTEX_REQUEUE` and `FUTEX_CMP_REQUEUE`.
+#[allow(clippy::too_many_arguments)]
+pub fn futex(
+    pid: u64,
+    aspace: &Mutex<AddrSpace>,
+    uaddr: usize,
+    futex_op: u32,
+    val: u32,
+    timeout: usize,
+    uaddr2: usize,
+    val3: u32,
+) -> LinuxResult<isize> {
+    if uaddr % 4 != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let read_word = || read_futex_word(aspace, uaddr);
+    let private = futex_op & FUTEX_PRIVATE_FLAG != 0;
+    let key = futex_key(aspace, uaddr, private);
+    let cmd = futex_op & !(FUTEX_PRIVATE_FLAG | FUTEX_CLOCK_REALTIME);
+    match cmd {
+        FUTEX_WAIT | FUTEX_WAIT_BITSET => {
+            let timeout = if timeout == 0 {
+                None
+            } else if cmd == FUTEX_WAIT {
+                // relative to now
+                Some(read_timespec(aspace, timeout)?)
+            } else {
+                let deadline = read_timespec(aspace, timeout)?;
+                let now = if futex_op & FUTEX_CLOCK_REALTIME != 0 {
+                    axhal::time::wall_time()
+                } else {
+                    axhal::time::monotonic_time()
+                };
+                Some(deadline.saturating_sub(now))
+            };
+            let bitset = if cmd == FUTEX_WAIT {
+                FUTEX_BITSET_MATCH_ANY
+            } else {
+                val3
+            };
+            futex_wait(pid, key, read_word, val, bitset, timeout)?;
+            Ok(0)
+        }
+        FUTEX_WAKE => Ok(futex_wake(key, val as usize, FUTEX_BITSET_MATCH_ANY) as _),
+        FUTEX_WAKE_BITSET => {
+            if val3 == 0 {
+                return Err(LinuxError::EINVAL);
+            }
+            Ok(futex_wake(key, val as usize, val3) as _)
+        }
+        FUTEX_REQUEUE | FUTEX_CMP_REQUEUE => {
+            if uaddr2 % 4 != 0 {
+                return Err(LinuxError::EINVAL);
+            }
+            let key2 = futex_key(aspace, uaddr2, private);
+            let cmp_val = (cmd == FUTEX_CMP_REQUEUE).then_some(val3);
+            let (woken, requeued) =
+                futex_requeue(key, val as usize, key2, timeout, cmp_val, read_word)?;
+            if cmd == FUTEX_CMP_REQUEUE {
+                Ok((woken + requeued) as _)
+            } else {
+                Ok(woken as _)
+            }
+        }
+        _ => {
+            warn!("unsupported futex operation: {:#x}", futex_op);
+            Err(LinuxError::ENOSYS)
+        }
+    }
+}
+
+/// Clears the TID at `tid_ptr` and wakes up a thread waiting on it, done for
+/// `CLONE_CHILD_CLEARTID` or `set_tid_address` when a thread exits.
+///
+/// It's how `pthread_join` in the C library knows the thread has exited.
+pub fn clear_child_tid(aspace: &Mutex<AddrSpace>, tid_ptr: usize) {
+    let zero = 0i32.to_ne_bytes();
+    if aspace.lock().write(VirtAddr::from(tid_ptr), &zero).is_err() {
+        warn!("failed to clear child tid at {:#x}", tid_ptr);
+        return;
+    }
+    let key = futex_key(aspace, tid_ptr, false);
+    futex_wake(key, 1, FUTEX_BITSET_MATCH_ANY);
+}
+
+/// Wakes up and removes all waiters of the process `pid`.
+///
+/// It's called when the process calls `exit_group`, so that the threads
+/// blocked on the futexes can exit, and when the process exits.
+pub fn clear_process(pid: u64) {
+    FUTEX_TABLE.lock().retain(|_, waiters| {
+        waiters.retain(|w| {
+            if w.pid == pid {
+                w.wake();
+                false
+            } else {
+                true
+            }
+        });
+        !waiters.is_empty()
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axtask as thread;
+    use core::sync::atomic::AtomicU32;
+    use std::sync::{Mutex as StdMutex, Once};
+
+    static INIT: Once = Once::new();
+    static SERIAL: StdMutex<()> = StdMutex::new(());
+
+    /// The tests run in different processes, so they don't share futexes.
+    fn setup(pid: u64) -> std::sync::MutexGuard<'static, ()> {
+        let lock = SERIAL.lock().unwrap();
+        INIT.call_once(thread::init_scheduler);
+        clear_process(pid);
+        lock
+    }
+
+    /// The key of a private futex, the process ID stands for its address
+    /// space.
+    fn key(pid: u64, uaddr: usize) -> FutexKey {
+        FutexKey::Private(pid as usize, uaddr)
+    }
+
+    fn waiter_count(key: FutexKey) -> usize {
+        FUTEX_TABLE.lock().get(&key).map_or(0, |w| w.len())
+    }
+
+    /// Yields until `n` threads are waiting on the futex.
+    fn wait_for_waiters(key: FutexKey, n: usize) {
+        while waiter_count(key) != n {
+            thread::yield_now();
+        }
+    }
+
+    fn spawn_waiter(
+        pid: u64,
+        key: FutexKey,
+        word: &'static AtomicU32,
+        bitset: u32,
+    ) -> thread::AxTaskRef {
+        thread::spawn(move || {
+            let read_word = || Ok(word.load(Ordering::Acquire));
+            futex_wait(pid, key, read_word, 0, bitset, None).unwrap();
+        })
+    }
+
+    #[test]
+    fn wait_wake() {
+        const PID: u64 = 1;
+        const UADDR: usize = 0x1000;
+        static WORD: AtomicU32 = AtomicU32::new(0);
+        let _lock = setup(PID);
+        let key = key(PID, UADDR);
+
+        // the value has changed
+        let read_word = || Ok(1);
+        assert_eq!(
+            futex_wait(PID, key, read_word, 0, FUTEX_BITSET_MATCH_ANY, None),
+            Err(LinuxError::EAGAIN)
+        );
+        assert_eq!(futex_wake(key, 1, FUTEX_BITSET_MATCH_ANY), 0);
+
+        let tasks: Vec<_> = (0..3)
+            .map(|_| spawn_waiter(PID, key, &WORD, FUTEX_BITSET_MATCH_ANY))
+            .collect();
+        wait_for_waiters(key, 3);
+        // another address space or futex
+        let other_aspace = FutexKey::Private(PID as usize + 1, UADDR);
+        assert_eq!(futex_wake(other_aspace, 3, FUTEX_BITSET_MATCH_ANY), 0);
+        let other_uaddr = FutexKey::Private(PID as usize, UADDR + 4);
+        assert_eq!(futex_wake(other_uaddr, 3, FUTEX_BITSET_MATCH_ANY), 0);
+
+        assert_eq!(futex_wake(key, 2, FUTEX_BITSET_MATCH_ANY), 2);
+        assert_eq!(waiter_count(key), 1);
+        assert_eq!(futex_wake(key, 2, FUTEX_BITSET_MATCH_ANY), 1);
+        assert_eq!(waiter_count(key), 0);
+        for task in tasks {
+            assert_eq!(task.join(), Some(0));
+        }
+    }
+
+    #[test]
+    fn bitset() {
+        const PID: u64 = 2;
+        const UADDR: usize = 0x1000;
+        static WORD: AtomicU32 = AtomicU32::new(0);
+        let _lock = setup(PID);
+        let key = key(PID, UADDR);
+
+        let read_word = || Ok(0);
+        assert_eq!(
+            futex_wait(PID, key, read_word, 0, 0, None),
+            Err(LinuxError::EINVAL)
+        );
+
+        let t1 = spawn_waiter(PID, key, &WORD, 0b01);
+        let t2 = spawn_waiter(PID, key, &WORD, 0b10);
+        wait_for_waiters(key, 2);
+        assert_eq!(futex_wake(key, 2, 0b100), 0);
+        assert_eq!(futex_wake(key, 2, 0b10), 1);
+        assert_eq!(t2.join(), Some(0));
+        assert_eq!(waiter_count(key), 1);
+        assert_eq!(futex_wake(key, 2, FUTEX_BITSET_MATCH_ANY), 1);
+        assert_eq!(t1.join(), Some(0));
+    }
+
+    #[test]
+    fn requeue() {
+        const PID: u64 = 3;
+        const UADDR: usize = 0x1000;
+        const UADDR2: usize = 0x2000;
+        static WORD: AtomicU32 = AtomicU32::new(0);
+        let _lock = setup(PID);
+        let (key1, key2) = (key(PID, UADDR), key(PID, UADDR2));
+
+        let tasks: Vec<_> = (0..4)
+            .map(|_| spawn_waiter(PID, key1, &WORD, FUTEX_BITSET_MATCH_ANY))
+            .collect();
+        wait_for_waiters(key1, 4);
+
+        // FUTEX_CMP_REQUEUE with a changed value
+        let read_word = || Ok(WORD.load(Ordering::Acquire));
+        assert_eq!(
+            futex_requeue(key1, 1, key2, 2, Some(1), read_word),
+            Err(LinuxError::EAGAIN)
+        );
+        assert_eq!(waiter_count(key1), 4);
+
+        assert_eq!(
+            futex_requeue(key1, 1, key2, 2, Some(0), read_word),
+            Ok((1, 2))
+        );
+        assert_eq!(waiter_count(key1), 1);
+        assert_eq!(waiter_count(key2), 2);
+
+        assert_eq!(futex_wake(key2, 4, FUTEX_BITSET_MATCH_ANY), 2);
+        assert_eq!(futex_wake(key1, 4, FUTEX_BITSET_MATCH_ANY), 1);
+        for task in tasks {
+            assert_eq!(task.join(), Some(0));
+        }
+    }
+
+    #[test]
+    fn timeout() {
+        const PID: u64 = 4;
+        const UADDR: usize = 0x1000;
+        let _lock = setup(PID);
+        let key = key(PID, UADDR);
+
+        let read_word = || Ok(0);
+        assert_eq!(
+            futex_wait(
+                PID,
+                key,
+                read_word,
+                0,
+                FUTEX_BITSET_MATCH_ANY,
+                Some(Duration::ZERO)
+            ),
+            Err(LinuxError::ETIMEDOUT)
+        );
+        // the timed out waiter is removed
+        assert_eq!(waiter_count(key), 0);
+    }
+
+    #[test]
+    fn exit_process() {
+        const PID: u64 = 5;
+        const SHARED: FutexKey = FutexKey::Shared(SharedKey::Object {
+            addr: 0x1000,
+            offset: 0,
+        });
+        static WORD: AtomicU32 = AtomicU32::new(0);
+        let _lock = setup(PID);
+        let (key1, key2) = (key(PID, 0x1000), key(PID, 0x2000));
+
+        let t1 = spawn_waiter(PID, key1, &WORD, FUTEX_BITSET_MATCH_ANY);
+        let t2 = spawn_waiter(PID, key2, &WORD, FUTEX_BITSET_MATCH_ANY);
+        let t3 = spawn_waiter(PID, SHARED, &WORD, FUTEX_BITSET_MATCH_ANY);
+        let other = spawn_waiter(PID + 1, SHARED, &WORD, FUTEX_BITSET_MATCH_ANY);
+        wait_for_waiters(key1, 1);
+        wait_for_waiters(key2, 1);
+        wait_for_waiters(SHARED, 2);
+
+        // `exit_group` wakes up the blocked threads of the process only
+        clear_process(PID);
+        assert_eq!(t1.join(), Some(0));
+        assert_eq!(t2.join(), Some(0));
+        assert_eq!(t3.join(), Some(0));
+        assert_eq!(waiter_count(key1), 0);
+        assert_eq!(waiter_count(key2), 0);
+        assert_eq!(waiter_count(SHARED), 1);
+
+        assert_eq!(futex_wake(SHARED, 1, FUTEX_BITSET_MATCH_ANY), 1);
+        assert_eq!(other.join(), Some(0));
+    }
+}
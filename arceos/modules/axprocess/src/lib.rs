@@ -0,0 +1,48 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) user process management for
+//! the monolithic kernels.
+//!
+//! It provides what the monolithic kernels in `tour` and `exercises` share:
+//!
+//! - [`Process`]: A group of threads sharing an address space, with the
+//!   parent-child relationship for `wait4`.
+//! - [`TaskExt`]: The task extended data of the user tasks.
+//! - The implementations of the process and thread related syscalls, such as
+//!   [`sys_clone`], [`sys_execve`], [`sys_wait4`] and [`sys_futex`].
+//! - [`shm`]: System V and POSIX shared memory.
+//!
+//! The kernels only dispatch the syscalls and load the programs.
+
+#![cfg_attr(not(test), no_std)]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+pub mod futex;
+pub mod shm;
+
+// The user space context is not available on the host, where the tests run.
+#[cfg(not(test))]
+mod mem;
+#[cfg(not(test))]
+mod process;
+#[cfg(not(test))]
+mod syscall;
+#[cfg(not(test))]
+mod task;
+
+#[cfg(not(test))]
+pub use self::{
+    mem::{copy_to_user, user_str, user_str_array},
+    process::{all_processes, find_process, Process},
+    syscall::{
+        init_user_stack, sys_clone, sys_execve, sys_futex, sys_set_tid_address, sys_shmat,
+        sys_shmdt, sys_wait4, CloneFlags,
+    },
+    task::{exit_current, spawn_user_process, spawn_user_task, spawn_user_thread, TaskExt},
+};
+
+/// The size of the user stack of a new program.
+pub const USER_STACK_SIZE: usize = 0x10000;
+/// The size of the kernel stack of a user task.
+pub const KERNEL_STACK_SIZE: usize = 0x40000; // 256 KiB
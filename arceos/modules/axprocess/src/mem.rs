@@ -0,0 +1,100 @@
+//! Accessing the memory of the current process.
+
+use core::ffi::c_char;
+use core::mem::size_of;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::mem::VirtAddr;
+use axtask::{current, TaskExtRef};
+use memory_addr::PAGE_SIZE_4K;
+
+/// The maximum length of a string copied from the user space, including the
+/// terminating NUL (`MAX_ARG_STRLEN` of Linux).
+const MAX_STR_LEN: usize = 32 * PAGE_SIZE_4K;
+
+/// The maximum number of strings in an array copied from the user space.
+const MAX_STR_ARRAY_LEN: usize = 0x10000;
+
+/// Copies `buf.len()` bytes from `uaddr` in the address space of the current
+/// process.
+///
+/// Fails with `EFAULT` if the memory is not accessible.
+fn copy_from_user(uaddr: usize, buf: &mut [u8]) -> LinuxResult {
+    if uaddr == 0 {
+        return Err(LinuxError::EFAULT);
+    }
+    current()
+        .task_ext()
+        .aspace
+        .lock()
+        .read(VirtAddr::from(uaddr), buf)
+        .map_err(|_| LinuxError::EFAULT)
+}
+
+/// Copies a NUL-terminated string from the user space.
+///
+/// The string is read page by page, so it may end right before an
+/// inaccessible page. Fails with `EFAULT` if the memory is not accessible, or
+/// `ENAMETOOLONG` if the string is longer than `MAX_STR_LEN`.
+pub fn user_str(ptr: *const c_char) -> LinuxResult<String> {
+    let mut uaddr = ptr as usize;
+    let mut bytes = Vec::new();
+    loop {
+        let len = bytes.len();
+        if len >= MAX_STR_LEN {
+            return Err(LinuxError::ENAMETOOLONG);
+        }
+        let chunk = (PAGE_SIZE_4K - uaddr % PAGE_SIZE_4K).min(MAX_STR_LEN - len);
+        bytes.resize(len + chunk, 0);
+        copy_from_user(uaddr, &mut bytes[len..])?;
+        if let Some(pos) = bytes[len..].iter().position(|&b| b == 0) {
+            bytes.truncate(len + pos);
+            break;
+        }
+        uaddr += chunk;
+    }
+    String::from_utf8(bytes).map_err(|_| LinuxError::EINVAL)
+}
+
+/// Copies a NULL-terminated array of strings (e.g., `argv`) from the user
+/// space. A NULL array is empty.
+///
+/// Fails with `EFAULT` if the memory is not accessible, or `E2BIG` if there
+/// are more than `MAX_STR_ARRAY_LEN` strings.
+pub fn user_str_array(ptr: *const *const c_char) -> LinuxResult<Vec<String>> {
+    let mut strs = Vec::new();
+    if ptr.is_null() {
+        return Ok(strs);
+    }
+    loop {
+        if strs.len() >= MAX_STR_ARRAY_LEN {
+            return Err(LinuxError::E2BIG);
+        }
+        let mut str_ptr = [0; size_of::<usize>()];
+        copy_from_user(ptr as usize + strs.len() * size_of::<usize>(), &mut str_ptr)?;
+        let str_ptr = usize::from_ne_bytes(str_ptr);
+        if str_ptr == 0 {
+            break;
+        }
+        strs.push(user_str(str_ptr as *const c_char)?);
+    }
+    Ok(strs)
+}
+
+/// Copies `data` to `uaddr` in the address space of the current process.
+///
+/// Fails with `EFAULT` if the memory is not accessible.
+pub fn copy_to_user(uaddr: usize, data: &[u8]) -> LinuxResult {
+    if uaddr == 0 {
+        return Err(LinuxError::EFAULT);
+    }
+    current()
+        .task_ext()
+        .aspace
+        .lock()
+        .write(VirtAddr::from(uaddr), data)
+        .map_err(|_| LinuxError::EFAULT)
+}
@@ -0,0 +1,247 @@
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, AtomicUsize, Ordering};
+
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+
+use axerrno::{LinuxError, LinuxResult};
+use axmm::{AddrSpace, ReclaimSource};
+use axsync::Mutex;
+use axtask::WaitQueue;
+
+/// The init process, which adopts orphaned children.
+static INIT_PROCESS: Mutex<Option<Arc<Process>>> = Mutex::new(None);
+
+/// A process, i.e., a group of threads sharing the same address space.
+///
+/// The process ID is the task ID of its first thread, so the main thread
+/// of a process has `tid == pid` as on Linux.
+pub struct Process {
+    pid: u64,
+    /// The name of the program, changed by `execve`.
+    name: Mutex<String>,
+    /// The address space shared by the threads.
+    aspace: Arc<Mutex<AddrSpace>>,
+    parent: Mutex<Weak<Process>>,
+    children: Mutex<Vec<Arc<Process>>>,
+    /// The number of threads that have not exited yet.
+    live_threads: AtomicUsize,
+    exit_code: AtomicI32,
+    /// Set by `exit_group`, other threads exit on their next syscall.
+    group_exiting: AtomicBool,
+    /// The process has exited but has not been reaped by its parent.
+    zombie: AtomicBool,
+    /// Bumped every time a child becomes a zombie.
+    child_exit_seq: AtomicU64,
+    /// The parent waits here for its children to exit.
+    child_exit_wq: WaitQueue,
+    /// Set when the process calls `execve` or exits, see
+    /// [`Process::wait_vfork_done`].
+    vfork_done: AtomicBool,
+    /// The parent blocked in `vfork` waits here.
+    vfork_wq: WaitQueue,
+}
+
+impl Process {
+    pub(crate) fn new(
+        pid: u64,
+        name: &str,
+        aspace: Arc<Mutex<AddrSpace>>,
+        parent: Option<&Arc<Process>>,
+    ) -> Arc<Self> {
+        let process = Arc::new(Self {
+            pid,
+            name: Mutex::new(name.into()),
+            aspace,
+            parent: Mutex::new(parent.map_or(Weak::new(), Arc::downgrade)),
+            children: Mutex::new(Vec::new()),
+            live_threads: AtomicUsize::new(1),
+            exit_code: AtomicI32::new(0),
+            group_exiting: AtomicBool::new(false),
+            zombie: AtomicBool::new(false),
+            child_exit_seq: AtomicU64::new(0),
+            child_exit_wq: WaitQueue::new(),
+            vfork_done: AtomicBool::new(false),
+            vfork_wq: WaitQueue::new(),
+        });
+        if let Some(parent) = parent {
+            parent.children.lock().push(process.clone());
+        }
+        let src: Weak<dyn ReclaimSource> = Arc::downgrade(&process);
+        axmm::register_reclaim_source(src);
+        process
+    }
+
+    /// Returns the process ID.
+    pub fn pid(&self) -> u64 {
+        self.pid
+    }
+
+    /// Returns the process ID of the parent, or 0 if it has no parent.
+    pub fn ppid(&self) -> u64 {
+        self.parent.lock().upgrade().map_or(0, |p| p.pid)
+    }
+
+    /// Returns the name of the program.
+    pub fn name(&self) -> String {
+        self.name.lock().clone()
+    }
+
+    /// Sets the name of the program.
+    pub fn set_name(&self, name: &str) {
+        *self.name.lock() = name.into();
+    }
+
+    /// Returns the address space of the process.
+    pub fn aspace(&self) -> &Arc<Mutex<AddrSpace>> {
+        &self.aspace
+    }
+
+    /// Whether another thread has called `exit_group` on this process.
+    pub fn is_group_exiting(&self) -> bool {
+        self.group_exiting.load(Ordering::Acquire)
+    }
+
+    /// Whether the process has exited and is waiting to be reaped.
+    pub fn is_zombie(&self) -> bool {
+        self.zombie.load(Ordering::Acquire)
+    }
+
+    /// Returns the exit code of the process.
+    pub fn exit_code(&self) -> i32 {
+        self.exit_code.load(Ordering::Acquire)
+    }
+
+    /// Returns the number of threads that have not exited yet.
+    pub fn thread_count(&self) -> usize {
+        self.live_threads.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn add_thread(&self) {
+        self.live_threads.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Marks the whole process as exiting with `exit_code`.
+    pub(crate) fn set_group_exit(&self, exit_code: i32) {
+        if !self.group_exiting.swap(true, Ordering::AcqRel) {
+            self.exit_code.store(exit_code, Ordering::Release);
+        }
+    }
+
+    /// Called when a thread of this process exits.
+    ///
+    /// Returns `true` if it was the last thread, and the process becomes a
+    /// zombie.
+    pub(crate) fn exit_thread(&self, exit_code: i32) -> bool {
+        if self.live_threads.fetch_sub(1, Ordering::AcqRel) != 1 {
+            return false;
+        }
+        if !self.is_group_exiting() {
+            self.exit_code.store(exit_code, Ordering::Release);
+        }
+
+        // Give the orphans to the init process.
+        let children = core::mem::take(&mut *self.children.lock());
+        if !children.is_empty() {
+            let init = INIT_PROCESS.lock().clone();
+            match init {
+                Some(init) if init.pid != self.pid => {
+                    for child in &children {
+                        *child.parent.lock() = Arc::downgrade(&init);
+                    }
+                    init.children.lock().extend(children);
+                    init.notify_child_exit();
+                }
+                _ => {}
+            }
+        }
+
+        self.zombie.store(true, Ordering::Release);
+        self.wake_vfork_parent();
+        if let Some(parent) = self.parent.lock().upgrade() {
+            parent.notify_child_exit();
+        }
+        true
+    }
+
+    fn notify_child_exit(&self) {
+        self.child_exit_seq.fetch_add(1, Ordering::AcqRel);
+        self.child_exit_wq.notify_all(false);
+    }
+
+    /// Waits for a child process to exit and reaps it.
+    ///
+    /// `pid` selects the child as in `wait4`: `-1` for any child, or a
+    /// positive process ID. If `nohang` is `true`, returns `Ok(None)`
+    /// immediately if no child has exited yet.
+    ///
+    /// Returns the process ID and the exit code of the reaped child.
+    pub fn wait_child(&self, pid: i64, nohang: bool) -> LinuxResult<Option<(u64, i32)>> {
+        let matches = |child: &Arc<Process>| pid == -1 || child.pid as i64 == pid;
+        loop {
+            let seq = self.child_exit_seq.load(Ordering::Acquire);
+            {
+                let mut children = self.children.lock();
+                if !children.iter().any(matches) {
+                    return Err(LinuxError::ECHILD);
+                }
+                if let Some(idx) = children.iter().position(|c| matches(c) && c.is_zombie()) {
+                    let child = children.remove(idx);
+                    return Ok(Some((child.pid, child.exit_code())));
+                }
+            }
+            if nohang {
+                return Ok(None);
+            }
+            self.child_exit_wq
+                .wait_until(|| self.child_exit_seq.load(Ordering::Acquire) != seq);
+        }
+    }
+
+    /// Wakes up the parent blocked in `vfork`, called when the process calls
+    /// `execve` or exits.
+    pub(crate) fn wake_vfork_parent(&self) {
+        self.vfork_done.store(true, Ordering::Release);
+        self.vfork_wq.notify_all(false);
+    }
+
+    /// Blocks the parent that created this process by `vfork`, until this
+    /// process calls `execve` or exits.
+    pub(crate) fn wait_vfork_done(&self) {
+        self.vfork_wq
+            .wait_until(|| self.vfork_done.load(Ordering::Acquire));
+    }
+}
+
+impl ReclaimSource for Process {
+    fn try_reclaim(&self, nr_pages: usize) -> usize {
+        // The address space is skipped if it's in use, e.g., by the faulting
+        // thread of this process.
+        self.aspace
+            .try_lock()
+            .map_or(0, |mut aspace| aspace.reclaim(nr_pages))
+    }
+}
+
+/// Makes `process` the init process, which adopts the orphaned processes.
+pub(crate) fn set_init_process(process: Arc<Process>) {
+    *INIT_PROCESS.lock() = Some(process);
+}
+
+/// Returns all processes that have not been reaped, starting from the init
+/// process.
+pub fn all_processes() -> Vec<Arc<Process>> {
+    let mut processes: Vec<Arc<Process>> = INIT_PROCESS.lock().iter().cloned().collect();
+    let mut i = 0;
+    while i < processes.len() {
+        let children = processes[i].children.lock().clone();
+        processes.extend(children);
+        i += 1;
+    }
+    processes
+}
+
+/// Finds the process with the given ID.
+pub fn find_process(pid: u64) -> Option<Arc<Process>> {
+    all_processes().into_iter().find(|p| p.pid == pid)
+}
@@ -0,0 +1,533 @@
+//! Shared memory between processes.
+//!
+//! Both System V shared memory segments (`shmget`/`shmat`/`shmdt`/`shmctl`)
+//! and POSIX shared memory objects are supported. The latter are files under
+//! [`SHM_DIR`], as `shm_open` and `shm_unlink` in the C library just open and
+//! unlink them.
+
+use core::ffi::c_int;
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use arceos_posix_api::{self as api, ctypes, FileLike};
+use axerrno::{AxResult, LinuxError, LinuxResult};
+use axhal::mem::VirtAddr;
+use axhal::paging::MappingFlags;
+use axio::PollState;
+use axmm::{AddrSpace, MmapFile, SharedPages};
+use axsync::Mutex;
+use memory_addr::{align_up_4k, is_aligned_4k, VirtAddrRange};
+
+/// The directory of POSIX shared memory objects.
+pub const SHM_DIR: &str = "/dev/shm/";
+
+/// Create a new segment with a key that is not shared with other processes.
+const IPC_PRIVATE: i32 = 0;
+/// Create the segment if the key does not exist.
+const IPC_CREAT: i32 = 0o1000;
+/// Fail if the key exists.
+const IPC_EXCL: i32 = 0o2000;
+/// Remove the segment.
+const IPC_RMID: i32 = 0;
+/// Attach the segment for read-only access.
+const SHM_RDONLY: i32 = 0o10000;
+
+/// File type bits of a regular file in `st_mode`.
+const S_IFREG: u32 = 0o100000;
+
+/// The start of the search for a free area if no address is specified.
+const SHM_BASE: usize = 0x2000_0000;
+
+struct ShmSegment {
+    key: i32,
+    pages: Arc<SharedPages>,
+    /// The number of attachments (`shm_nattch`).
+    nattch: usize,
+    /// Removed by `IPC_RMID`, but still attached.
+    removed: bool,
+}
+
+/// An attached segment.
+struct ShmAttachment {
+    shmid: i32,
+    size: usize,
+}
+
+struct ShmTable {
+    next_id: i32,
+    segments: BTreeMap<i32, ShmSegment>,
+    /// The attached segments, indexed by the process ID and the address where
+    /// the segment is attached.
+    attachments: BTreeMap<(u64, usize), ShmAttachment>,
+}
+
+impl ShmTable {
+    const fn new() -> Self {
+        Self {
+            next_id: 0,
+            segments: BTreeMap::new(),
+            attachments: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the identifier of the segment with `key`, or creates it.
+    fn get(&mut self, key: i32, size: usize, flags: i32) -> LinuxResult<i32> {
+        if key != IPC_PRIVATE {
+            let found = self
+                .segments
+                .iter()
+                .find(|(_, seg)| seg.key == key && !seg.removed);
+            if let Some((&shmid, seg)) = found {
+                if flags & IPC_CREAT != 0 && flags & IPC_EXCL != 0 {
+                    return Err(LinuxError::EEXIST);
+                }
+                if size > seg.pages.size() {
+                    return Err(LinuxError::EINVAL);
+                }
+                return Ok(shmid);
+            }
+            if flags & IPC_CREAT == 0 {
+                return Err(LinuxError::ENOENT);
+            }
+        }
+        if size == 0 {
+            return Err(LinuxError::EINVAL);
+        }
+
+        let shmid = self.next_id;
+        self.next_id += 1;
+        let segment = ShmSegment {
+            key,
+            pages: Arc::new(SharedPages::new(size)),
+            nattch: 0,
+            removed: false,
+        };
+        self.segments.insert(shmid, segment);
+        Ok(shmid)
+    }
+
+    /// Returns the frames of the segment `shmid` to attach.
+    fn pages(&self, shmid: i32) -> LinuxResult<Arc<SharedPages>> {
+        match self.segments.get(&shmid) {
+            Some(seg) if !seg.removed => Ok(seg.pages.clone()),
+            _ => Err(LinuxError::EINVAL),
+        }
+    }
+
+    fn attach(&mut self, pid: u64, addr: usize, shmid: i32, size: usize) {
+        if let Some(seg) = self.segments.get_mut(&shmid) {
+            seg.nattch += 1;
+            self.attachments
+                .insert((pid, addr), ShmAttachment { shmid, size });
+        }
+    }
+
+    /// Detaches the segment attached at `addr` from the process `pid`, and
+    /// frees the segment if it has been removed and is no longer attached.
+    ///
+    /// Returns the size of the attached segment.
+    fn detach(&mut self, pid: u64, addr: usize) -> Option<usize> {
+        let attachment = self.attachments.remove(&(pid, addr))?;
+        if let Some(seg) = self.segments.get_mut(&attachment.shmid) {
+            seg.nattch -= 1;
+            if seg.removed && seg.nattch == 0 {
+                self.segments.remove(&attachment.shmid);
+            }
+        }
+        Some(attachment.size)
+    }
+
+    /// Detaches all segments from the process `pid`.
+    fn detach_all(&mut self, pid: u64) {
+        let addrs: Vec<_> = self
+            .attachments
+            .range((pid, 0)..=(pid, usize::MAX))
+            .map(|(&(_, addr), _)| addr)
+            .collect();
+        for addr in addrs {
+            self.detach(pid, addr);
+        }
+    }
+
+    /// Copies the attachments of the process `parent` to `child`.
+    fn fork(&mut self, parent: u64, child: u64) {
+        let inherited: Vec<_> = self
+            .attachments
+            .range((parent, 0)..=(parent, usize::MAX))
+            .map(|(&(_, addr), a)| (addr, a.shmid, a.size))
+            .collect();
+        for (addr, shmid, size) in inherited {
+            self.attach(child, addr, shmid, size);
+        }
+    }
+
+    /// Removes the segment `shmid`. It's freed after it has been detached by
+    /// all processes.
+    fn remove(&mut self, shmid: i32) -> LinuxResult {
+        let seg = self.segments.get_mut(&shmid).ok_or(LinuxError::EINVAL)?;
+        if seg.removed {
+            return Err(LinuxError::EINVAL);
+        }
+        if seg.nattch == 0 {
+            self.segments.remove(&shmid);
+        } else {
+            seg.removed = true;
+        }
+        Ok(())
+    }
+}
+
+static SHM_TABLE: Mutex<ShmTable> = Mutex::new(ShmTable::new());
+
+/// POSIX shared memory objects, indexed by the names under [`SHM_DIR`].
+static SHM_OBJECTS: Mutex<BTreeMap<String, Arc<SharedPages>>> = Mutex::new(BTreeMap::new());
+
+/// Returns the identifier of the segment with `key`, or creates it.
+pub fn shmget(key: i32, size: usize, flags: i32) -> LinuxResult<i32> {
+    SHM_TABLE.lock().get(key, size, flags)
+}
+
+/// Attaches the segment `shmid` to the process `pid` with the address space
+/// `aspace` at `addr`, or at a free address if `addr` is 0.
+pub fn shmat(
+    pid: u64,
+    aspace: &Mutex<AddrSpace>,
+    shmid: i32,
+    addr: usize,
+    flags: i32,
+) -> LinuxResult<usize> {
+    let mut table = SHM_TABLE.lock();
+    let pages = table.pages(shmid)?;
+    if !is_aligned_4k(addr) {
+        return Err(LinuxError::EINVAL);
+    }
+    let size = align_up_4k(pages.size());
+    let mut mapping_flags = MappingFlags::READ | MappingFlags::USER;
+    if flags & SHM_RDONLY == 0 {
+        mapping_flags |= MappingFlags::WRITE;
+    }
+
+    let mut aspace = aspace.lock();
+    let start = if addr == 0 {
+        let limit = VirtAddrRange::from_start_size(aspace.base(), aspace.size());
+        aspace
+            .find_free_area(VirtAddr::from(SHM_BASE), size, limit)
+            .ok_or(LinuxError::ENOMEM)?
+    } else {
+        VirtAddr::from(addr)
+    };
+    aspace.map_shared(start, size, mapping_flags, pages, 0)?;
+    table.attach(pid, start.as_usize(), shmid, size);
+    Ok(start.as_usize())
+}
+
+/// Detaches the segment attached at `addr` from the process `pid` with the
+/// address space `aspace`.
+pub fn shmdt(pid: u64, aspace: &Mutex<AddrSpace>, addr: usize) -> LinuxResult<isize> {
+    let size = SHM_TABLE
+        .lock()
+        .detach(pid, addr)
+        .ok_or(LinuxError::EINVAL)?;
+    aspace.lock().unmap(VirtAddr::from(addr), size)?;
+    Ok(0)
+}
+
+/// Controls the segment `shmid`, only `IPC_RMID` is supported.
+///
+/// A removed segment is released after it has been detached by all processes.
+pub fn shmctl(shmid: i32, cmd: i32) -> LinuxResult<isize> {
+    match cmd {
+        IPC_RMID => {
+            SHM_TABLE.lock().remove(shmid)?;
+            Ok(0)
+        }
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+/// Copies the attachments of the parent process to a forked child, as the
+/// child inherits the mappings.
+///
+/// It must be called before the child can run.
+pub fn fork_attachments(parent: u64, child: u64) {
+    SHM_TABLE.lock().fork(parent, child);
+}
+
+/// Detaches all segments from a process, whose address space is going to be
+/// cleared (by `execve` or on exit).
+pub fn detach_all(pid: u64) {
+    SHM_TABLE.lock().detach_all(pid);
+}
+
+/// Returns the POSIX shared memory object `name`, or creates it with
+/// `O_CREAT`.
+fn open_object(name: &str, flags: u32) -> LinuxResult<Arc<SharedPages>> {
+    if name.is_empty() || name.contains('/') {
+        return Err(LinuxError::EINVAL);
+    }
+    let mut objects = SHM_OBJECTS.lock();
+    match objects.get(name) {
+        Some(_) if flags & ctypes::O_CREAT != 0 && flags & ctypes::O_EXCL != 0 => {
+            Err(LinuxError::EEXIST)
+        }
+        Some(pages) => Ok(pages.clone()),
+        None if flags & ctypes::O_CREAT != 0 => {
+            let pages = Arc::new(SharedPages::new(0));
+            objects.insert(name.into(), pages.clone());
+            Ok(pages)
+        }
+        None => Err(LinuxError::ENOENT),
+    }
+}
+
+/// Opens the POSIX shared memory object `name` (the path under [`SHM_DIR`])
+/// and inserts it into the file descriptor table.
+///
+/// `O_TRUNC` is ignored, as the objects cannot be shrunk.
+pub fn shm_open(name: &str, flags: c_int) -> LinuxResult<c_int> {
+    let pages = open_object(name, flags as u32)?;
+    api::add_file_like(Arc::new(ShmFile {
+        pages,
+        pos: Mutex::new(0),
+        writable: flags as u32 & 0b11 != ctypes::O_RDONLY,
+    }))
+}
+
+/// Removes the name of the POSIX shared memory object `name`.
+///
+/// The object is released after it has been closed and unmapped.
+pub fn shm_unlink(name: &str) -> LinuxResult {
+    SHM_OBJECTS
+        .lock()
+        .remove(name)
+        .map(|_| ())
+        .ok_or(LinuxError::ENOENT)
+}
+
+/// An opened POSIX shared memory object.
+pub struct ShmFile {
+    pages: Arc<SharedPages>,
+    pos: Mutex<usize>,
+    writable: bool,
+}
+
+impl ShmFile {
+    /// Returns the shared memory object of `fd`, or `EINVAL` if `fd` is not
+    /// a shared memory object.
+    pub fn from_fd(fd: c_int) -> LinuxResult<Arc<Self>> {
+        api::get_file_like(fd)?
+            .into_any()
+            .downcast::<Self>()
+            .map_err(|_| LinuxError::EINVAL)
+    }
+
+    /// Returns the shared frames of the object.
+    pub fn pages(&self) -> &Arc<SharedPages> {
+        &self.pages
+    }
+
+    /// Returns whether the object is opened for writing.
+    pub fn is_writable(&self) -> bool {
+        self.writable
+    }
+}
+
+impl FileLike for ShmFile {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        let mut pos = self.pos.lock();
+        let n = self.pages.read_at(*pos, buf);
+        *pos += n;
+        Ok(n)
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        if !self.writable {
+            return Err(LinuxError::EBADF);
+        }
+        let mut pos = self.pos.lock();
+        let n = self.pages.write_at(*pos, buf);
+        *pos += n;
+        Ok(n)
+    }
+
+    fn stat(&self) -> LinuxResult<ctypes::stat> {
+        let size = self.pages.size();
+        Ok(ctypes::stat {
+            st_ino: 1,
+            st_nlink: 1,
+            st_mode: S_IFREG | 0o600,
+            st_uid: 1000,
+            st_gid: 1000,
+            st_size: size as _,
+            st_blocks: size.div_ceil(512) as _,
+            st_blksize: 512,
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: true,
+            writable: true,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+}
+
+/// Private mappings of the object are copies of its data.
+impl MmapFile for ShmFile {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> AxResult<usize> {
+        Ok(self.pages.read_at(offset as usize, buf))
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> AxResult<usize> {
+        Ok(self.pages.write_at(offset as usize, buf))
+    }
+
+    fn size(&self) -> AxResult<u64> {
+        Ok(self.pages.size() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const O_CREAT: u32 = ctypes::O_CREAT;
+    const O_EXCL: u32 = ctypes::O_EXCL;
+
+    #[test]
+    fn shmget() {
+        let mut table = ShmTable::new();
+        assert_eq!(table.get(1, 0x1000, 0), Err(LinuxError::ENOENT));
+        assert_eq!(table.get(1, 0, IPC_CREAT), Err(LinuxError::EINVAL));
+
+        let shmid = table.get(1, 0x1000, IPC_CREAT).unwrap();
+        assert_eq!(table.get(1, 0x1000, 0), Ok(shmid));
+        assert_eq!(table.get(1, 0x800, IPC_CREAT), Ok(shmid));
+        assert_eq!(table.get(1, 0x2000, 0), Err(LinuxError::EINVAL));
+        assert_eq!(
+            table.get(1, 0x1000, IPC_CREAT | IPC_EXCL),
+            Err(LinuxError::EEXIST)
+        );
+        assert_eq!(table.pages(shmid).unwrap().size(), 0x1000);
+
+        // private segments are always new
+        let private1 = table.get(IPC_PRIVATE, 0x1000, 0).unwrap();
+        let private2 = table.get(IPC_PRIVATE, 0x1000, 0).unwrap();
+        assert_ne!(private1, private2);
+        assert_ne!(private1, shmid);
+        assert_eq!(table.pages(private2 + 1).err(), Some(LinuxError::EINVAL));
+    }
+
+    #[test]
+    fn shmat_shmdt() {
+        let mut table = ShmTable::new();
+        let shmid = table.get(IPC_PRIVATE, 0x1000, 0).unwrap();
+        table.attach(1, 0x2000_0000, shmid, 0x1000);
+        table.attach(1, 0x3000_0000, shmid, 0x1000);
+        assert_eq!(table.segments[&shmid].nattch, 2);
+
+        assert_eq!(table.detach(1, 0x4000_0000), None);
+        assert_eq!(table.detach(2, 0x2000_0000), None);
+        assert_eq!(table.detach(1, 0x2000_0000), Some(0x1000));
+        assert_eq!(table.detach(1, 0x2000_0000), None);
+        assert_eq!(table.segments[&shmid].nattch, 1);
+    }
+
+    #[test]
+    fn rmid() {
+        let mut table = ShmTable::new();
+        let shmid = table.get(1, 0x1000, IPC_CREAT).unwrap();
+        table.attach(1, 0x2000_0000, shmid, 0x1000);
+
+        // still attached, but cannot be found or attached again
+        assert_eq!(table.remove(shmid), Ok(()));
+        assert!(table.segments.contains_key(&shmid));
+        assert_eq!(table.pages(shmid).err(), Some(LinuxError::EINVAL));
+        assert_eq!(table.get(1, 0x1000, 0), Err(LinuxError::ENOENT));
+        assert_eq!(table.remove(shmid), Err(LinuxError::EINVAL));
+
+        // freed on the last detach
+        assert_eq!(table.detach(1, 0x2000_0000), Some(0x1000));
+        assert!(!table.segments.contains_key(&shmid));
+        assert_eq!(table.remove(shmid), Err(LinuxError::EINVAL));
+
+        // freed immediately if not attached
+        let shmid = table.get(1, 0x1000, IPC_CREAT).unwrap();
+        assert_eq!(table.remove(shmid), Ok(()));
+        assert!(table.segments.is_empty());
+    }
+
+    #[test]
+    fn fork_and_exit() {
+        let mut table = ShmTable::new();
+        let shmid1 = table.get(IPC_PRIVATE, 0x1000, 0).unwrap();
+        let shmid2 = table.get(IPC_PRIVATE, 0x2000, 0).unwrap();
+        table.attach(1, 0x2000_0000, shmid1, 0x1000);
+        table.attach(1, 0x3000_0000, shmid2, 0x2000);
+        table.attach(3, 0x2000_0000, shmid1, 0x1000);
+
+        table.fork(1, 2);
+        assert_eq!(table.segments[&shmid1].nattch, 3);
+        assert_eq!(table.segments[&shmid2].nattch, 2);
+        table.remove(shmid1).unwrap();
+        table.remove(shmid2).unwrap();
+
+        // the parent exits
+        table.detach_all(1);
+        assert_eq!(table.segments[&shmid1].nattch, 2);
+        assert_eq!(table.segments[&shmid2].nattch, 1);
+
+        // the child exits, `shmid2` is no longer attached
+        table.detach_all(2);
+        assert!(!table.segments.contains_key(&shmid2));
+        assert_eq!(table.segments[&shmid1].nattch, 1);
+        assert_eq!(table.detach(3, 0x2000_0000), Some(0x1000));
+        assert!(table.segments.is_empty());
+        assert!(table.attachments.is_empty());
+    }
+
+    #[test]
+    fn shm_open() {
+        assert_eq!(open_object("", O_CREAT).err(), Some(LinuxError::EINVAL));
+        assert_eq!(open_object("a/b", O_CREAT).err(), Some(LinuxError::EINVAL));
+        assert_eq!(
+            open_object("test_shm_open", 0).err(),
+            Some(LinuxError::ENOENT)
+        );
+
+        let pages = open_object("test_shm_open", O_CREAT).unwrap();
+        assert_eq!(pages.size(), 0);
+        let opened = open_object("test_shm_open", 0).unwrap();
+        assert!(Arc::ptr_eq(&pages, &opened));
+        assert_eq!(
+            open_object("test_shm_open", O_CREAT | O_EXCL).err(),
+            Some(LinuxError::EEXIST)
+        );
+
+        // the objects can grow but not shrink
+        opened.grow(0x3000).unwrap();
+        assert_eq!(pages.size(), 0x3000);
+        assert!(pages.grow(0x1000).is_err());
+
+        assert_eq!(shm_unlink("test_shm_open"), Ok(()));
+        assert_eq!(shm_unlink("test_shm_open"), Err(LinuxError::ENOENT));
+        assert_eq!(
+            open_object("test_shm_open", 0).err(),
+            Some(LinuxError::ENOENT)
+        );
+        // still alive for the opened ones
+        assert_eq!(pages.size(), 0x3000);
+    }
+}
@@ -0,0 +1,271 @@
+//! The process and thread related syscalls.
+//!
+//! The functions return [`LinuxResult`], the kernels convert them to the
+//! return values of the syscalls.
+
+use core::ffi::c_char;
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+
+use axerrno::{AxResult, LinuxError, LinuxResult};
+use axhal::arch::UspaceContext;
+use axhal::mem::VirtAddr;
+use axhal::paging::MappingFlags;
+use axmm::AddrSpace;
+use axsync::Mutex;
+use axtask::{current, TaskExtRef};
+
+use crate::mem::{copy_to_user, user_str, user_str_array};
+use crate::{futex, shm, task};
+
+bitflags::bitflags! {
+    #[derive(Debug)]
+    /// flags for sys_clone
+    ///
+    /// See <https://github.com/torvalds/linux/blob/master/include/uapi/linux/sched.h>
+    pub struct CloneFlags: usize {
+        /// The child shares the address space with the parent.
+        const CLONE_VM = 0x0000_0100;
+        /// The child shares the filesystem information.
+        const CLONE_FS = 0x0000_0200;
+        /// The child shares the file descriptor table.
+        const CLONE_FILES = 0x0000_0400;
+        /// The child shares the signal handlers.
+        const CLONE_SIGHAND = 0x0000_0800;
+        /// The parent is suspended until the child calls `execve` or exits.
+        const CLONE_VFORK = 0x0000_4000;
+        /// The child is a thread in the same process.
+        const CLONE_THREAD = 0x0001_0000;
+        /// The child shares System V semaphore undo values.
+        const CLONE_SYSVSEM = 0x0004_0000;
+        /// Set the TLS register of the child.
+        const CLONE_SETTLS = 0x0008_0000;
+        /// Store the child TID at `ptid` in the parent's memory.
+        const CLONE_PARENT_SETTID = 0x0010_0000;
+        /// Clear the child TID at `ctid` when the child exits.
+        const CLONE_CHILD_CLEARTID = 0x0020_0000;
+        /// Store the child TID at `ctid` in the child's memory.
+        const CLONE_CHILD_SETTID = 0x0100_0000;
+    }
+}
+
+/// The exit signal of the child is in the low byte of the clone flags.
+const CSIGNAL: usize = 0xff;
+
+/// Return immediately from `wait4` if no child has exited.
+const WNOHANG: u32 = 1;
+
+/// Sets the `clear_child_tid` of the current thread, returns its TID.
+pub fn sys_set_tid_address(tid_ptr: usize) -> LinuxResult<isize> {
+    let curr = current();
+    curr.task_ext().set_clear_child_tid(tid_ptr as _);
+    Ok(curr.id().as_u64() as isize)
+}
+
+/// Creates a child thread or process, which starts with the user context
+/// `uctx`.
+///
+/// The kernel prepares `uctx` from the trap frame of the syscall, as the
+/// stack and TLS registers of the child are architecture specific.
+///
+/// With `CLONE_THREAD` (and `CLONE_VM`), the child is a new thread sharing
+/// the address space of the current process. Otherwise the child is a new
+/// process with a copy of the current address space, even if `CLONE_VM` is
+/// set. `fork` and `vfork` from libc both end up here. With `CLONE_VFORK`,
+/// the current thread is blocked until the child calls `execve` or exits.
+///
+/// Returns the TID of the child.
+pub fn sys_clone(
+    flags: usize,
+    uctx: UspaceContext,
+    ptid: usize,
+    ctid: usize,
+) -> LinuxResult<isize> {
+    let clone_flags = CloneFlags::from_bits_truncate(flags & !CSIGNAL);
+    let is_thread = clone_flags.contains(CloneFlags::CLONE_THREAD);
+    if is_thread && !clone_flags.contains(CloneFlags::CLONE_VM) {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let set_child_tid = if clone_flags.contains(CloneFlags::CLONE_CHILD_SETTID) {
+        ctid
+    } else {
+        0
+    };
+    let clear_child_tid = if clone_flags.contains(CloneFlags::CLONE_CHILD_CLEARTID) {
+        ctid as u64
+    } else {
+        0
+    };
+
+    let child = if is_thread {
+        task::spawn_user_thread(uctx, set_child_tid, clear_child_tid)
+    } else {
+        let aspace = current()
+            .task_ext()
+            .aspace
+            .lock()
+            .try_clone()
+            .map_err(|_| LinuxError::ENOMEM)?;
+        task::spawn_user_process(
+            Arc::new(Mutex::new(aspace)),
+            uctx,
+            set_child_tid,
+            clear_child_tid,
+        )
+    };
+
+    let tid = child.id().as_u64() as i32;
+    if clone_flags.contains(CloneFlags::CLONE_PARENT_SETTID) && ptid != 0 {
+        copy_to_user(ptid, &tid.to_ne_bytes())?;
+    }
+    if clone_flags.contains(CloneFlags::CLONE_VFORK) && !is_thread {
+        child.task_ext().process.wait_vfork_done();
+    }
+    Ok(tid as isize)
+}
+
+/// Replaces the image of the current process with the program at `path`.
+///
+/// `load` loads the program into the cleared address space, and returns the
+/// entry point. The user stack is initialized with `argv` and `envp`.
+///
+/// Returns the user context to enter the new program. If the program fails to
+/// load after the old image is cleared, the process exits.
+pub fn sys_execve(
+    path: *const c_char,
+    argv: *const *const c_char,
+    envp: *const *const c_char,
+    load: impl FnOnce(&str, &mut AddrSpace) -> AxResult<usize>,
+) -> LinuxResult<UspaceContext> {
+    let path = user_str(path)?;
+    let args = user_str_array(argv)?;
+    let envs = user_str_array(envp)?;
+    info!("sys_execve: {:?} {:?}", path, args);
+
+    let curr = current();
+    if curr.task_ext().process.thread_count() > 1 {
+        // We cannot stop the other threads before tearing down the image.
+        warn!("execve from a multi-threaded process is not supported");
+        return Err(LinuxError::EBUSY);
+    }
+    // Fail early if the program does not exist, as the old image cannot be
+    // restored once it is cleared.
+    axfs::api::metadata(&path).map_err(LinuxError::from)?;
+
+    shm::detach_all(curr.task_ext().proc_id());
+    let mut aspace = curr.task_ext().aspace.lock();
+    aspace.clear();
+    let image = load(&path, &mut aspace).and_then(|entry| {
+        let ustack_top = init_user_stack(&mut aspace, true, &args, &envs)?;
+        Ok((entry, ustack_top))
+    });
+    drop(aspace);
+    match image {
+        Ok((entry, ustack_top)) => {
+            let process = &curr.task_ext().process;
+            process.set_name(path.rsplit('/').next().unwrap_or(&path));
+            process.wake_vfork_parent();
+            Ok(UspaceContext::new(entry, ustack_top))
+        }
+        Err(e) => {
+            warn!("sys_execve: failed to load {:?}: {:?}", path, e);
+            task::exit_current(-1, true)
+        }
+    }
+}
+
+/// Waits for a child process to exit.
+///
+/// Process groups are not supported, so any `pid <= 0` waits for any child.
+pub fn sys_wait4(pid: i32, wstatus: usize, options: u32) -> LinuxResult<isize> {
+    let pid = if pid <= 0 { -1 } else { pid as i64 };
+    let nohang = options & WNOHANG != 0;
+    match current().task_ext().process.wait_child(pid, nohang)? {
+        Some((child_pid, exit_code)) => {
+            if wstatus != 0 {
+                let status: i32 = (exit_code & 0xff) << 8;
+                copy_to_user(wstatus, &status.to_ne_bytes())?;
+            }
+            Ok(child_pid as isize)
+        }
+        None => Ok(0),
+    }
+}
+
+/// The `futex` syscall on the current process, see [`futex::futex`].
+pub fn sys_futex(
+    uaddr: usize,
+    futex_op: u32,
+    val: u32,
+    timeout: usize,
+    uaddr2: usize,
+    val3: u32,
+) -> LinuxResult<isize> {
+    let curr = current();
+    let ext = curr.task_ext();
+    futex::futex(
+        ext.proc_id(),
+        &ext.aspace,
+        uaddr,
+        futex_op,
+        val,
+        timeout,
+        uaddr2,
+        val3,
+    )
+}
+
+/// Attaches the System V shared memory segment `shmid` to the current
+/// process, see [`shm::shmat`].
+pub fn sys_shmat(shmid: i32, addr: usize, flags: i32) -> LinuxResult<usize> {
+    let curr = current();
+    let ext = curr.task_ext();
+    shm::shmat(ext.proc_id(), &ext.aspace, shmid, addr, flags)
+}
+
+/// Detaches the System V shared memory segment at `addr` from the current
+/// process, see [`shm::shmdt`].
+pub fn sys_shmdt(addr: usize) -> LinuxResult<isize> {
+    let curr = current();
+    let ext = curr.task_ext();
+    shm::shmdt(ext.proc_id(), &ext.aspace, addr)
+}
+
+/// Maps the user stack at the top of `uspace`, and pushes the arguments, the
+/// environment variables and the auxiliary vector onto it.
+///
+/// Returns the initial user stack pointer.
+pub fn init_user_stack(
+    uspace: &mut AddrSpace,
+    populating: bool,
+    args: &[String],
+    envs: &[String],
+) -> AxResult<VirtAddr> {
+    let ustack_top = uspace.end();
+    let ustack_vaddr = ustack_top - crate::USER_STACK_SIZE;
+    info!(
+        "Mapping user stack: {:#x?} -> {:#x?}",
+        ustack_vaddr, ustack_top
+    );
+    uspace.map_alloc(
+        ustack_vaddr,
+        crate::USER_STACK_SIZE,
+        MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
+        populating,
+    )?;
+
+    let av = BTreeMap::new();
+    let (stack_data, ustack_pointer) = kernel_elf_parser::get_app_stack_region(
+        args,
+        envs,
+        &av,
+        ustack_vaddr,
+        crate::USER_STACK_SIZE,
+    );
+    uspace.write(VirtAddr::from_usize(ustack_pointer), stack_data.as_slice())?;
+
+    Ok(ustack_pointer.into())
+}
@@ -0,0 +1,179 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use alloc::sync::Arc;
+
+use axhal::arch::UspaceContext;
+use axhal::mem::VirtAddr;
+use axmm::AddrSpace;
+use axsync::Mutex;
+use axtask::{AxTaskRef, TaskExtRef, TaskInner};
+
+use crate::process::{self, Process};
+use crate::{futex, shm};
+
+/// Task extended data for the monolithic kernel.
+pub struct TaskExt {
+    /// The process this thread belongs to.
+    pub process: Arc<Process>,
+    /// The clear thread tid field
+    ///
+    /// See <https://manpages.debian.org/unstable/manpages-dev/set_tid_address.2.en.html#clear_child_tid>
+    ///
+    /// When the thread exits, the kernel clears the word at this address if it is not NULL,
+    /// and wakes up a futex waiter on it.
+    clear_child_tid: AtomicU64,
+    /// The user space context.
+    pub uctx: UspaceContext,
+    /// The virtual memory address space.
+    pub aspace: Arc<Mutex<AddrSpace>>,
+}
+
+impl TaskExt {
+    pub const fn new(
+        process: Arc<Process>,
+        uctx: UspaceContext,
+        aspace: Arc<Mutex<AddrSpace>>,
+    ) -> Self {
+        Self {
+            process,
+            uctx,
+            clear_child_tid: AtomicU64::new(0),
+            aspace,
+        }
+    }
+
+    /// Returns the process ID of the task.
+    pub fn proc_id(&self) -> u64 {
+        self.process.pid()
+    }
+
+    pub(crate) fn clear_child_tid(&self) -> u64 {
+        self.clear_child_tid.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_clear_child_tid(&self, clear_child_tid: u64) {
+        self.clear_child_tid
+            .store(clear_child_tid, Ordering::Relaxed);
+    }
+}
+
+axtask::def_task_ext!(TaskExt);
+
+fn new_user_task(name: &str, aspace: &Arc<Mutex<AddrSpace>>) -> TaskInner {
+    let mut task = TaskInner::new(
+        || {
+            let curr = axtask::current();
+            let kstack_top = curr.kernel_stack_top().unwrap();
+            info!(
+                "Enter user space: entry={:#x}, ustack={:#x}, kstack={:#x}",
+                curr.task_ext().uctx.get_ip(),
+                curr.task_ext().uctx.get_sp(),
+                kstack_top,
+            );
+            unsafe { curr.task_ext().uctx.enter_uspace(kstack_top) };
+        },
+        name.into(),
+        crate::KERNEL_STACK_SIZE,
+    );
+    task.ctx_mut()
+        .set_page_table_root(aspace.lock().page_table_root());
+    task
+}
+
+/// Spawns the init process with the given address space and user context.
+pub fn spawn_user_task(aspace: Arc<Mutex<AddrSpace>>, uctx: UspaceContext) -> AxTaskRef {
+    let mut task = new_user_task("userboot", &aspace);
+    let process = Process::new(task.id().as_u64(), task.name(), aspace.clone(), None);
+    process::set_init_process(process.clone());
+    task.init_task_ext(TaskExt::new(process, uctx, aspace));
+    axtask::spawn_task(task)
+}
+
+/// Writes the TID of a new task to `tid_ptr` in its address space (for
+/// `CLONE_CHILD_SETTID`), if `tid_ptr` is not NULL.
+fn write_child_tid(aspace: &Mutex<AddrSpace>, tid_ptr: usize, tid: u64) {
+    if tid_ptr != 0 {
+        let tid = (tid as i32).to_ne_bytes();
+        if aspace.lock().write(VirtAddr::from(tid_ptr), &tid).is_err() {
+            warn!("failed to set child tid at {:#x}", tid_ptr);
+        }
+    }
+}
+
+/// Spawns a new thread in the process of the current task.
+///
+/// The new thread shares the address space of the current task.
+pub fn spawn_user_thread(
+    uctx: UspaceContext,
+    set_child_tid: usize,
+    clear_child_tid: u64,
+) -> AxTaskRef {
+    let curr = axtask::current();
+    let process = curr.task_ext().process.clone();
+    let aspace = curr.task_ext().aspace.clone();
+    let mut task = new_user_task(curr.name(), &aspace);
+    write_child_tid(&aspace, set_child_tid, task.id().as_u64());
+    // The attached segments belong to the process, so they are not counted
+    // again for the new thread.
+    process.add_thread();
+    let ext = TaskExt::new(process, uctx, aspace);
+    ext.set_clear_child_tid(clear_child_tid);
+    task.init_task_ext(ext);
+    axtask::spawn_task(task)
+}
+
+/// Spawns a child process of the current process.
+///
+/// The child runs in `aspace`, usually a copy of the current address space.
+pub fn spawn_user_process(
+    aspace: Arc<Mutex<AddrSpace>>,
+    uctx: UspaceContext,
+    set_child_tid: usize,
+    clear_child_tid: u64,
+) -> AxTaskRef {
+    let curr = axtask::current();
+    let mut task = new_user_task(curr.name(), &aspace);
+    write_child_tid(&aspace, set_child_tid, task.id().as_u64());
+    let process = Process::new(
+        task.id().as_u64(),
+        &curr.task_ext().process.name(),
+        aspace.clone(),
+        Some(&curr.task_ext().process),
+    );
+    // The child inherits the attached segments with the address space, they
+    // must be counted before the child can detach them.
+    shm::fork_attachments(curr.task_ext().proc_id(), process.pid());
+    let ext = TaskExt::new(process, uctx, aspace);
+    ext.set_clear_child_tid(clear_child_tid);
+    task.init_task_ext(ext);
+    axtask::spawn_task(task)
+}
+
+/// Exits the current thread.
+///
+/// If `group` is `true`, the whole process is going to exit with `exit_code`
+/// (`exit_group`): other threads exit as soon as they enter the kernel, or
+/// return from a futex wait.
+///
+/// When the last thread exits, the user memory of the process is released and
+/// the process becomes a zombie until its parent reaps it by `wait4`.
+pub fn exit_current(exit_code: i32, group: bool) -> ! {
+    let curr = axtask::current();
+    let ext = curr.task_ext();
+    let pid = ext.proc_id();
+    if group {
+        ext.process.set_group_exit(exit_code);
+        // Wake up the threads blocked on the futexes, so that they can exit.
+        futex::clear_process(pid);
+    }
+    let clear_child_tid = ext.clear_child_tid() as usize;
+    if clear_child_tid != 0 {
+        futex::clear_child_tid(&ext.aspace, clear_child_tid);
+    }
+    if ext.process.exit_thread(exit_code) {
+        futex::clear_process(pid);
+        shm::detach_all(pid);
+        ext.aspace.lock().clear();
+    }
+    axtask::exit(exit_code)
+}
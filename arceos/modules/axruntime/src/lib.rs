@@ -13,6 +13,7 @@
 //! - `fs`: Enable filesystem support.
 //! - `net`: Enable networking support.
 //! - `display`: Enable graphics support.
+//! - `swap`: Enable swapping pages out to a block device.
 //!
 //! All the features are optional and disabled by default.
 
@@ -25,9 +26,18 @@ extern crate axlog;
 #[cfg(all(target_os = "none", not(test)))]
 mod lang_items;
 
+#[cfg(any(feature = "fs", feature = "swap"))]
+extern crate alloc;
+
 #[cfg(feature = "smp")]
 mod mp;
 
+#[cfg(feature = "swap")]
+mod swap;
+
+#[cfg(feature = "fs")]
+mod sysfs;
+
 #[cfg(feature = "smp")]
 pub use self::mp::rust_main_secondary;
 
@@ -153,11 +163,16 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
     #[cfg(feature = "multitask")]
     axtask::init_scheduler();
 
-    #[cfg(any(feature = "fs", feature = "net", feature = "display"))]
+    #[cfg(any(feature = "fs", feature = "net", feature = "display", feature = "swap"))]
     {
-        #[allow(unused_variables)]
-        let all_devices = axdriver::init_drivers();
-
+        #[allow(unused_variables, unused_mut)]
+        let mut all_devices = axdriver::init_drivers();
+        // The swap area is on the last block device, so that the other ones
+        // holding the filesystems are named vda, vdb, ... in order.
+        #[cfg(feature = "swap")]
+        self::swap::init_swap(&mut all_devices.block);
+        #[cfg(feature = "fs")]
+        let sysfs_attrs = self::sysfs::collect(&all_devices);
         #[cfg(feature = "fs")]
         axfs::init_filesystems(all_devices.block);
 
@@ -166,6 +181,22 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
 
         #[cfg(feature = "display")]
         axdisplay::init_display(all_devices.display);
+
+        #[cfg(all(feature = "fs", feature = "display"))]
+        {
+            let info = axdisplay::framebuffer_info();
+            let fb = axfs::devfs::FrameBufferDev::new(
+                info.fb_base_vaddr.into(),
+                info.fb_size,
+                axdisplay::framebuffer_flush,
+            );
+            if let Err(e) = axfs::devfs::add("fb0", alloc::sync::Arc::new(fb)) {
+                warn!("failed to add /dev/fb0: {:?}", e);
+            }
+        }
+
+        #[cfg(feature = "fs")]
+        self::sysfs::publish(sysfs_attrs);
     }
 
     #[cfg(feature = "smp")]
@@ -193,11 +224,29 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
     unsafe { main() };
 
     #[cfg(feature = "multitask")]
-    axtask::exit(0);
+    {
+        // The system is shut down when the main task exits.
+        sync_filesystems();
+        axtask::exit(0);
+    }
     #[cfg(not(feature = "multitask"))]
     {
         debug!("main task exited: exit_code={}", 0);
-        axhal::misc::terminate();
+        terminate();
+    }
+}
+
+/// Shuts down the system, after writing back the cached data of the
+/// filesystems to the disks.
+pub fn terminate() -> ! {
+    sync_filesystems();
+    axhal::misc::terminate()
+}
+
+fn sync_filesystems() {
+    #[cfg(feature = "fs")]
+    if let Err(e) = axfs::api::sync() {
+        error!("failed to write back the disks: {:?}", e);
     }
 }
 
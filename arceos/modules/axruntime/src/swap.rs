@@ -0,0 +1,44 @@
+use alloc::boxed::Box;
+use axdriver::prelude::{AxBlockDevice, BlockDriverOps};
+use axdriver::AxDeviceContainer;
+use axerrno::{AxError, AxResult};
+
+/// Uses a block device as the swap area of [`axmm`].
+struct SwapDisk(AxBlockDevice);
+
+impl axmm::SwapDevice for SwapDisk {
+    fn block_size(&self) -> usize {
+        self.0.block_size()
+    }
+
+    fn num_blocks(&self) -> u64 {
+        self.0.num_blocks()
+    }
+
+    fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> AxResult {
+        self.0.read_block(block_id, buf).map_err(|_| AxError::Io)
+    }
+
+    fn write_block(&mut self, block_id: u64, buf: &[u8]) -> AxResult {
+        self.0.write_block(block_id, buf).map_err(|_| AxError::Io)
+    }
+}
+
+/// Initializes the swap area on the last block device.
+///
+/// If the `fs` feature is enabled, the device is not used when it's the only
+/// one, which holds the root filesystem.
+pub fn init_swap(blk_devs: &mut AxDeviceContainer<AxBlockDevice>) {
+    let min_devs = if cfg!(feature = "fs") { 2 } else { 1 };
+    if blk_devs.len() < min_devs {
+        warn!("No block device for the swap area");
+        return;
+    }
+    if let Some(dev) = blk_devs.take_last() {
+        info!(
+            "Initialize swap area on block device: {}",
+            dev.device_name()
+        );
+        axmm::init_swap(Box::new(SwapDisk(dev)));
+    }
+}
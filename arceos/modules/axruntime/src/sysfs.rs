@@ -0,0 +1,71 @@
+//! Attributes of the probed devices in `/sys`.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::{format, vec};
+
+use axdriver::prelude::*;
+use axdriver::AllDevices;
+
+/// Collects the attributes of the devices, as pairs of paths relative to
+/// `/sys` and values.
+///
+/// It must be called before the devices are handed over to the subsystems.
+pub fn collect(all_devices: &AllDevices) -> Vec<(String, String)> {
+    let mut attrs = vec![];
+    for (idx, dev) in all_devices.block.iter().enumerate() {
+        // Named the same as in `/dev`.
+        let Some(name) = axfs::block_dev_name(idx) else {
+            break;
+        };
+        let dir = format!("block/{name}");
+        // In 512-byte sectors, regardless of the block size.
+        let sectors = dev.num_blocks() * dev.block_size() as u64 / 512;
+        attrs.push((format!("{dir}/size"), format!("{sectors}\n")));
+        attrs.push((
+            format!("{dir}/device/model"),
+            format!("{}\n", dev.device_name()),
+        ));
+    }
+    #[cfg(feature = "net")]
+    for (idx, dev) in all_devices.net.iter().enumerate() {
+        let dir = format!("class/net/eth{idx}");
+        let mac = dev.mac_address().0;
+        let address = format!(
+            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}\n",
+            mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]
+        );
+        attrs.push((format!("{dir}/address"), address));
+        attrs.push((
+            format!("{dir}/device/model"),
+            format!("{}\n", dev.device_name()),
+        ));
+    }
+    attrs
+}
+
+/// Adds the collected attributes to `/sys`, along with the ones of the
+/// subsystems that have been initialized.
+#[allow(unused_mut)]
+pub fn publish(mut attrs: Vec<(String, String)>) {
+    #[cfg(feature = "display")]
+    {
+        let info = axdisplay::framebuffer_info();
+        let bpp = info.fb_size * 8 / (info.width as usize * info.height as usize).max(1);
+        let dir = "class/graphics/fb0";
+        attrs.push((
+            format!("{dir}/virtual_size"),
+            format!("{},{}\n", info.width, info.height),
+        ));
+        attrs.push((format!("{dir}/bits_per_pixel"), format!("{bpp}\n")));
+        attrs.push((
+            format!("{dir}/stride"),
+            format!("{}\n", info.width as usize * bpp / 8),
+        ));
+    }
+    for (path, value) in attrs {
+        if let Err(e) = axfs::sysfs::add_file(&path, &value) {
+            warn!("failed to add /sys/{}: {:?}", path, e);
+        }
+    }
+}
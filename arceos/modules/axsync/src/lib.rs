@@ -2,26 +2,74 @@
 //!
 //! Currently supported primitives:
 //!
-//! - [`Mutex`]: A mutual exclusion primitive.
+//! - [`Mutex`]: A mutual exclusion primitive, optionally with priority
+//!   inheritance.
+//! - [`Condvar`]: A condition variable used with [`Mutex`].
+//! - [`RwLock`]: A readers-writer lock.
+//! - [`Semaphore`]: A counting semaphore.
+//! - [`Barrier`]: A barrier to synchronize a number of tasks.
+//! - [`Once`] and [`OnceLock`]: One-time initialization.
 //! - mod [`spin`]: spinlocks imported from the [`kspin`] crate.
 //!
+//! All but the spinlocks block the waiting tasks in the wait queues of
+//! `axtask`.
+//!
 //! # Cargo Features
 //!
 //! - `multitask`: For use in the multi-threaded environments. If the feature is
-//!   not enabled, [`Mutex`] will be an alias of [`spin::SpinNoIrq`]. This
-//!   feature is enabled by default.
+//!   not enabled, [`Mutex`] will be an alias of [`spin::SpinNoIrq`], and the
+//!   other blocking primitives are not available. This feature is enabled by
+//!   default.
+//! - `irq`: Interrupts are enabled. The methods with timeouts can be used,
+//!   such as [`Condvar::wait_timeout`] and [`Semaphore::acquire_timeout`].
 
 #![cfg_attr(not(test), no_std)]
 #![feature(doc_cfg)]
 
 pub use kspin as spin;
 
+#[cfg(feature = "multitask")]
+extern crate alloc;
+
+#[cfg(test)]
+mod tests;
+
+#[cfg(feature = "multitask")]
+mod barrier;
+#[cfg(feature = "multitask")]
+mod condvar;
 #[cfg(feature = "multitask")]
 mod mutex;
+#[cfg(feature = "multitask")]
+mod once;
+#[cfg(feature = "multitask")]
+mod pi;
+#[cfg(feature = "multitask")]
+mod rwlock;
+#[cfg(feature = "multitask")]
+mod semaphore;
 
+#[cfg(feature = "multitask")]
+#[doc(cfg(feature = "multitask"))]
+pub use self::barrier::{Barrier, BarrierWaitResult};
+#[cfg(feature = "multitask")]
+#[doc(cfg(feature = "multitask"))]
+pub use self::condvar::Condvar;
+#[cfg(all(feature = "multitask", feature = "irq"))]
+#[doc(cfg(all(feature = "multitask", feature = "irq")))]
+pub use self::condvar::WaitTimeoutResult;
 #[cfg(feature = "multitask")]
 #[doc(cfg(feature = "multitask"))]
 pub use self::mutex::{Mutex, MutexGuard};
+#[cfg(feature = "multitask")]
+#[doc(cfg(feature = "multitask"))]
+pub use self::once::{Once, OnceLock};
+#[cfg(feature = "multitask")]
+#[doc(cfg(feature = "multitask"))]
+pub use self::rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+#[cfg(feature = "multitask")]
+#[doc(cfg(feature = "multitask"))]
+pub use self::semaphore::Semaphore;
 
 #[cfg(not(feature = "multitask"))]
 #[doc(cfg(not(feature = "multitask")))]
@@ -1,4 +1,4 @@
-//! A naïve sleeping mutex.
+//! A naïve sleeping mutex, with an optional priority-inheritance mode.
 
 use core::cell::UnsafeCell;
 use core::fmt;
@@ -13,6 +13,9 @@ use axtask::{current, WaitQueue};
 /// When the mutex is locked, the current task will block and be put into the
 /// wait queue. When the mutex is unlocked, all tasks waiting on the queue
 /// will be woken up.
+///
+/// A mutex created by [`Mutex::new_pi`] avoids the priority inversion, see
+/// its documentation for details.
 pub struct Mutex<T: ?Sized> {
     wq: WaitQueue,
     owner_id: AtomicU64,
@@ -23,10 +26,14 @@ pub struct Mutex<T: ?Sized> {
 ///
 /// When the guard falls out of scope it will release the lock.
 pub struct MutexGuard<'a, T: ?Sized + 'a> {
-    lock: &'a Mutex<T>,
+    pub(crate) lock: &'a Mutex<T>,
     data: *mut T,
 }
 
+/// The bit of `owner_id` set for a priority-inheritance mutex, the task IDs
+/// never reach it.
+const PI_FLAG: u64 = 1 << 63;
+
 // Same unsafe impls as `std::sync::Mutex`
 unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
 unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
@@ -42,6 +49,26 @@ impl<T> Mutex<T> {
         }
     }
 
+    /// Creates a new priority-inheritance [`Mutex`] wrapping the supplied
+    /// data.
+    ///
+    /// The owner of the mutex runs with the highest real-time priority of the
+    /// tasks waiting for it (see [`axtask::set_inherited_prio`]), until it
+    /// unlocks the mutex. If the owner is waiting for another such mutex, the
+    /// priority is passed on to the owner of that one, and so on. On unlock,
+    /// the mutex is handed over to the waiter with the highest priority.
+    ///
+    /// The nice values of the normal tasks are not inherited, the normal
+    /// waiters only get the mutex in the order they wait for it.
+    #[inline(always)]
+    pub const fn new_pi(data: T) -> Self {
+        Self {
+            wq: WaitQueue::new(),
+            owner_id: AtomicU64::new(PI_FLAG),
+            data: UnsafeCell::new(data),
+        }
+    }
+
     /// Consumes this [`Mutex`] and unwraps the underlying data.
     #[inline(always)]
     pub fn into_inner(self) -> T {
@@ -61,7 +88,13 @@ impl<T: ?Sized> Mutex<T> {
     /// the instant it is called. Do not use it for synchronization purposes. However, it may be useful as a heuristic.
     #[inline(always)]
     pub fn is_locked(&self) -> bool {
-        self.owner_id.load(Ordering::Relaxed) != 0
+        self.owner_id.load(Ordering::Relaxed) & !PI_FLAG != 0
+    }
+
+    /// Returns `true` if it's a priority-inheritance mutex.
+    #[inline(always)]
+    pub fn is_pi(&self) -> bool {
+        self.owner_id.load(Ordering::Relaxed) & PI_FLAG != 0
     }
 
     /// Locks the [`Mutex`] and returns a guard that permits access to the inner data.
@@ -70,23 +103,28 @@ impl<T: ?Sized> Mutex<T> {
     /// and the lock will be dropped when the guard falls out of scope.
     pub fn lock(&self) -> MutexGuard<T> {
         let current_id = current().id().as_u64();
+        let flag = self.owner_id.load(Ordering::Relaxed) & PI_FLAG;
         loop {
             // Can fail to lock even if the spinlock is not locked. May be more efficient than `try_lock`
             // when called in a loop.
             match self.owner_id.compare_exchange_weak(
-                0,
-                current_id,
+                flag,
+                flag | current_id,
                 Ordering::Acquire,
                 Ordering::Relaxed,
             ) {
                 Ok(_) => break,
                 Err(owner_id) => {
                     assert_ne!(
-                        owner_id,
+                        owner_id & !PI_FLAG,
                         current_id,
                         "{} tried to acquire mutex it already owns.",
                         current().id_name()
                     );
+                    if flag != 0 {
+                        self.lock_pi_slow(current_id);
+                        break;
+                    }
                     // Wait until the lock looks unlocked before retrying
                     self.wq.wait_until(|| !self.is_locked());
                 }
@@ -102,11 +140,17 @@ impl<T: ?Sized> Mutex<T> {
     #[inline(always)]
     pub fn try_lock(&self) -> Option<MutexGuard<T>> {
         let current_id = current().id().as_u64();
+        let flag = self.owner_id.load(Ordering::Relaxed) & PI_FLAG;
         // The reason for using a strong compare_exchange is explained here:
         // https://github.com/Amanieu/parking_lot/pull/207#issuecomment-575869107
         if self
             .owner_id
-            .compare_exchange(0, current_id, Ordering::Acquire, Ordering::Relaxed)
+            .compare_exchange(
+                flag,
+                flag | current_id,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
             .is_ok()
         {
             Some(MutexGuard {
@@ -118,6 +162,20 @@ impl<T: ?Sized> Mutex<T> {
         }
     }
 
+    /// Creates a guard for the [`Mutex`] without locking it.
+    ///
+    /// # Safety
+    ///
+    /// The lock must be held by the current task, and its guard must have
+    /// been forgotten, e.g., the lock is exposed to FFI and used with a
+    /// [`Condvar`](crate::Condvar).
+    pub unsafe fn make_guard_unchecked(&self) -> MutexGuard<T> {
+        MutexGuard {
+            lock: self,
+            data: self.data.get(),
+        }
+    }
+
     /// Force unlock the [`Mutex`].
     ///
     /// # Safety
@@ -126,6 +184,9 @@ impl<T: ?Sized> Mutex<T> {
     /// thread. However, this can be useful in some instances for exposing
     /// the lock to FFI that doesn’t know how to deal with RAII.
     pub unsafe fn force_unlock(&self) {
+        if self.is_pi() {
+            return self.unlock_pi();
+        }
         let owner_id = self.owner_id.swap(0, Ordering::Release);
         assert_eq!(
             owner_id,
@@ -149,6 +210,52 @@ impl<T: ?Sized> Mutex<T> {
     }
 }
 
+impl<T: ?Sized> Mutex<T> {
+    fn addr(&self) -> usize {
+        self as *const Self as *const () as usize
+    }
+
+    /// Waits for the priority-inheritance mutex to be handed over to the
+    /// current task, with the priority lent to the owner.
+    fn lock_pi_slow(&self, current_id: u64) {
+        let mut state = crate::pi::lock_state();
+        // Unlocking and handing over are serialized by the state lock, so
+        // the owner cannot change once it's seen here.
+        if let Err(owner_id) = self.owner_id.compare_exchange(
+            PI_FLAG,
+            PI_FLAG | current_id,
+            Ordering::Acquire,
+            Ordering::Relaxed,
+        ) {
+            state.enqueue(self.addr(), owner_id & !PI_FLAG, &current());
+            drop(state);
+            self.wq
+                .wait_until(|| self.owner_id.load(Ordering::Acquire) == PI_FLAG | current_id);
+        }
+    }
+
+    fn unlock_pi(&self) {
+        let curr = current();
+        let mut state = crate::pi::lock_state();
+        assert_eq!(
+            self.owner_id.load(Ordering::Relaxed),
+            PI_FLAG | curr.id().as_u64(),
+            "{} tried to release mutex it doesn't own",
+            curr.id_name()
+        );
+        match state.dequeue(self.addr(), &curr) {
+            Some(next) => {
+                self.owner_id
+                    .store(PI_FLAG | next.id().as_u64(), Ordering::Release);
+                // Woken up before the current task may be preempted for the
+                // lowered priority, when the state is unlocked.
+                self.wq.notify_task(true, &next);
+            }
+            None => self.owner_id.store(PI_FLAG, Ordering::Release),
+        }
+    }
+}
+
 impl<T: ?Sized + Default> Default for Mutex<T> {
     #[inline(always)]
     fn default() -> Self {
@@ -198,14 +305,16 @@ impl<'a, T: ?Sized> Drop for MutexGuard<'a, T> {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use crate::Mutex;
     use axtask as thread;
-    use std::sync::Once;
+    use core::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Mutex as StdMutex, Once};
 
-    static INIT: Once = Once::new();
+    pub(crate) static INIT: Once = Once::new();
+    pub(crate) static SERIAL: StdMutex<()> = StdMutex::new(());
 
-    fn may_interrupt() {
+    pub(crate) fn may_interrupt() {
         // simulate interrupts
         if rand::random::<u32>() % 3 == 0 {
             thread::yield_now();
@@ -214,6 +323,7 @@ mod tests {
 
     #[test]
     fn lots_and_lots() {
+        let _lock = SERIAL.lock();
         INIT.call_once(thread::init_scheduler);
 
         const NUM_TASKS: u32 = 10;
@@ -249,4 +359,143 @@ mod tests {
         assert_eq!(*M.lock(), NUM_ITERS * NUM_TASKS * 3);
         println!("Mutex test OK");
     }
+
+    #[test]
+    fn pi_boost() {
+        let _lock = SERIAL.lock();
+        INIT.call_once(thread::init_scheduler);
+
+        use thread::{current, SchedPolicy};
+        static M: Mutex<()> = Mutex::new_pi(());
+        static ORDER: StdMutex<Vec<&str>> = StdMutex::new(Vec::new());
+        static LOCKED: AtomicBool = AtomicBool::new(false);
+        assert!(M.is_pi());
+
+        let low = thread::spawn_raw(
+            || {
+                let guard = M.lock();
+                LOCKED.store(true, Ordering::Release);
+                while current().rt_prio() == 0 {
+                    thread::yield_now();
+                }
+                // inherits the priority of `high`, and runs before `mid`
+                assert_eq!(current().rt_prio(), 50);
+                assert_eq!(current().sched_policy(), SchedPolicy::Normal);
+                ORDER.lock().unwrap().push("low");
+                drop(guard);
+                assert_eq!(current().rt_prio(), 0);
+            },
+            "low".into(),
+            0x1000,
+        );
+        while !LOCKED.load(Ordering::Acquire) {
+            thread::yield_now();
+        }
+
+        let mid = thread::spawn_raw(|| ORDER.lock().unwrap().push("mid"), "mid".into(), 0x1000);
+        thread::set_sched_policy(&mid, SchedPolicy::Fifo(10)).unwrap();
+        let high = thread::spawn_raw(
+            || {
+                let _guard = M.lock();
+                ORDER.lock().unwrap().push("high");
+            },
+            "high".into(),
+            0x1000,
+        );
+        thread::set_sched_policy(&high, SchedPolicy::Fifo(50)).unwrap();
+
+        for task in [low, high, mid] {
+            assert_eq!(task.join(), Some(0));
+        }
+        assert_eq!(*ORDER.lock().unwrap(), ["low", "high", "mid"]);
+        assert!(!M.is_locked());
+    }
+
+    #[test]
+    fn pi_chain() {
+        let _lock = SERIAL.lock();
+        INIT.call_once(thread::init_scheduler);
+
+        use thread::{current, SchedPolicy, TaskState};
+        static M1: Mutex<()> = Mutex::new_pi(());
+        static M2: Mutex<()> = Mutex::new_pi(());
+
+        // the current task holds `M1`, `mid` holds `M2` and waits for `M1`
+        let guard1 = M1.lock();
+        let mid = thread::spawn_raw(
+            || {
+                let guard2 = M2.lock();
+                let guard1 = M1.lock();
+                assert_eq!(current().rt_prio(), 60);
+                drop(guard1);
+                // still inherits from `high` by `M2`
+                assert_eq!(current().rt_prio(), 60);
+                drop(guard2);
+                assert_eq!(current().rt_prio(), 0);
+            },
+            "mid".into(),
+            0x1000,
+        );
+        while mid.state() != TaskState::Blocked {
+            thread::yield_now();
+        }
+        assert_eq!(current().rt_prio(), 0);
+
+        // `high` waits for `M2`, its priority goes through `mid` to the
+        // current task
+        let high = thread::spawn_raw(|| drop(M2.lock()), "high".into(), 0x1000);
+        thread::set_sched_policy(&high, SchedPolicy::Fifo(60)).unwrap();
+        while high.state() != TaskState::Blocked {
+            thread::yield_now();
+        }
+        assert_eq!(mid.rt_prio(), 60);
+        assert_eq!(current().rt_prio(), 60);
+
+        drop(guard1);
+        assert_eq!(current().rt_prio(), 0);
+        assert_eq!(mid.rt_prio(), 60);
+        assert_eq!(high.join(), Some(0));
+        assert_eq!(mid.join(), Some(0));
+        assert!(!M1.is_locked() && !M2.is_locked());
+    }
+
+    #[test]
+    fn pi_normal_waiters() {
+        let _lock = SERIAL.lock();
+        INIT.call_once(thread::init_scheduler);
+
+        use thread::{current, SchedPolicy, TaskState};
+        static M: Mutex<()> = Mutex::new_pi(());
+        static ORDER: StdMutex<Vec<&str>> = StdMutex::new(Vec::new());
+
+        // the nice values of the normal waiters are not inherited
+        let guard = M.lock();
+        let waiter = |name: &'static str, nice: isize| {
+            let task = thread::spawn_raw(
+                move || {
+                    thread::set_priority(nice);
+                    let _guard = M.lock();
+                    ORDER.lock().unwrap().push(name);
+                },
+                name.into(),
+                0x1000,
+            );
+            while task.state() != TaskState::Blocked {
+                thread::yield_now();
+            }
+            task
+        };
+        let first = waiter("first", 19);
+        let second = waiter("second", -20);
+        assert_eq!(current().rt_prio(), 0);
+        assert_eq!(current().sched_policy(), SchedPolicy::Normal);
+
+        // they get the mutex in the order they wait for it
+        drop(guard);
+        for task in [first, second] {
+            assert_eq!(task.join(), Some(0));
+        }
+        assert_eq!(*ORDER.lock().unwrap(), ["first", "second"]);
+        assert!(!M.is_locked());
+    }
 }
@@ -0,0 +1,127 @@
+//! Priority inheritance of the mutexes.
+//!
+//! A task blocked by a priority-inheritance mutex lends its real-time
+//! priority to the owner. If the owner is blocked by another such mutex, the
+//! priority is passed on to the owner of that one, and so on.
+//!
+//! Only the real-time priorities are lent. The normal tasks have none, so a
+//! normal owner keeps its own nice value (the weight under CFS) whatever the
+//! nice values of the normal waiters are, and the normal waiters get the
+//! mutex in the order they wait for it.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use axtask::{AxTaskRef, CurrentTask};
+use kspin::{SpinNoIrq, SpinNoIrqGuard};
+
+/// The waiters and owners of all priority-inheritance mutexes. A global lock
+/// is used, as the chains of the blocked tasks may go across many mutexes.
+static PI_STATE: SpinNoIrq<PiState> = SpinNoIrq::new(PiState::new());
+
+/// A priority-inheritance mutex that some tasks are waiting for.
+struct PiLock {
+    owner: u64,
+    waiters: Vec<AxTaskRef>,
+}
+
+pub(crate) struct PiState {
+    /// The mutexes with waiters, indexed by their addresses.
+    locks: BTreeMap<usize, PiLock>,
+    /// The mutex each waiting task is blocked by, indexed by the task IDs.
+    blocked_on: BTreeMap<u64, usize>,
+}
+
+/// Locks the states of the priority inheritance.
+pub(crate) fn lock_state() -> SpinNoIrqGuard<'static, PiState> {
+    PI_STATE.lock()
+}
+
+impl PiState {
+    const fn new() -> Self {
+        Self {
+            locks: BTreeMap::new(),
+            blocked_on: BTreeMap::new(),
+        }
+    }
+
+    /// Adds the current task to the waiters of the mutex at `lock` owned by
+    /// the task `owner`, and lends its priority along the chain.
+    pub fn enqueue(&mut self, lock: usize, owner: u64, curr: &CurrentTask) {
+        self.locks
+            .entry(lock)
+            .or_insert_with(|| PiLock {
+                owner,
+                waiters: Vec::new(),
+            })
+            .waiters
+            .push(curr.as_task_ref().clone());
+        self.blocked_on.insert(curr.id().as_u64(), lock);
+        self.propagate(owner);
+    }
+
+    /// Hands the mutex at `lock` over from the current task to the waiter
+    /// with the highest priority (the earliest one if there are several), and
+    /// returns the waiter.
+    ///
+    /// The new owner inherits the priorities of the other waiters, while the
+    /// current task gives back the one it inherits from the waiters.
+    pub fn dequeue(&mut self, lock: usize, curr: &CurrentTask) -> Option<AxTaskRef> {
+        let entry = self.locks.get_mut(&lock)?;
+        let (index, _) = entry
+            .waiters
+            .iter()
+            .enumerate()
+            .rev() // the last maximum is the earliest one
+            .max_by_key(|(_, task)| task.rt_prio())?;
+        let next = entry.waiters.remove(index);
+        if entry.waiters.is_empty() {
+            self.locks.remove(&lock);
+        } else {
+            entry.owner = next.id().as_u64();
+        }
+        self.blocked_on.remove(&next.id().as_u64());
+
+        self.update_prio(&next);
+        self.update_prio(curr.as_task_ref());
+        Some(next)
+    }
+
+    /// Updates the priority the task inherits from the waiters of the
+    /// mutexes it holds, returns whether its priority is changed.
+    fn update_prio(&self, task: &AxTaskRef) -> bool {
+        let id = task.id().as_u64();
+        let prio = self
+            .locks
+            .values()
+            .filter(|lock| lock.owner == id)
+            .flat_map(|lock| lock.waiters.iter())
+            .map(|waiter| waiter.rt_prio())
+            .max()
+            .unwrap_or(0);
+        let old_prio = task.rt_prio();
+        axtask::set_inherited_prio(task, prio);
+        task.rt_prio() != old_prio
+    }
+
+    /// Updates the priorities along the chain from the task `owner`, until
+    /// one is not changed or not blocked by a mutex.
+    ///
+    /// It stops on a deadlock too, as the priorities in the cycle stop
+    /// rising at last.
+    fn propagate(&self, mut owner: u64) {
+        while let Some(task) = axtask::find_task(owner) {
+            if !self.update_prio(&task) {
+                break;
+            }
+            match self
+                .blocked_on
+                .get(&owner)
+                .and_then(|lock| self.locks.get(lock))
+            {
+                Some(lock) => owner = lock.owner,
+                None => break,
+            }
+        }
+    }
+}
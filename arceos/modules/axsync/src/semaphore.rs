@@ -0,0 +1,96 @@
+//! A naïve counting semaphore.
+
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use axtask::WaitQueue;
+
+/// A counting semaphore.
+///
+/// It holds a number of permits. [`Semaphore::acquire`] takes one, and the
+/// current task will block and be put into the wait queue if there is none
+/// left. [`Semaphore::release`] puts one back and wakes up a waiting task.
+pub struct Semaphore {
+    wq: WaitQueue,
+    count: AtomicUsize,
+}
+
+impl Semaphore {
+    /// Creates a new semaphore with the given number of permits.
+    pub const fn new(count: usize) -> Self {
+        Self {
+            wq: WaitQueue::new(),
+            count: AtomicUsize::new(count),
+        }
+    }
+
+    /// Returns the number of the permits left.
+    ///
+    /// Like [`Mutex::is_locked`](crate::Mutex::is_locked), the result may be
+    /// out of date the instant it is called.
+    pub fn available_permits(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Takes a permit, blocking the current task until there is one.
+    pub fn acquire(&self) {
+        while !self.try_acquire() {
+            self.wq
+                .wait_until(|| self.count.load(Ordering::Relaxed) > 0);
+        }
+    }
+
+    /// Try to take a permit, returns `true` if successful.
+    pub fn try_acquire(&self) -> bool {
+        self.count
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |count| {
+                count.checked_sub(1)
+            })
+            .is_ok()
+    }
+
+    /// Like [`Semaphore::acquire`], but gives up after the duration has
+    /// elapsed. Returns `true` if a permit is taken.
+    #[cfg(feature = "irq")]
+    pub fn acquire_timeout(&self, dur: core::time::Duration) -> bool {
+        let deadline = axhal::time::wall_time() + dur;
+        while !self.try_acquire() {
+            let now = axhal::time::wall_time();
+            if now >= deadline {
+                return false;
+            }
+            self.wq
+                .wait_timeout_until(deadline - now, || self.count.load(Ordering::Relaxed) > 0);
+        }
+        true
+    }
+
+    /// Puts a permit back, and wakes up a waiting task.
+    pub fn release(&self) {
+        self.count.fetch_add(1, Ordering::Release);
+        self.wq.notify_one(true);
+    }
+
+    /// Like [`Semaphore::release`], but does nothing and returns `false` if
+    /// there are already `max_permits` permits.
+    pub fn try_release(&self, max_permits: usize) -> bool {
+        let released = self
+            .count
+            .fetch_update(Ordering::Release, Ordering::Relaxed, |count| {
+                (count < max_permits).then_some(count + 1)
+            })
+            .is_ok();
+        if released {
+            self.wq.notify_one(true);
+        }
+        released
+    }
+}
+
+impl fmt::Debug for Semaphore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Semaphore")
+            .field("permits", &self.available_permits())
+            .finish()
+    }
+}
@@ -0,0 +1,194 @@
+use crate::mutex::tests::{may_interrupt, INIT, SERIAL};
+use crate::{Barrier, Condvar, Mutex, Once, OnceLock, RwLock, Semaphore};
+use axtask as thread;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[test]
+fn condvar() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(thread::init_scheduler);
+
+    const NUM_TASKS: usize = 5;
+    static TURN: Mutex<usize> = Mutex::new(0);
+    static CV: Condvar = Condvar::new();
+
+    // the tasks run in turn, in the reverse order of spawning
+    let tasks: Vec<_> = (0..NUM_TASKS)
+        .rev()
+        .map(|i| {
+            thread::spawn(move || {
+                let mut turn = CV.wait_while(TURN.lock(), |turn| *turn != i);
+                *turn += 1;
+                may_interrupt();
+                drop(turn);
+                CV.notify_all();
+            })
+        })
+        .collect();
+
+    let turn = CV.wait_while(TURN.lock(), |turn| *turn != NUM_TASKS);
+    assert_eq!(*turn, NUM_TASKS);
+    drop(turn);
+    for task in tasks {
+        assert_eq!(task.join(), Some(0));
+    }
+}
+
+#[test]
+fn rwlock() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(thread::init_scheduler);
+
+    const NUM_TASKS: usize = 5;
+    const NUM_ITERS: usize = 1000;
+    static RW: RwLock<(usize, usize)> = RwLock::new((0, 0));
+
+    let r1 = RW.read();
+    let r2 = RW.try_read().unwrap();
+    assert_eq!(RW.reader_count(), 2);
+    assert!(RW.try_write().is_none());
+    let writer = thread::spawn(|| RW.write().0 += 1);
+    thread::yield_now();
+    // blocked by the readers
+    assert_eq!(r1.0 + r2.0, 0);
+    drop(r1);
+    drop(r2);
+    assert_eq!(writer.join(), Some(0));
+    assert_eq!(RW.read().0, 1);
+
+    let tasks: Vec<_> = (0..NUM_TASKS)
+        .map(|_| {
+            thread::spawn(|| {
+                for _ in 0..NUM_ITERS {
+                    let mut val = RW.write();
+                    assert_eq!(RW.writer_count(), 1);
+                    val.0 += 1;
+                    may_interrupt();
+                    val.1 += 1;
+                    drop(val);
+
+                    let val = RW.read();
+                    // no half-updated values
+                    assert_eq!(val.0, val.1 + 1);
+                    may_interrupt();
+                }
+            })
+        })
+        .collect();
+    for task in tasks {
+        assert_eq!(task.join(), Some(0));
+    }
+    assert_eq!(
+        *RW.read(),
+        (NUM_TASKS * NUM_ITERS + 1, NUM_TASKS * NUM_ITERS)
+    );
+    assert_eq!(RW.reader_count() + RW.writer_count(), 0);
+}
+
+#[test]
+fn semaphore() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(thread::init_scheduler);
+
+    const NUM_TASKS: usize = 6;
+    static SEM: Semaphore = Semaphore::new(2);
+    static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+    static MAX_ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+    let tasks: Vec<_> = (0..NUM_TASKS)
+        .map(|_| {
+            thread::spawn(|| {
+                SEM.acquire();
+                let active = ACTIVE.fetch_add(1, Ordering::Relaxed) + 1;
+                MAX_ACTIVE.fetch_max(active, Ordering::Relaxed);
+                thread::yield_now();
+                ACTIVE.fetch_sub(1, Ordering::Relaxed);
+                SEM.release();
+            })
+        })
+        .collect();
+    for task in tasks {
+        assert_eq!(task.join(), Some(0));
+    }
+    assert_eq!(MAX_ACTIVE.load(Ordering::Relaxed), 2);
+    assert_eq!(SEM.available_permits(), 2);
+
+    assert!(SEM.try_acquire());
+    assert!(SEM.try_acquire());
+    assert!(!SEM.try_acquire());
+    SEM.release();
+    assert!(SEM.try_release(2));
+    assert!(!SEM.try_release(2));
+    assert_eq!(SEM.available_permits(), 2);
+}
+
+#[test]
+fn barrier() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(thread::init_scheduler);
+
+    const NUM_TASKS: usize = 5;
+    const NUM_ROUNDS: usize = 3;
+    static BARRIER: Barrier = Barrier::new(NUM_TASKS);
+    static ARRIVED: AtomicUsize = AtomicUsize::new(0);
+    static LEADERS: AtomicUsize = AtomicUsize::new(0);
+
+    let tasks: Vec<_> = (0..NUM_TASKS)
+        .map(|_| {
+            thread::spawn(|| {
+                for round in 1..=NUM_ROUNDS {
+                    may_interrupt();
+                    ARRIVED.fetch_add(1, Ordering::Relaxed);
+                    if BARRIER.wait().is_leader() {
+                        LEADERS.fetch_add(1, Ordering::Relaxed);
+                    }
+                    assert!(ARRIVED.load(Ordering::Relaxed) >= round * NUM_TASKS);
+                }
+            })
+        })
+        .collect();
+    for task in tasks {
+        assert_eq!(task.join(), Some(0));
+    }
+    assert_eq!(LEADERS.load(Ordering::Relaxed), NUM_ROUNDS);
+}
+
+#[test]
+fn once() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(thread::init_scheduler);
+
+    const NUM_TASKS: usize = 5;
+    static ONCE: Once = Once::new();
+    static CELL: OnceLock<usize> = OnceLock::new();
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    let tasks: Vec<_> = (0..NUM_TASKS)
+        .map(|i| {
+            thread::spawn(move || {
+                ONCE.call_once(|| {
+                    thread::yield_now();
+                    CALLS.fetch_add(1, Ordering::Relaxed);
+                });
+                assert!(ONCE.is_completed());
+                let val = CELL.get_or_init(|| {
+                    thread::yield_now();
+                    i
+                });
+                assert_eq!(CELL.get(), Some(val));
+            })
+        })
+        .collect();
+    for task in tasks {
+        assert_eq!(task.join(), Some(0));
+    }
+    assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+    assert_eq!(CELL.get(), Some(&0));
+    assert_eq!(CELL.set(1), Err(1));
+
+    let mut cell = OnceLock::new();
+    assert!(cell.get().is_none());
+    assert!(cell.set(String::from("once")).is_ok());
+    assert_eq!(cell.take().as_deref(), Some("once"));
+    assert!(cell.get_mut().is_none());
+}
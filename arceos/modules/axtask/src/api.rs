@@ -2,10 +2,15 @@
 
 use alloc::{string::String, sync::Arc};
 
-pub(crate) use crate::run_queue::{AxRunQueue, RUN_QUEUE};
+pub(crate) use crate::run_queue::current_run_queue;
 
 #[doc(cfg(feature = "multitask"))]
-pub use crate::task::{CurrentTask, TaskId, TaskInner};
+pub use crate::cpumask::CpuMask;
+#[doc(cfg(feature = "multitask"))]
+pub use crate::sched_class::{DeadlineParams, SchedPolicy, RT_PRIO_MAX, RT_PRIO_MIN};
+
+#[doc(cfg(feature = "multitask"))]
+pub use crate::task::{all_tasks, find_task, CurrentTask, TaskId, TaskInner, TaskState};
 #[doc(cfg(feature = "multitask"))]
 pub use crate::task_ext::{TaskExtMut, TaskExtRef};
 #[doc(cfg(feature = "multitask"))]
@@ -18,14 +23,14 @@ cfg_if::cfg_if! {
     if #[cfg(feature = "sched_rr")] {
         const MAX_TIME_SLICE: usize = 5;
         pub(crate) type AxTask = scheduler::RRTask<TaskInner, MAX_TIME_SLICE>;
-        pub(crate) type Scheduler = scheduler::RRScheduler<TaskInner, MAX_TIME_SLICE>;
+        pub(crate) type NormalScheduler = scheduler::RRScheduler<TaskInner, MAX_TIME_SLICE>;
     } else if #[cfg(feature = "sched_cfs")] {
         pub(crate) type AxTask = scheduler::CFSTask<TaskInner>;
-        pub(crate) type Scheduler = scheduler::CFScheduler<TaskInner>;
+        pub(crate) type NormalScheduler = scheduler::CFScheduler<TaskInner>;
     } else {
         // If no scheduler features are set, use FIFO as the default.
         pub(crate) type AxTask = scheduler::FifoTask<TaskInner>;
-        pub(crate) type Scheduler = scheduler::FifoScheduler<TaskInner>;
+        pub(crate) type NormalScheduler = scheduler::FifoScheduler<TaskInner>;
     }
 }
 
@@ -63,6 +68,17 @@ pub fn current() -> CurrentTask {
     CurrentTask::get()
 }
 
+/// Returns the credentials of the current task, or the superuser's if the
+/// current task is not initialized.
+pub fn current_cred() -> crate::Cred {
+    current_may_uninit().map_or(crate::Cred::ROOT, |curr| curr.cred())
+}
+
+/// Sets the credentials of the current task.
+pub fn set_current_cred(cred: crate::Cred) {
+    current().set_cred(cred)
+}
+
 /// Initializes the task scheduler (for the primary CPU).
 pub fn init_scheduler() {
     info!("Initialize scheduling...");
@@ -71,7 +87,7 @@ pub fn init_scheduler() {
     #[cfg(feature = "irq")]
     crate::timers::init();
 
-    info!("  use {} scheduler.", Scheduler::scheduler_name());
+    info!("  use {} scheduler.", NormalScheduler::scheduler_name());
 }
 
 /// Initializes the task scheduler for secondary CPUs.
@@ -86,13 +102,13 @@ pub fn init_scheduler_secondary() {
 #[doc(cfg(feature = "irq"))]
 pub fn on_timer_tick() {
     crate::timers::check_events();
-    RUN_QUEUE.lock().scheduler_timer_tick();
+    current_run_queue().scheduler_timer_tick();
 }
 
 /// Adds the given task to the run queue, returns the task reference.
 pub fn spawn_task(task: TaskInner) -> AxTaskRef {
     let task_ref = task.into_arc();
-    RUN_QUEUE.lock().add_task(task_ref.clone());
+    crate::run_queue::add_task(task_ref.clone());
     task_ref
 }
 
@@ -123,19 +139,53 @@ where
 ///
 /// The range of the priority is dependent on the underlying scheduler. For
 /// example, in the [CFS] scheduler, the priority is the nice value, ranging from
-/// -20 to 19.
+/// -20 to 19. For a real-time task (see [`SchedPolicy`]), it's the real-time
+/// priority.
 ///
 /// Returns `true` if the priority is set successfully.
 ///
 /// [CFS]: https://en.wikipedia.org/wiki/Completely_Fair_Scheduler
 pub fn set_priority(prio: isize) -> bool {
-    RUN_QUEUE.lock().set_current_priority(prio)
+    current_run_queue().set_current_priority(prio)
+}
+
+/// Sets the scheduling policy of the task.
+///
+/// The real-time priority must be in the range of [`RT_PRIO_MIN`] to
+/// [`RT_PRIO_MAX`]. For a deadline task, it fails with `ResourceBusy` if the
+/// bandwidth (`runtime / period`) of all deadline tasks exceeds 95% of the
+/// CPUs, or `Unsupported` without the `sched_edf` feature.
+pub fn set_sched_policy(task: &AxTaskRef, policy: SchedPolicy) -> axerrno::AxResult {
+    crate::run_queue::set_sched_policy(task, policy)
+}
+
+/// Sets the real-time priority the task inherits from the tasks blocked by
+/// it, e.g., waiting for a lock it holds. Sets it to 0 to restore.
+///
+/// The task runs with the higher one of its own priority and the inherited
+/// one (see [`TaskInner::rt_prio`]), and a normal task runs as a
+/// [`SchedPolicy::Fifo`] task while it inherits a real-time priority. It
+/// does not affect the deadline tasks.
+///
+/// It's used by the priority-inheritance mutexes to avoid the priority
+/// inversion. If it lowers the priority of the current task, the task is
+/// rescheduled when the preemption is enabled again.
+pub fn set_inherited_prio(task: &AxTaskRef, prio: u8) {
+    crate::run_queue::set_inherited_prio(task, prio)
+}
+
+/// Sets the CPUs the current task is allowed to run on.
+///
+/// Returns `false` if none of the CPUs is in the system. See
+/// [`TaskInner::set_affinity`] for details.
+pub fn set_current_affinity(cpus: CpuMask) -> bool {
+    current().set_affinity(cpus)
 }
 
 /// Current task gives up the CPU time voluntarily, and switches to another
 /// ready task.
 pub fn yield_now() {
-    RUN_QUEUE.lock().yield_current();
+    current_run_queue().yield_current();
 }
 
 /// Current task is going to sleep for the given duration.
@@ -150,14 +200,16 @@ pub fn sleep(dur: core::time::Duration) {
 /// If the feature `irq` is not enabled, it uses busy-wait instead.
 pub fn sleep_until(deadline: axhal::time::TimeValue) {
     #[cfg(feature = "irq")]
-    RUN_QUEUE.lock().sleep_until(deadline);
+    current_run_queue().sleep_until(deadline);
     #[cfg(not(feature = "irq"))]
     axhal::time::busy_wait_until(deadline);
 }
 
 /// Exits the current task.
 pub fn exit(exit_code: i32) -> ! {
-    RUN_QUEUE.lock().exit_current(exit_code)
+    #[cfg(feature = "sched_edf")]
+    crate::sched_class::release(&current().sched_policy());
+    current_run_queue().exit_current(exit_code)
 }
 
 /// The idle task routine.
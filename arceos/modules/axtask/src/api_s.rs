@@ -1,5 +1,29 @@
 //! Task APIs for single-task configuration.
 
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::Cred;
+
+static UID: AtomicU32 = AtomicU32::new(Cred::ROOT.uid);
+static GID: AtomicU32 = AtomicU32::new(Cred::ROOT.gid);
+static UMASK: AtomicU32 = AtomicU32::new(Cred::ROOT.umask);
+
+/// Returns the credentials of the only task.
+pub fn current_cred() -> Cred {
+    Cred {
+        uid: UID.load(Ordering::Relaxed),
+        gid: GID.load(Ordering::Relaxed),
+        umask: UMASK.load(Ordering::Relaxed),
+    }
+}
+
+/// Sets the credentials of the only task.
+pub fn set_current_cred(cred: Cred) {
+    UID.store(cred.uid, Ordering::Relaxed);
+    GID.store(cred.gid, Ordering::Relaxed);
+    UMASK.store(cred.umask, Ordering::Relaxed);
+}
+
 /// For single-task situation, we just relax the CPU and wait for incoming
 /// interrupts.
 pub fn yield_now() {
@@ -0,0 +1,77 @@
+use core::fmt;
+
+/// A set of CPUs, e.g., the CPUs a task is allowed to run on.
+///
+/// The CPU `i` is represented by the bit `i`, so at most [`usize::BITS`]
+/// CPUs are supported.
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub struct CpuMask(usize);
+
+impl CpuMask {
+    /// Creates a set from the bits of the CPUs in it.
+    pub const fn from_bits(bits: usize) -> Self {
+        Self(bits)
+    }
+
+    /// Creates an empty set.
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Creates a set with all the CPUs of the system, i.e., the first
+    /// [`axconfig::SMP`] CPUs.
+    pub const fn full() -> Self {
+        if axconfig::SMP >= usize::BITS as usize {
+            Self(usize::MAX)
+        } else {
+            Self((1 << axconfig::SMP) - 1)
+        }
+    }
+
+    /// Creates a set with only the CPU `cpu_id`.
+    pub const fn one(cpu_id: usize) -> Self {
+        Self(1 << cpu_id)
+    }
+
+    /// Returns the bits of the CPUs in the set.
+    pub const fn bits(&self) -> usize {
+        self.0
+    }
+
+    /// Returns whether the set is empty.
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns whether the CPU `cpu_id` is in the set.
+    pub const fn contains(&self, cpu_id: usize) -> bool {
+        cpu_id < usize::BITS as usize && self.0 & (1 << cpu_id) != 0
+    }
+
+    /// Adds the CPU `cpu_id` to the set.
+    pub fn insert(&mut self, cpu_id: usize) {
+        self.0 |= 1 << cpu_id;
+    }
+
+    /// Removes the CPU `cpu_id` from the set.
+    pub fn remove(&mut self, cpu_id: usize) {
+        self.0 &= !(1 << cpu_id);
+    }
+
+    /// Returns the CPUs in both sets.
+    pub const fn intersection(&self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+
+    /// Returns the IDs of the CPUs in the set in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> {
+        let bits = self.0;
+        (0..usize::BITS as usize).filter(move |i| bits & (1 << i) != 0)
+    }
+}
+
+impl fmt::Debug for CpuMask {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
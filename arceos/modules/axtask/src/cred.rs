@@ -0,0 +1,29 @@
+//! Credentials of the tasks, which are checked in accessing files.
+
+/// The user and group IDs of a task, and its file mode creation mask.
+///
+/// A new task inherits the credentials of the task that creates it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cred {
+    /// The user ID.
+    pub uid: u32,
+    /// The group ID.
+    pub gid: u32,
+    /// The permission bits cleared from the mode of the newly created files.
+    pub umask: u32,
+}
+
+impl Cred {
+    /// The credentials of the superuser, which the first task has.
+    pub const ROOT: Self = Self {
+        uid: 0,
+        gid: 0,
+        umask: 0o022,
+    };
+
+    /// Returns whether it is the superuser, who bypasses the permission
+    /// checks.
+    pub const fn is_root(&self) -> bool {
+        self.uid == 0
+    }
+}
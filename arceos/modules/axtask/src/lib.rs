@@ -4,6 +4,11 @@
 //! creation, scheduling, sleeping, termination, etc. The scheduler algorithm
 //! is configurable by cargo features.
 //!
+//! With multi-task support, each CPU has its own run queue. A woken up or
+//! newly spawned task is added to the least loaded CPU it is allowed to run
+//! on (see [`TaskInner::set_affinity`]), and the idle CPUs steal ready tasks
+//! from the busy ones.
+//!
 //! # Cargo Features
 //!
 //! - `multitask`: Enable multi-task support. If it's enabled, complex task
@@ -20,6 +25,15 @@
 //!   the `multitask` and `preempt` features if it is enabled.
 //! - `sched_cfs`: Use the [Completely Fair Scheduler][3]. It also enables the
 //!   the `multitask` and `preempt` features if it is enabled.
+//! - `sched_edf`: Enable the earliest deadline first scheduling for the tasks
+//!   with the [`SchedPolicy::Deadline`] policy. It also enables the
+//!   `multitask` and `preempt` features if it is enabled.
+//!
+//! The schedulers above are used for the normal tasks. The real-time tasks
+//! ([`SchedPolicy::Fifo`] and [`SchedPolicy::RoundRobin`]) always run before
+//! them, and the deadline tasks run before the real-time ones, see
+//! [`set_sched_policy`]. A task may also inherit a real-time priority from
+//! the tasks blocked by it, see [`set_inherited_prio`].
 //!
 //! [1]: scheduler::FifoScheduler
 //! [2]: scheduler::RRScheduler
@@ -36,13 +50,19 @@
 #[cfg(test)]
 mod tests;
 
+mod cred;
+
+pub use self::cred::Cred;
+
 cfg_if::cfg_if! {
     if #[cfg(feature = "multitask")] {
         #[macro_use]
         extern crate log;
         extern crate alloc;
 
+        mod cpumask;
         mod run_queue;
+        mod sched_class;
         mod task;
         mod task_ext;
         mod api;
@@ -53,9 +73,9 @@ cfg_if::cfg_if! {
 
         #[doc(cfg(feature = "multitask"))]
         pub use self::api::*;
-        pub use self::api::{sleep, sleep_until, yield_now};
+        pub use self::api::{current_cred, set_current_cred, sleep, sleep_until, yield_now};
     } else {
         mod api_s;
-        pub use self::api_s::{sleep, sleep_until, yield_now};
+        pub use self::api_s::{current_cred, set_current_cred, sleep, sleep_until, yield_now};
     }
 }
@@ -1,14 +1,30 @@
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
-use kspin::SpinNoIrq;
+use core::ops::{Deref, DerefMut};
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use axerrno::AxResult;
+use kernel_guard::NoPreemptIrqSave;
+use kspin::{SpinNoIrq, SpinRaw};
 use lazyinit::LazyInit;
-use scheduler::BaseScheduler;
 
+use crate::sched_class::{ClassScheduler, SchedEntity, SchedPolicy};
 use crate::task::{CurrentTask, TaskState};
-use crate::{AxTaskRef, Scheduler, TaskInner, WaitQueue};
+use crate::{AxTask, AxTaskRef, TaskInner, WaitQueue};
 
-// TODO: per-CPU
-pub(crate) static RUN_QUEUE: LazyInit<SpinNoIrq<AxRunQueue>> = LazyInit::new();
+/// The run queue of each CPU, a CPU only runs the tasks in its own one.
+static RUN_QUEUES: [LazyInit<SpinRaw<AxRunQueue>>; axconfig::SMP] =
+    [const { LazyInit::new() }; axconfig::SMP];
+
+/// The tasks to be added to the run queue of each CPU, e.g., woken up or
+/// migrated by other CPUs. They are moved into the run queue when the CPU
+/// locks it next time.
+static PENDING_TASKS: [SpinNoIrq<VecDeque<AxTaskRef>>; axconfig::SMP] =
+    [const { SpinNoIrq::new(VecDeque::new()) }; axconfig::SMP];
+
+/// The number of ready tasks of each CPU, including the pending ones.
+static NR_READY: [AtomicUsize; axconfig::SMP] = [const { AtomicUsize::new(0) }; axconfig::SMP];
 
 // TODO: per-CPU
 static EXITED_TASKS: SpinNoIrq<VecDeque<AxTaskRef>> = SpinNoIrq::new(VecDeque::new());
@@ -18,22 +34,277 @@ static WAIT_FOR_EXIT: WaitQueue = WaitQueue::new();
 #[percpu::def_percpu]
 static IDLE_TASK: LazyInit<AxTaskRef> = LazyInit::new();
 
+/// The task being switched out on each CPU, which is released by the next
+/// task after the switch completes.
+#[percpu::def_percpu]
+static PREV_TASK: usize = 0;
+
+/// The number of timer ticks between two load balancing of a CPU.
+#[cfg(feature = "irq")]
+const BALANCE_INTERVAL: usize = 10;
+
 pub(crate) struct AxRunQueue {
-    scheduler: Scheduler,
+    cpu_id: usize,
+    scheduler: ClassScheduler,
+    #[cfg(feature = "irq")]
+    ticks: usize,
+}
+
+/// The locked run queue of the current CPU, returned by
+/// [`current_run_queue`].
+///
+/// The lock is held across the context switches, and released by the task
+/// switched to. As the task may resume on another CPU, the run queue of the
+/// CPU it's running on is unlocked when it's dropped.
+pub(crate) struct CurrentRunQueueRef {
+    rq: NonNull<AxRunQueue>,
+    _guard: NoPreemptIrqSave,
+}
+
+/// Locks the run queue of the current CPU, and moves the pending tasks into
+/// it.
+pub(crate) fn current_run_queue() -> CurrentRunQueueRef {
+    // IRQs and preemption are disabled first, so that the task cannot be
+    // migrated before locking the run queue.
+    let guard = NoPreemptIrqSave::new();
+    let mut locked = RUN_QUEUES[axhal::cpu::this_cpu_id()].lock();
+    let mut rq = CurrentRunQueueRef {
+        rq: NonNull::from(&mut *locked),
+        _guard: guard,
+    };
+    core::mem::forget(locked);
+    rq.take_pending_tasks();
+    rq
+}
+
+impl Deref for CurrentRunQueueRef {
+    type Target = AxRunQueue;
+    fn deref(&self) -> &Self::Target {
+        unsafe { self.rq.as_ref() }
+    }
+}
+
+impl DerefMut for CurrentRunQueueRef {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { self.rq.as_mut() }
+    }
+}
+
+impl Drop for CurrentRunQueueRef {
+    fn drop(&mut self) {
+        unsafe { force_unlock_current() };
+    }
+}
+
+/// Unlocks the run queue of the current CPU, which is locked by the task
+/// switched out.
+///
+/// # Safety
+///
+/// The run queue must be locked by the current task, or by the task switched
+/// out to start the current one.
+pub(crate) unsafe fn force_unlock_current() {
+    RUN_QUEUES[axhal::cpu::this_cpu_id()].force_unlock();
+}
+
+/// Releases the task switched out, after the switch to the current task
+/// completes on this CPU.
+///
+/// # Safety
+///
+/// It must be called once after each context switch, by the task switched to.
+pub(crate) unsafe fn finish_task_switch() {
+    let prev = AxTaskRef::from_raw(PREV_TASK.read_current_raw() as *const AxTask);
+    prev.set_on_cpu(false);
+}
+
+/// Selects the CPU to run the task on: the least loaded one among the CPUs it
+/// is allowed to run on, preferring the one it ran on last.
+fn select_cpu(task: &AxTaskRef) -> usize {
+    let prev = task.cpu_id();
+    let allowed = task.cpumask().intersection(crate::CpuMask::full());
+    let mut best: Option<(usize, usize)> = None;
+    for cpu in allowed.iter().filter(|&cpu| RUN_QUEUES[cpu].is_inited()) {
+        let load = NR_READY[cpu].load(Ordering::Relaxed);
+        match best {
+            Some((_, min)) if load > min || (load == min && cpu != prev) => {}
+            _ => best = Some((cpu, load)),
+        }
+    }
+    // The CPUs allowed may have not been started yet.
+    best.map(|(cpu, _)| cpu)
+        .or_else(|| allowed.iter().next())
+        .unwrap_or(prev)
+}
+
+/// Adds a ready task to the pending tasks of the CPU selected for it, returns
+/// the CPU ID.
+fn enqueue_task(task: AxTaskRef) -> usize {
+    let cpu = select_cpu(&task);
+    task.set_cpu_id(cpu);
+    NR_READY[cpu].fetch_add(1, Ordering::Relaxed);
+    PENDING_TASKS[cpu].lock().push_back(task);
+    cpu
+}
+
+/// Adds a newly spawned task to the run queue of the CPU selected for it.
+pub(crate) fn add_task(task: AxTaskRef) {
+    assert!(task.is_ready());
+    let cpu = enqueue_task(task.clone());
+    debug!("task spawn: {} on CPU {}", task.id_name(), cpu);
+}
+
+/// Wakes up the blocked task, and adds it to the run queue of the CPU
+/// selected for it.
+///
+/// If `resched` is true and it's added to the current CPU, the current task
+/// will be preempted when the preemption is enabled.
+pub(crate) fn unblock_task(task: AxTaskRef, resched: bool) {
+    // A task may be woken up by several events (timer or `notify()`), only
+    // the first one takes effect.
+    if task.transition_state(TaskState::Blocked, TaskState::Ready) {
+        debug!("task unblock: {}", task.id_name());
+        let cpu = enqueue_task(task);
+        if resched && cpu == axhal::cpu::this_cpu_id() {
+            #[cfg(feature = "preempt")]
+            crate::current().set_preempt_pending(true);
+        }
+    }
+}
+
+/// Changes the scheduling policy of the task, and moves it to the queue of
+/// the new class if it's ready.
+pub(crate) fn set_sched_policy(task: &AxTaskRef, policy: SchedPolicy) -> AxResult {
+    crate::sched_class::admit(&task.sched_policy(), &policy)?;
+    update_sched_entity(task, |entity| entity.set_policy(policy));
+
+    // A task on another CPU is preempted at the next timer tick if needed.
+    if crate::current().id() == task.id() {
+        current_run_queue().check_preempt_current();
+    }
+    Ok(())
+}
+
+/// Changes the real-time priority the task inherits, and moves it to the
+/// queue of the new class if it's ready.
+///
+/// It may be called with spinlocks held, so the current task is not
+/// rescheduled at once, but when the preemption is enabled again.
+pub(crate) fn set_inherited_prio(task: &AxTaskRef, prio: u8) {
+    if task.sched_entity().inherited_prio() == prio {
+        return;
+    }
+    update_sched_entity(task, |entity| entity.set_inherited_prio(prio));
+
+    #[cfg(feature = "preempt")]
+    if crate::current().id() == task.id() {
+        let rq = current_run_queue();
+        if rq.scheduler.should_preempt(task) {
+            crate::current().set_preempt_pending(true);
+        }
+    }
+}
+
+/// Updates the scheduling states of the task with the run queue it's in
+/// locked, as the class of the task may be changed.
+fn update_sched_entity(task: &AxTaskRef, f: impl FnOnce(&SchedEntity)) {
+    let _guard = NoPreemptIrqSave::new();
+    let mut rq = loop {
+        let cpu = task.cpu_id();
+        if !RUN_QUEUES[cpu].is_inited() {
+            break None; // pending on a CPU not started yet
+        }
+        // No run queue is locked, so it's safe to wait for the lock.
+        let rq = RUN_QUEUES[cpu].lock();
+        if task.cpu_id() == cpu {
+            break Some(rq);
+        }
+        // it has been migrated
+    };
+    let queued = rq.as_mut().and_then(|rq| rq.scheduler.remove_task(task));
+    f(task.sched_entity());
+    if let Some(task) = queued {
+        rq.unwrap().scheduler.add_task(task);
+    }
 }
 
 impl AxRunQueue {
-    pub fn new() -> SpinNoIrq<Self> {
-        let gc_task = TaskInner::new(gc_entry, "gc".into(), axconfig::TASK_STACK_SIZE).into_arc();
-        let mut scheduler = Scheduler::new();
-        scheduler.add_task(gc_task);
-        SpinNoIrq::new(Self { scheduler })
+    fn new(cpu_id: usize) -> SpinRaw<Self> {
+        SpinRaw::new(Self {
+            cpu_id,
+            scheduler: ClassScheduler::new(),
+            #[cfg(feature = "irq")]
+            ticks: 0,
+        })
+    }
+
+    /// Moves the pending tasks of this CPU into the scheduler.
+    fn take_pending_tasks(&mut self) {
+        let mut added = false;
+        while let Some(task) = PENDING_TASKS[self.cpu_id].lock().pop_front() {
+            // It may be still switching out on another CPU.
+            while task.on_cpu() {
+                core::hint::spin_loop();
+            }
+            self.scheduler.add_task(task);
+            added = true;
+        }
+
+        // e.g., a real-time task is woken up
+        #[cfg(feature = "preempt")]
+        if added {
+            let curr = crate::current();
+            if !curr.is_idle() && self.scheduler.should_preempt(curr.as_task_ref()) {
+                curr.set_preempt_pending(true);
+            }
+        }
+        #[cfg(not(feature = "preempt"))]
+        let _ = added;
+    }
+
+    /// Picks the next task allowed to run on this CPU, the others are moved
+    /// to the CPUs they are allowed to run on.
+    fn pick_next_task(&mut self) -> Option<AxTaskRef> {
+        while let Some(task) = self.scheduler.pick_next_task() {
+            NR_READY[self.cpu_id].fetch_sub(1, Ordering::Relaxed);
+            if task.cpumask().contains(self.cpu_id) {
+                return Some(task);
+            }
+            enqueue_task(task);
+        }
+        None
     }
 
-    pub fn add_task(&mut self, task: AxTaskRef) {
-        debug!("task spawn: {}", task.id_name());
-        assert!(task.is_ready());
-        self.scheduler.add_task(task);
+    /// Steals a ready task from the busiest CPU, if it has at least
+    /// `imbalance` more ready tasks than this CPU.
+    ///
+    /// The stolen task is not counted as a ready task of this CPU until it's
+    /// added to the scheduler.
+    fn steal_task(&mut self, imbalance: usize) -> Option<AxTaskRef> {
+        let load = NR_READY[self.cpu_id].load(Ordering::Relaxed);
+        let (victim, victim_load) = (0..axconfig::SMP)
+            .filter(|&cpu| cpu != self.cpu_id && RUN_QUEUES[cpu].is_inited())
+            .map(|cpu| (cpu, NR_READY[cpu].load(Ordering::Relaxed)))
+            .max_by_key(|&(_, load)| load)?;
+        if victim_load < load + imbalance {
+            return None;
+        }
+        // Do not wait for the busy run queue.
+        let mut rq = RUN_QUEUES[victim].try_lock()?;
+        let task = rq
+            .scheduler
+            .steal_task(|task| task.cpumask().contains(self.cpu_id))?;
+        NR_READY[victim].fetch_sub(1, Ordering::Relaxed);
+        drop(rq);
+
+        debug!(
+            "task steal: {} from CPU {} to CPU {}",
+            task.id_name(),
+            victim,
+            self.cpu_id
+        );
+        task.set_cpu_id(self.cpu_id);
+        Some(task)
     }
 
     #[cfg(feature = "irq")]
@@ -43,6 +314,15 @@ impl AxRunQueue {
             #[cfg(feature = "preempt")]
             curr.set_preempt_pending(true);
         }
+
+        self.ticks += 1;
+        if self.ticks % BALANCE_INTERVAL == 0 {
+            // Pull a task from a CPU with more tasks waiting than this one.
+            if let Some(task) = self.steal_task(2) {
+                self.scheduler.add_task(task);
+                NR_READY[self.cpu_id].fetch_add(1, Ordering::Relaxed);
+            }
+        }
     }
 
     pub fn yield_current(&mut self) {
@@ -57,16 +337,37 @@ impl AxRunQueue {
             .set_priority(crate::current().as_task_ref(), prio)
     }
 
+    /// Reschedules if a ready task should run before the current task, e.g.,
+    /// its scheduling policy has been changed.
+    pub fn check_preempt_current(&mut self) {
+        let curr = crate::current();
+        if !curr.is_idle() && self.scheduler.should_preempt(curr.as_task_ref()) {
+            #[cfg(feature = "preempt")]
+            self.preempt_resched();
+            #[cfg(not(feature = "preempt"))]
+            self.resched(true);
+        }
+    }
+
+    /// Moves the current task to another CPU if it is not allowed to run on
+    /// this one any more.
+    pub fn migrate_current(&mut self) {
+        let curr = crate::current();
+        if !curr.cpumask().contains(self.cpu_id) {
+            debug!("task migrate: {} from CPU {}", curr.id_name(), self.cpu_id);
+            self.resched(false);
+        }
+    }
+
     #[cfg(feature = "preempt")]
     pub fn preempt_resched(&mut self) {
         let curr = crate::current();
         assert!(curr.is_running());
 
         // When we get the mutable reference of the run queue, we must
-        // have held the `SpinNoIrq` lock with both IRQs and preemption
-        // disabled. So we need to set `current_disable_count` to 1 in
-        // `can_preempt()` to obtain the preemption permission before
-        //  locking the run queue.
+        // have held the lock with both IRQs and preemption disabled. So we
+        // need to set `current_disable_count` to 1 in `can_preempt()` to
+        // obtain the preemption permission before locking the run queue.
         let can_preempt = curr.can_preempt(1);
 
         debug!(
@@ -91,9 +392,9 @@ impl AxRunQueue {
             axhal::misc::terminate();
         } else {
             curr.set_state(TaskState::Exited);
-            curr.notify_exit(exit_code, self);
+            curr.notify_exit(exit_code);
             EXITED_TASKS.lock().push_back(curr.clone());
-            WAIT_FOR_EXIT.notify_one_locked(false, self);
+            WAIT_FOR_EXIT.notify_one(false);
             self.resched(false);
         }
         unreachable!("task exited!");
@@ -117,18 +418,6 @@ impl AxRunQueue {
         self.resched(false);
     }
 
-    pub fn unblock_task(&mut self, task: AxTaskRef, resched: bool) {
-        debug!("task unblock: {}", task.id_name());
-        if task.is_blocked() {
-            task.set_state(TaskState::Ready);
-            self.scheduler.add_task(task); // TODO: priority
-            if resched {
-                #[cfg(feature = "preempt")]
-                crate::current().set_preempt_pending(true);
-            }
-        }
-    }
-
     #[cfg(feature = "irq")]
     pub fn sleep_until(&mut self, deadline: axhal::time::TimeValue) {
         let curr = crate::current();
@@ -138,8 +427,10 @@ impl AxRunQueue {
 
         let now = axhal::time::wall_time();
         if now < deadline {
-            crate::timers::set_alarm_wakeup(deadline, curr.clone());
+            // The alarm may go off on another CPU at once, so the task must
+            // be blocked before.
             curr.set_state(TaskState::Blocked);
+            crate::timers::set_alarm_wakeup(deadline, curr.clone());
             self.resched(false);
         }
     }
@@ -152,14 +443,24 @@ impl AxRunQueue {
         let prev = crate::current();
         if prev.is_running() {
             prev.set_state(TaskState::Ready);
-            if !prev.is_idle() {
+            if prev.is_idle() {
+                // the idle task is not in the run queue
+            } else if prev.cpumask().contains(self.cpu_id) {
                 self.scheduler.put_prev_task(prev.clone(), preempt);
+                NR_READY[self.cpu_id].fetch_add(1, Ordering::Relaxed);
+            } else {
+                // The affinity has been changed, it's picked up by another
+                // CPU after it's switched out.
+                enqueue_task(prev.clone());
             }
         }
-        let next = self.scheduler.pick_next_task().unwrap_or_else(|| unsafe {
-            // Safety: IRQs must be disabled at this time.
-            IDLE_TASK.current_ref_raw().get_unchecked().clone()
-        });
+        let next = self
+            .pick_next_task()
+            .or_else(|| self.steal_task(1))
+            .unwrap_or_else(|| unsafe {
+                // Safety: IRQs must be disabled at this time.
+                IDLE_TASK.current_ref_raw().get_unchecked().clone()
+            });
         self.switch_to(prev, next);
     }
 
@@ -185,8 +486,14 @@ impl AxRunQueue {
             assert!(Arc::strong_count(prev_task.as_task_ref()) > 1);
             assert!(Arc::strong_count(&next_task) >= 1);
 
+            // Other CPUs must not run `prev_task` until it's switched out,
+            // see `finish_task_switch()`.
+            next_task.set_on_cpu(true);
+            PREV_TASK.write_current_raw(Arc::into_raw(prev_task.clone()) as usize);
+
             CurrentTask::set_current(prev_task, next_task);
             (*prev_ctx_ptr).switch_to(&*next_ctx_ptr);
+            finish_task_switch();
         }
     }
 }
@@ -214,6 +521,8 @@ fn gc_entry() {
 }
 
 pub(crate) fn init() {
+    let cpu_id = axhal::cpu::this_cpu_id();
+
     // Create the `idle` task (not current task).
     const IDLE_TASK_STACK_SIZE: usize = 4096;
     let idle_task = TaskInner::new(|| crate::run_idle(), "idle".into(), IDLE_TASK_STACK_SIZE);
@@ -226,10 +535,41 @@ pub(crate) fn init() {
     main_task.set_state(TaskState::Running);
     unsafe { CurrentTask::init_current(main_task) };
 
-    RUN_QUEUE.init_once(AxRunQueue::new());
+    RUN_QUEUES[cpu_id].init_once(AxRunQueue::new(cpu_id));
+    let gc_task = TaskInner::new(gc_entry, "gc".into(), axconfig::TASK_STACK_SIZE).into_arc();
+    add_task(gc_task);
+}
+
+/// Starts the run queue of a CPU where no task ever runs, so the ready tasks
+/// on it can only be stolen by the other CPUs (for the tests on the host,
+/// where only one CPU runs).
+#[cfg(test)]
+pub(crate) fn init_parked_cpu(cpu_id: usize) {
+    RUN_QUEUES[cpu_id].init_once(AxRunQueue::new(cpu_id));
+}
+
+/// Moves the pending tasks of a parked CPU into its scheduler, as it does
+/// when it reschedules.
+#[cfg(test)]
+pub(crate) fn flush_parked_cpu(cpu_id: usize) {
+    RUN_QUEUES[cpu_id].lock().take_pending_tasks();
+}
+
+/// Returns the number of ready tasks of the CPU.
+#[cfg(test)]
+pub(crate) fn nr_ready(cpu_id: usize) -> usize {
+    NR_READY[cpu_id].load(Ordering::Relaxed)
+}
+
+/// Makes a parked CPU look fully loaded, so no more task is put on it.
+#[cfg(test)]
+pub(crate) fn hide_parked_cpu(cpu_id: usize) {
+    NR_READY[cpu_id].fetch_add(usize::MAX / 2, Ordering::Relaxed);
 }
 
 pub(crate) fn init_secondary() {
+    let cpu_id = axhal::cpu::this_cpu_id();
+
     // Put the subsequent execution into the `idle` task.
     let idle_task = TaskInner::new_init("idle".into()).into_arc();
     idle_task.set_state(TaskState::Running);
@@ -237,4 +577,6 @@ pub(crate) fn init_secondary() {
         i.init_once(idle_task.clone());
     });
     unsafe { CurrentTask::init_current(idle_task) }
+
+    RUN_QUEUES[cpu_id].init_once(AxRunQueue::new(cpu_id));
 }
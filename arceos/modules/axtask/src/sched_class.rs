@@ -0,0 +1,556 @@
+//! Scheduling classes of the tasks.
+//!
+//! The ready tasks are picked by class: first the deadline tasks in order of
+//! their deadlines (with the `sched_edf` feature), then the real-time tasks in
+//! order of their priorities, and at last the normal tasks by the scheduler
+//! selected by the cargo features.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+#[cfg(all(test, feature = "sched_edf"))]
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use axerrno::{ax_err, AxResult};
+use kspin::SpinNoIrq;
+use scheduler::BaseScheduler;
+
+use crate::{AxTaskRef, NormalScheduler};
+
+/// The lowest priority of the real-time tasks.
+pub const RT_PRIO_MIN: u8 = 1;
+/// The highest priority of the real-time tasks.
+pub const RT_PRIO_MAX: u8 = 99;
+
+/// The time slice of the round-robin real-time tasks, in timer ticks.
+const RR_TIME_SLICE: usize = 10;
+
+/// The bandwidth of a CPU, i.e., all of its time.
+#[cfg(feature = "sched_edf")]
+const BW_UNIT: u64 = 1 << 20;
+
+/// The bandwidth can be reserved by the deadline tasks, 95% of each CPU.
+#[cfg(feature = "sched_edf")]
+const DL_BW_LIMIT: u64 = BW_UNIT * 95 / 100 * axconfig::SMP as u64;
+
+/// The bandwidth reserved by the deadline tasks.
+#[cfg(feature = "sched_edf")]
+static DL_BANDWIDTH: SpinNoIrq<u64> = SpinNoIrq::new(0);
+
+/// The time added to the clock of the deadline tasks in the tests, as the
+/// clock does not advance on the host.
+#[cfg(all(test, feature = "sched_edf"))]
+pub(crate) static TIME_OFFSET: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the current time of the deadline tasks, in nanoseconds.
+#[cfg(feature = "sched_edf")]
+fn now() -> u64 {
+    #[cfg(test)]
+    let offset = TIME_OFFSET.load(Ordering::Relaxed);
+    #[cfg(not(test))]
+    let offset = 0;
+    axhal::time::monotonic_time_nanos() + offset
+}
+
+/// The scheduling policy of a task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchedPolicy {
+    /// Scheduled by the scheduler selected by the cargo features, when no
+    /// real-time or deadline task is ready.
+    Normal,
+    /// Real-time with a fixed priority from [`RT_PRIO_MIN`] to
+    /// [`RT_PRIO_MAX`]. It runs until it blocks, yields or is preempted by a
+    /// task with higher priority.
+    Fifo(u8),
+    /// Like [`SchedPolicy::Fifo`], but the tasks with the same priority run
+    /// in turn by time slices.
+    RoundRobin(u8),
+    /// Earliest deadline first, it requires the `sched_edf` feature.
+    Deadline(DeadlineParams),
+}
+
+/// The parameters of a [`SchedPolicy::Deadline`] task, in nanoseconds.
+///
+/// The task can run for `runtime` in each `period`, and it must be done in
+/// `deadline` from the start of the period. It's throttled until the next
+/// period if the runtime is used up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeadlineParams {
+    /// The CPU time the task can use in each period.
+    pub runtime: u64,
+    /// The relative deadline from the start of each period.
+    pub deadline: u64,
+    /// The length of each period.
+    pub period: u64,
+}
+
+impl SchedPolicy {
+    /// Returns the bandwidth reserved by the task with the policy.
+    #[cfg(feature = "sched_edf")]
+    fn bandwidth(&self) -> u64 {
+        match self {
+            Self::Deadline(p) => {
+                (((p.runtime as u128) * BW_UNIT as u128).div_ceil(p.period as u128)) as u64
+            }
+            _ => 0,
+        }
+    }
+}
+
+/// Checks the new policy of a task, and reserves the bandwidth for it if
+/// it's a deadline task.
+///
+/// It fails with `ResourceBusy` if there is not enough bandwidth left.
+#[cfg_attr(not(feature = "sched_edf"), allow(unused_variables))]
+pub(crate) fn admit(old: &SchedPolicy, new: &SchedPolicy) -> AxResult {
+    match *new {
+        SchedPolicy::Normal => {}
+        SchedPolicy::Fifo(prio) | SchedPolicy::RoundRobin(prio) => {
+            if !(RT_PRIO_MIN..=RT_PRIO_MAX).contains(&prio) {
+                return ax_err!(InvalidInput, "invalid real-time priority");
+            }
+        }
+        #[cfg(feature = "sched_edf")]
+        SchedPolicy::Deadline(p) => {
+            if p.runtime == 0 || p.runtime > p.deadline || p.deadline > p.period {
+                return ax_err!(InvalidInput, "invalid deadline parameters");
+            }
+        }
+        #[cfg(not(feature = "sched_edf"))]
+        SchedPolicy::Deadline(_) => {
+            return ax_err!(Unsupported, "deadline scheduling is not enabled");
+        }
+    }
+
+    #[cfg(feature = "sched_edf")]
+    {
+        let mut total = DL_BANDWIDTH.lock();
+        let (old_bw, new_bw) = (old.bandwidth(), new.bandwidth());
+        if new_bw > old_bw && *total - old_bw + new_bw > DL_BW_LIMIT {
+            return ax_err!(ResourceBusy, "not enough bandwidth for the deadline task");
+        }
+        *total = *total - old_bw + new_bw;
+    }
+    Ok(())
+}
+
+/// Releases the bandwidth reserved by an exited task.
+#[cfg(feature = "sched_edf")]
+pub(crate) fn release(policy: &SchedPolicy) {
+    *DL_BANDWIDTH.lock() -= policy.bandwidth();
+}
+
+/// The class of a task, picked in reverse order.
+#[derive(Clone, Copy)]
+enum Class {
+    Normal,
+    Fifo(u8),
+    RoundRobin(u8),
+    #[cfg(feature = "sched_edf")]
+    Deadline,
+}
+
+struct SchedState {
+    policy: SchedPolicy,
+    /// The real-time priority inherited from the tasks it blocks, 0 if none.
+    inherited_prio: u8,
+    /// The time slice left of a round-robin task, in timer ticks.
+    time_slice: usize,
+    /// The absolute deadline of the current period, in nanoseconds.
+    #[cfg(feature = "sched_edf")]
+    abs_deadline: u64,
+    /// The runtime left in the current period, in nanoseconds.
+    #[cfg(feature = "sched_edf")]
+    runtime_left: i64,
+    /// When the task starts to run the last time, in nanoseconds.
+    #[cfg(feature = "sched_edf")]
+    exec_start: u64,
+}
+
+/// The scheduling states of a task.
+pub(crate) struct SchedEntity(SpinNoIrq<SchedState>);
+
+impl SchedEntity {
+    /// Creates the states of a new task, which inherits the policy of the
+    /// task creating it, except for the deadline tasks.
+    pub fn new(parent_policy: SchedPolicy) -> Self {
+        let policy = match parent_policy {
+            SchedPolicy::Deadline(_) => SchedPolicy::Normal,
+            policy => policy,
+        };
+        Self(SpinNoIrq::new(SchedState {
+            policy,
+            inherited_prio: 0,
+            time_slice: RR_TIME_SLICE,
+            #[cfg(feature = "sched_edf")]
+            abs_deadline: 0,
+            #[cfg(feature = "sched_edf")]
+            runtime_left: 0,
+            #[cfg(feature = "sched_edf")]
+            exec_start: 0,
+        }))
+    }
+
+    pub fn policy(&self) -> SchedPolicy {
+        self.0.lock().policy
+    }
+
+    /// Sets the policy, it must not be in any run queue.
+    pub fn set_policy(&self, policy: SchedPolicy) {
+        let mut state = self.0.lock();
+        state.policy = policy;
+        state.time_slice = RR_TIME_SLICE;
+        #[cfg(feature = "sched_edf")]
+        {
+            // If it's the current task, it's charged from now on.
+            let now = now();
+            state.exec_start = now;
+            if matches!(policy, SchedPolicy::Deadline(_)) {
+                state.replenish(now);
+            }
+        }
+    }
+
+    pub fn inherited_prio(&self) -> u8 {
+        self.0.lock().inherited_prio
+    }
+
+    /// Sets the real-time priority inherited from the tasks it blocks, it
+    /// must not be in any run queue.
+    pub fn set_inherited_prio(&self, prio: u8) {
+        self.0.lock().inherited_prio = prio.min(RT_PRIO_MAX);
+    }
+
+    /// Returns the real-time priority it runs with, including the inherited
+    /// one. It's 0 for the normal tasks, and [`RT_PRIO_MAX`] for the deadline
+    /// tasks.
+    pub fn rt_prio(&self) -> u8 {
+        let state = self.0.lock();
+        match state.policy {
+            SchedPolicy::Normal => state.inherited_prio,
+            SchedPolicy::Fifo(prio) | SchedPolicy::RoundRobin(prio) => {
+                prio.max(state.inherited_prio)
+            }
+            SchedPolicy::Deadline(_) => RT_PRIO_MAX,
+        }
+    }
+
+    fn class(&self) -> Class {
+        let state = self.0.lock();
+        let inherited = state.inherited_prio;
+        match state.policy {
+            // A normal task runs as a FIFO real-time task when it inherits a
+            // real-time priority.
+            SchedPolicy::Normal if inherited > 0 => Class::Fifo(inherited),
+            SchedPolicy::Normal => Class::Normal,
+            SchedPolicy::Fifo(prio) => Class::Fifo(prio.max(inherited)),
+            SchedPolicy::RoundRobin(prio) => Class::RoundRobin(prio.max(inherited)),
+            #[cfg(feature = "sched_edf")]
+            SchedPolicy::Deadline(_) => Class::Deadline,
+            #[cfg(not(feature = "sched_edf"))]
+            SchedPolicy::Deadline(_) => unreachable!("deadline scheduling is not enabled"),
+        }
+    }
+}
+
+#[cfg(feature = "sched_edf")]
+impl SchedState {
+    fn params(&self) -> DeadlineParams {
+        match self.policy {
+            SchedPolicy::Deadline(params) => params,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Starts a new period at `now`.
+    fn replenish(&mut self, now: u64) {
+        let params = self.params();
+        self.abs_deadline = now + params.deadline;
+        self.runtime_left = params.runtime as i64;
+    }
+
+    /// Charges the time it has run since the last time.
+    fn account(&mut self, now: u64) {
+        self.runtime_left -= now.saturating_sub(self.exec_start) as i64;
+        self.exec_start = now;
+    }
+
+    /// Updates the deadline and the runtime when the task becomes ready at
+    /// `now`. Returns `Ok(deadline)` if it can run, or `Err(time)` if it's
+    /// throttled until `time`.
+    fn update(&mut self, now: u64) -> Result<u64, u64> {
+        let params = self.params();
+        if self.runtime_left <= 0 {
+            let next_period = self.abs_deadline - params.deadline + params.period;
+            if now < next_period {
+                return Err(next_period);
+            }
+            self.replenish(now);
+        } else if now >= self.abs_deadline
+            || self.runtime_left as u128 * params.period as u128
+                > (self.abs_deadline - now) as u128 * params.runtime as u128
+        {
+            // The runtime left cannot be used up before the deadline within
+            // the bandwidth, start a new period (the CBS wake-up rule).
+            self.replenish(now);
+        }
+        Ok(self.abs_deadline)
+    }
+}
+
+/// The scheduler of a CPU, with the ready tasks of all classes.
+pub(crate) struct ClassScheduler {
+    /// The ready deadline tasks, ordered by their absolute deadlines.
+    #[cfg(feature = "sched_edf")]
+    dl_ready: BTreeMap<(u64, u64), AxTaskRef>,
+    /// The deadline tasks that have used up their runtime, ordered by the
+    /// start of their next periods.
+    #[cfg(feature = "sched_edf")]
+    dl_throttled: BTreeMap<(u64, u64), AxTaskRef>,
+    /// The ready real-time tasks of each priority.
+    rt_queues: [VecDeque<AxTaskRef>; RT_PRIO_MAX as usize + 1],
+    /// The bit `i` is set if `rt_queues[i]` is not empty.
+    rt_bitmap: u128,
+    normal: NormalScheduler,
+    /// The ready normal tasks indexed by their IDs, as the tasks in `normal`
+    /// cannot be iterated.
+    normal_tasks: BTreeMap<u64, AxTaskRef>,
+}
+
+impl ClassScheduler {
+    pub fn new() -> Self {
+        Self {
+            #[cfg(feature = "sched_edf")]
+            dl_ready: BTreeMap::new(),
+            #[cfg(feature = "sched_edf")]
+            dl_throttled: BTreeMap::new(),
+            rt_queues: [const { VecDeque::new() }; RT_PRIO_MAX as usize + 1],
+            rt_bitmap: 0,
+            normal: NormalScheduler::new(),
+            normal_tasks: BTreeMap::new(),
+        }
+    }
+
+    pub fn add_task(&mut self, task: AxTaskRef) {
+        match task.sched_entity().class() {
+            Class::Normal => {
+                self.normal_tasks.insert(task.id().as_u64(), task.clone());
+                self.normal.add_task(task);
+            }
+            Class::Fifo(prio) | Class::RoundRobin(prio) => self.push_rt_task(task, prio, false),
+            #[cfg(feature = "sched_edf")]
+            Class::Deadline => self.add_deadline_task(task, now()),
+        }
+    }
+
+    pub fn remove_task(&mut self, task: &AxTaskRef) -> Option<AxTaskRef> {
+        match task.sched_entity().class() {
+            Class::Normal => {
+                self.normal_tasks.remove(&task.id().as_u64());
+                self.normal.remove_task(task)
+            }
+            Class::Fifo(prio) | Class::RoundRobin(prio) => {
+                let queue = &mut self.rt_queues[prio as usize];
+                let removed = queue
+                    .iter()
+                    .position(|t| Arc::ptr_eq(t, task))
+                    .and_then(|i| queue.remove(i));
+                if queue.is_empty() {
+                    self.rt_bitmap &= !(1 << prio);
+                }
+                removed
+            }
+            #[cfg(feature = "sched_edf")]
+            Class::Deadline => {
+                for queue in [&mut self.dl_ready, &mut self.dl_throttled] {
+                    let key = queue.iter().find(|(_, t)| Arc::ptr_eq(t, task));
+                    if let Some(key) = key.map(|(key, _)| *key) {
+                        return queue.remove(&key);
+                    }
+                }
+                None
+            }
+        }
+    }
+
+    pub fn pick_next_task(&mut self) -> Option<AxTaskRef> {
+        #[cfg(feature = "sched_edf")]
+        {
+            let now = now();
+            while let Some(entry) = self.dl_throttled.first_entry() {
+                if entry.key().0 > now {
+                    break;
+                }
+                self.add_deadline_task(entry.remove(), now);
+            }
+            if let Some((_, task)) = self.dl_ready.pop_first() {
+                task.sched_entity().0.lock().exec_start = now;
+                return Some(task);
+            }
+        }
+
+        if let Some(prio) = self.highest_rt_prio() {
+            let queue = &mut self.rt_queues[prio as usize];
+            let task = queue.pop_front();
+            if queue.is_empty() {
+                self.rt_bitmap &= !(1 << prio);
+            }
+            return task;
+        }
+        let task = self.normal.pick_next_task()?;
+        self.normal_tasks.remove(&task.id().as_u64());
+        Some(task)
+    }
+
+    /// Removes a ready task accepted by `filter`, e.g., to move it to another
+    /// CPU. The tasks are searched in the order they are picked, but the
+    /// throttled deadline tasks are not ready.
+    pub fn steal_task(&mut self, filter: impl Fn(&AxTaskRef) -> bool) -> Option<AxTaskRef> {
+        #[cfg(feature = "sched_edf")]
+        if let Some(key) = self
+            .dl_ready
+            .iter()
+            .find(|(_, task)| filter(task))
+            .map(|(key, _)| *key)
+        {
+            return self.dl_ready.remove(&key);
+        }
+
+        let mut bitmap = self.rt_bitmap;
+        while bitmap != 0 {
+            let prio = u128::BITS - 1 - bitmap.leading_zeros();
+            bitmap &= !(1 << prio);
+            let queue = &mut self.rt_queues[prio as usize];
+            if let Some(i) = queue.iter().position(&filter) {
+                let task = queue.remove(i);
+                if queue.is_empty() {
+                    self.rt_bitmap &= !(1 << prio);
+                }
+                return task;
+            }
+        }
+
+        let task = self
+            .normal_tasks
+            .values()
+            .find(|task| filter(task))?
+            .clone();
+        self.remove_task(&task)
+    }
+
+    pub fn put_prev_task(&mut self, prev: AxTaskRef, preempt: bool) {
+        match prev.sched_entity().class() {
+            Class::Normal => {
+                self.normal_tasks.insert(prev.id().as_u64(), prev.clone());
+                self.normal.put_prev_task(prev, preempt);
+            }
+            // A preempted real-time task keeps its place in the queue.
+            Class::Fifo(prio) => self.push_rt_task(prev, prio, preempt),
+            Class::RoundRobin(prio) => {
+                let mut state = prev.sched_entity().0.lock();
+                let expired = state.time_slice == 0;
+                if expired {
+                    state.time_slice = RR_TIME_SLICE;
+                }
+                drop(state);
+                self.push_rt_task(prev, prio, preempt && !expired);
+            }
+            #[cfg(feature = "sched_edf")]
+            Class::Deadline => {
+                let now = now();
+                prev.sched_entity().0.lock().account(now);
+                self.add_deadline_task(prev, now);
+            }
+        }
+    }
+
+    pub fn task_tick(&mut self, current: &AxTaskRef) -> bool {
+        let expired = match current.sched_entity().class() {
+            Class::Normal => self.normal.task_tick(current),
+            Class::Fifo(_) => false,
+            Class::RoundRobin(_) => {
+                let mut state = current.sched_entity().0.lock();
+                state.time_slice = state.time_slice.saturating_sub(1);
+                state.time_slice == 0
+            }
+            #[cfg(feature = "sched_edf")]
+            Class::Deadline => {
+                let mut state = current.sched_entity().0.lock();
+                state.account(now());
+                state.runtime_left <= 0
+            }
+        };
+        expired || self.should_preempt(current)
+    }
+
+    /// Sets the priority of the current task, it's the real-time priority if
+    /// it's a real-time task.
+    pub fn set_priority(&mut self, current: &AxTaskRef, prio: isize) -> bool {
+        let entity = current.sched_entity();
+        // The policy is matched instead of the class, which may be boosted
+        // by the inherited priority.
+        match entity.policy() {
+            SchedPolicy::Normal => self.normal.set_priority(current, prio),
+            policy @ (SchedPolicy::Fifo(_) | SchedPolicy::RoundRobin(_)) => {
+                let Some(prio) = u8::try_from(prio)
+                    .ok()
+                    .filter(|prio| (RT_PRIO_MIN..=RT_PRIO_MAX).contains(prio))
+                else {
+                    return false;
+                };
+                entity.set_policy(match policy {
+                    SchedPolicy::Fifo(_) => SchedPolicy::Fifo(prio),
+                    _ => SchedPolicy::RoundRobin(prio),
+                });
+                true
+            }
+            SchedPolicy::Deadline(_) => false,
+        }
+    }
+
+    /// Returns whether a ready task should run before the current task.
+    pub fn should_preempt(&self, current: &AxTaskRef) -> bool {
+        let earliest_deadline = self.earliest_deadline();
+        match current.sched_entity().class() {
+            Class::Normal => earliest_deadline.is_some() || self.rt_bitmap != 0,
+            Class::Fifo(prio) | Class::RoundRobin(prio) => {
+                earliest_deadline.is_some() || self.highest_rt_prio().is_some_and(|p| p > prio)
+            }
+            #[cfg(feature = "sched_edf")]
+            Class::Deadline => earliest_deadline
+                .is_some_and(|deadline| deadline < current.sched_entity().0.lock().abs_deadline),
+        }
+    }
+}
+
+impl ClassScheduler {
+    fn push_rt_task(&mut self, task: AxTaskRef, prio: u8, front: bool) {
+        let queue = &mut self.rt_queues[prio as usize];
+        if front {
+            queue.push_front(task);
+        } else {
+            queue.push_back(task);
+        }
+        self.rt_bitmap |= 1 << prio;
+    }
+
+    fn highest_rt_prio(&self) -> Option<u8> {
+        (self.rt_bitmap != 0).then(|| (u128::BITS - 1 - self.rt_bitmap.leading_zeros()) as u8)
+    }
+
+    #[cfg(feature = "sched_edf")]
+    fn add_deadline_task(&mut self, task: AxTaskRef, now: u64) {
+        let id = task.id().as_u64();
+        let res = task.sched_entity().0.lock().update(now);
+        match res {
+            Ok(deadline) => self.dl_ready.insert((deadline, id), task),
+            Err(next_period) => self.dl_throttled.insert((next_period, id), task),
+        };
+    }
+
+    fn earliest_deadline(&self) -> Option<u64> {
+        #[cfg(feature = "sched_edf")]
+        return self.dl_ready.first_key_value().map(|(key, _)| key.0);
+        #[cfg(not(feature = "sched_edf"))]
+        None
+    }
+}
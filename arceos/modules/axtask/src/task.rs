@@ -1,19 +1,21 @@
+use alloc::collections::BTreeMap;
+use alloc::sync::Weak;
+use alloc::vec::Vec;
 use alloc::{boxed::Box, string::String, sync::Arc};
 use core::ops::Deref;
-use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, AtomicU8, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, AtomicU8, AtomicUsize, Ordering};
 use core::{alloc::Layout, cell::UnsafeCell, fmt, ptr::NonNull};
 
-#[cfg(feature = "preempt")]
-use core::sync::atomic::AtomicUsize;
-
 #[cfg(feature = "tls")]
 use axhal::tls::TlsArea;
 
 use axhal::arch::TaskContext;
+use kspin::SpinNoIrq;
 use memory_addr::{align_up_4k, VirtAddr};
 
+use crate::sched_class::{SchedEntity, SchedPolicy};
 use crate::task_ext::AxTaskExt;
-use crate::{AxRunQueue, AxTask, AxTaskRef, WaitQueue};
+use crate::{AxTask, AxTaskRef, CpuMask, Cred, WaitQueue};
 
 /// A unique identifier for a thread.
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -22,13 +24,34 @@ pub struct TaskId(u64);
 /// The possible states of a task.
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
-pub(crate) enum TaskState {
+pub enum TaskState {
+    /// The task is running on a CPU.
     Running = 1,
+    /// The task is ready to run, waiting in the run queue.
     Ready = 2,
+    /// The task is blocked, e.g., in a wait queue.
     Blocked = 3,
+    /// The task has exited, but has not been dropped yet.
     Exited = 4,
 }
 
+/// All tasks that have not been dropped, indexed by their IDs.
+static TASK_TABLE: SpinNoIrq<BTreeMap<u64, Weak<AxTask>>> = SpinNoIrq::new(BTreeMap::new());
+
+/// Returns all tasks that have not been dropped, ordered by their IDs.
+pub fn all_tasks() -> Vec<AxTaskRef> {
+    TASK_TABLE
+        .lock()
+        .values()
+        .filter_map(Weak::upgrade)
+        .collect()
+}
+
+/// Returns the task with the given ID, if it has not been dropped.
+pub fn find_task(id: u64) -> Option<AxTaskRef> {
+    TASK_TABLE.lock().get(&id).and_then(Weak::upgrade)
+}
+
 /// The inner task structure.
 pub struct TaskInner {
     id: TaskId,
@@ -43,6 +66,14 @@ pub struct TaskInner {
     #[cfg(feature = "irq")]
     in_timer_list: AtomicBool,
 
+    /// The CPU the task is running on, or it is queued on or ran on last.
+    cpu_id: AtomicUsize,
+    /// The bits of the CPUs the task is allowed to run on.
+    cpumask: AtomicUsize,
+    /// Whether the task is running on a CPU, or being switched out.
+    on_cpu: AtomicBool,
+    sched: SchedEntity,
+
     #[cfg(feature = "preempt")]
     need_resched: AtomicBool,
     #[cfg(feature = "preempt")]
@@ -54,6 +85,7 @@ pub struct TaskInner {
     kstack: Option<TaskStack>,
     ctx: UnsafeCell<TaskContext>,
     task_ext: AxTaskExt,
+    cred: SpinNoIrq<Cred>,
 
     #[cfg(feature = "tls")]
     tls: TlsArea,
@@ -135,6 +167,66 @@ impl TaskInner {
         Some(self.exit_code.load(Ordering::Acquire))
     }
 
+    /// Returns the ID of the CPU the task is running on, or the one it is
+    /// queued on or ran on last if it's not running.
+    pub fn cpu_id(&self) -> usize {
+        self.cpu_id.load(Ordering::Acquire)
+    }
+
+    /// Returns the CPUs the task is allowed to run on.
+    pub fn cpumask(&self) -> CpuMask {
+        CpuMask::from_bits(self.cpumask.load(Ordering::Acquire))
+    }
+
+    /// Sets the CPUs the task is allowed to run on.
+    ///
+    /// The CPUs not in the system are ignored, and it returns `false` if
+    /// none is left, or the task is an idle task.
+    ///
+    /// The current task is migrated at once if it's not allowed to run on the
+    /// current CPU any more. The others are migrated the next time they are
+    /// scheduled.
+    pub fn set_affinity(&self, cpus: CpuMask) -> bool {
+        let cpus = cpus.intersection(CpuMask::full());
+        if cpus.is_empty() || self.is_idle {
+            return false;
+        }
+        self.cpumask.store(cpus.bits(), Ordering::Release);
+        if crate::current_may_uninit().is_some_and(|curr| curr.id() == self.id) {
+            crate::current_run_queue().migrate_current();
+        }
+        true
+    }
+
+    /// Returns the scheduling policy of the task.
+    ///
+    /// A new task has the policy of the task spawning it, except that it's
+    /// [`SchedPolicy::Normal`] if the latter is a deadline task.
+    pub fn sched_policy(&self) -> SchedPolicy {
+        self.sched.policy()
+    }
+
+    /// Returns the real-time priority the task runs with, including the one
+    /// inherited by [`set_inherited_prio`](crate::set_inherited_prio).
+    ///
+    /// It's 0 for a normal task that inherits nothing, and [`RT_PRIO_MAX`]
+    /// for a deadline task.
+    ///
+    /// [`RT_PRIO_MAX`]: crate::RT_PRIO_MAX
+    pub fn rt_prio(&self) -> u8 {
+        self.sched.rt_prio()
+    }
+
+    /// Returns the credentials of the task.
+    pub fn cred(&self) -> Cred {
+        *self.cred.lock()
+    }
+
+    /// Sets the credentials of the task.
+    pub fn set_cred(&self, cred: Cred) {
+        *self.cred.lock() = cred;
+    }
+
     /// Returns the pointer to the user-defined task extended data.
     ///
     /// # Safety
@@ -174,6 +266,12 @@ impl TaskInner {
             in_wait_queue: AtomicBool::new(false),
             #[cfg(feature = "irq")]
             in_timer_list: AtomicBool::new(false),
+            cpu_id: AtomicUsize::new(axhal::cpu::this_cpu_id()),
+            cpumask: AtomicUsize::new(CpuMask::full().bits()),
+            on_cpu: AtomicBool::new(false),
+            sched: SchedEntity::new(
+                crate::current_may_uninit().map_or(SchedPolicy::Normal, |curr| curr.sched_policy()),
+            ),
             #[cfg(feature = "preempt")]
             need_resched: AtomicBool::new(false),
             #[cfg(feature = "preempt")]
@@ -183,6 +281,7 @@ impl TaskInner {
             kstack: None,
             ctx: UnsafeCell::new(TaskContext::new()),
             task_ext: AxTaskExt::empty(),
+            cred: SpinNoIrq::new(crate::current_cred()),
             #[cfg(feature = "tls")]
             tls: TlsArea::alloc(),
         }
@@ -199,6 +298,7 @@ impl TaskInner {
     pub(crate) fn new_init(name: String) -> Self {
         let mut t = Self::new_common(TaskId::new(), name);
         t.is_init = true;
+        t.on_cpu = AtomicBool::new(true);
         if t.name == "idle" {
             t.is_idle = true;
         }
@@ -206,11 +306,15 @@ impl TaskInner {
     }
 
     pub(crate) fn into_arc(self) -> AxTaskRef {
-        Arc::new(AxTask::new(self))
+        let id = self.id.as_u64();
+        let task = Arc::new(AxTask::new(self));
+        TASK_TABLE.lock().insert(id, Arc::downgrade(&task));
+        task
     }
 
+    /// Gets the state of the task.
     #[inline]
-    pub(crate) fn state(&self) -> TaskState {
+    pub fn state(&self) -> TaskState {
         self.state.load(Ordering::Acquire).into()
     }
 
@@ -229,11 +333,6 @@ impl TaskInner {
         matches!(self.state(), TaskState::Ready)
     }
 
-    #[inline]
-    pub(crate) fn is_blocked(&self) -> bool {
-        matches!(self.state(), TaskState::Blocked)
-    }
-
     #[inline]
     pub(crate) const fn is_init(&self) -> bool {
         self.is_init
@@ -244,6 +343,35 @@ impl TaskInner {
         self.is_idle
     }
 
+    /// Changes the state from `from` to `to`, returns `false` if the state
+    /// is not `from`.
+    #[inline]
+    pub(crate) fn transition_state(&self, from: TaskState, to: TaskState) -> bool {
+        self.state
+            .compare_exchange(from as u8, to as u8, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
+    #[inline]
+    pub(crate) fn sched_entity(&self) -> &SchedEntity {
+        &self.sched
+    }
+
+    #[inline]
+    pub(crate) fn set_cpu_id(&self, cpu_id: usize) {
+        self.cpu_id.store(cpu_id, Ordering::Release)
+    }
+
+    #[inline]
+    pub(crate) fn on_cpu(&self) -> bool {
+        self.on_cpu.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub(crate) fn set_on_cpu(&self, on_cpu: bool) {
+        self.on_cpu.store(on_cpu, Ordering::Release)
+    }
+
     #[inline]
     pub(crate) fn in_wait_queue(&self) -> bool {
         self.in_wait_queue.load(Ordering::Acquire)
@@ -297,16 +425,16 @@ impl TaskInner {
     fn current_check_preempt_pending() {
         let curr = crate::current();
         if curr.need_resched.load(Ordering::Acquire) && curr.can_preempt(0) {
-            let mut rq = crate::RUN_QUEUE.lock();
+            let mut rq = crate::current_run_queue();
             if curr.need_resched.load(Ordering::Acquire) {
                 rq.preempt_resched();
             }
         }
     }
 
-    pub(crate) fn notify_exit(&self, exit_code: i32, rq: &mut AxRunQueue) {
+    pub(crate) fn notify_exit(&self, exit_code: i32) {
         self.exit_code.store(exit_code, Ordering::Release);
-        self.wait_for_exit.notify_all_locked(false, rq);
+        self.wait_for_exit.notify_all(false);
     }
 
     #[inline]
@@ -343,6 +471,7 @@ impl fmt::Debug for TaskInner {
 impl Drop for TaskInner {
     fn drop(&mut self) {
         debug!("task drop: {}", self.id_name());
+        TASK_TABLE.lock().remove(&self.id.as_u64());
     }
 }
 
@@ -430,7 +559,10 @@ impl Deref for CurrentTask {
 
 extern "C" fn task_entry() -> ! {
     // release the lock that was implicitly held across the reschedule
-    unsafe { crate::RUN_QUEUE.force_unlock() };
+    unsafe {
+        crate::run_queue::finish_task_switch();
+        crate::run_queue::force_unlock_current();
+    }
     #[cfg(feature = "irq")]
     axhal::arch::enable_irqs();
     let task = crate::current();
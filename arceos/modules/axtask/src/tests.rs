@@ -1,5 +1,5 @@
 use core::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Mutex, Once};
+use std::sync::{Arc, Mutex, Once};
 
 use crate::{api as axtask, current, WaitQueue};
 
@@ -128,3 +128,348 @@ fn test_task_join() {
         assert_eq!(tasks[i].join(), Some(i as _));
     }
 }
+
+#[test]
+fn test_find_task() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    let task = axtask::spawn_raw(|| axtask::exit(0), "find_me".into(), 0x1000);
+    let id = task.id().as_u64();
+    let found = axtask::find_task(id).unwrap();
+    assert!(Arc::ptr_eq(&found, &task));
+    assert!(axtask::all_tasks().iter().any(|t| t.id() == task.id()));
+    assert!(axtask::find_task(current().id().as_u64()).is_some());
+
+    assert_eq!(task.join(), Some(0));
+    assert_eq!(found.state(), axtask::TaskState::Exited);
+}
+
+#[test]
+fn test_task_cred() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    let cred = crate::Cred {
+        uid: 1000,
+        gid: 100,
+        umask: 0o077,
+    };
+    assert!(axtask::current_cred().is_root());
+    axtask::set_current_cred(cred);
+    assert_eq!(current().cred(), cred);
+
+    // inherited by the new tasks
+    let task = axtask::spawn_raw(
+        move || {
+            assert_eq!(axtask::current_cred(), cred);
+            axtask::set_current_cred(crate::Cred::ROOT);
+        },
+        "cred".into(),
+        0x1000,
+    );
+    assert_eq!(task.join(), Some(0));
+    assert_eq!(task.cred(), crate::Cred::ROOT);
+    assert_eq!(axtask::current_cred(), cred);
+
+    axtask::set_current_cred(crate::Cred::ROOT);
+}
+
+#[test]
+fn test_affinity() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    use axtask::CpuMask;
+
+    assert_eq!(current().cpumask(), CpuMask::full());
+    assert!(!axtask::set_current_affinity(CpuMask::empty()));
+    assert!(!axtask::set_current_affinity(CpuMask::one(axconfig::SMP)));
+    assert_eq!(current().cpumask(), CpuMask::full());
+
+    assert!(axtask::set_current_affinity(CpuMask::one(0)));
+    assert_eq!(current().cpumask(), CpuMask::one(0));
+    assert_eq!(current().cpu_id(), 0);
+
+    // the CPUs not in the system are ignored
+    let task = axtask::spawn_raw(
+        || {
+            let mut cpus = CpuMask::one(0);
+            cpus.insert(axconfig::SMP);
+            assert!(axtask::set_current_affinity(cpus));
+            assert_eq!(current().cpumask(), CpuMask::one(0));
+            axtask::yield_now();
+            assert_eq!(current().cpu_id(), 0);
+        },
+        "affinity".into(),
+        0x1000,
+    );
+    assert_eq!(task.join(), Some(0));
+    assert!(axtask::set_current_affinity(CpuMask::full()));
+}
+
+#[test]
+fn test_steal_task() {
+    if axconfig::SMP < 2 {
+        println!("steal_task: skipped, it needs `AX_SMP` > 1");
+        return;
+    }
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    use crate::run_queue::{flush_parked_cpu, hide_parked_cpu, init_parked_cpu, nr_ready};
+    use crate::{CpuMask, TaskInner};
+
+    const NUM_TASKS: usize = 4;
+    static STOLEN_TASKS: AtomicUsize = AtomicUsize::new(0);
+
+    // CPU 1 never runs, its tasks only run if CPU 0 steals them.
+    init_parked_cpu(1);
+    let spawn_on_cpu1 = |name: &str| {
+        let task = TaskInner::new(
+            || {
+                assert_eq!(current().cpu_id(), 0);
+                STOLEN_TASKS.fetch_add(1, Ordering::Relaxed);
+            },
+            name.into(),
+            0x1000,
+        );
+        assert!(task.set_affinity(CpuMask::one(1)));
+        axtask::spawn_task(task)
+    };
+    let pinned = spawn_on_cpu1("pinned");
+    let tasks: Vec<_> = (0..NUM_TASKS)
+        .map(|i| spawn_on_cpu1(&format!("S{}", i)))
+        .collect();
+    flush_parked_cpu(1);
+    assert_eq!(nr_ready(1), NUM_TASKS + 1);
+
+    // The task pinned to CPU 1 is never stolen, the others are.
+    axtask::yield_now();
+    let ready = nr_ready(0);
+    for task in &tasks {
+        assert!(task.set_affinity(CpuMask::full()));
+    }
+    for task in tasks {
+        assert_eq!(task.join(), Some(0));
+    }
+    axtask::yield_now();
+    assert_eq!(STOLEN_TASKS.load(Ordering::Relaxed), NUM_TASKS);
+    assert_eq!(nr_ready(0), ready);
+    assert_eq!(nr_ready(1), 1);
+
+    assert!(pinned.set_affinity(CpuMask::full()));
+    assert_eq!(pinned.join(), Some(0));
+    assert_eq!(nr_ready(1), 0);
+
+    // Keep the tasks of the other tests off CPU 1.
+    hide_parked_cpu(1);
+}
+
+#[test]
+fn test_sched_policy() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    use axtask::{DeadlineParams, SchedPolicy};
+
+    let curr = current().as_task_ref().clone();
+    assert_eq!(curr.sched_policy(), SchedPolicy::Normal);
+    assert!(axtask::set_sched_policy(&curr, SchedPolicy::Fifo(0)).is_err());
+    assert!(axtask::set_sched_policy(&curr, SchedPolicy::RoundRobin(100)).is_err());
+    let params = DeadlineParams {
+        runtime: 2_000_000,
+        deadline: 1_000_000,
+        period: 3_000_000,
+    };
+    assert!(axtask::set_sched_policy(&curr, SchedPolicy::Deadline(params)).is_err());
+    assert_eq!(curr.sched_policy(), SchedPolicy::Normal);
+
+    // the real-time tasks run before the normal ones, in order of priority
+    static ORDER: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+    let policies = [
+        SchedPolicy::Normal,
+        SchedPolicy::Fifo(10),
+        SchedPolicy::RoundRobin(50),
+        SchedPolicy::Fifo(50),
+    ];
+    let tasks: Vec<_> = policies
+        .into_iter()
+        .enumerate()
+        .map(|(i, policy)| {
+            let task = axtask::spawn_raw(
+                move || ORDER.lock().unwrap().push(i),
+                format!("T{}", i),
+                0x1000,
+            );
+            axtask::set_sched_policy(&task, policy).unwrap();
+            assert_eq!(task.sched_policy(), policy);
+            task
+        })
+        .collect();
+    for task in tasks {
+        assert_eq!(task.join(), Some(0));
+    }
+    assert_eq!(*ORDER.lock().unwrap(), [2, 3, 1, 0]);
+
+    // inherited by the new tasks
+    axtask::set_sched_policy(&curr, SchedPolicy::RoundRobin(1)).unwrap();
+    assert!(axtask::set_priority(20));
+    assert_eq!(curr.sched_policy(), SchedPolicy::RoundRobin(20));
+    let task = axtask::spawn_raw(
+        || assert_eq!(current().sched_policy(), SchedPolicy::RoundRobin(20)),
+        "inherit".into(),
+        0x1000,
+    );
+    assert_eq!(task.join(), Some(0));
+    axtask::set_sched_policy(&curr, SchedPolicy::Normal).unwrap();
+}
+
+#[test]
+fn test_inherited_prio() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    use axtask::SchedPolicy;
+
+    static ORDER: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+    let tasks: Vec<_> = (0..3)
+        .map(|i| {
+            axtask::spawn_raw(
+                move || ORDER.lock().unwrap().push(i),
+                format!("T{}", i),
+                0x1000,
+            )
+        })
+        .collect();
+    axtask::set_sched_policy(&tasks[1], SchedPolicy::Fifo(20)).unwrap();
+    // the higher one of its own and the inherited priority is used
+    axtask::set_inherited_prio(&tasks[1], 10);
+    assert_eq!(tasks[1].rt_prio(), 20);
+    // a normal task runs as a real-time one
+    axtask::set_inherited_prio(&tasks[2], 30);
+    assert_eq!(tasks[2].rt_prio(), 30);
+    assert_eq!(tasks[2].sched_policy(), SchedPolicy::Normal);
+    for task in tasks {
+        assert_eq!(task.join(), Some(0));
+    }
+    assert_eq!(*ORDER.lock().unwrap(), [2, 1, 0]);
+
+    let curr = current().as_task_ref().clone();
+    assert_eq!(curr.rt_prio(), 0);
+    axtask::set_inherited_prio(&curr, 10);
+    assert_eq!(curr.rt_prio(), 10);
+    axtask::set_inherited_prio(&curr, 0);
+    assert_eq!(curr.rt_prio(), 0);
+}
+
+#[cfg(feature = "sched_edf")]
+#[test]
+fn test_sched_edf() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    use crate::sched_class::TIME_OFFSET;
+    use axerrno::AxError;
+    use axtask::{DeadlineParams, SchedPolicy, RT_PRIO_MAX};
+
+    const MS: u64 = 1_000_000;
+    // The clock does not advance on the host, it's moved by the tasks.
+    let advance = |ns: u64| TIME_OFFSET.fetch_add(ns, Ordering::Relaxed);
+    let deadline_policy = |runtime: u64, deadline: u64, period: u64| {
+        SchedPolicy::Deadline(DeadlineParams {
+            runtime,
+            deadline,
+            period,
+        })
+    };
+
+    // the deadline tasks run before the real-time ones, in order of their
+    // deadlines
+    static ORDER: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+    let policies = [
+        SchedPolicy::Fifo(RT_PRIO_MAX),
+        deadline_policy(MS, 30 * MS, 100 * MS),
+        deadline_policy(MS, 10 * MS, 100 * MS),
+        deadline_policy(MS, 20 * MS, 100 * MS),
+    ];
+    let tasks: Vec<_> = policies
+        .into_iter()
+        .enumerate()
+        .map(|(i, policy)| {
+            let task = axtask::spawn_raw(
+                move || ORDER.lock().unwrap().push(i),
+                format!("D{}", i),
+                0x1000,
+            );
+            axtask::set_sched_policy(&task, policy).unwrap();
+            task
+        })
+        .collect();
+    for task in tasks {
+        assert_eq!(task.join(), Some(0));
+    }
+    assert_eq!(*ORDER.lock().unwrap(), [2, 3, 1, 0]);
+
+    // no more than 95% of each CPU can be reserved
+    let tasks: Vec<_> = (0..=axconfig::SMP)
+        .map(|i| axtask::spawn_raw(|| {}, format!("B{}", i), 0x1000))
+        .collect();
+    let busy = deadline_policy(90 * MS, 100 * MS, 100 * MS);
+    for task in &tasks[..axconfig::SMP] {
+        assert_eq!(axtask::set_sched_policy(task, busy), Ok(()));
+    }
+    let last = &tasks[axconfig::SMP];
+    assert_eq!(
+        axtask::set_sched_policy(last, busy),
+        Err(AxError::ResourceBusy)
+    );
+    assert_eq!(last.sched_policy(), SchedPolicy::Normal);
+    // the bandwidth is released when the tasks exit
+    let tasks: Vec<_> = tasks.into_iter().filter(|t| t.id() != last.id()).collect();
+    for task in tasks {
+        assert_eq!(task.join(), Some(0));
+    }
+    assert_eq!(axtask::set_sched_policy(last, busy), Ok(()));
+    assert_eq!(last.clone().join(), Some(0));
+
+    // a task running out of its runtime is throttled until its next period
+    static PHASE: AtomicUsize = AtomicUsize::new(0);
+    let task = axtask::spawn_raw(
+        move || {
+            PHASE.store(1, Ordering::Relaxed);
+            advance(2 * MS); // run for 2ms
+            axtask::yield_now();
+            PHASE.store(2, Ordering::Relaxed);
+        },
+        "throttled".into(),
+        0x1000,
+    );
+    axtask::set_sched_policy(&task, deadline_policy(MS, 10 * MS, 10 * MS)).unwrap();
+    axtask::yield_now();
+    assert_eq!(PHASE.load(Ordering::Relaxed), 1);
+    axtask::yield_now();
+    assert_eq!(PHASE.load(Ordering::Relaxed), 1);
+    advance(10 * MS);
+    axtask::yield_now();
+    assert_eq!(PHASE.load(Ordering::Relaxed), 2);
+    assert_eq!(task.join(), Some(0));
+
+    // the current task is charged from the time it becomes a deadline task
+    static NORMAL_RAN: AtomicUsize = AtomicUsize::new(0);
+    let task = axtask::spawn_raw(
+        move || {
+            NORMAL_RAN.store(1, Ordering::Relaxed);
+            advance(100 * MS); // let the throttled task run again
+        },
+        "normal".into(),
+        0x1000,
+    );
+    let curr = current().as_task_ref().clone();
+    axtask::set_sched_policy(&curr, deadline_policy(5 * MS, 100 * MS, 100 * MS)).unwrap();
+    axtask::yield_now();
+    assert_eq!(NORMAL_RAN.load(Ordering::Relaxed), 0);
+    axtask::set_sched_policy(&curr, SchedPolicy::Normal).unwrap();
+    assert_eq!(task.join(), Some(0));
+    assert_eq!(NORMAL_RAN.load(Ordering::Relaxed), 1);
+}
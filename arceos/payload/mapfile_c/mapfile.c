@@ -44,6 +44,76 @@ void verify_file(const char *fname)
     close(fd);
 }
 
+void fill_file(const char *fname, int flags, char first, char second)
+{
+    int fd;
+    char page[4096];
+
+    fd = open(fname, flags, 0600);
+    if (fd < 0) {
+        printf("Open file error!\n");
+        exit(-1);
+    }
+    memset(page, first, sizeof(page));
+    if (write(fd, page, sizeof(page)) != sizeof(page)) {
+        printf("Write file error!\n");
+        exit(-1);
+    }
+    memset(page, second, sizeof(page));
+    if (write(fd, page, sizeof(page)) != sizeof(page)) {
+        printf("Write file error!\n");
+        exit(-1);
+    }
+    close(fd);
+}
+
+/* Only the pages written through a shared mapping are written back. */
+void verify_shared_file(const char *fname)
+{
+    int fd;
+    char *addr = NULL;
+    char buf[8192];
+
+    fill_file(fname, O_WRONLY | O_CREAT | O_TRUNC, 'a', 'a');
+    fd = open(fname, O_RDWR);
+    if (fd < 0) {
+        printf("Open file error!\n");
+        exit(-1);
+    }
+    addr = mmap(NULL, sizeof(buf), PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+    if (addr == MAP_FAILED) {
+        printf("Map file error!\n");
+        exit(-1);
+    }
+    /* Both pages are read into memory, then the second one is changed in
+     * the file behind the mapping. */
+    if (addr[0] != 'a' || addr[4096] != 'a') {
+        printf("Shared mapping content error!\n");
+        exit(-1);
+    }
+    fill_file(fname, O_WRONLY, 'a', 'b');
+
+    addr[0] = 'c';
+    if (msync(addr, sizeof(buf), MS_SYNC) < 0) {
+        printf("Sync file error!\n");
+        exit(-1);
+    }
+    munmap(addr, sizeof(buf));
+    close(fd);
+
+    fd = open(fname, O_RDONLY);
+    if (fd < 0 || read(fd, buf, sizeof(buf)) != sizeof(buf)) {
+        printf("Read file error!\n");
+        exit(-1);
+    }
+    close(fd);
+    if (buf[0] != 'c' || buf[1] != 'a' || buf[4096] != 'b') {
+        printf("Shared mapping write-back error!\n");
+        exit(-1);
+    }
+    printf("Shared mapping write-back ok\n");
+}
+
 int main()
 {
     int fd;
@@ -54,6 +124,7 @@ int main()
 
     create_file(fname);
     verify_file(fname);
+    verify_shared_file(fname);
 
     printf("MapFile ok!\n");
     return 0;
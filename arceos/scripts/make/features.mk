@@ -33,6 +33,10 @@ ifeq ($(APP_TYPE), c)
   endif
 endif
 
+ifneq ($(INITRD),)
+  override FEATURES += initramfs
+endif
+
 override FEATURES := $(strip $(FEATURES))
 
 ax_feat :=
@@ -33,6 +33,12 @@ qemu_args-$(BLK) += \
   -device virtio-blk-$(vdev-suffix),drive=disk0 \
   -drive id=disk0,if=none,format=raw,file=$(DISK_IMG)
 
+ifneq ($(INITRD),)
+  ifneq ($(INITRD_BUILTIN), y)
+    qemu_args-y += -initrd $(INITRD)
+  endif
+endif
+
 qemu_args-$(NET) += \
   -device virtio-net-$(vdev-suffix),netdev=net0
 
@@ -2,5 +2,9 @@
 
 define unit_test
   $(call run_cmd,cargo test,-p axfs $(1) --features "myfs" -- --nocapture)
+  $(call run_cmd,cargo test,-p axfs $(1) --features "initramfs" -- --nocapture)
+  $(call run_cmd,cargo test,-p axfs $(1) --features "extfs" -- --nocapture)
   $(call run_cmd,cargo test,--workspace $(1) -- --nocapture)
+  $(call run_cmd,cargo test,-p axtask $(1) --features "sched_edf" -- --nocapture)
+  $(call run_cmd,AX_SMP=2 cargo test,-p axtask $(1) -- --nocapture)
 endef
@@ -22,6 +22,11 @@ define make_disk_image
   $(if $(filter $(1),fat32), $(call make_disk_image_fat32,$(2)))
 endef
 
+define make_initrd
+  @printf "    $(GREEN_C)Creating$(END_C) initramfs archive \"$(2)\" from \"$(1)\" ...\n"
+  @cd $(1) && find . | cpio -o -H newc --quiet > $(abspath $(2))
+endef
+
 define mk_pflash
   @RUSTFLAGS="" cargo build -p origin  --target riscv64gc-unknown-none-elf --release
   @rust-objcopy --binary-architecture=riscv64 --strip-all -O binary ./target/riscv64gc-unknown-none-elf/release/origin /tmp/origin.bin
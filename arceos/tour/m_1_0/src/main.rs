@@ -8,24 +8,21 @@ extern crate alloc;
 #[macro_use]
 extern crate axlog;
 
-mod task;
 mod syscall;
 mod loader;
 
-use axstd::io;
 use axhal::paging::MappingFlags;
 use axhal::arch::UspaceContext;
 use axhal::mem::VirtAddr;
 use axsync::Mutex;
 use alloc::sync::Arc;
-use axmm::AddrSpace;
+use alloc::string::String;
 use loader::load_user_app;
 use axtask::TaskExtRef;
 use axhal::trap::{register_trap_handler, PAGE_FAULT};
 
-const USER_STACK_SIZE: usize = 0x10000;
-const KERNEL_STACK_SIZE: usize = 0x40000; // 256 KiB
 const APP_ENTRY: usize = 0x1000;
+const APP_PATH: &str = "/sbin/origin";
 
 #[cfg_attr(feature = "axstd", no_mangle)]
 fn main() {
@@ -33,16 +30,17 @@ fn main() {
     let mut uspace = axmm::new_user_aspace().unwrap();
 
     // Load user app binary file into address space.
-    if let Err(e) = load_user_app("/sbin/origin", &mut uspace) {
+    if let Err(e) = load_user_app(APP_PATH, &mut uspace) {
         panic!("Cannot load app! {:?}", e);
     }
 
     // Init user stack.
-    let ustack_top = init_user_stack(&mut uspace, false).unwrap();
+    let ustack_top =
+        axprocess::init_user_stack(&mut uspace, false, &[String::from(APP_PATH)], &[]).unwrap();
     ax_println!("New user address space: {:#x?}", uspace);
 
     // Let's kick off the user process.
-    let user_task = task::spawn_user_task(
+    let user_task = axprocess::spawn_user_task(
         Arc::new(Mutex::new(uspace)),
         UspaceContext::new(APP_ENTRY.into(), ustack_top),
     );
@@ -52,39 +50,27 @@ fn main() {
     ax_println!("monolithic kernel exit [{:?}] normally!", exit_code);
 }
 
-fn init_user_stack(uspace: &mut AddrSpace, populating: bool) -> io::Result<VirtAddr> {
-    let ustack_top = uspace.end();
-    let ustack_vaddr = ustack_top - crate::USER_STACK_SIZE;
-    ax_println!(
-        "Mapping user stack: {:#x?} -> {:#x?}",
-        ustack_vaddr, ustack_top
-    );
-    uspace.map_alloc(
-        ustack_vaddr,
-        crate::USER_STACK_SIZE,
-        MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
-        populating,
-    ).unwrap();
-    Ok(ustack_top)
-}
-
-
 #[register_trap_handler(PAGE_FAULT)]
 fn handle_page_fault(vaddr: VirtAddr, access_flags: MappingFlags, is_user: bool) -> bool {
-    if is_user {
-        if !axtask::current()
-            .task_ext()
-            .aspace
-            .lock()
-            .handle_page_fault(vaddr, access_flags)
-        {
-            ax_println!("{}: segmentation fault, exit!", axtask::current().id_name());
-            axtask::exit(-1);
-        } else {
-            ax_println!("{}: handle page fault OK!", axtask::current().id_name());
+    let curr = axtask::current();
+    // The kernel may also fault on user memory, e.g., when a syscall writes to
+    // a copy-on-write page. Kernel tasks have no user space at all.
+    if !is_user && unsafe { curr.task_ext_ptr() }.is_null() {
+        return false;
+    }
+    if !curr
+        .task_ext()
+        .aspace
+        .lock()
+        .handle_page_fault(vaddr, access_flags)
+    {
+        if !is_user {
+            return false;
         }
-        true
+        ax_println!("{}: segmentation fault, exit!", curr.id_name());
+        axprocess::exit_current(-1, true);
     } else {
-        false
+        ax_println!("{}: handle page fault OK!", curr.id_name());
     }
+    true
 }
@@ -1,8 +1,7 @@
-#![allow(dead_code)]
-
 use core::ffi::{c_void, c_char, c_int};
-use axhal::arch::TrapFrame;
+use axhal::arch::{TrapFrame, UspaceContext};
 use axhal::trap::{register_trap_handler, SYSCALL};
+use axprocess::CloneFlags;
 use axerrno::LinuxError;
 use axtask::current;
 use axtask::TaskExtRef;
@@ -17,33 +16,116 @@ const SYS_WRITEV: usize = 66;
 const SYS_EXIT: usize = 93;
 const SYS_EXIT_GROUP: usize = 94;
 const SYS_SET_TID_ADDRESS: usize = 96;
+const SYS_FUTEX: usize = 98;
+const SYS_SCHED_YIELD: usize = 124;
+const SYS_GETPID: usize = 172;
+const SYS_GETPPID: usize = 173;
+const SYS_GETTID: usize = 178;
+const SYS_SHMGET: usize = 194;
+const SYS_SHMCTL: usize = 195;
+const SYS_SHMAT: usize = 196;
+const SYS_SHMDT: usize = 197;
+const SYS_CLONE: usize = 220;
+const SYS_EXECVE: usize = 221;
+const SYS_WAIT4: usize = 260;
 
 const AT_FDCWD: i32 = -100;
 
+/// Macro to generate syscall body
+///
+/// It will receive a function which return Result<_, LinuxError> and convert it to
+/// the type which is specified by the caller.
+#[macro_export]
+macro_rules! syscall_body {
+    ($fn: ident, $($stmt: tt)*) => {{
+        #[allow(clippy::redundant_closure_call)]
+        let res = (|| -> axerrno::LinuxResult<_> { $($stmt)* })();
+        match res {
+            Ok(_) | Err(axerrno::LinuxError::EAGAIN) => debug!(concat!(stringify!($fn), " => {:?}"),  res),
+            Err(_) => info!(concat!(stringify!($fn), " => {:?}"), res),
+        }
+        match res {
+            Ok(v) => v as _,
+            Err(e) => {
+                -e.code() as _
+            }
+        }
+    }};
+}
+
 #[register_trap_handler(SYSCALL)]
 fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
     ax_println!("handle_syscall [{}] ...", syscall_num);
+    if current().task_ext().process.is_group_exiting() {
+        // Another thread has called `exit_group`.
+        axprocess::exit_current(0, false);
+    }
     let ret = match syscall_num {
          SYS_IOCTL => sys_ioctl(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _) as _,
-        SYS_SET_TID_ADDRESS => sys_set_tid_address(tf.arg0() as _),
+        SYS_SET_TID_ADDRESS => syscall_body!(sys_set_tid_address, {
+            axprocess::sys_set_tid_address(tf.arg0() as _)
+        }),
         SYS_OPENAT => sys_openat(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _, tf.arg3() as _),
         SYS_CLOSE => sys_close(tf.arg0() as _),
         SYS_READ => sys_read(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
         SYS_WRITE => sys_write(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
         SYS_WRITEV => sys_writev(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
         SYS_EXIT_GROUP => {
-            ax_println!("[SYS_EXIT_GROUP]: system is exiting ..");
-            axtask::exit(tf.arg0() as _)
+            ax_println!("[SYS_EXIT_GROUP]: process is exiting ..");
+            axprocess::exit_current(tf.arg0() as _, true)
         },
         SYS_EXIT => {
-            ax_println!("[SYS_EXIT]: system is exiting ..");
-            axtask::exit(tf.arg0() as _)
+            ax_println!("[SYS_EXIT]: thread is exiting ..");
+            axprocess::exit_current(tf.arg0() as _, false)
+        },
+        SYS_FUTEX => syscall_body!(sys_futex, {
+            axprocess::sys_futex(
+                tf.arg0() as _,
+                tf.arg1() as _,
+                tf.arg2() as _,
+                tf.arg3() as _,
+                tf.arg4() as _,
+                tf.arg5() as _,
+            )
+        }),
+        SYS_SCHED_YIELD => {
+            axtask::yield_now();
+            0
         },
+        SYS_GETPID => current().task_ext().proc_id() as _,
+        SYS_GETPPID => current().task_ext().process.ppid() as _,
+        SYS_GETTID => current().id().as_u64() as _,
+        SYS_CLONE => sys_clone(
+            tf,
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg3() as _,
+            tf.arg4() as _,
+        ),
+        SYS_EXECVE => sys_execve(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        SYS_WAIT4 => syscall_body!(sys_wait4, {
+            axprocess::sys_wait4(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _)
+        }),
+        SYS_SHMGET => syscall_body!(sys_shmget, {
+            axprocess::shm::shmget(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _)
+        }),
+        SYS_SHMAT => syscall_body!(sys_shmat, {
+            axprocess::sys_shmat(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _)
+        }),
+        SYS_SHMDT => syscall_body!(sys_shmdt, axprocess::sys_shmdt(tf.arg0() as _)),
+        SYS_SHMCTL => syscall_body!(sys_shmctl, {
+            axprocess::shm::shmctl(tf.arg0() as _, tf.arg1() as _)
+        }),
         _ => {
             ax_println!("Unimplemented syscall: {}", syscall_num);
             -LinuxError::ENOSYS.code() as _
         }
     };
+    if current().task_ext().process.is_group_exiting() {
+        // Another thread has called `exit_group` while it was blocked.
+        axprocess::exit_current(0, false);
+    }
     ret
 }
 
@@ -68,13 +150,52 @@ fn sys_writev(fd: i32, iov: *const api::ctypes::iovec, iocnt: i32) -> isize {
     unsafe { api::sys_writev(fd, iov, iocnt) }
 }
 
-fn sys_set_tid_address(tid_ptd: *const i32) -> isize {
-    let curr = current();
-    curr.task_ext().set_clear_child_tid(tid_ptd as _);
-    curr.id().as_u64() as isize
-}
-
 fn sys_ioctl(_fd: i32, _op: usize, _argp: *mut c_void) -> i32 {
     ax_println!("Ignore SYS_IOCTL");
     0
 }
+
+/// Creates a child thread or process, see [`axprocess::sys_clone`].
+fn sys_clone(
+    tf: &TrapFrame,
+    flags: usize,
+    newsp: usize,
+    ptid: usize,
+    tls: usize,
+    ctid: usize,
+) -> isize {
+    syscall_body!(sys_clone, {
+        // The child returns 0 from the syscall. The trap handler only skips
+        // the `ecall` instruction for the parent, so do it for the child here.
+        let mut child_tf = *tf;
+        child_tf.sepc += 4;
+        child_tf.regs.a0 = 0;
+        if newsp != 0 {
+            child_tf.regs.sp = newsp;
+        }
+        if CloneFlags::from_bits_truncate(flags).contains(CloneFlags::CLONE_SETTLS) {
+            child_tf.regs.tp = tls;
+        }
+        axprocess::sys_clone(flags, UspaceContext::from(&child_tf), ptid, ctid)
+    })
+}
+
+/// Replaces the image of the current process with the program at `path`.
+///
+/// It never returns on success.
+fn sys_execve(
+    path: *const c_char,
+    argv: *const *const c_char,
+    envp: *const *const c_char,
+) -> isize {
+    match axprocess::sys_execve(path, argv, envp, crate::loader::load_user_app) {
+        Ok(uctx) => {
+            let kstack_top = current().kernel_stack_top().unwrap();
+            unsafe { uctx.enter_uspace(kstack_top) }
+        }
+        Err(e) => {
+            info!("sys_execve => {:?}", e);
+            -e.code() as _
+        }
+    }
+}
@@ -82,20 +82,6 @@ int rmdir(const char *pathname)
     return 0;
 }
 
-// TODO:
-int fsync(int fd)
-{
-    unimplemented();
-    return 0;
-}
-
-// TODO
-int fdatasync(int __fildes)
-{
-    unimplemented();
-    return 0;
-}
-
 // TODO:
 int fchown(int fd, uid_t owner, gid_t group)
 {
@@ -21,6 +21,7 @@
 #define IOV_MAX    1024
 
 #define PTHREAD_STACK_MIN 2048
+#define SEM_VALUE_MAX     0x7fffffff
 
 #define LOGIN_NAME_MAX 256
 #ifndef NAME_MAX
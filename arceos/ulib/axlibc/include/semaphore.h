@@ -0,0 +1,25 @@
+#ifndef _SEMAPHORE_H
+#define _SEMAPHORE_H
+
+#include <features.h>
+#include <time.h>
+
+typedef struct {
+    volatile int __val[4 * sizeof(long)];
+} sem_t;
+
+#define SEM_FAILED ((sem_t *)0)
+
+#ifdef AX_CONFIG_MULTITASK
+
+int sem_init(sem_t *__sem, int __pshared, unsigned __value);
+int sem_destroy(sem_t *__sem);
+int sem_wait(sem_t *__sem);
+int sem_trywait(sem_t *__sem);
+int sem_timedwait(sem_t *__restrict__ __sem, const struct timespec *__restrict__ __abstime);
+int sem_post(sem_t *__sem);
+int sem_getvalue(sem_t *__restrict__ __sem, int *__restrict__ __sval);
+
+#endif // AX_CONFIG_MULTITASK
+
+#endif // _SEMAPHORE_H
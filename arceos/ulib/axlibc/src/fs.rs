@@ -1,7 +1,11 @@
-use core::ffi::{c_char, c_int};
+use core::ffi::{c_char, c_int, c_ulong, c_void};
 
 use arceos_posix_api::{
-    sys_fstat, sys_getcwd, sys_lseek, sys_lstat, sys_open, sys_rename, sys_stat,
+    sys_access, sys_chmod, sys_chown, sys_fdatasync, sys_fgetxattr, sys_flistxattr, sys_flock,
+    sys_fremovexattr, sys_fsetxattr, sys_fstat, sys_fsync, sys_getcwd, sys_getxattr,
+    sys_inotify_add_watch, sys_inotify_init1, sys_inotify_rm_watch, sys_link, sys_listxattr,
+    sys_lseek, sys_lstat, sys_mount, sys_open, sys_readlink, sys_removexattr, sys_rename,
+    sys_setxattr, sys_stat, sys_symlink, sys_sync, sys_umask, sys_umount2,
 };
 
 use crate::{ctypes, utils::e};
@@ -65,3 +69,250 @@ pub unsafe extern "C" fn getcwd(buf: *mut c_char, size: usize) -> *mut c_char {
 pub unsafe extern "C" fn rename(old: *const c_char, new: *const c_char) -> c_int {
     e(sys_rename(old, new))
 }
+
+/// Create a symbolic link `linkpath` pointing to `target`.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+#[no_mangle]
+pub unsafe extern "C" fn symlink(target: *const c_char, linkpath: *const c_char) -> c_int {
+    e(sys_symlink(target, linkpath))
+}
+
+/// Read the target of the symbolic link `path` into `buf`.
+///
+/// Return the number of bytes placed in `buf`, otherwise return -1.
+#[no_mangle]
+pub unsafe extern "C" fn readlink(
+    path: *const c_char,
+    buf: *mut c_char,
+    size: usize,
+) -> ctypes::ssize_t {
+    e(sys_readlink(path, buf, size) as _) as _
+}
+
+/// Create a hard link `new` to the file `old`.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+#[no_mangle]
+pub unsafe extern "C" fn link(old: *const c_char, new: *const c_char) -> c_int {
+    e(sys_link(old, new))
+}
+
+/// Change the permission bits of the file `path` to `mode`.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+#[no_mangle]
+pub unsafe extern "C" fn chmod(path: *const c_char, mode: ctypes::mode_t) -> c_int {
+    e(sys_chmod(path, mode))
+}
+
+/// Change the owner and the group of the file `path`.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+#[no_mangle]
+pub unsafe extern "C" fn chown(
+    path: *const c_char,
+    uid: ctypes::uid_t,
+    gid: ctypes::gid_t,
+) -> c_int {
+    e(sys_chown(path, uid, gid))
+}
+
+/// Set the file mode creation mask to `mask`, and return the previous one.
+#[no_mangle]
+pub unsafe extern "C" fn umask(mask: ctypes::mode_t) -> ctypes::mode_t {
+    sys_umask(mask)
+}
+
+/// Check whether the file `path` can be accessed with `mode`.
+///
+/// Return 0 if the access is permitted, otherwise return -1.
+#[no_mangle]
+pub unsafe extern "C" fn access(path: *const c_char, mode: c_int) -> c_int {
+    e(sys_access(path, mode))
+}
+
+/// Mount the filesystem of the type `fstype` on the directory `target`.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+#[no_mangle]
+pub unsafe extern "C" fn mount(
+    source: *const c_char,
+    target: *const c_char,
+    fstype: *const c_char,
+    flags: c_ulong,
+    data: *const c_void,
+) -> c_int {
+    e(sys_mount(source, target, fstype, flags, data))
+}
+
+/// Unmount the filesystem mounted on `target`.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+#[no_mangle]
+pub unsafe extern "C" fn umount(target: *const c_char) -> c_int {
+    e(sys_umount2(target, 0))
+}
+
+/// Unmount the filesystem mounted on `target` with the `flags`, e.g.,
+/// `MNT_DETACH`.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+#[no_mangle]
+pub unsafe extern "C" fn umount2(target: *const c_char, flags: c_int) -> c_int {
+    e(sys_umount2(target, flags))
+}
+
+/// Write back all the cached data of the filesystems to the disks.
+#[no_mangle]
+pub unsafe extern "C" fn sync() {
+    sys_sync();
+}
+
+/// Write back the cached data and metadata of the file `fd` to the disk.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+#[no_mangle]
+pub unsafe extern "C" fn fsync(fd: c_int) -> c_int {
+    e(sys_fsync(fd))
+}
+
+/// Write back the cached data of the file `fd` to the disk.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+#[no_mangle]
+pub unsafe extern "C" fn fdatasync(fd: c_int) -> c_int {
+    e(sys_fdatasync(fd))
+}
+
+/// Apply or remove an advisory lock on the whole file `fd`.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+#[no_mangle]
+pub unsafe extern "C" fn flock(fd: c_int, operation: c_int) -> c_int {
+    e(sys_flock(fd, operation))
+}
+
+/// Get the value of the extended attribute `name` of the file `path`.
+///
+/// Return the size of the value, otherwise return -1.
+#[no_mangle]
+pub unsafe extern "C" fn getxattr(
+    path: *const c_char,
+    name: *const c_char,
+    value: *mut c_void,
+    size: usize,
+) -> ctypes::ssize_t {
+    e(sys_getxattr(path, name, value, size) as _) as _
+}
+
+/// Get the value of the extended attribute `name` of the file `fd`.
+///
+/// Return the size of the value, otherwise return -1.
+#[no_mangle]
+pub unsafe extern "C" fn fgetxattr(
+    fd: c_int,
+    name: *const c_char,
+    value: *mut c_void,
+    size: usize,
+) -> ctypes::ssize_t {
+    e(sys_fgetxattr(fd, name, value, size) as _) as _
+}
+
+/// Set the value of the extended attribute `name` of the file `path`.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+#[no_mangle]
+pub unsafe extern "C" fn setxattr(
+    path: *const c_char,
+    name: *const c_char,
+    value: *const c_void,
+    size: usize,
+    flags: c_int,
+) -> c_int {
+    e(sys_setxattr(path, name, value, size, flags))
+}
+
+/// Set the value of the extended attribute `name` of the file `fd`.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+#[no_mangle]
+pub unsafe extern "C" fn fsetxattr(
+    fd: c_int,
+    name: *const c_char,
+    value: *const c_void,
+    size: usize,
+    flags: c_int,
+) -> c_int {
+    e(sys_fsetxattr(fd, name, value, size, flags))
+}
+
+/// List the names of the extended attributes of the file `path`.
+///
+/// Return the size of the list, otherwise return -1.
+#[no_mangle]
+pub unsafe extern "C" fn listxattr(
+    path: *const c_char,
+    list: *mut c_char,
+    size: usize,
+) -> ctypes::ssize_t {
+    e(sys_listxattr(path, list, size) as _) as _
+}
+
+/// List the names of the extended attributes of the file `fd`.
+///
+/// Return the size of the list, otherwise return -1.
+#[no_mangle]
+pub unsafe extern "C" fn flistxattr(fd: c_int, list: *mut c_char, size: usize) -> ctypes::ssize_t {
+    e(sys_flistxattr(fd, list, size) as _) as _
+}
+
+/// Remove the extended attribute `name` of the file `path`.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+#[no_mangle]
+pub unsafe extern "C" fn removexattr(path: *const c_char, name: *const c_char) -> c_int {
+    e(sys_removexattr(path, name))
+}
+
+/// Remove the extended attribute `name` of the file `fd`.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+#[no_mangle]
+pub unsafe extern "C" fn fremovexattr(fd: c_int, name: *const c_char) -> c_int {
+    e(sys_fremovexattr(fd, name))
+}
+
+/// Create a new inotify instance.
+///
+/// Return its file descriptor, otherwise return -1.
+#[no_mangle]
+pub unsafe extern "C" fn inotify_init() -> c_int {
+    e(sys_inotify_init1(0))
+}
+
+/// Create a new inotify instance with the flags `IN_NONBLOCK` and
+/// `IN_CLOEXEC`.
+///
+/// Return its file descriptor, otherwise return -1.
+#[no_mangle]
+pub unsafe extern "C" fn inotify_init1(flags: c_int) -> c_int {
+    e(sys_inotify_init1(flags))
+}
+
+/// Watch the file `pathname` for the events in `mask` with the inotify
+/// instance `fd`.
+///
+/// Return the watch descriptor, otherwise return -1.
+#[no_mangle]
+pub unsafe extern "C" fn inotify_add_watch(fd: c_int, pathname: *const c_char, mask: u32) -> c_int {
+    e(sys_inotify_add_watch(fd, pathname, mask))
+}
+
+/// Remove the watch `wd` from the inotify instance `fd`.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+#[no_mangle]
+pub unsafe extern "C" fn inotify_rm_watch(fd: c_int, wd: c_int) -> c_int {
+    e(sys_inotify_rm_watch(fd, wd))
+}
@@ -63,6 +63,8 @@ mod net;
 mod pipe;
 #[cfg(feature = "multitask")]
 mod pthread;
+#[cfg(feature = "multitask")]
+mod semaphore;
 #[cfg(feature = "alloc")]
 mod strftime;
 #[cfg(feature = "fp_simd")]
@@ -73,6 +75,7 @@ mod io;
 mod mktime;
 mod rand;
 mod resource;
+mod sched;
 mod setjmp;
 mod sys;
 mod time;
@@ -86,6 +89,8 @@ pub use self::errno::strerror;
 pub use self::mktime::mktime;
 pub use self::rand::{rand, random, srand};
 pub use self::resource::{getrlimit, setrlimit};
+pub use self::sched::{sched_get_priority_max, sched_get_priority_min};
+pub use self::sched::{sched_getaffinity, sched_setaffinity};
 pub use self::setjmp::{longjmp, setjmp};
 pub use self::sys::sysconf;
 pub use self::time::{clock_gettime, nanosleep};
@@ -108,10 +113,28 @@ pub use self::net::{
     recvfrom, send, sendto, shutdown, socket,
 };
 
+#[cfg(feature = "multitask")]
+pub use self::pthread::{
+    pthread_cond_broadcast, pthread_cond_destroy, pthread_cond_init, pthread_cond_signal,
+    pthread_cond_timedwait, pthread_cond_wait,
+};
 #[cfg(feature = "multitask")]
 pub use self::pthread::{pthread_create, pthread_exit, pthread_join, pthread_self};
 #[cfg(feature = "multitask")]
 pub use self::pthread::{pthread_mutex_init, pthread_mutex_lock, pthread_mutex_unlock};
+#[cfg(feature = "multitask")]
+pub use self::pthread::{
+    pthread_rwlock_destroy, pthread_rwlock_init, pthread_rwlock_rdlock, pthread_rwlock_tryrdlock,
+    pthread_rwlock_trywrlock, pthread_rwlock_unlock, pthread_rwlock_wrlock,
+};
+#[cfg(feature = "multitask")]
+pub use self::sched::{sched_getattr, sched_getparam, sched_getscheduler};
+#[cfg(feature = "multitask")]
+pub use self::sched::{sched_setattr, sched_setparam, sched_setscheduler};
+#[cfg(feature = "multitask")]
+pub use self::semaphore::{
+    sem_destroy, sem_getvalue, sem_init, sem_post, sem_timedwait, sem_trywait, sem_wait,
+};
 
 #[cfg(feature = "pipe")]
 pub use self::pipe::pipe;
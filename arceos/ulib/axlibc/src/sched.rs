@@ -0,0 +1,92 @@
+use arceos_posix_api::{sys_sched_get_priority_max, sys_sched_get_priority_min};
+use arceos_posix_api::{sys_sched_getaffinity, sys_sched_setaffinity};
+use core::ffi::{c_int, c_uint};
+
+use crate::{ctypes, utils::e};
+
+/// Set the CPUs the thread `pid` is allowed to run on.
+#[no_mangle]
+pub unsafe extern "C" fn sched_setaffinity(
+    pid: c_int,
+    cpusetsize: usize,
+    mask: *const ctypes::cpu_set_t,
+) -> c_int {
+    e(sys_sched_setaffinity(pid, cpusetsize, mask))
+}
+
+/// Get the CPUs the thread `pid` is allowed to run on.
+#[no_mangle]
+pub unsafe extern "C" fn sched_getaffinity(
+    pid: c_int,
+    cpusetsize: usize,
+    mask: *mut ctypes::cpu_set_t,
+) -> c_int {
+    e(sys_sched_getaffinity(pid, cpusetsize, mask))
+}
+
+/// Get the highest priority of the scheduling policy.
+#[no_mangle]
+pub unsafe extern "C" fn sched_get_priority_max(policy: c_int) -> c_int {
+    e(sys_sched_get_priority_max(policy))
+}
+
+/// Get the lowest priority of the scheduling policy.
+#[no_mangle]
+pub unsafe extern "C" fn sched_get_priority_min(policy: c_int) -> c_int {
+    e(sys_sched_get_priority_min(policy))
+}
+
+/// Set the scheduling policy and the priority of the thread `pid`.
+#[cfg(feature = "multitask")]
+#[no_mangle]
+pub unsafe extern "C" fn sched_setscheduler(
+    pid: c_int,
+    policy: c_int,
+    param: *const ctypes::sched_param,
+) -> c_int {
+    e(arceos_posix_api::sys_sched_setscheduler(pid, policy, param))
+}
+
+/// Get the scheduling policy of the thread `pid`.
+#[cfg(feature = "multitask")]
+#[no_mangle]
+pub unsafe extern "C" fn sched_getscheduler(pid: c_int) -> c_int {
+    e(arceos_posix_api::sys_sched_getscheduler(pid))
+}
+
+/// Set the priority of the thread `pid`.
+#[cfg(feature = "multitask")]
+#[no_mangle]
+pub unsafe extern "C" fn sched_setparam(pid: c_int, param: *const ctypes::sched_param) -> c_int {
+    e(arceos_posix_api::sys_sched_setparam(pid, param))
+}
+
+/// Get the priority of the thread `pid`.
+#[cfg(feature = "multitask")]
+#[no_mangle]
+pub unsafe extern "C" fn sched_getparam(pid: c_int, param: *mut ctypes::sched_param) -> c_int {
+    e(arceos_posix_api::sys_sched_getparam(pid, param))
+}
+
+/// Set the scheduling policy and the attributes of the thread `pid`.
+#[cfg(feature = "multitask")]
+#[no_mangle]
+pub unsafe extern "C" fn sched_setattr(
+    pid: c_int,
+    attr: *mut ctypes::sched_attr,
+    flags: c_uint,
+) -> c_int {
+    e(arceos_posix_api::sys_sched_setattr(pid, attr, flags))
+}
+
+/// Get the scheduling policy and the attributes of the thread `pid`.
+#[cfg(feature = "multitask")]
+#[no_mangle]
+pub unsafe extern "C" fn sched_getattr(
+    pid: c_int,
+    attr: *mut ctypes::sched_attr,
+    size: c_uint,
+    flags: c_uint,
+) -> c_int {
+    e(arceos_posix_api::sys_sched_getattr(pid, attr, size, flags))
+}
@@ -1,5 +1,7 @@
-use arceos_posix_api::{sys_exit, sys_getpid};
-use core::ffi::c_int;
+use arceos_posix_api::{sys_exit, sys_getgid, sys_getpid, sys_getuid, sys_setgid, sys_setuid};
+use core::ffi::{c_int, c_uint};
+
+use crate::utils::e;
 
 /// Get current thread ID.
 #[no_mangle]
@@ -14,7 +16,50 @@ pub unsafe extern "C" fn abort() -> ! {
 }
 
 /// Exits the current thread.
+///
+/// The cached data of the filesystems is written back first, as the system is
+/// shut down when the main thread exits.
 #[no_mangle]
 pub unsafe extern "C" fn exit(exit_code: c_int) -> ! {
+    #[cfg(feature = "fs")]
+    arceos_posix_api::sys_sync();
     sys_exit(exit_code)
 }
+
+/// Get the user ID of the current task.
+#[no_mangle]
+pub unsafe extern "C" fn getuid() -> c_uint {
+    sys_getuid()
+}
+
+/// Get the effective user ID of the current task, which is the same as the
+/// user ID.
+#[no_mangle]
+pub unsafe extern "C" fn geteuid() -> c_uint {
+    sys_getuid()
+}
+
+/// Get the group ID of the current task.
+#[no_mangle]
+pub unsafe extern "C" fn getgid() -> c_uint {
+    sys_getgid()
+}
+
+/// Get the effective group ID of the current task, which is the same as the
+/// group ID.
+#[no_mangle]
+pub unsafe extern "C" fn getegid() -> c_uint {
+    sys_getgid()
+}
+
+/// Set the user ID of the current task.
+#[no_mangle]
+pub unsafe extern "C" fn setuid(uid: c_uint) -> c_int {
+    e(sys_setuid(uid))
+}
+
+/// Set the group ID of the current task.
+#[no_mangle]
+pub unsafe extern "C" fn setgid(gid: c_uint) -> c_int {
+    e(sys_setgid(gid))
+}
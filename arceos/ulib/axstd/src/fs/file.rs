@@ -16,7 +16,7 @@ pub struct File {
 }
 
 /// Metadata information about a file.
-pub struct Metadata(api::AxFileAttr);
+pub struct Metadata(pub(super) api::AxFileAttr);
 
 /// Options and flags which can be used to configure how a file is opened.
 #[derive(Clone, Debug)]
@@ -90,6 +90,12 @@ impl Metadata {
         self.0.is_file()
     }
 
+    /// Returns `true` if this metadata is for a symbolic link, which is only
+    /// possible for the metadata from [`symlink_metadata`](super::symlink_metadata).
+    pub const fn is_symlink(&self) -> bool {
+        matches!(self.0.file_type(), FileType::SymLink)
+    }
+
     /// Returns the size of the file, in bytes, this metadata is for.
     #[allow(clippy::len_without_is_empty)]
     pub const fn len(&self) -> u64 {
@@ -158,6 +164,17 @@ impl File {
         api::ax_truncate_file(&self.inner, size)
     }
 
+    /// Attempts to sync all OS-internal metadata to disk.
+    pub fn sync_all(&self) -> Result<()> {
+        api::ax_sync_file(&self.inner)
+    }
+
+    /// Similar to [`sync_all`](Self::sync_all), but may not synchronize the
+    /// metadata not needed to read the data back.
+    pub fn sync_data(&self) -> Result<()> {
+        api::ax_sync_file(&self.inner)
+    }
+
     /// Queries metadata about the underlying file.
     pub fn metadata(&self) -> Result<Metadata> {
         api::ax_file_attr(&self.inner).map(Metadata)
@@ -42,6 +42,11 @@ pub fn metadata(path: &str) -> io::Result<Metadata> {
     File::open(path)?.metadata()
 }
 
+/// Queries the metadata about a file without following symbolic links.
+pub fn symlink_metadata(path: &str) -> io::Result<Metadata> {
+    arceos_api::fs::ax_symlink_attr(path).map(Metadata)
+}
+
 /// Returns an iterator over the entries within a directory.
 pub fn read_dir(path: &str) -> io::Result<ReadDir> {
     ReadDir::new(path)
@@ -75,3 +80,53 @@ pub fn remove_file(path: &str) -> io::Result<()> {
 pub fn rename(old: &str, new: &str) -> io::Result<()> {
     arceos_api::fs::ax_rename(old, new)
 }
+
+/// Creates a new symbolic link `link` pointing to `original`.
+pub fn symlink(original: &str, link: &str) -> io::Result<()> {
+    arceos_api::fs::ax_symlink(original, link)
+}
+
+/// Reads a symbolic link, returning the path it points to.
+pub fn read_link(path: &str) -> io::Result<String> {
+    arceos_api::fs::ax_read_link(path)
+}
+
+/// Creates a new hard link `link` to the file `original`.
+///
+/// This only works then both paths are in the same mounted fs.
+pub fn hard_link(original: &str, link: &str) -> io::Result<()> {
+    arceos_api::fs::ax_hard_link(original, link)
+}
+
+/// Changes the permissions found on a file or a directory.
+pub fn set_permissions(path: &str, perm: Permissions) -> io::Result<()> {
+    arceos_api::fs::ax_set_permissions(path, perm)
+}
+
+/// Changes the owner and the group of the file at the path. The ones given
+/// as `None` are not changed.
+pub fn chown(path: &str, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+    arceos_api::fs::ax_chown(path, uid, gid)
+}
+
+/// Mounts a new filesystem of the type `fstype` (e.g., `tmpfs` or `ext2`) on
+/// the directory `target`.
+///
+/// `source` is the block device file for the disk-based filesystems, e.g.,
+/// `/dev/vdb`, and is ignored by the others.
+pub fn mount(source: &str, target: &str, fstype: &str) -> io::Result<()> {
+    arceos_api::fs::ax_mount(source, target, fstype)
+}
+
+/// Unmounts the filesystem mounted on `target`.
+///
+/// It fails if there are files opened in the filesystem, or the current
+/// directory is in it.
+pub fn umount(target: &str) -> io::Result<()> {
+    arceos_api::fs::ax_umount(target, false)
+}
+
+/// Writes back all the cached data of the filesystems to the disks.
+pub fn sync() -> io::Result<()> {
+    arceos_api::fs::ax_sync()
+}
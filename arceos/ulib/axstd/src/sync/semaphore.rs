@@ -0,0 +1,87 @@
+//! A naïve counting semaphore.
+
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::time::Duration;
+
+use arceos_api::task::{self as api, AxWaitQueueHandle};
+
+use crate::time::Instant;
+
+/// A counting semaphore.
+///
+/// It holds a number of permits. [`Semaphore::acquire`] takes one, and the
+/// current thread will block and be put into the wait queue if there is none
+/// left. [`Semaphore::release`] puts one back and wakes up a waiting thread.
+pub struct Semaphore {
+    wq: AxWaitQueueHandle,
+    count: AtomicUsize,
+}
+
+impl Semaphore {
+    /// Creates a new semaphore with the given number of permits.
+    pub const fn new(count: usize) -> Self {
+        Self {
+            wq: AxWaitQueueHandle::new(),
+            count: AtomicUsize::new(count),
+        }
+    }
+
+    /// Returns the number of the permits left.
+    ///
+    /// Like [`Mutex::is_locked`](super::Mutex::is_locked), the result may be
+    /// out of date the instant it is called.
+    pub fn available_permits(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Takes a permit, blocking the current thread until there is one.
+    pub fn acquire(&self) {
+        while !self.try_acquire() {
+            api::ax_wait_queue_wait(&self.wq, || self.count.load(Ordering::Relaxed) > 0, None);
+        }
+    }
+
+    /// Try to take a permit, returns `true` if successful.
+    pub fn try_acquire(&self) -> bool {
+        self.count
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |count| {
+                count.checked_sub(1)
+            })
+            .is_ok()
+    }
+
+    /// Like [`Semaphore::acquire`], but gives up after the duration has
+    /// elapsed. Returns `true` if a permit is taken.
+    ///
+    /// The timeout only works with the `irq` feature, otherwise it's ignored.
+    pub fn acquire_timeout(&self, dur: Duration) -> bool {
+        let start = Instant::now();
+        while !self.try_acquire() {
+            let elapsed = start.elapsed();
+            if elapsed >= dur {
+                return false;
+            }
+            api::ax_wait_queue_wait(
+                &self.wq,
+                || self.count.load(Ordering::Relaxed) > 0,
+                Some(dur - elapsed),
+            );
+        }
+        true
+    }
+
+    /// Puts a permit back, and wakes up a waiting thread.
+    pub fn release(&self) {
+        self.count.fetch_add(1, Ordering::Release);
+        api::ax_wait_queue_wake(&self.wq, 1);
+    }
+}
+
+impl fmt::Debug for Semaphore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Semaphore")
+            .field("permits", &self.available_permits())
+            .finish()
+    }
+}
@@ -7,6 +7,8 @@ use alloc::{string::String, sync::Arc};
 use core::{cell::UnsafeCell, num::NonZeroU64};
 
 use arceos_api::task::{self as api, AxTaskHandle};
+
+pub use arceos_api::task::AxCpuMask as CpuMask;
 use axerrno::ax_err_type;
 
 /// A unique identifier for a running thread.
@@ -49,6 +51,8 @@ pub struct Builder {
     name: Option<String>,
     // The size of the stack for the spawned thread in bytes
     stack_size: Option<usize>,
+    // The CPUs the spawned thread is allowed to run on
+    affinity: Option<CpuMask>,
 }
 
 impl Builder {
@@ -58,6 +62,7 @@ impl Builder {
         Builder {
             name: None,
             stack_size: None,
+            affinity: None,
         }
     }
 
@@ -73,6 +78,15 @@ impl Builder {
         self
     }
 
+    /// Sets the CPUs the new thread is allowed to run on.
+    ///
+    /// The CPUs not in the system are ignored, and spawning fails if none is
+    /// left.
+    pub fn affinity(mut self, cpus: CpuMask) -> Builder {
+        self.affinity = Some(cpus);
+        self
+    }
+
     /// Spawns a new thread by taking ownership of the `Builder`, and returns an
     /// [`io::Result`] to its [`JoinHandle`].
     ///
@@ -99,6 +113,13 @@ impl Builder {
         let stack_size = self
             .stack_size
             .unwrap_or(arceos_api::config::TASK_STACK_SIZE);
+        let affinity = self.affinity;
+        if affinity.is_some_and(|cpus| cpus.intersection(CpuMask::full()).is_empty()) {
+            return Err(ax_err_type!(
+                InvalidInput,
+                "no CPU in the affinity is available"
+            ));
+        }
 
         let my_packet = Arc::new(Packet {
             result: UnsafeCell::new(None),
@@ -106,6 +127,10 @@ impl Builder {
         let their_packet = my_packet.clone();
 
         let main = move || {
+            if let Some(cpus) = affinity {
+                // it has been checked above, so it must succeed
+                api::ax_set_current_affinity(cpus).unwrap();
+            }
             let ret = f();
             // SAFETY: `their_packet` as been built just above and moved by the
             // closure (it is an Arc<...>) and `my_packet` will be stored in the